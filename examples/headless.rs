@@ -0,0 +1,27 @@
+//! Runs a ROM without a window and prints the screen it ends on, e.g.
+//! `cargo run --example headless -- test/ibm_logo.ch8 60 chip8`
+
+use chip8::{Emulator, Platform};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().ok_or("Usage: headless <rom> [frames] [chip8|schip|xochip]")?;
+    let frames: u32 = args.next().map_or(Ok(60), |frames| frames.parse())?;
+    let platform: Platform = args.next().map_or(Ok(Platform::Chip8), |name| name.parse())?;
+
+    let mut emulator = Emulator::new(platform);
+    emulator.load(&std::fs::read(&path)?)?;
+    while emulator.frames() < frames && !emulator.halted() {
+        emulator.run_frame()?;
+    }
+
+    for row in emulator.screen().rows() {
+        let line: String = row.iter().map(|&lit| if lit { '#' } else { ' ' }).collect();
+        println!("{}", line.trim_end());
+    }
+    println!("{} cycles in {:.2}s of emulated time", emulator.chip8().cycles, emulator.elapsed().as_secs_f64());
+    for report in emulator.diagnostics() {
+        eprintln!("{}", report);
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Plays a program with scripted key presses and checks what it draws, as a ROM's own tests
+//! might. Exits with an error if a check fails.
+//! `cargo run --example scripted_test`
+
+use chip8::{Emulator, Instruction, Platform, Screen};
+
+/// Waits for a key, shows its hex digit at the top left, then waits for it to be let go
+const PROGRAM: [Instruction; 7] = [
+    Instruction::GetKey { register: 0 },               // 200: V0 = the next key held
+    Instruction::ClearScreen,                          // 202: clear the screen
+    Instruction::FontChar { register: 0 },             // 204: I = V0's glyph
+    Instruction::Draw { x_r: 1, y_r: 1, height: 5 },   // 206: draw it at (V1, V1), which is (0, 0)
+    Instruction::SkipNotPressed { register: 0 },       // 208: if the key is still held,
+    Instruction::Jump { dest: 0x208 },                 // 20a:   check again
+    Instruction::Jump { dest: 0x200 },                 // 20c: otherwise wait for the next
+];
+
+/// The 4x5 glyph at the top left, as `#` and spaces
+fn glyph(screen: &Screen) -> Vec<String> {
+    (0..5).map(|y| (0..4).map(|x| if screen.pixel(x, y) { '#' } else { ' ' }).collect()).collect()
+}
+
+/// Holds `key` for a few frames, then lets go and gives the program a frame to notice
+fn tap(emulator: &mut Emulator, key: usize) -> Result<(), chip8::Error> {
+    emulator.set_key(key, true);
+    for _ in 0..3 {
+        emulator.run_frame()?;
+    }
+    emulator.set_key(key, false);
+    emulator.run_frame()?;
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut emulator = Emulator::new(Platform::Chip8);
+    emulator.chip8_mut().load_instructions(&PROGRAM);
+
+    emulator.run_frame()?;
+    if emulator.screen().rows().flatten().any(|&lit| lit) {
+        return Err("Drew something before a key was pressed".into());
+    }
+
+    let script = [
+        (7, ["####", "   #", "  # ", " #  ", " #  "]),
+        (0xa, ["####", "#  #", "####", "#  #", "#  #"]),
+    ];
+    for (key, expected) in script {
+        tap(&mut emulator, key)?;
+        let drawn = glyph(emulator.screen());
+        if drawn != expected {
+            return Err(format!("After tapping {:X}, expected {:?} but the screen showed {:?}", key, expected, drawn).into());
+        }
+        println!("{:X} ok", key);
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+//! A frontend in the terminal, showing everything one has to do: run a frame 60 times a second,
+//! draw the screen when it changes, and pass keys in. Type hex digits then Enter to tap keys, e.g.
+//! `cargo run --example terminal -- test/keypad.ch8`
+
+use std::io::Write;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use chip8::{Beep, Emulator, Platform, Screen};
+
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+/// How long a typed key stays held, since a terminal only says when it was typed
+const TAP_FRAMES: u32 = 6;
+
+/// Draws two rows of pixels per line of text, with half blocks
+fn draw(screen: &Screen, out: &mut impl Write) -> std::io::Result<()> {
+    write!(out, "\x1b[H")?;
+    for y in (0..screen.height()).step_by(2) {
+        let line: String = (0..screen.width())
+            .map(|x| match (screen.pixel(x, y), screen.pixel(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            })
+            .collect();
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::args().nth(1).ok_or("Usage: terminal <rom>")?;
+    let mut emulator = Emulator::new(Platform::Chip8);
+    emulator.load(&std::fs::read(&path)?)?;
+
+    // Reading stdin blocks, so it gets a thread of its own
+    let (taps, typed) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            for key in line.chars().filter_map(|c| c.to_digit(16)) {
+                if taps.send(key as usize).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut out = std::io::stdout().lock();
+    write!(out, "\x1b[2J")?;
+    draw(emulator.screen(), &mut out)?;
+    let mut held = [0; 16];
+    let mut next_frame = Instant::now();
+    while !emulator.halted() {
+        for key in typed.try_iter() {
+            held[key] = TAP_FRAMES;
+        }
+        emulator.set_keys(held.map(|frames| frames > 0));
+        held = held.map(|frames: u32| frames.saturating_sub(1));
+        if emulator.run_frame()? {
+            draw(emulator.screen(), &mut out)?;
+        }
+        // The terminal bell is as close as this gets to a buzzer
+        if emulator.take_beeps().iter().any(|(beep, _)| matches!(beep, Beep::Started(_))) {
+            write!(out, "\x07")?;
+        }
+        next_frame += FRAME;
+        std::thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use crate::xref::Access;
+
+/// A byte an instruction read or wrote, as `Chip8` records them while `accesses` is on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// Instructions run before this one, the same count `--trace` prints
+    pub cycle: u64,
+    pub pc: usize,
+    pub address: usize,
+    /// `Access::Read` or `Access::Write`
+    pub access: Access,
+    /// The byte read, or the byte written
+    pub value: u8,
+}
+
+/// Where `--access-log` sends the records: `tcp:HOST:PORT` for a socket, anything else a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    File(String),
+    Socket(String),
+}
+
+impl FromStr for Destination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("tcp:") {
+            Some(address) if address.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok()) => {
+                Ok(Destination::Socket(address.to_string()))
+            },
+            Some(address) => Err(format!("Expected tcp:HOST:PORT, not tcp:{}", address)),
+            None if s.is_empty() => Err(String::from("Expected a file or tcp:HOST:PORT")),
+            None => Ok(Destination::File(s.to_string())),
+        }
+    }
+}
+
+/// A file, gzipped, or a socket, left uncompressed so whoever's listening can read records as they come
+pub enum Output {
+    File(GzEncoder<BufWriter<File>>),
+    Socket(BufWriter<TcpStream>),
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::File(file) => file.write(buf),
+            Output::Socket(socket) => socket.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::File(file) => file.flush(),
+            Output::Socket(socket) => socket.flush(),
+        }
+    }
+}
+
+impl Output {
+    pub fn open(destination: &Destination) -> io::Result<Self> {
+        Ok(match destination {
+            Destination::File(path) => Output::File(GzEncoder::new(BufWriter::new(File::create(path)?), Compression::fast())),
+            Destination::Socket(address) => Output::Socket(BufWriter::new(TcpStream::connect(address)?)),
+        })
+    }
+
+    /// Writes the end of the gzip stream, if any, and flushes
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::File(file) => file.finish()?.flush(),
+            Output::Socket(mut socket) => socket.flush(),
+        }
+    }
+}
+
+/// Writes memory accesses as CSV, a header line then `cycle,pc,address,access,value` with numbers in
+/// decimal and the access `read` or `write`, for pandas and the like
+pub struct AccessLog<W: Write> {
+    out: W,
+    /// Records written so far
+    pub written: u64,
+}
+
+impl<W: Write> AccessLog<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        writeln!(out, "cycle,pc,address,access,value")?;
+        Ok(AccessLog { out, written: 0 })
+    }
+
+    pub fn write(&mut self, accesses: &[MemoryAccess]) -> io::Result<()> {
+        for access in accesses {
+            writeln!(self.out, "{},{},{},{},{}", access.cycle, access.pc, access.address, access.access, access.value)?;
+        }
+        self.written += accesses.len() as u64;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::testing::Machine;
+    use crate::xref::Access;
+    use super::{AccessLog, Destination, MemoryAccess};
+
+    #[test]
+    fn parses_destinations() {
+        assert_eq!("reads.csv.gz".parse(), Ok(Destination::File("reads.csv.gz".into())));
+        assert_eq!("tcp:localhost:9000".parse(), Ok(Destination::Socket("localhost:9000".into())));
+        for bad in ["", "tcp:localhost", "tcp::9000", "tcp:localhost:http"] {
+            assert!(bad.parse::<Destination>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn logs_reads_and_writes() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 7 },
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::StoreMemory { register: 0 },
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::LoadMemory { register: 0 },
+        ]);
+        machine.run(2);
+        machine.chip8.accesses = Some(Vec::new());
+        machine.run(3);
+        let accesses = machine.chip8.accesses.take().unwrap();
+        assert_eq!(accesses, [
+            MemoryAccess { cycle: 2, pc: 0x204, address: 0x300, access: Access::Write, value: 7 },
+            MemoryAccess { cycle: 4, pc: 0x208, address: 0x300, access: Access::Read, value: 7 },
+        ]);
+        let mut log = AccessLog::new(Vec::new()).unwrap();
+        log.write(&accesses).unwrap();
+        assert_eq!(log.written, 2);
+        assert_eq!(String::from_utf8(log.into_inner()).unwrap(), "cycle,pc,address,access,value\n2,516,768,write,7\n4,520,768,read,7\n");
+    }
+}
@@ -0,0 +1,87 @@
+use crate::chip8::Instruction;
+use crate::decode::decode;
+
+/// Marks every byte of every instruction reachable from `start` by following control flow.
+/// Anything left unmarked is presumably data. Computed jumps can't be followed,
+/// so code only reached through them will look like data.
+pub fn mark_code(memory: &[u8], start: usize, code: &mut [bool]) {
+    let mut todo = vec![start];
+    while let Some(pc) = todo.pop() {
+        if pc + 1 >= memory.len() || code[pc] {
+            continue;
+        }
+        let raw = memory[pc + 1] as u16 | (memory[pc] as u16) << 8;
+        let instruction = match decode(raw) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        code[pc] = true;
+        code[pc + 1] = true;
+        match instruction {
+            Instruction::Jump { dest } => todo.push(dest as usize),
+            Instruction::CallSubroutine { dest } => {
+                todo.push(dest as usize);
+                todo.push(pc + 2);
+            },
+            Instruction::Return | Instruction::JumpOffset { .. } | Instruction::Exit => {},
+            // The address in the word after is data, so it's left for the disassembler to show as such
+            Instruction::LongIndex => todo.push(pc + 4),
+            Instruction::SkipEQ { .. }
+            | Instruction::SkipNEQ { .. }
+            | Instruction::SkipEQR { .. }
+            | Instruction::SkipNEQR { .. }
+            | Instruction::SkipPressed { .. }
+            | Instruction::SkipNotPressed { .. }
+            | Instruction::SkipPressed2 { .. }
+            | Instruction::SkipNotPressed2 { .. } => {
+                todo.push(pc + 2);
+                // Skipping F000 steps over its address as well
+                let long = memory.get(pc + 2..pc + 4) == Some(&[0xf0, 0x00]);
+                todo.push(if long { pc + 6 } else { pc + 4 });
+            },
+            _ => todo.push(pc + 2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mark_code;
+
+    #[test]
+    fn follows_control_flow() {
+        let mut memory = [0; 0x220];
+        let program: [u8; 15] = [
+            0x22, 0x08, // 200: call 208
+            0x30, 0x01, // 202: skip if V0 == 1
+            0x12, 0x0c, // 204: jump 20c
+            0x12, 0x02, // 206: jump 202
+            0x60, 0x01, // 208: V0 = 1
+            0x00, 0xee, // 20a: return
+            0x12, 0x0c, // 20c: jump 20c
+            0xff,       // 20e: data
+        ];
+        memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+        let mut code = [false; 0x220];
+        mark_code(&memory, 0x200, &mut code);
+        assert!(code[0x200..0x20e].iter().all(|&b| b));
+        assert!(code[0x20e..].iter().all(|&b| !b));
+        assert!(code[..0x200].iter().all(|&b| !b));
+    }
+
+    #[test]
+    fn skips_over_long_index_addresses() {
+        let mut memory = [0; 0x210];
+        let program: [u8; 10] = [
+            0x30, 0x01, // 200: skip if V0 == 1
+            0xf0, 0x00, // 202: I = the word after
+            0x12, 0x08, // 204: its address, which would be jump 208
+            0x12, 0x06, // 206: jump 206
+            0x00, 0xe0, // 208: data
+        ];
+        memory[0x200..0x200 + program.len()].copy_from_slice(&program);
+        let mut code = [false; 0x210];
+        mark_code(&memory, 0x200, &mut code);
+        assert_eq!(code[0x200..0x20a], [true, true, true, true, false, false, true, true, false, false]);
+    }
+}
@@ -0,0 +1,313 @@
+use crate::bits::{U4, U12};
+use crate::chip8::Instruction;
+
+/// An operand in the usual CHIP-8 mnemonics, e.g. `V1`, `[I]`, `DT` or `0x05`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Register(U4),
+    Index,
+    /// `[I]`, the memory FX55/FX65 read and write
+    IndexedMemory,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    /// `HF`, SCHIP's big font
+    BigFont,
+    /// `R`, SCHIP's RPL flags
+    Flags,
+    /// `LONG`, XO-CHIP's address in the word after the instruction
+    Long,
+    /// `AUDIO`, XO-CHIP's audio pattern
+    Audio,
+    /// `PITCH`, XO-CHIP's pitch register
+    Pitch,
+    Decimal,
+    Number(u16),
+}
+
+/// Hex with `0x`, `#` or `$` in front, or else decimal
+fn parse_number(text: &str) -> Result<u16, String> {
+    let hex = text.strip_prefix("0x").or_else(|| text.strip_prefix('#')).or_else(|| text.strip_prefix('$'));
+    match hex {
+        Some(digits) => u16::from_str_radix(digits, 16),
+        None => text.parse(),
+    }.map_err(|_| format!("{} isn't a number", text))
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Self, String> {
+        let upper = text.to_ascii_uppercase();
+        Ok(match upper.as_str() {
+            "I" => Operand::Index,
+            "[I]" => Operand::IndexedMemory,
+            "DT" => Operand::DelayTimer,
+            "ST" => Operand::SoundTimer,
+            "K" => Operand::Key,
+            "F" => Operand::Font,
+            "HF" => Operand::BigFont,
+            "R" => Operand::Flags,
+            "LONG" => Operand::Long,
+            "AUDIO" => Operand::Audio,
+            "PITCH" => Operand::Pitch,
+            "B" => Operand::Decimal,
+            _ => match upper.strip_prefix('V').and_then(|digit| u8::from_str_radix(digit, 16).ok()) {
+                Some(register) if upper.len() == 2 => Operand::Register(register),
+                _ => Operand::Number(parse_number(&text.to_ascii_lowercase())?),
+            },
+        })
+    }
+}
+
+fn fits(value: u16, max: u16) -> Result<u16, String> {
+    if value <= max {
+        Ok(value)
+    } else {
+        Err(format!("{:#x} is more than {:#x}", value, max))
+    }
+}
+
+/// One instruction in the mnemonics of Cowgod's CHIP-8 reference, e.g. `LD V1, 0x05` or `DRW V0, V1, 5`.
+/// Case doesn't matter. `SHR`/`SHL` with one register shift it in place.
+pub fn parse_instruction(text: &str) -> Result<Instruction, String> {
+    let text = text.trim();
+    let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let operands = operands.split(',')
+        .map(str::trim)
+        .filter(|operand| !operand.is_empty())
+        .map(Operand::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    let address = |value: u16| fits(value, 0xfff).map(|value| value as U12);
+    let byte = |value: u16| fits(value, 0xff).map(|value| value as u8);
+    use Operand::*;
+    Ok(match (mnemonic.to_ascii_uppercase().as_str(), operands.as_slice()) {
+        ("CLS", &[]) => Instruction::ClearScreen,
+        ("RET", &[]) => Instruction::Return,
+        ("LOW", &[]) => Instruction::Lores,
+        ("HIGH", &[]) => Instruction::Hires,
+        ("SCD", &[Number(rows)]) => Instruction::ScrollDown { rows: fits(rows, 0xf)? as U4 },
+        ("SCU", &[Number(rows)]) => Instruction::ScrollUp { rows: fits(rows, 0xf)? as U4 },
+        ("SCR", &[]) => Instruction::ScrollRight,
+        ("SCL", &[]) => Instruction::ScrollLeft,
+        ("EXIT", &[]) => Instruction::Exit,
+        ("PLANE", &[Number(planes)]) => Instruction::SelectPlanes { planes: fits(planes, 0xf)? as U4 },
+        ("JP", &[Number(dest)]) => Instruction::Jump { dest: address(dest)? },
+        ("JP", &[Register(0), Number(dest)]) => Instruction::JumpOffset { dest: address(dest)? },
+        ("CALL", &[Number(dest)]) => Instruction::CallSubroutine { dest: address(dest)? },
+        ("SE", &[Register(register), Number(value)]) => Instruction::SkipEQ { register, value: byte(value)? },
+        ("SE", &[Register(register1), Register(register2)]) => Instruction::SkipEQR { register1, register2 },
+        ("SNE", &[Register(register), Number(value)]) => Instruction::SkipNEQ { register, value: byte(value)? },
+        ("SNE", &[Register(register1), Register(register2)]) => Instruction::SkipNEQR { register1, register2 },
+        ("LD", &[Register(register), Number(value)]) => Instruction::SetRegister { register, value: byte(value)? },
+        ("LD", &[Register(register1), Register(register2)]) => Instruction::MovRegister { register1, register2 },
+        ("LD", &[Index, Number(value)]) => Instruction::SetIndexRegister { value: address(value)? },
+        ("LD", &[Index, Long]) => Instruction::LongIndex,
+        ("LD", &[Register(register), DelayTimer]) => Instruction::GetDelayTimer { register },
+        ("LD", &[Register(register), Key]) => Instruction::GetKey { register },
+        ("LD", &[DelayTimer, Register(register)]) => Instruction::SetDelayTimer { register },
+        ("LD", &[SoundTimer, Register(register)]) => Instruction::SetSoundTimer { register },
+        ("LD", &[Audio, IndexedMemory]) => Instruction::LoadAudio,
+        ("LD", &[Pitch, Register(register)]) => Instruction::SetPitch { register },
+        ("LD", &[Font, Register(register)]) => Instruction::FontChar { register },
+        ("LD", &[BigFont, Register(register)]) => Instruction::BigFontChar { register },
+        ("LD", &[Decimal, Register(register)]) => Instruction::RegToDecimal { register },
+        ("LD", &[IndexedMemory, Register(register)]) => Instruction::StoreMemory { register },
+        ("LD", &[Register(register), IndexedMemory]) => Instruction::LoadMemory { register },
+        ("LD", &[IndexedMemory, Register(register1), Register(register2)]) => Instruction::StoreRange { register1, register2 },
+        ("LD", &[Register(register1), Register(register2), IndexedMemory]) => Instruction::LoadRange { register1, register2 },
+        ("LD", &[Flags, Register(register)]) => Instruction::SaveFlags { register },
+        ("LD", &[Register(register), Flags]) => Instruction::LoadFlags { register },
+        ("ADD", &[Register(register), Number(value)]) => Instruction::AddToRegister { register, value: byte(value)? },
+        ("ADD", &[Register(register1), Register(register2)]) => Instruction::Add { register1, register2 },
+        ("ADD", &[Index, Register(register)]) => Instruction::AddToIndex { register },
+        ("OR", &[Register(register1), Register(register2)]) => Instruction::BinaryOr { register1, register2 },
+        ("AND", &[Register(register1), Register(register2)]) => Instruction::BinaryAnd { register1, register2 },
+        ("XOR", &[Register(register1), Register(register2)]) => Instruction::BinaryXor { register1, register2 },
+        ("SUB", &[Register(register1), Register(register2)]) => Instruction::SubtractForward { register1, register2 },
+        ("SUBN", &[Register(register1), Register(register2)]) => Instruction::SubtractBackward { register1, register2 },
+        ("SHR", &[Register(register1)]) => Instruction::ShiftRight { register1, register2: register1 },
+        ("SHR", &[Register(register1), Register(register2)]) => Instruction::ShiftRight { register1, register2 },
+        ("SHL", &[Register(register1)]) => Instruction::ShiftLeft { register1, register2: register1 },
+        ("SHL", &[Register(register1), Register(register2)]) => Instruction::ShiftLeft { register1, register2 },
+        ("RND", &[Register(register), Number(value)]) => Instruction::Random { register, value: byte(value)? },
+        ("DRW", &[Register(x_r), Register(y_r), Number(height)]) => Instruction::Draw { x_r, y_r, height: fits(height, 0xf)? as U4 },
+        ("SKP", &[Register(register)]) => Instruction::SkipPressed { register },
+        ("SKNP", &[Register(register)]) => Instruction::SkipNotPressed { register },
+        ("BGC", &[]) => Instruction::CycleBackground,
+        ("ADDN", &[Register(register1), Register(register2)]) => Instruction::AddNibbles { register1, register2 },
+        ("COL", &[Register(x_r), Register(y_r)]) => Instruction::ColorZones { x_r, y_r },
+        ("COL", &[Register(x_r), Register(y_r), Number(rows)]) => match fits(rows, 0xf)? as U4 {
+            0 => Instruction::ColorZones { x_r, y_r },
+            rows => Instruction::ColorRows { x_r, y_r, rows },
+        },
+        ("SKP2", &[Register(register)]) => Instruction::SkipPressed2 { register },
+        ("SKNP2", &[Register(register)]) => Instruction::SkipNotPressed2 { register },
+        ("MEGAOFF", &[]) => Instruction::MegaOff,
+        ("MEGAON", &[]) => Instruction::MegaOn,
+        ("LDHI", &[Index, Number(high)]) => Instruction::MegaIndex { high: byte(high)? },
+        ("LDPAL", &[Number(count)]) => Instruction::LoadPalette { count: byte(count)? },
+        ("SPRW", &[Number(width)]) => Instruction::SpriteWidth { width: byte(width)? },
+        ("SPRH", &[Number(height)]) => Instruction::SpriteHeight { height: byte(height)? },
+        ("ALPHA", &[Number(alpha)]) => Instruction::Alpha { alpha: byte(alpha)? },
+        ("DIGISND", &[Number(mode)]) => Instruction::PlaySample { mode: fits(mode, 0xf)? as U4 },
+        ("STOPSND", &[]) => Instruction::StopSample,
+        ("BMODE", &[Number(mode)]) => Instruction::BlendMode { mode: fits(mode, 0xf)? as U4 },
+        ("CCOL", &[Number(color)]) => Instruction::CollisionColor { color: byte(color)? },
+        _ => return Err(format!("Can't assemble {}", text)),
+    })
+}
+
+/// The inverse of `parse_instruction`: the mnemonic and operands, e.g. `("LD", ["V1", "0x05"])`
+pub fn mnemonic(instruction: &Instruction) -> (&'static str, Vec<String>) {
+    let v = |register: U4| format!("V{:X}", register);
+    let address = |value: U12| format!("{:#05x}", value);
+    let byte = |value: u8| format!("{:#04x}", value);
+    let fixed = |operand: &str| operand.to_string();
+    match *instruction {
+        Instruction::ClearScreen => ("CLS", vec![]),
+        Instruction::Return => ("RET", vec![]),
+        Instruction::Lores => ("LOW", vec![]),
+        Instruction::Hires => ("HIGH", vec![]),
+        Instruction::ScrollDown { rows } => ("SCD", vec![rows.to_string()]),
+        Instruction::ScrollUp { rows } => ("SCU", vec![rows.to_string()]),
+        Instruction::ScrollRight => ("SCR", vec![]),
+        Instruction::ScrollLeft => ("SCL", vec![]),
+        Instruction::Exit => ("EXIT", vec![]),
+        Instruction::SelectPlanes { planes } => ("PLANE", vec![planes.to_string()]),
+        Instruction::Jump { dest } => ("JP", vec![address(dest)]),
+        Instruction::JumpOffset { dest } => ("JP", vec![v(0), address(dest)]),
+        Instruction::CallSubroutine { dest } => ("CALL", vec![address(dest)]),
+        Instruction::SkipEQ { register, value } => ("SE", vec![v(register), byte(value)]),
+        Instruction::SkipEQR { register1, register2 } => ("SE", vec![v(register1), v(register2)]),
+        Instruction::SkipNEQ { register, value } => ("SNE", vec![v(register), byte(value)]),
+        Instruction::SkipNEQR { register1, register2 } => ("SNE", vec![v(register1), v(register2)]),
+        Instruction::SetRegister { register, value } => ("LD", vec![v(register), byte(value)]),
+        Instruction::MovRegister { register1, register2 } => ("LD", vec![v(register1), v(register2)]),
+        Instruction::SetIndexRegister { value } => ("LD", vec![fixed("I"), address(value)]),
+        Instruction::LongIndex => ("LD", vec![fixed("I"), fixed("LONG")]),
+        Instruction::GetDelayTimer { register } => ("LD", vec![v(register), fixed("DT")]),
+        Instruction::GetKey { register } => ("LD", vec![v(register), fixed("K")]),
+        Instruction::SetDelayTimer { register } => ("LD", vec![fixed("DT"), v(register)]),
+        Instruction::SetSoundTimer { register } => ("LD", vec![fixed("ST"), v(register)]),
+        Instruction::LoadAudio => ("LD", vec![fixed("AUDIO"), fixed("[I]")]),
+        Instruction::SetPitch { register } => ("LD", vec![fixed("PITCH"), v(register)]),
+        Instruction::FontChar { register } => ("LD", vec![fixed("F"), v(register)]),
+        Instruction::BigFontChar { register } => ("LD", vec![fixed("HF"), v(register)]),
+        Instruction::RegToDecimal { register } => ("LD", vec![fixed("B"), v(register)]),
+        Instruction::StoreMemory { register } => ("LD", vec![fixed("[I]"), v(register)]),
+        Instruction::LoadMemory { register } => ("LD", vec![v(register), fixed("[I]")]),
+        Instruction::StoreRange { register1, register2 } => ("LD", vec![fixed("[I]"), v(register1), v(register2)]),
+        Instruction::LoadRange { register1, register2 } => ("LD", vec![v(register1), v(register2), fixed("[I]")]),
+        Instruction::SaveFlags { register } => ("LD", vec![fixed("R"), v(register)]),
+        Instruction::LoadFlags { register } => ("LD", vec![v(register), fixed("R")]),
+        Instruction::AddToRegister { register, value } => ("ADD", vec![v(register), byte(value)]),
+        Instruction::Add { register1, register2 } => ("ADD", vec![v(register1), v(register2)]),
+        Instruction::AddToIndex { register } => ("ADD", vec![fixed("I"), v(register)]),
+        Instruction::BinaryOr { register1, register2 } => ("OR", vec![v(register1), v(register2)]),
+        Instruction::BinaryAnd { register1, register2 } => ("AND", vec![v(register1), v(register2)]),
+        Instruction::BinaryXor { register1, register2 } => ("XOR", vec![v(register1), v(register2)]),
+        Instruction::SubtractForward { register1, register2 } => ("SUB", vec![v(register1), v(register2)]),
+        Instruction::SubtractBackward { register1, register2 } => ("SUBN", vec![v(register1), v(register2)]),
+        Instruction::ShiftRight { register1, register2 } => ("SHR", vec![v(register1), v(register2)]),
+        Instruction::ShiftLeft { register1, register2 } => ("SHL", vec![v(register1), v(register2)]),
+        Instruction::Random { register, value } => ("RND", vec![v(register), byte(value)]),
+        Instruction::Draw { x_r, y_r, height } => ("DRW", vec![v(x_r), v(y_r), height.to_string()]),
+        Instruction::SkipPressed { register } => ("SKP", vec![v(register)]),
+        Instruction::SkipNotPressed { register } => ("SKNP", vec![v(register)]),
+        Instruction::CycleBackground => ("BGC", vec![]),
+        Instruction::AddNibbles { register1, register2 } => ("ADDN", vec![v(register1), v(register2)]),
+        Instruction::ColorZones { x_r, y_r } => ("COL", vec![v(x_r), v(y_r)]),
+        Instruction::ColorRows { x_r, y_r, rows } => ("COL", vec![v(x_r), v(y_r), rows.to_string()]),
+        Instruction::SkipPressed2 { register } => ("SKP2", vec![v(register)]),
+        Instruction::SkipNotPressed2 { register } => ("SKNP2", vec![v(register)]),
+        Instruction::MegaOff => ("MEGAOFF", vec![]),
+        Instruction::MegaOn => ("MEGAON", vec![]),
+        Instruction::MegaIndex { high } => ("LDHI", vec![fixed("I"), byte(high)]),
+        Instruction::LoadPalette { count } => ("LDPAL", vec![byte(count)]),
+        Instruction::SpriteWidth { width } => ("SPRW", vec![byte(width)]),
+        Instruction::SpriteHeight { height } => ("SPRH", vec![byte(height)]),
+        Instruction::Alpha { alpha } => ("ALPHA", vec![byte(alpha)]),
+        Instruction::PlaySample { mode } => ("DIGISND", vec![mode.to_string()]),
+        Instruction::StopSample => ("STOPSND", vec![]),
+        Instruction::BlendMode { mode } => ("BMODE", vec![mode.to_string()]),
+        Instruction::CollisionColor { color } => ("CCOL", vec![byte(color)]),
+    }
+}
+
+/// An address in memory, e.g. `0x220`
+pub fn parse_address(text: &str) -> Result<usize, String> {
+    Ok(fits(parse_number(&text.trim().to_ascii_lowercase())?, 0xfff)? as usize)
+}
+
+/// An address and the instruction to put there, e.g. `0x220: LD V1, 0x05`
+pub fn parse_patch(text: &str) -> Result<(usize, Instruction), String> {
+    let (address, instruction) = text.split_once(':').ok_or("Expected ADDRESS: INSTRUCTION")?;
+    // Instructions are two bytes, so the last byte of memory can't start one
+    let address = fits(parse_address(address)? as u16, 0xffe)? as usize;
+    Ok((address, parse_instruction(instruction)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::decode::decode;
+    use super::{mnemonic, parse_address, parse_instruction, parse_patch};
+
+    #[test]
+    fn parses_mnemonics() {
+        let cases = [
+            ("cls", Instruction::ClearScreen),
+            ("JP 0x2a0", Instruction::Jump { dest: 0x2a0 }),
+            ("JP V0, #300", Instruction::JumpOffset { dest: 0x300 }),
+            ("LD VA, 10", Instruction::SetRegister { register: 0xa, value: 10 }),
+            ("ld v1,v2", Instruction::MovRegister { register1: 1, register2: 2 }),
+            ("LD I, $2e0", Instruction::SetIndexRegister { value: 0x2e0 }),
+            ("LD [I], V3", Instruction::StoreMemory { register: 3 }),
+            ("LD V3, [I]", Instruction::LoadMemory { register: 3 }),
+            ("LD [I], V3, V1", Instruction::StoreRange { register1: 3, register2: 1 }),
+            ("LD V1, V3, [I]", Instruction::LoadRange { register1: 1, register2: 3 }),
+            ("LD R, V7", Instruction::SaveFlags { register: 7 }),
+            ("exit", Instruction::Exit),
+            ("PLANE 3", Instruction::SelectPlanes { planes: 3 }),
+            ("SCU 4", Instruction::ScrollUp { rows: 4 }),
+            ("LD AUDIO, [I]", Instruction::LoadAudio),
+            ("ld pitch, vb", Instruction::SetPitch { register: 0xb }),
+            ("ld v2, r", Instruction::LoadFlags { register: 2 }),
+            ("LD B, V3", Instruction::RegToDecimal { register: 3 }),
+            ("SHR V4", Instruction::ShiftRight { register1: 4, register2: 4 }),
+            ("DRW V0, V1, 5", Instruction::Draw { x_r: 0, y_r: 1, height: 5 }),
+            ("BGC", Instruction::CycleBackground),
+            ("ADDN V1, V2", Instruction::AddNibbles { register1: 1, register2: 2 }),
+            ("COL V0, V2", Instruction::ColorZones { x_r: 0, y_r: 2 }),
+            ("COL V0, V2, 0", Instruction::ColorZones { x_r: 0, y_r: 2 }),
+            ("col v0, v2, 4", Instruction::ColorRows { x_r: 0, y_r: 2, rows: 4 }),
+            ("SKNP2 V3", Instruction::SkipNotPressed2 { register: 3 }),
+            ("megaon", Instruction::MegaOn),
+            ("LDHI I, 0x12", Instruction::MegaIndex { high: 0x12 }),
+            ("SPRW 0", Instruction::SpriteWidth { width: 0 }),
+            ("BMODE 2", Instruction::BlendMode { mode: 2 }),
+        ];
+        for (text, instruction) in cases {
+            assert_eq!(parse_instruction(text), Ok(instruction), "{}", text);
+        }
+        assert_eq!(parse_patch("0x220: LD V1, 0x05"), Ok((0x220, Instruction::SetRegister { register: 1, value: 5 })));
+        for bad in ["BMODE 16", "LD V1, 0x100", "DRW V0, V1, 16", "JP V1, 0x300", "LD VG, 1", "NOP", "LD V1", "COL V0, V1, 16"] {
+            assert!(parse_instruction(bad).is_err(), "{}", bad);
+        }
+        assert!(parse_patch("0xfff: CLS").is_err());
+        assert!(parse_patch("CLS").is_err());
+        assert_eq!(parse_address(" 0X300"), Ok(0x300));
+        assert!(parse_address("0x1000").is_err());
+    }
+
+    use proptest::prelude::*;
+    proptest! {
+        #[test]
+        fn parses_what_it_prints(raw in 0..u16::MAX) {
+            if let Some(instruction) = decode(raw) {
+                let (name, operands) = mnemonic(&instruction);
+                prop_assert_eq!(parse_instruction(&format!("{} {}", name, operands.join(", "))), Ok(instruction));
+            }
+        }
+    }
+}
@@ -0,0 +1,614 @@
+use std::f32::consts::TAU;
+use std::io::{Error, Write};
+use std::str::FromStr;
+#[cfg(feature = "audio")]
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::chip8::{Beep, Chip8, Timestamp};
+use crate::replay::Player;
+
+/// The shape of the beeper's wave. Audio patterns are 1-bit, so they play as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!("Unknown waveform {}, expected square, triangle or sine", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// Of the wave, or for a pattern, the bits of it played a second
+    pub frequency: f32,
+    /// XO-CHIP's 128 1-bit samples, played on a loop from the first byte's highest bit
+    pub pattern: Option<[u8; 16]>,
+    pub waveform: Waveform,
+    /// From 0 to 1
+    pub volume: f32,
+}
+
+pub const DEFAULT_TONE: Tone = Tone { frequency: 440.0, pattern: None, waveform: Waveform::Square, volume: 1.0 };
+
+/// How the beeper should sound, from `--waveform`, `--beep-frequency` and `--volume` or the settings
+/// file, with `None` for anything left to the default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Buzzer {
+    pub waveform: Option<Waveform>,
+    /// In Hz
+    pub frequency: Option<u32>,
+    /// A percentage
+    pub volume: Option<u8>,
+}
+
+impl Buzzer {
+    /// Anything not set here is taken from `fallback`
+    pub fn or(self, fallback: Buzzer) -> Buzzer {
+        Buzzer {
+            waveform: self.waveform.or(fallback.waveform),
+            frequency: self.frequency.or(fallback.frequency),
+            volume: self.volume.or(fallback.volume),
+        }
+    }
+
+    /// The tone for programs that haven't loaded an audio pattern, whose volume patterns get too
+    pub fn tone(&self) -> Tone {
+        Tone {
+            frequency: self.frequency.map_or(DEFAULT_TONE.frequency, |frequency| frequency as f32),
+            pattern: None,
+            waveform: self.waveform.unwrap_or_default(),
+            volume: self.volume.map_or(DEFAULT_TONE.volume, |volume| volume as f32 / 100.0),
+        }
+    }
+}
+
+/// A frequency in Hz the beeper can play: audible, and low enough not to alias at common sample rates
+pub fn parse_frequency(s: &str) -> Result<u32, String> {
+    s.parse().ok().filter(|frequency| (20..=20_000).contains(frequency)).ok_or(format!("Bad frequency {}, expected 20 to 20000 Hz", s))
+}
+
+/// A volume as a percentage
+pub fn parse_volume(s: &str) -> Result<u8, String> {
+    s.trim_end_matches('%').parse().ok().filter(|&volume| volume <= 100).ok_or(format!("Bad volume {}, expected 0 to 100", s))
+}
+
+/// Bits of the audio pattern played a second at an XO-CHIP pitch: 4000 at 64, doubling every 48 up
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+/// Somewhere for the beeper to go, told when in emulated time each change happened
+pub trait AudioSink {
+    fn start(&mut self, tone: Tone, at: Timestamp);
+    fn stop(&mut self, at: Timestamp);
+}
+
+/// Turns the core's beeper changes into start/stop calls on a sink. A new audio pattern or pitch
+/// while it's sounding stops the old tone and starts the new one.
+pub struct Beeper {
+    /// For programs that haven't loaded an audio pattern, which is all but XO-CHIP ones
+    pub tone: Tone,
+    /// What the core last said to play
+    wanted: Option<Tone>,
+    playing: Option<Tone>,
+}
+
+impl Beeper {
+    pub fn new(tone: Tone) -> Self {
+        Beeper { tone, wanted: None, playing: None }
+    }
+
+    /// Call after every cycle, to take the core's latest change
+    pub fn update(&mut self, chip8: &mut Chip8, sink: &mut impl AudioSink) {
+        if let Some(beep) = chip8.beep.take() {
+            self.wanted = match beep {
+                Beep::Started(Some(pattern)) => Some(Tone { frequency: pattern_rate(pattern.pitch), pattern: Some(pattern.bits), ..self.tone }),
+                Beep::Started(None) => Some(self.tone),
+                Beep::Stopped => None,
+            };
+        }
+        if self.wanted != self.playing {
+            if self.playing.is_some() {
+                sink.stop(chip8.timestamp());
+            }
+            if let Some(tone) = self.wanted {
+                sink.start(tone, chip8.timestamp());
+            }
+            self.playing = self.wanted;
+        }
+    }
+
+    /// For a new machine in place of the old one, which has nothing to say about what was playing
+    pub fn reset(&mut self, chip8: &Chip8, sink: &mut impl AudioSink) {
+        self.wanted = None;
+        self.silence(chip8, sink);
+    }
+
+    /// Stops the tone for a pause that isn't the program's doing, e.g. the window being minimized.
+    /// The next `update` starts it again if the sound timer is still running.
+    pub fn silence(&mut self, chip8: &Chip8, sink: &mut impl AudioSink) {
+        if self.playing.take().is_some() {
+            sink.stop(chip8.timestamp());
+        }
+    }
+}
+
+/// Just logs, for when there's no sound card or chip8 was built without the `audio` feature
+pub struct LogSink;
+
+impl AudioSink for LogSink {
+    fn start(&mut self, tone: Tone, at: Timestamp) {
+        log::debug!("Beep started at cycle {}, {}ns ({}Hz)", at.cycle, at.nanos, tone.frequency);
+    }
+
+    fn stop(&mut self, at: Timestamp) {
+        log::debug!("Beep stopped at cycle {}, {}ns", at.cycle, at.nanos);
+    }
+}
+
+// Loud enough to hear without clipping anything it gets mixed with
+const PCM_AMPLITUDE: i16 = i16::MAX / 4;
+
+/// For audio written to a file: CD quality, which anything plays
+pub const WAV_SAMPLE_RATE: u32 = 44_100;
+
+/// The beeper's wave, or its audio pattern, a sample at a time
+#[derive(Debug, Default)]
+pub struct Oscillator {
+    tone: Option<Tone>,
+    /// Position within the current wave period or time through the pattern, from 0 to 1
+    phase: f32,
+}
+
+impl Oscillator {
+    /// Changes what's playing. Silence starts the next tone from the beginning of its wave.
+    pub fn set(&mut self, tone: Option<Tone>) {
+        self.tone = tone;
+        if tone.is_none() {
+            self.phase = 0.0;
+        }
+    }
+
+    pub fn next(&mut self, sample_rate: u32) -> i16 {
+        let Some(tone) = self.tone else {
+            return 0;
+        };
+        // From -1 to 1
+        let level = match tone.pattern {
+            None => {
+                self.phase = (self.phase + tone.frequency / sample_rate as f32).fract();
+                match tone.waveform {
+                    Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+                    Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+                    Waveform::Sine => (self.phase * TAU).sin(),
+                }
+            },
+            Some(pattern) => {
+                let bit = (self.phase * 128.0) as usize;
+                self.phase = (self.phase + tone.frequency / 128.0 / sample_rate as f32).fract();
+                if pattern[bit / 8] & 0x80 >> (bit % 8) != 0 { 1.0 } else { -1.0 }
+            },
+        };
+        (level * tone.volume * PCM_AMPLITUDE as f32) as i16
+    }
+}
+
+/// Synthesizes the beeper, for writing audio out alongside emulated time
+pub struct PcmSink {
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+    oscillator: Oscillator,
+}
+
+impl PcmSink {
+    pub fn new(sample_rate: u32) -> Self {
+        PcmSink { sample_rate, samples: Vec::new(), oscillator: Oscillator::default() }
+    }
+
+    /// Generates samples up to `elapsed` since the start, in whatever state the beeper's in
+    pub fn fill_until(&mut self, elapsed: Duration) {
+        let target = (elapsed.as_nanos() * self.sample_rate as u128 / 1_000_000_000) as usize;
+        while self.samples.len() < target {
+            let sample = self.oscillator.next(self.sample_rate);
+            self.samples.push(sample);
+        }
+    }
+
+    /// Writes the samples as a mono 16 bit PCM WAV file
+    pub fn write_wav(&self, mut write: impl Write) -> Result<(), Error> {
+        let data_len = self.samples.len() as u32 * 2;
+        write.write_all(b"RIFF")?;
+        write.write_all(&(36 + data_len).to_le_bytes())?;
+        write.write_all(b"WAVEfmt ")?;
+        write.write_all(&16u32.to_le_bytes())?;
+        write.write_all(&1u16.to_le_bytes())?; // PCM
+        write.write_all(&1u16.to_le_bytes())?; // mono
+        write.write_all(&self.sample_rate.to_le_bytes())?;
+        write.write_all(&(self.sample_rate * 2).to_le_bytes())?; // bytes per second
+        write.write_all(&2u16.to_le_bytes())?; // bytes per sample
+        write.write_all(&16u16.to_le_bytes())?; // bits per sample
+        write.write_all(b"data")?;
+        write.write_all(&data_len.to_le_bytes())?;
+        for sample in &self.samples {
+            write.write_all(&sample.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl AudioSink for PcmSink {
+    fn start(&mut self, tone: Tone, at: Timestamp) {
+        self.fill_until(Duration::from_nanos(at.nanos));
+        self.oscillator.set(Some(tone));
+    }
+
+    fn stop(&mut self, at: Timestamp) {
+        self.fill_until(Duration::from_nanos(at.nanos));
+        self.oscillator.set(None);
+    }
+}
+
+/// Plays a replay from the start through a beeper playing `tone`, returning the audio up to its end.
+/// Every change lands on the sample for its emulated time. `each_cycle` sees the machine after
+/// each cycle, for anything else to gather on the way.
+pub fn synthesize(player: &mut Player, tone: Tone, sample_rate: u32, mut each_cycle: impl FnMut(&Chip8)) -> PcmSink {
+    player.seek(0);
+    let mut beeper = Beeper::new(tone);
+    let mut audio = PcmSink::new(sample_rate);
+    // Beeps start and stop at their timestamps, which are emulated time since playback started
+    while !player.at_end() {
+        player.step(1);
+        beeper.update(&mut player.chip8, &mut audio);
+        each_cycle(&player.chip8);
+    }
+    audio.fill_until(player.elapsed());
+    audio
+}
+
+/// Plays the beeper through the default sound card as it happens, on cpal's audio thread
+#[cfg(feature = "audio")]
+pub struct CpalSink {
+    oscillator: Arc<Mutex<Oscillator>>,
+    // Plays until dropped
+    _stream: cpal::Stream,
+}
+
+#[cfg(feature = "audio")]
+impl CpalSink {
+    pub fn open() -> Result<Self, String> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+        let device = cpal::default_host().default_output_device().ok_or("No sound card")?;
+        let config = device.default_output_config().map_err(|e| format!("Couldn't configure {}: {}", device.name().unwrap_or_default(), e))?;
+        let oscillator = Arc::new(Mutex::new(Oscillator::default()));
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => CpalSink::build::<f32>(&device, &config.into(), oscillator.clone()),
+            cpal::SampleFormat::I16 => CpalSink::build::<i16>(&device, &config.into(), oscillator.clone()),
+            cpal::SampleFormat::U16 => CpalSink::build::<u16>(&device, &config.into(), oscillator.clone()),
+            format => return Err(format!("Can't play {} samples", format)),
+        }.map_err(|e| format!("Couldn't open the sound card: {}", e))?;
+        stream.play().map_err(|e| format!("Couldn't start the sound card: {}", e))?;
+        Ok(CpalSink { oscillator, _stream: stream })
+    }
+
+    fn build<T: cpal::SizedSample + cpal::FromSample<i16>>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        oscillator: Arc<Mutex<Oscillator>>,
+    ) -> Result<cpal::Stream, cpal::BuildStreamError> {
+        use cpal::traits::DeviceTrait;
+        let (sample_rate, channels) = (config.sample_rate.0, config.channels as usize);
+        device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut oscillator = oscillator.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    frame.fill(T::from_sample(oscillator.next(sample_rate)));
+                }
+            },
+            |e| log::error!("Sound card stopped: {}", e),
+            None,
+        )
+    }
+}
+
+#[cfg(feature = "audio")]
+impl AudioSink for CpalSink {
+    fn start(&mut self, tone: Tone, _at: Timestamp) {
+        self.oscillator.lock().unwrap().set(Some(tone));
+    }
+
+    fn stop(&mut self, _at: Timestamp) {
+        self.oscillator.lock().unwrap().set(None);
+    }
+}
+
+/// The sound card with the `audio` feature, falling back to logging if it can't be opened
+pub fn output() -> Box<dyn AudioSink> {
+    #[cfg(feature = "audio")]
+    match CpalSink::open() {
+        Ok(sink) => return Box::new(sink),
+        Err(e) => log::warn!("{}, so the beeper will be silent", e),
+    }
+    Box::new(LogSink)
+}
+
+impl<S: AudioSink + ?Sized> AudioSink for Box<S> {
+    fn start(&mut self, tone: Tone, at: Timestamp) {
+        (**self).start(tone, at);
+    }
+
+    fn stop(&mut self, at: Timestamp) {
+        (**self).stop(at);
+    }
+}
+
+/// Records what would have been played, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct VirtualSink {
+    pub events: Vec<AudioEvent>,
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioEvent {
+    Start { tone: Tone, at: Timestamp },
+    Stop { at: Timestamp },
+}
+
+#[cfg(test)]
+impl AudioSink for VirtualSink {
+    fn start(&mut self, tone: Tone, at: Timestamp) {
+        self.events.push(AudioEvent::Start { tone, at });
+    }
+
+    fn stop(&mut self, at: Timestamp) {
+        self.events.push(AudioEvent::Stop { at });
+    }
+}
+
+#[cfg(test)]
+impl VirtualSink {
+    /// `(start, stop)` cycles of each beep, with `None` for a beep that's still going
+    pub fn beeps(&self) -> Vec<(u64, Option<u64>)> {
+        let mut beeps = Vec::new();
+        for event in &self.events {
+            match *event {
+                AudioEvent::Start { at, .. } => beeps.push((at.cycle, None)),
+                AudioEvent::Stop { at } => {
+                    let beep = beeps.last_mut().expect("Stopped before starting");
+                    assert!(beep.1.is_none(), "Stopped twice");
+                    beep.1 = Some(at.cycle);
+                },
+            }
+        }
+        beeps
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        matches!(self.events.last(), Some(AudioEvent::Start { .. }))
+    }
+
+    pub fn assert_beeps(&self, expected: &[(u64, Option<u64>)]) {
+        assert_eq!(self.beeps(), expected, "Beeps didn't match, events were {:?}", self.events);
+    }
+
+    pub fn assert_tones(&self, expected: Tone) {
+        for event in &self.events {
+            if let AudioEvent::Start { tone, at } = event {
+                assert_eq!(*tone, expected, "Wrong tone for beep at cycle {}", at.cycle);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+    use std::time::Duration;
+    use super::{
+        parse_frequency, parse_volume, pattern_rate, synthesize, AudioEvent, AudioSink, Beeper, Buzzer, PcmSink, Tone, VirtualSink, Waveform, DEFAULT_TONE,
+        PCM_AMPLITUDE,
+    };
+    use crate::chip8::{Chip8, Instruction, Timestamp};
+    use crate::encode::assemble;
+    use crate::platform::Platform;
+    use crate::replay::{Player, Recording};
+    use crate::testing::Machine;
+
+    // Runs a program one instruction per millisecond
+    fn run(program: &[u8], cycles: usize, beeper: &mut Beeper) -> VirtualSink {
+        let mut machine = Machine::new(program);
+        let mut sink = VirtualSink::default();
+        for _ in 0..cycles {
+            machine.step();
+            beeper.update(&mut machine.chip8, &mut sink);
+        }
+        sink
+    }
+
+    #[test]
+    fn sound_timer_beeps_for_its_duration() {
+        let program = [
+            0x60, 0x06, // V0 = 6
+            0xf0, 0x18, // sound timer = V0
+            0x12, 0x04, // loop
+        ];
+        // 6 frames at 60Hz is 100ms, so 100 cycles
+        let sink = run(&program, 200, &mut Beeper::new(DEFAULT_TONE));
+        let beeps = sink.beeps();
+        assert_eq!(beeps.len(), 1);
+        let (start, stop) = beeps[0];
+        assert_eq!(start, 2);
+        // One cycle per millisecond
+        match sink.events[0] {
+            AudioEvent::Start { at, .. } => assert_eq!(at.nanos, 2_000_000),
+            AudioEvent::Stop { .. } => unreachable!(),
+        }
+        let length = stop.unwrap() - start;
+        assert!((95..=105).contains(&length), "Beeped for {} cycles", length);
+        assert!(!sink.is_beeping());
+        sink.assert_tones(DEFAULT_TONE);
+    }
+
+    #[test]
+    fn retriggering_extends_the_beep() {
+        let program = [
+            0x60, 0x02, // V0 = 2
+            0xf0, 0x18, // sound timer = V0
+            0xf0, 0x18, // sound timer = V0 again, while still beeping
+            0x12, 0x06, // loop
+        ];
+        let tone = Tone { frequency: 1000.0, ..DEFAULT_TONE };
+        let sink = run(&program, 10, &mut Beeper::new(tone));
+        sink.assert_beeps(&[(2, None)]);
+        assert!(sink.is_beeping());
+        sink.assert_tones(tone);
+    }
+
+    #[test]
+    fn silencing_lasts_until_the_next_update() {
+        let mut machine = Machine::new(&[0x60, 0x06, 0xf0, 0x18, 0x12, 0x04]);
+        let mut beeper = Beeper::new(DEFAULT_TONE);
+        let mut sink = VirtualSink::default();
+        machine.run(3);
+        beeper.update(&mut machine.chip8, &mut sink);
+        beeper.silence(&machine.chip8, &mut sink);
+        assert!(!sink.is_beeping());
+        beeper.silence(&machine.chip8, &mut sink);
+        assert_eq!(sink.events.len(), 2);
+        beeper.update(&mut machine.chip8, &mut sink);
+        sink.assert_beeps(&[(3, Some(3)), (3, None)]);
+    }
+
+    #[test]
+    fn pcm_is_a_square_wave_while_beeping() {
+        let at = |millis: u64| Timestamp { cycle: millis, nanos: millis * 1_000_000 };
+        let mut sink = PcmSink::new(8000);
+        sink.start(Tone { frequency: 1000.0, ..DEFAULT_TONE }, at(10));
+        sink.stop(at(20));
+        sink.fill_until(Duration::from_millis(30));
+        assert_eq!(sink.samples.len(), 240);
+        assert!(sink.samples[..80].iter().all(|&s| s == 0));
+        assert!(sink.samples[160..].iter().all(|&s| s == 0));
+        // 8 samples per period, half high and half low
+        let beep = &sink.samples[80..160];
+        assert_eq!(beep.iter().filter(|&&s| s > 0).count(), 40);
+        assert_eq!(beep[..8].iter().filter(|&&s| s > 0).count(), 4);
+
+        let mut wav = Vec::new();
+        sink.write_wav(&mut wav).unwrap();
+        assert_eq!(wav.len(), 44 + 240 * 2);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[36..40], b"data");
+    }
+
+    #[test]
+    fn synthesizes_replays_to_the_sample() {
+        let mut chip8 = Chip8::new(Platform::default());
+        // A frame's beep once key 1 is held
+        chip8.read_program(&assemble(&[
+            Instruction::SetRegister { register: 0, value: 1 },
+            Instruction::SkipPressed { register: 0 },
+            Instruction::Jump { dest: 0x202 },
+            Instruction::SetSoundTimer { register: 0 },
+            Instruction::Jump { dest: 0x208 },
+        ])[..]).unwrap();
+        let clock_gap = Duration::from_millis(1);
+        let mut recording = Recording::start(&chip8, [false; 16], clock_gap);
+        for cycle in 0..100 {
+            let mut keys = [false; 16];
+            keys[1] = cycle >= 20;
+            recording.record_cycle(keys, clock_gap);
+            chip8.cycle(keys, clock_gap);
+        }
+        let mut cycles = 0;
+        let audio = synthesize(&mut Player::new(recording), DEFAULT_TONE, 8000, |_| cycles += 1);
+        assert_eq!(cycles, 100);
+        // 8 samples a millisecond for 100ms. FX18 runs at cycle 22, or 23ms, and the timer runs
+        // out on the cycle after the 60Hz tick at 33.3ms.
+        assert_eq!(audio.samples.len(), 800);
+        let sounding: Vec<usize> = (0..audio.samples.len()).filter(|&i| audio.samples[i] != 0).collect();
+        assert_eq!(sounding.first(), Some(&(23 * 8)));
+        assert_eq!(sounding.last(), Some(&(34 * 8 - 1)));
+    }
+
+    #[test]
+    fn plays_the_chosen_waveform_and_volume() {
+        let settings = Buzzer { waveform: Some(Waveform::Square), frequency: Some(1000), volume: Some(50) };
+        let buzzer = Buzzer { waveform: Some("triangle".parse().unwrap()), ..Buzzer::default() }.or(settings);
+        let tone = buzzer.tone();
+        assert_eq!(tone, Tone { frequency: 1000.0, pattern: None, waveform: Waveform::Triangle, volume: 0.5 });
+        assert_eq!(Buzzer::default().tone(), DEFAULT_TONE);
+        // 8 samples a period, each an eighth further through it
+        let wave = |tone| {
+            let mut sink = PcmSink::new(8000);
+            sink.start(tone, Timestamp::default());
+            sink.fill_until(Duration::from_millis(1));
+            sink.samples
+        };
+        let half = PCM_AMPLITUDE / 2;
+        assert_eq!(wave(tone), [-half / 2, 0, half / 2, half, half / 2, 0, -half / 2, -half]);
+        let sine = wave(Tone { waveform: Waveform::Sine, volume: 1.0, ..tone });
+        assert_eq!(sine[1], PCM_AMPLITUDE);
+        assert_eq!(sine[3], 0);
+        assert!((sine[0] as f32 / PCM_AMPLITUDE as f32 - FRAC_1_SQRT_2).abs() < 0.001, "{:?}", sine);
+
+        assert_eq!(parse_frequency("220"), Ok(220));
+        assert_eq!(parse_volume("30%"), Ok(30));
+        assert!(parse_frequency("5").is_err());
+        assert!(parse_volume("101").is_err());
+        assert!("sawtooth".parse::<Waveform>().is_err());
+    }
+
+    #[test]
+    fn plays_xo_chip_audio_patterns() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::LoadAudio,
+            Instruction::SetRegister { register: 0, value: 4 },
+            Instruction::SetSoundTimer { register: 0 },
+            Instruction::SetRegister { register: 1, value: 112 },
+            Instruction::SetPitch { register: 1 },
+            Instruction::Jump { dest: 0x20c },
+        ]).on(Platform::XoChip);
+        let pattern = [0xf0; 16];
+        machine.chip8.memory[0x300..0x310].copy_from_slice(&pattern);
+        let mut beeper = Beeper::new(DEFAULT_TONE);
+        let mut sink = VirtualSink::default();
+        for _ in 0..100 {
+            machine.step();
+            beeper.update(&mut machine.chip8, &mut sink);
+        }
+        // Changing the pitch while it sounds starts the pattern over at the new rate
+        sink.assert_beeps(&[(4, Some(6)), (6, Some(67))]);
+        let tones: Vec<Tone> = sink.events.iter().filter_map(|event| match *event {
+            AudioEvent::Start { tone, .. } => Some(tone),
+            AudioEvent::Stop { .. } => None,
+        }).collect();
+        assert_eq!(tones, [
+            Tone { frequency: 4000.0, pattern: Some(pattern), ..DEFAULT_TONE },
+            Tone { frequency: 8000.0, pattern: Some(pattern), ..DEFAULT_TONE },
+        ]);
+        assert_eq!(pattern_rate(16), 2000.0);
+
+        // At 8000 bits a second and 8000 samples, a sample a bit: 4 high, 4 low
+        let mut pcm = PcmSink::new(8000);
+        pcm.start(tones[1], Timestamp::default());
+        pcm.fill_until(Duration::from_millis(2));
+        let high: Vec<bool> = pcm.samples.iter().map(|&sample| sample > 0).collect();
+        assert_eq!(high, [[true; 4], [false; 4]].concat().repeat(2));
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+use std::time::Duration;
+use crate::bits::fnv1a;
+use crate::chip8::Chip8;
+use crate::soak::KeyFuzzer;
+
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// A hash of everything a snapshot keeps, random number generator included, and the cycle count
+pub fn state_hash(chip8: &Chip8) -> u64 {
+    let snapshot = chip8.snapshot();
+    let mut bytes = snapshot.bytes;
+    bytes.extend(bincode::serialize(&snapshot.rng).expect("The rng always serializes"));
+    bytes.extend_from_slice(&chip8.cycles.to_be_bytes());
+    fnv1a(&bytes)
+}
+
+/// The first frame two runs ended differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    /// Counting from 0
+    pub frame: u64,
+    /// Each run's cycle count and state hash at the end of the frame
+    pub first: (u64, u64),
+    pub second: (u64, u64),
+}
+
+/// How an audit went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Audit {
+    /// Both runs matched for every frame they ran
+    Matched { frames: u64, cycles: u64 },
+    Diverged(Divergence),
+    /// One of the runs panicked, which has already been printed, so the runs can't be compared
+    Crashed,
+}
+
+impl fmt::Display for Audit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Audit::Matched { frames, cycles } => write!(f, "Both runs matched for {} frames ({} cycles)", frames, cycles),
+            Audit::Diverged(Divergence { frame, first, second }) => write!(
+                f,
+                "The runs differ after frame {}: cycle {} with state {:016x}, and cycle {} with state {:016x}",
+                frame, first.0, first.1, second.0, second.1,
+            ),
+            Audit::Crashed => write!(f, "The core crashed"),
+        }
+    }
+}
+
+/// Runs a machine from `setup` twice at once, each on its own thread with the same seed and keys from a `KeyFuzzer`, for `duration` of emulated time or until it halts, then
+/// compares the state at the end of every frame. Anything that doesn't come from the ROM, the seed
+/// or the keys, like real time or another thread, shows up as the runs parting ways.
+pub fn audit(setup: impl Fn() -> Chip8 + Sync, duration: Duration, clock_speed: u32, seed: u64) -> Audit {
+    let run = || {
+        let mut chip8 = setup();
+        chip8.seed_rng(seed);
+        let clock_gap = Duration::from_secs(1) / clock_speed;
+        let mut fuzzer = KeyFuzzer::new(seed);
+        let mut elapsed = Duration::ZERO;
+        let mut next_frame = FRAME;
+        let mut frames = Vec::new();
+        while elapsed < duration && !chip8.halted() {
+            fuzzer.observe(&chip8);
+            let keys = fuzzer.keys(elapsed);
+            elapsed += clock_gap;
+            chip8.cycle(keys, clock_gap);
+            if elapsed >= next_frame {
+                next_frame += FRAME;
+                frames.push((chip8.cycles, state_hash(&chip8)));
+            }
+        }
+        // The frame it halted or ran out of time in
+        frames.push((chip8.cycles, state_hash(&chip8)));
+        frames
+    };
+    let (first, second) = std::thread::scope(|scope| {
+        let first = scope.spawn(run);
+        let second = scope.spawn(run);
+        (first.join(), second.join())
+    });
+    let (Ok(first), Ok(second)) = (first, second) else {
+        return Audit::Crashed;
+    };
+    let frames = first.len().max(second.len());
+    let end = |frames: &[(u64, u64)], frame| frames.get(frame).or(frames.last()).copied().unwrap();
+    match (0..frames).find(|&frame| end(&first, frame) != end(&second, frame)) {
+        Some(frame) => Audit::Diverged(Divergence { frame: frame as u64, first: end(&first, frame), second: end(&second, frame) }),
+        None => Audit::Matched { frames: frames as u64, cycles: first.last().unwrap().0 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU8, Ordering};
+    use std::time::Duration;
+    use crate::chip8::{Chip8, Instruction};
+    use crate::platform::Platform;
+    use super::{audit, Audit};
+
+    /// Draws a random sprite wherever a held key says, so both the seed and the keys matter
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.load_instructions(&[
+            Instruction::Random { register: 1, value: 0xff },
+            Instruction::GetKey { register: 0 },
+            Instruction::FontChar { register: 1 },
+            Instruction::Draw { x_r: 0, y_r: 1, height: 5 },
+            Instruction::Jump { dest: 0x200 },
+        ]);
+        chip8
+    }
+
+    #[test]
+    fn finds_where_runs_differ() {
+        assert_eq!(audit(machine, Duration::from_secs(10), 500, 7), Audit::Matched { frames: 600, cycles: 5000 });
+        // Something outside the machine, here how many times it's been set up, leaks in
+        let runs = AtomicU8::new(0);
+        let leaky = || {
+            let mut chip8 = machine();
+            chip8.registers[5].0 = runs.fetch_add(1, Ordering::Relaxed);
+            chip8
+        };
+        match audit(leaky, Duration::from_secs(10), 500, 7) {
+            Audit::Diverged(divergence) => assert_eq!(divergence.frame, 0),
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use crate::chip8::Chip8;
+use crate::display::RgbaBuffer;
+use crate::headless::{self, Stop};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+
+/// A named set of quirks to run a ROM under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub quirks: Quirks,
+}
+
+/// `NAME=QUIRK,QUIRK`, or just `NAME` for the defaults, with quirks named as for `Quirks::apply`,
+/// e.g. `schip=jump_with_vx,no_clip_sprites`
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, quirks) = s.split_once('=').unwrap_or((s, ""));
+        if name.is_empty() {
+            return Err(String::from("Expected NAME=QUIRK,QUIRK"));
+        }
+        let mut profile = Profile { name: name.to_string(), quirks: Quirks::default() };
+        for quirk in quirks.split(',').filter(|quirk| !quirk.is_empty()) {
+            profile.quirks.apply(quirk)?;
+        }
+        Ok(profile)
+    }
+}
+
+/// The default quirks, then each quirk flipped on its own
+pub fn default_profiles() -> Vec<Profile> {
+    let mut profiles = vec![Profile { name: String::from("default"), quirks: Quirks::default() }];
+    for name in Quirks::NAMES {
+        let mut quirks = Quirks::default();
+        let flipped = !quirks.get(name).unwrap();
+        quirks.set(name, flipped).unwrap();
+        let name = if flipped { name.to_string() } else { format!("no_{}", name) };
+        profiles.push(Profile { name, quirks });
+    }
+    profiles
+}
+
+/// How a ROM ran under one profile
+#[derive(Debug)]
+pub struct Report {
+    pub name: String,
+    /// Instructions per second of real time, or `None` if the core crashed
+    pub ips: Option<f64>,
+    pub stop: Option<Stop>,
+    /// The first cycle whose state differs from the first profile's, and the address of the instruction it ran
+    pub divergence: Option<(u64, usize)>,
+}
+
+/// The parts of the state a quirk can change, for spotting where profiles part ways
+pub fn state(chip8: &Chip8) -> impl PartialEq + '_ {
+    (chip8.pc, chip8.registers, chip8.index_register, &chip8.stack, &chip8.display, &chip8.memory[..])
+}
+
+/// Runs `rom` for up to `cycles` instructions under each profile, timing it, then runs the profiles
+/// again side by side to find where each first behaves differently from the first one.
+/// Every profile starts from the same state, random number generator included.
+pub fn bench(rom: &[u8], profiles: &[Profile], cycles: u64, clock_speed: u32) -> Vec<Report> {
+    let mut template = Chip8::new(Platform::default());
+    template.read_program(rom).expect("Failed to read ROM");
+    let initial = template.snapshot();
+    let machine = |quirks: &Quirks| {
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.restore(&initial);
+        chip8.quirks = quirks.clone();
+        chip8
+    };
+
+    let mut reports: Vec<Report> = profiles.iter().map(|profile| {
+        let mut chip8 = machine(&profile.quirks);
+        let began = Instant::now();
+        // The panic has already been printed with the call stack
+        let run = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            headless::run(&mut chip8, cycles, clock_speed, [false; 16], &mut RgbaBuffer::new(), &mut |_| {})
+        }));
+        let elapsed = began.elapsed().as_secs_f64();
+        Report {
+            name: profile.name.clone(),
+            ips: run.is_ok().then(|| chip8.cycles as f64 / elapsed),
+            stop: run.ok(),
+            divergence: None,
+        }
+    }).collect();
+
+    // Crashes are deterministic, so anything that crashed above would crash again here
+    let mut machines: Vec<Option<Chip8>> = profiles.iter()
+        .zip(&reports)
+        .map(|(profile, report)| report.stop.map(|_| machine(&profile.quirks)))
+        .collect();
+    let clock_gap = Duration::from_secs(1) / clock_speed;
+    for cycle in 0..cycles {
+        let pc = machines[0].as_ref().map(|chip8| chip8.pc);
+        for chip8 in machines.iter_mut().flatten() {
+            if !chip8.halted() {
+                chip8.cycle([false; 16], clock_gap);
+            }
+        }
+        let (baseline, others) = machines.split_first().unwrap();
+        let Some(baseline) = baseline else { break };
+        for (report, chip8) in reports[1..].iter_mut().zip(others) {
+            if let Some(chip8) = chip8.as_ref().filter(|_| report.divergence.is_none()) {
+                if state(chip8) != state(baseline) {
+                    report.divergence = Some((cycle, pc.unwrap()));
+                }
+            }
+        }
+        if reports[1..].iter().all(|report| report.divergence.is_some() || report.stop.is_none()) {
+            break;
+        }
+    }
+    reports
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::encode::assemble;
+    use crate::headless::Stop;
+    use crate::quirks::Quirks;
+    use super::{bench, default_profiles, Profile};
+
+    #[test]
+    fn parses_profiles() {
+        assert_eq!("plain".parse(), Ok(Profile { name: "plain".into(), quirks: Quirks::default() }));
+        assert_eq!(
+            "schip=jump_with_vx,no_clip_sprites".parse(),
+            Ok(Profile { name: "schip".into(), quirks: Quirks { jump_with_vx: true, clip_sprites: false, ..Quirks::default() } })
+        );
+        assert!("=jump_with_vx".parse::<Profile>().is_err());
+        assert!("schip=jump".parse::<Profile>().is_err());
+    }
+
+    #[test]
+    fn finds_where_profiles_diverge() {
+        let rom = assemble(&[
+            Instruction::SetRegister { register: 2, value: 2 },
+            Instruction::Random { register: 5, value: 0xff },
+            Instruction::JumpOffset { dest: 0x206 },
+            Instruction::Jump { dest: 0x206 },
+            Instruction::Jump { dest: 0x208 },
+        ]);
+        let reports = bench(&rom, &default_profiles(), 100, 500);
+        assert_eq!(reports[0].name, "default");
+        assert_eq!(reports[0].stop, Some(Stop::Halted));
+        assert_eq!(reports[0].divergence, None);
+        // Only the jump itself goes somewhere else, so random numbers don't count as a difference
+        let report = |name: &str| reports.iter().find(|report| report.name == name).unwrap();
+        assert_eq!(report("jump_with_vx").divergence, Some((2, 0x204)));
+        assert_eq!(report("no_clip_sprites").divergence, None);
+        assert!(reports.iter().all(|report| report.ips.is_some()));
+    }
+}
@@ -34,9 +34,33 @@ pub fn get_nibble(instruction: u16, index: u8) -> u8 {
     get_nibbles(instruction, index, 1) as u8
 }
 
+/// 64-bit FNV-1a, used to identify ROMs and screens.
+/// Stable across builds and platforms, unlike std's DefaultHasher.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// CRC-32 as PNG and zip use it, a bit at a time since images here are small
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| if crc & 1 == 1 { crc >> 1 ^ 0xedb88320 } else { crc >> 1 })
+    })
+}
+
+/// Adler-32, zlib's checksum
+pub fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{get_nibbles, get_nibble};
+    use super::{adler32, crc32, get_nibbles, get_nibble, fnv1a};
     #[test]
     fn some_nibbles() {
         assert_eq!(get_nibble(0xdeaf, 0), 0xd);
@@ -44,16 +68,27 @@ mod tests {
         assert_eq!(get_nibble(0xdeaf, 2), 0xa);
         assert_eq!(get_nibble(0xdeaf, 3), 0xf);
     }
+    #[test]
+    fn fnv1a_known_values() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+    #[test]
+    fn checksum_known_values() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
     use proptest::prelude::*;
     proptest! {
         #[test]
         fn never_panics(
-            instruction in 0..std::u16::MAX, 
+            instruction in 0..u16::MAX, 
             index in 0..4, 
             num_nibbles in 1..4) 
         {
             if index + num_nibbles <= 4 {
-                get_nibbles(instruction as u16, index as u8, num_nibbles as u8);
+                get_nibbles(instruction, index as u8, num_nibbles as u8);
             }
         }
     }
@@ -0,0 +1,41 @@
+use crate::chip8::Chip8;
+use crate::platform::Platform;
+
+/// The IBM logo, drawn by the core itself before the ROM starts
+pub const BOOT_ROM: &[u8] = include_bytes!("../test/ibm_logo.ch8");
+
+/// Slow enough that the logo goes up a letter at a time
+pub const BOOT_CLOCK_SPEED: u32 = 20;
+
+/// The logo takes 20 instructions, then stays up for as long again
+const BOOT_CYCLES: u64 = 40;
+
+/// A machine running the boot animation
+pub fn machine() -> Chip8 {
+    let mut chip8 = Chip8::new(Platform::Chip8);
+    chip8.read_program(BOOT_ROM).expect("The boot ROM fits in memory");
+    chip8
+}
+
+/// Whether the animation has drawn everything and been shown long enough
+pub fn finished(chip8: &Chip8) -> bool {
+    chip8.halted() && chip8.cycles >= BOOT_CYCLES
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{finished, machine, BOOT_CLOCK_SPEED};
+
+    #[test]
+    fn draws_the_logo_then_holds_it() {
+        let mut chip8 = machine();
+        let clock_gap = Duration::from_secs(1) / BOOT_CLOCK_SPEED;
+        while !finished(&chip8) {
+            chip8.cycle([false; 16], clock_gap);
+        }
+        assert_eq!(chip8.cycles, 40);
+        // The top of the I in IBM
+        assert!(chip8.display.pixel(12, 8));
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+use crate::chip8::Rect;
+
+/// Events that drop into the debugger when they happen, for finding the code responsible
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoints {
+    /// FX18 setting the sound timer, or the sound timer running out
+    pub sound: bool,
+    /// 00E0 clearing the screen
+    pub clear: bool,
+    /// Draws that change a pixel inside any of these
+    pub draws: Vec<Rect>,
+}
+
+/// A breakpoint that triggered, with the address of the instruction responsible
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Break {
+    SoundSet { pc: usize, value: u8 },
+    /// The timer ran out just as the instruction at `pc` was about to run
+    SoundExpired { pc: usize },
+    Clear { pc: usize },
+    Draw { pc: usize, rect: Rect },
+}
+
+impl fmt::Display for Break {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Break::SoundSet { pc, value } => write!(f, "sound timer set to {} at {:#05x}", value, pc),
+            Break::SoundExpired { pc } => write!(f, "sound timer ran out before {:#05x}", pc),
+            Break::Clear { pc } => write!(f, "screen cleared at {:#05x}", pc),
+            Break::Draw { pc, rect } => {
+                write!(f, "drew inside {},{},{},{} at {:#05x}", rect.x, rect.y, rect.width, rect.height, pc)
+            },
+        }
+    }
+}
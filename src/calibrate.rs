@@ -0,0 +1,163 @@
+use crate::chip8::Instruction;
+
+/// Polls in a row for the program to count as waiting
+const POLLS_TO_WAIT: u32 = 3;
+/// Most cycles between polls in a wait loop
+const WAIT_LOOP_CYCLES: u32 = 8;
+/// Frames between verdicts
+const WINDOW_FRAMES: u32 = 60;
+
+/// Which way the clock speed should go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tempo {
+    /// The program often has no time left over to wait on the delay timer
+    TooSlow,
+    /// The program spends most of every frame waiting on the delay timer
+    TooFast,
+}
+
+/// Guesses whether the clock speed suits a ROM from how it spends each 60Hz frame.
+/// Programs paced by the delay timer spin reading it once their frame's work is done,
+/// so frames that don't end in that wait mean the program is running behind, and frames
+/// that are mostly waiting mean it could run slower. Frames spent polling the keypad
+/// (menus, "press any key") say nothing about speed, so they're left out.
+#[derive(Default)]
+pub struct Calibrator {
+    frame: u64,
+    cycles: u32,
+    key_polls: u32,
+    /// The latest run of delay timer reads close together: when in the frame it started,
+    /// the latest read and how many reads there were
+    wait_start: u32,
+    last_timer_poll: Option<u32>,
+    timer_polls: u32,
+    /// Frames in the current window by how they were spent
+    waiting_frames: u32,
+    busy_frames: u32,
+    frames: u32,
+    /// Cycles in frames that weren't waiting for keys, and how many of those went on waiting for the timer
+    measured_cycles: u64,
+    idle_cycles: u64,
+    used_timer: bool,
+}
+
+impl Calibrator {
+    /// Call with each instruction as it's executed and the 60Hz frame it ran in.
+    /// Gives a verdict at most once a second of emulated time.
+    pub fn observe(&mut self, instruction: Option<Instruction>, frame: u64) -> Option<Tempo> {
+        let mut verdict = None;
+        if frame != self.frame {
+            self.frame = frame;
+            verdict = self.end_frame();
+        }
+        match instruction {
+            Some(Instruction::GetDelayTimer { .. }) => {
+                if !self.waiting() {
+                    self.wait_start = self.cycles;
+                    self.timer_polls = 0;
+                }
+                self.last_timer_poll = Some(self.cycles);
+                self.timer_polls += 1;
+                self.used_timer = true;
+            },
+            Some(Instruction::GetKey { .. } | Instruction::SkipPressed { .. } | Instruction::SkipNotPressed { .. }) => {
+                self.key_polls += 1;
+            },
+            _ => {},
+        }
+        self.cycles += 1;
+        verdict
+    }
+
+    /// Whether the latest timer read was recent enough to be part of a loop still going
+    fn waiting(&self) -> bool {
+        self.last_timer_poll.is_some_and(|last| self.cycles - last <= WAIT_LOOP_CYCLES)
+    }
+
+    fn end_frame(&mut self) -> Option<Tempo> {
+        if self.key_polls >= POLLS_TO_WAIT {
+            // Waiting for input
+        } else {
+            self.measured_cycles += self.cycles as u64;
+            if self.waiting() && self.timer_polls >= POLLS_TO_WAIT {
+                self.waiting_frames += 1;
+                self.idle_cycles += (self.cycles - self.wait_start) as u64;
+            } else {
+                self.busy_frames += 1;
+            }
+        }
+        self.cycles = 0;
+        self.key_polls = 0;
+        self.last_timer_poll = None;
+        self.timer_polls = 0;
+        self.frames += 1;
+        if self.frames < WINDOW_FRAMES {
+            return None;
+        }
+
+        let measured = self.waiting_frames + self.busy_frames;
+        let verdict = if !self.used_timer || measured < WINDOW_FRAMES / 2 {
+            None
+        } else if self.busy_frames * 5 > measured {
+            Some(Tempo::TooSlow)
+        } else if self.idle_cycles * 4 > self.measured_cycles * 3 {
+            Some(Tempo::TooFast)
+        } else {
+            None
+        };
+        *self = Calibrator { frame: self.frame, ..Calibrator::default() };
+        verdict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chip8::Instruction;
+    use crate::decode::decode;
+    use crate::testing::Machine;
+    use super::{Calibrator, Tempo};
+
+    fn verdicts(program: &[Instruction], instructions_per_second: u32, seconds: u32) -> Vec<Tempo> {
+        let mut machine = Machine::from_instructions(program).with_clock_gap(Duration::from_secs(1) / instructions_per_second);
+        let mut calibrator = Calibrator::default();
+        let mut verdicts = Vec::new();
+        for _ in 0..instructions_per_second * seconds {
+            let instruction = machine.chip8.instruction_at(machine.chip8.pc).and_then(decode);
+            machine.step();
+            verdicts.extend(calibrator.observe(instruction, machine.chip8.timestamp().frame()));
+        }
+        verdicts
+    }
+
+    /// About 200 instructions of work each frame, then waits for the delay timer
+    const TIMER_PACED: [Instruction; 10] = [
+        Instruction::SetRegister { register: 0, value: 1 },
+        Instruction::SetDelayTimer { register: 0 },
+        Instruction::SetRegister { register: 1, value: 66 },
+        Instruction::AddToRegister { register: 1, value: 0xff },
+        Instruction::SkipEQ { register: 1, value: 0 },
+        Instruction::Jump { dest: 0x206 },
+        Instruction::GetDelayTimer { register: 2 },
+        Instruction::SkipEQ { register: 2, value: 0 },
+        Instruction::Jump { dest: 0x20c },
+        Instruction::Jump { dest: 0x200 },
+    ];
+
+    #[test]
+    fn speeds_up_programs_without_slack() {
+        assert_eq!(verdicts(&TIMER_PACED, 500, 3), [Tempo::TooSlow; 3]);
+        assert_eq!(verdicts(&TIMER_PACED, 15_000, 3), []);
+        assert_eq!(verdicts(&TIMER_PACED, 100_000, 3), [Tempo::TooFast; 3]);
+    }
+
+    #[test]
+    fn ignores_waiting_for_keys() {
+        let program = [
+            Instruction::GetDelayTimer { register: 0 },
+            Instruction::SkipPressed { register: 0 },
+            Instruction::Jump { dest: 0x200 },
+        ];
+        assert_eq!(verdicts(&program, 500, 3), []);
+    }
+}
@@ -1,13 +1,32 @@
 use std::cmp::max;
 use std::cmp::min;
+use std::collections::HashMap;
 use std::io::Read;
 use std::num::Wrapping;
-use std::ops::Range;
-use std::slice::Iter;
+use std::ops::{Index, IndexMut, Range};
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
-use std::time::Instant;
-use crate::bits::{U4, U12};
-use crate::decode::decode;
+use crate::bits::{fnv1a, U4, U12};
+use crate::accesslog::MemoryAccess;
+use crate::analysis::mark_code;
+use crate::breakpoints::{Break, Breakpoints};
+use crate::logging;
+use crate::look::Palette;
+use crate::mega::{self, Blend, MegaRegisters, MegaScreen, MEGA_HEIGHT, MEGA_MEMORY_SIZE, MEGA_WIDTH};
+use crate::diagnostics::{Diagnostic, Diagnostics, Policy, Severity};
+use crate::disasm::disassemble;
+use crate::encode::assemble;
+use crate::encode::encode;
+use crate::platform::{starts_two_page, Platform};
+use crate::protection::Region;
+use crate::quirks::Quirks;
+use crate::rom::trimmed_len;
+use crate::stats::Stats;
+use crate::snapshot::{read_chunks, write_chunk, Snapshot};
+use crate::symbols::Symbols;
+use crate::vip::{self, VipClock, VIP_TICK_SPEED};
+use crate::xref::{Access, Xref, Xrefs};
 use rand_xoshiro::Xoroshiro64StarStar;
 use rand_core::SeedableRng;
 use rand_core::RngCore;
@@ -25,6 +44,9 @@ pub enum Instruction {
     SetRegister { register: U4, value: u8 },
     AddToRegister { register: U4, value: u8 },
     SetIndexRegister { value: U12 },
+    /// XO-CHIP: set I to the 16 bits in the word after this one, and skip over them
+    LongIndex,
+    JumpOffset { dest: U12 },
     MovRegister { register1: U4, register2: U4 },
     BinaryOr { register1: U4, register2: U4 },
     BinaryAnd { register1: U4, register2: U4 },
@@ -36,30 +58,434 @@ pub enum Instruction {
     ShiftLeft { register1: U4, register2: U4 },
     Random { register: U4, value: u8 },
     Draw { x_r: U4, y_r: U4, height: U4 },
-    SkipPressed { key: U4 },
-    SkipNotPressed { key: U4 },
+    SkipPressed { register: U4 },
+    SkipNotPressed { register: U4 },
     GetDelayTimer { register: U4 },
     GetKey { register: U4 },
     FontChar { register: U4 },
+    /// SCHIP: point I at the big font's digit for VX
+    BigFontChar { register: U4 },
     SetDelayTimer { register: U4 },
     SetSoundTimer { register: U4 },
     AddToIndex { register: U4 },
     RegToDecimal { register: U4 },
     StoreMemory { register: U4 },
     LoadMemory { register: U4 },
+    /// XO-CHIP: save VX to VY at I, leaving I alone. Backwards if Y is before X.
+    StoreRange { register1: U4, register2: U4 },
+    /// XO-CHIP: load VX to VY from I, leaving I alone. Backwards if Y is before X.
+    LoadRange { register1: U4, register2: U4 },
+    /// SCHIP: save V0 to VX in the RPL flags
+    SaveFlags { register: U4 },
+    /// SCHIP: load V0 to VX from the RPL flags
+    LoadFlags { register: U4 },
+    /// SCHIP: switch to the 64x32 screen
+    Lores,
+    /// SCHIP: switch to the 128x64 screen
+    Hires,
+    /// SCHIP: move the screen down, blanking the rows it uncovers
+    ScrollDown { rows: U4 },
+    /// XO-CHIP: move the screen up, blanking the rows it uncovers
+    ScrollUp { rows: U4 },
+    /// SCHIP: move the screen 4 pixels right
+    ScrollRight,
+    /// SCHIP: move the screen 4 pixels left
+    ScrollLeft,
+    /// SCHIP: stop the interpreter
+    Exit,
+    /// XO-CHIP: choose the display planes, a bit each, that draws, clears and scrolls affect
+    SelectPlanes { planes: U4 },
+    /// XO-CHIP: load the 16 bytes at I into the audio pattern
+    LoadAudio,
+    /// XO-CHIP: set the pitch the audio pattern plays at
+    SetPitch { register: U4 },
+    /// CHIP-8X: step the background colour on to the next of blue, black, green and red
+    CycleBackground,
+    /// CHIP-8X: add VY to VX a nibble at a time, each only 0 to 7 and wrapping around without carrying
+    AddNibbles { register1: U4, register2: U4 },
+    /// CHIP-8X: colour the columns of zones between VX's nibbles and the rows between V(X+1)'s with VY
+    ColorZones { x_r: U4, y_r: U4 },
+    /// CHIP-8X: colour `rows` rows from V(X+1) down in the 8-pixel column holding VX with VY
+    ColorRows { x_r: U4, y_r: U4, rows: U4 },
+    /// CHIP-8X: skip if the key in VX is down on the second keypad
+    SkipPressed2 { register: U4 },
+    /// CHIP-8X: skip if the key in VX is up on the second keypad
+    SkipNotPressed2 { register: U4 },
+    /// MEGA-CHIP: switch back to the SCHIP screen
+    MegaOff,
+    /// MEGA-CHIP: switch to the 256x192 colour screen
+    MegaOn,
+    /// MEGA-CHIP: set I to `high` and then the 16 bits in the word after this one, and skip over them
+    MegaIndex { high: u8 },
+    /// MEGA-CHIP: load `count` ARGB colours from I into the palette, from index 1 up
+    LoadPalette { count: u8 },
+    /// MEGA-CHIP: set the width of the sprites DXYN draws, 0 meaning 256
+    SpriteWidth { width: u8 },
+    /// MEGA-CHIP: set the height of the sprites DXYN draws, 0 meaning 256
+    SpriteHeight { height: u8 },
+    /// MEGA-CHIP: set the screen's opacity
+    Alpha { alpha: u8 },
+    /// MEGA-CHIP: play the digitised sound at I, looping unless `mode` is 1
+    PlaySample { mode: U4 },
+    /// MEGA-CHIP: stop the digitised sound
+    StopSample,
+    /// MEGA-CHIP: choose how sprites mix with what's under them, as for `mega::Blend`
+    BlendMode { mode: U4 },
+    /// MEGA-CHIP: set the palette index that sprites collide with
+    CollisionColor { color: u8 },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Cycle {
     RedrawRequested,
-    Complete
+    Complete,
+    /// SCHIP's 00FD: the program is done, and the frontend can stop
+    Exit,
+}
+
+/// XO-CHIP's audio pattern and the pitch it's played at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioPattern {
+    pub bits: [u8; 16],
+    pub pitch: u8,
+}
+
+/// What the beeper should be doing, which the core reports each time it changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Beep {
+    /// The sound timer is running, with the buzzer or an audio pattern. A new pattern or pitch
+    /// while it runs is reported as starting again.
+    Started(Option<AudioPattern>),
+    Stopped,
+}
+
+/// When something happened in emulated time, for keeping video and audio in step
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp {
+    /// Instructions executed before it
+    pub cycle: u64,
+    /// Emulated nanoseconds since the machine was created
+    pub nanos: u64,
+}
+
+impl Timestamp {
+    /// 60Hz timer frames since the machine was created
+    pub fn frame(&self) -> u64 {
+        self.nanos * 60 / 1_000_000_000
+    }
+}
+
+/// Which draw last touched each pixel in the latest frame that had any, for seeing overdraw and
+/// what flickers. Erasing a pixel counts as touching it.
+#[derive(Debug, Clone, Default)]
+pub struct DrawOrder {
+    /// Row by row like the screen: 1 for the frame's first draw, 2 for its second, and 0 for untouched
+    pub pixels: Vec<u32>,
+    /// Draws in the frame
+    pub draws: u32,
+    frame: Option<u64>,
+}
+
+impl DrawOrder {
+    /// Counts a draw, starting over if it's the first of a new frame or the screen changed size
+    fn start_draw(&mut self, frame: u64, size: usize) {
+        if self.frame != Some(frame) || self.pixels.len() != size {
+            self.frame = Some(frame);
+            self.pixels = vec![0; size];
+            self.draws = 0;
+        }
+        self.draws += 1;
+    }
 }
 
 pub const INIT_INDEX: usize = 0x200;
+/// Bytes of memory on every platform but MEGA-CHIP, and all that code can reach on any
+pub const MEMORY_SIZE: usize = 4096;
+/// Calls deep the stack holds, so its length fits in the byte `CORE` keeps it in
+pub const MAX_CALL_DEPTH: usize = u8::MAX as usize;
+/// The screen's size in lores, which every platform starts in
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
-type Screen = [[bool; SCREEN_WIDTH]; SCREEN_HEIGHT];
-const BLANK_SCREEN: Screen = [[false; SCREEN_WIDTH]; SCREEN_HEIGHT];
-const FONT: [u8; 80] = [
+/// SCHIP's hires size, switched to with 00FF
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+/// The hires platform's size, two of the VIP's display pages stacked
+pub const TWO_PAGE_WIDTH: usize = 64;
+pub const TWO_PAGE_HEIGHT: usize = 64;
+/// Where hires ROMs' own code starts, after the `1260` and the machine code it jumps to
+pub const TWO_PAGE_START: usize = 0x2c0;
+/// XO-CHIP's display planes. A pixel's colour is which of them it's lit in, so there are 4.
+pub const PLANES: usize = 2;
+/// CHIP-8X's foreground colours, by the number programs give them
+pub const CHIP8X_COLORS: [[u8; 3]; 8] = [
+    [0x00, 0x00, 0x00],
+    [0xff, 0x00, 0x00],
+    [0x00, 0x00, 0xff],
+    [0xff, 0x00, 0xff],
+    [0x00, 0xff, 0x00],
+    [0xff, 0xff, 0x00],
+    [0x00, 0xff, 0xff],
+    [0xff, 0xff, 0xff],
+];
+/// And its backgrounds, in the order 02A0 steps through them
+pub const CHIP8X_BACKGROUNDS: [[u8; 3]; 4] = [[0x00, 0x00, 0x80], [0x00, 0x00, 0x00], [0x00, 0x80, 0x00], [0x80, 0x00, 0x00]];
+/// Zones of the screen CHIP-8X colours with BXY0 are this many pixels across and down
+pub const ZONE_WIDTH: usize = 8;
+pub const ZONE_HEIGHT: usize = 4;
+/// XO-CHIP's pitch register starts here, which plays the audio pattern at 4000 bits a second
+pub const DEFAULT_PITCH: u8 = 64;
+/// A 60Hz timer tick, in the sixtieths of a nanosecond the tick accumulator counts
+const TICK: u64 = 1_000_000_000;
+
+/// CHIP-8X's colours, from the VIP's colour board: one for the background, and one for lit pixels
+/// in each row of each 8-pixel-wide column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Colors {
+    /// Which of `CHIP8X_BACKGROUNDS`
+    pub background: u8,
+    /// Which of `CHIP8X_COLORS`, row by row with a column each `ZONE_WIDTH` pixels
+    pub foreground: Vec<u8>,
+}
+
+impl Colors {
+    /// Red on blue, as the colour board starts
+    fn new(width: usize, height: usize) -> Self {
+        Colors { background: 0, foreground: vec![1; width / ZONE_WIDTH * height] }
+    }
+}
+
+/// The pixels on screen, indexed by row then column like `screen[y][x]`.
+/// Its size changes when SCHIP programs switch between lores and hires.
+/// Indexing and `rows` are the first plane, the only one anything but XO-CHIP draws to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+    /// XO-CHIP's second plane
+    second: Vec<bool>,
+    /// CHIP-8X's colours, which take the place of the palette's
+    colors: Option<Colors>,
+    /// MEGA-CHIP's colour screen, which takes the place of the planes while it's on
+    mega: Option<MegaScreen>,
+}
+
+impl Screen {
+    /// A blank screen
+    pub fn new(width: usize, height: usize) -> Self {
+        Screen { width, height, pixels: vec![false; width * height], second: vec![false; width * height], colors: None, mega: None }
+    }
+
+    /// A blank MEGA-CHIP screen
+    pub fn mega() -> Self {
+        Screen { mega: Some(MegaScreen::new()), ..Screen::new(MEGA_WIDTH, MEGA_HEIGHT) }
+    }
+
+    /// A blank screen with CHIP-8X's colours
+    pub fn colored(width: usize, height: usize) -> Self {
+        Screen { colors: Some(Colors::new(width, height)), ..Screen::new(width, height) }
+    }
+
+    pub fn colors(&self) -> Option<&Colors> {
+        self.colors.as_ref()
+    }
+
+    pub fn mega_screen(&self) -> Option<&MegaScreen> {
+        self.mega.as_ref()
+    }
+
+    /// The pixel's colour on screen: from `palette` by its planes, unless the screen has colours of its own
+    pub fn rgb(&self, x: usize, y: usize, palette: &Palette) -> [u8; 3] {
+        if let Some(mega) = self.mega.as_ref().filter(|_| x < self.width && y < self.height) {
+            return mega.shown[y * self.width + x];
+        }
+        match &self.colors {
+            Some(colors) if self.pixel(x, y) => CHIP8X_COLORS[colors.foreground[y * self.width / ZONE_WIDTH + x / ZONE_WIDTH] as usize & 7],
+            Some(colors) => CHIP8X_BACKGROUNDS[colors.background as usize & 3],
+            None => palette.0[self.color(x, y) as usize],
+        }
+    }
+
+    /// Whether the core can show a screen this size
+    pub fn valid_size(width: usize, height: usize) -> bool {
+        matches!((width, height), (SCREEN_WIDTH, SCREEN_HEIGHT) | (HIRES_WIDTH, HIRES_HEIGHT) | (TWO_PAGE_WIDTH, TWO_PAGE_HEIGHT) | (MEGA_WIDTH, MEGA_HEIGHT))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn hires(&self) -> bool {
+        self.width == HIRES_WIDTH
+    }
+
+    /// Whether the pixel is lit in any plane, with anything off screen unlit
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.color(x, y) != 0
+    }
+
+    /// The planes the pixel is lit in, a bit each, so 0 is unlit and 1 is lit in the first plane only.
+    /// MEGA-CHIP's pixels are in the first plane if they aren't black.
+    pub fn color(&self, x: usize, y: usize) -> u8 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        let i = y * self.width + x;
+        if let Some(mega) = &self.mega {
+            return (mega.shown[i] != [0; 3]) as u8;
+        }
+        self.pixels[i] as u8 | (self.second[i] as u8) << 1
+    }
+
+    pub fn rows(&self) -> std::slice::Chunks<'_, bool> {
+        self.pixels.chunks(self.width)
+    }
+
+    pub fn rows_mut(&mut self) -> std::slice::ChunksMut<'_, bool> {
+        self.pixels.chunks_mut(self.width)
+    }
+
+    /// Every pixel of a plane, row by row
+    pub fn plane(&self, plane: usize) -> &[bool] {
+        match plane {
+            0 => &self.pixels,
+            _ => &self.second,
+        }
+    }
+
+    pub fn plane_mut(&mut self, plane: usize) -> &mut [bool] {
+        match plane {
+            0 => &mut self.pixels,
+            _ => &mut self.second,
+        }
+    }
+
+    /// The planes whose bits are set in `planes`, e.g. from FN01
+    fn selected(&mut self, planes: u8) -> impl Iterator<Item = &mut Vec<bool>> {
+        [&mut self.pixels, &mut self.second].into_iter()
+            .enumerate()
+            .filter(move |&(plane, _)| planes & 1 << plane != 0)
+            .map(|(_, pixels)| pixels)
+    }
+
+    /// Blanks the selected planes, leaving any others as they are
+    pub fn clear(&mut self, planes: u8) {
+        for pixels in self.selected(planes) {
+            pixels.fill(false);
+        }
+    }
+
+    /// Moves the selected planes down (or up, for negative `rows`), blanking the rows uncovered
+    pub fn scroll_vertically(&mut self, rows: isize, planes: u8) {
+        let shift = min(rows.unsigned_abs(), self.height) * self.width;
+        for pixels in self.selected(planes) {
+            if rows > 0 {
+                pixels.rotate_right(shift);
+                pixels[..shift].fill(false);
+            } else {
+                pixels.rotate_left(shift);
+                let len = pixels.len();
+                pixels[len - shift..].fill(false);
+            }
+        }
+    }
+
+    /// Moves the selected planes right (or left, for negative `columns`), blanking the columns uncovered
+    pub fn scroll_sideways(&mut self, columns: isize, planes: u8) {
+        let shift = min(columns.unsigned_abs(), self.width);
+        let width = self.width;
+        for pixels in self.selected(planes) {
+            for row in pixels.chunks_mut(width) {
+                if columns > 0 {
+                    row.rotate_right(shift);
+                    row[..shift].fill(false);
+                } else {
+                    row.rotate_left(shift);
+                    row[width - shift..].fill(false);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen::new(SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+}
+
+impl Index<usize> for Screen {
+    type Output = [bool];
+
+    fn index(&self, y: usize) -> &[bool] {
+        &self.pixels[y * self.width..][..self.width]
+    }
+}
+
+impl IndexMut<usize> for Screen {
+    fn index_mut(&mut self, y: usize) -> &mut [bool] {
+        &mut self.pixels[y * self.width..][..self.width]
+    }
+}
+
+/// An area of the screen, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// `X,Y,W,H` in pixels, e.g. `0,0,64,5` for the top 5 rows
+impl FromStr for Rect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers: Vec<usize> = s.split(',')
+            .map(|number| number.trim().parse().map_err(|e| format!("Bad number {}: {}", number, e)))
+            .collect::<Result<_, _>>()?;
+        match numbers[..] {
+            [x, y, width, height] => Ok(Rect { x, y, width, height }),
+            _ => Err(String::from("Expected X,Y,W,H")),
+        }
+    }
+}
+/// More draws than this in one 60Hz frame can't all be seen, and usually means
+/// the ROM expects a quirk we don't emulate (e.g. drawing waiting for vblank)
+pub const DRAW_STORM_THRESHOLD: u32 = 1000;
+/// Where the 8x10 digits SCHIP added go, after `FONT`
+pub const BIG_FONT_ADDRESS: usize = 0x50;
+/// SUPER-CHIP 1.1's big digits, 0 to 9 only
+pub const BIG_FONT: [u8; 100] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C  // 9
+];
+/// SCHIP has a flag for each of V0 to V7, from the HP-48's RPL user flags
+pub const RPL_FLAGS: usize = 8;
+pub const FONT: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -80,32 +506,127 @@ const FONT: [u8; 80] = [
 
 pub struct Chip8 {
     pub registers: [Wrapping<u8>; 16],
-    pub memory: [u8; 4096],
+    /// `MEMORY_SIZE` bytes, or for MEGA-CHIP, more if the ROM needs it
+    pub memory: Vec<u8>,
     pub pc: usize,
-    pub index_register: Wrapping<u16>,
+    /// 16 bits, or MEGA-CHIP's 24
+    pub index_register: Wrapping<u32>,
     pub delay_timer: u8,
     pub sound_timer: u8,
     pub display: Screen,
+    /// The display planes FN01 selected, a bit each. Only XO-CHIP can select other than the first.
+    pub planes: u8,
+    /// XO-CHIP's 128 1-bit samples from F002, played while the sound timer runs. Until one's
+    /// loaded the buzzer sounds as it does everywhere else.
+    pub audio_pattern: Option<[u8; 16]>,
+    /// XO-CHIP's pitch register, from FX3A
+    pub pitch: u8,
+    /// CHIP-8X's second keypad, which the frontend sets directly since `cycle` only takes the first.
+    /// It isn't kept in snapshots or replays.
+    pub second_keypad: [bool; 16],
+    pub mega: MegaRegisters,
     pub stack: Vec<usize>,
-    last_clock: Instant,
-    rng: Xoroshiro64StarStar
+    /// Instructions executed so far
+    pub cycles: u64,
+    /// Emulated nanoseconds since the machine was created, as of the latest cycle
+    elapsed: u64,
+    /// Emulated time since the last 60Hz timer tick, in sixtieths of a nanosecond so ticks land
+    /// exactly a sixtieth of a second apart however the time comes in
+    tick_accumulator: u64,
+    rng: Xoroshiro64StarStar,
+    /// Bytes statically reachable as code, from `read_program`
+    code: Option<Vec<bool>>,
+    /// PC of the FX33/FX55 that last wrote each byte
+    written_by: Vec<Option<u16>>,
+    /// Questionable things the program has done, each warned about once per address
+    pub diagnostics: Diagnostics,
+    /// What to do about jumps and calls to odd addresses
+    pub odd_pc: Policy,
+    /// Whether the last frame had more than `DRAW_STORM_THRESHOLD` draws
+    pub draw_storm: bool,
+    /// Draws per PC in the current frame
+    frame_draws: HashMap<usize, u32>,
+    pub draw_order: DrawOrder,
+    /// Labels for stack traces
+    pub symbols: Symbols,
+    /// Memory the program isn't allowed to write or execute
+    pub regions: Vec<Region>,
+    pub breakpoints: Breakpoints,
+    /// The breakpoint that triggered during the latest cycle, for the frontend to take
+    pub hit: Option<Break>,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// On the HP-48 these outlived the program, so the frontend keeps them on disk for each ROM
+    pub rpl: [u8; RPL_FLAGS],
+    /// Set when FX75 changes the RPL flags, for the frontend to take and save
+    pub rpl_changed: bool,
+    /// Set when the latest cycle changed what the beeper should do, for the frontend to take
+    pub beep: Option<Beep>,
+    /// What `beep` was last set to. Restores leave it alone, so a restored state that sounds
+    /// differently is reported like any other change.
+    last_beep: Beep,
+    /// The frame of the latest draw, for `Quirks::display_wait`
+    last_draw_frame: Option<u64>,
+    /// Shared with whoever's watching from another thread
+    pub stats: Arc<Stats>,
+    /// Who refers to each address: found statically by `read_program`, then added to as instructions run
+    pub xrefs: Xrefs,
+    /// While `Some`, every byte instructions read or write is added, for the frontend to take
+    pub accesses: Option<Vec<MemoryAccess>>,
+    /// Machine cycles the VIP would have left this frame, for `Quirks::vip_timing`
+    vip_clock: VipClock,
 }
 
 impl Chip8 {
-    pub fn new(start: Instant) -> Self {
+    /// A machine for the platform, with its quirks
+    pub fn new(platform: Platform) -> Self {
         let mut chip8 = Chip8 {
             registers: [Wrapping(0); 16],
-            memory: [0; 4096],
-            pc: INIT_INDEX,
+            memory: vec![0; MEMORY_SIZE],
+            pc: platform.load_address(),
             index_register: Wrapping(0),
             delay_timer: 0,
             sound_timer: 0,
-            display: BLANK_SCREEN,
+            display: match platform {
+                Platform::Hires => Screen::new(TWO_PAGE_WIDTH, TWO_PAGE_HEIGHT),
+                Platform::Chip8X => Screen::colored(SCREEN_WIDTH, SCREEN_HEIGHT),
+                _ => Screen::default(),
+            },
+            planes: 1,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            second_keypad: [false; 16],
+            mega: MegaRegisters::default(),
             stack: Vec::new(),
-            last_clock: start,
-            rng: Xoroshiro64StarStar::from_entropy()
+            cycles: 0,
+            elapsed: 0,
+            tick_accumulator: 0,
+            rng: Xoroshiro64StarStar::from_entropy(),
+            code: None,
+            written_by: vec![None; MEMORY_SIZE],
+            diagnostics: Diagnostics::default(),
+            odd_pc: Policy::default(),
+            draw_storm: false,
+            frame_draws: HashMap::new(),
+            draw_order: DrawOrder::default(),
+            symbols: Symbols::default(),
+            regions: Vec::new(),
+            breakpoints: Breakpoints::default(),
+            hit: None,
+            platform,
+            quirks: platform.quirks(),
+            rpl: [0; RPL_FLAGS],
+            rpl_changed: false,
+            beep: None,
+            last_beep: Beep::Stopped,
+            last_draw_frame: None,
+            stats: Arc::default(),
+            xrefs: Xrefs::default(),
+            accesses: None,
+            vip_clock: VipClock::default(),
         };
         chip8.memory[0..FONT.len()].copy_from_slice(&FONT);
+        chip8.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
         chip8
     }
 
@@ -113,27 +634,483 @@ impl Chip8 {
         self.memory[self.pc + 1] as u16 | (self.memory[self.pc] as u16) << 8
     }
 
+    pub fn instruction_at(&self, address: usize) -> Option<u16> {
+        let bytes = self.memory.get(address..address + 2)?;
+        Some(bytes[1] as u16 | (bytes[0] as u16) << 8)
+    }
+
+    /// Makes CXNN's random numbers repeatable
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Xoroshiro64StarStar::seed_from_u64(seed);
+    }
+
+    /// Whether the next instruction jumps to itself, which is how programs stop since CHIP-8 has no halt,
+    /// or is SCHIP's 00FD exit
+    pub fn halted(&self) -> bool {
+        let next = self.instruction_at(self.pc);
+        next == Some(0x1000 | self.pc as u16) || next.and_then(|raw| self.platform.decode(raw)) == Some(Instruction::Exit)
+    }
+
+    /// Whether running on can't change anything until a key is pressed: the program has halted,
+    /// or is waiting on FX0A with none of `keys` down, and the timers have run out
+    pub fn waiting_for_input(&self, keys: [bool; 16]) -> bool {
+        let waiting_for_key = !keys.contains(&true)
+            && matches!(self.instruction_at(self.pc).and_then(|raw| self.platform.decode(raw)), Some(Instruction::GetKey { .. }));
+        (self.halted() || waiting_for_key) && self.delay_timer == 0 && self.sound_timer == 0
+    }
+
     pub fn pc_inbounds(&self) -> bool {
-        self.pc >= INIT_INDEX && self.pc < 4095
+        self.pc >= INIT_INDEX && self.pc < MEMORY_SIZE - 1
     }
 
     pub fn should_beep(&self) -> bool {
         self.sound_timer > 0
     }
 
+    /// What the beeper should be doing now
+    pub fn beep_state(&self) -> Beep {
+        match self.sound_timer {
+            0 => Beep::Stopped,
+            _ => Beep::Started(self.audio_pattern.map(|bits| AudioPattern { bits, pitch: self.pitch })),
+        }
+    }
+
+    /// Reports a change to what the beeper should do in `beep`
+    fn check_beep(&mut self) {
+        let state = self.beep_state();
+        if state != self.last_beep {
+            self.beep = Some(state);
+            self.last_beep = state;
+        }
+    }
+
+    /// Loads the program where the platform starts running, which is only past 0x200 for CHIP-8X
     pub fn read_program(&mut self, read: impl std::io::Read) -> Result<usize, std::io::Error> {
-        let mut slice = &mut self.memory[INIT_INDEX .. ];
-        let mut take = read.take(slice.len() as u64);
-        take.read(&mut slice)
+        let load = self.platform.load_address();
+        let mut rom = Vec::new();
+        read.take(self.platform.max_rom_size() as u64).read_to_end(&mut rom)?;
+        let len = rom.len();
+        // Only MEGA-CHIP's ROMs can be too big for 4K. Its memory grows to a power of two that fits,
+        // rather than always being all 16M its I can reach, which would make every snapshot that big.
+        if load + len > self.memory.len() {
+            let size = (load + len).next_power_of_two();
+            self.memory.resize(size, 0);
+            self.written_by.resize(size, None);
+        }
+        self.memory[load..load + len].copy_from_slice(&rom);
+        // Hires ROMs jump to VIP machine code at 0x260 that sets up the display, which `new` has done already
+        if self.platform == Platform::Hires && starts_two_page(&self.memory[INIT_INDEX..]) {
+            self.pc = TWO_PAGE_START;
+        }
+        let mut code = vec![false; self.memory.len()];
+        mark_code(&self.memory, self.pc, &mut code);
+        self.code = Some(code);
+        self.xrefs = Xrefs::analyze(&disassemble(&self.memory[load..], self.platform));
+        Ok(len)
+    }
+
+    /// Writes an instruction over whatever is at `address`, e.g. from the debugger,
+    /// and counts anything it makes reachable as code
+    pub fn patch(&mut self, address: usize, instruction: &Instruction) {
+        self.memory[address..address + 2].copy_from_slice(&encode(instruction).to_be_bytes());
+        if let Some(code) = &mut self.code {
+            code[address] = false;
+            mark_code(&self.memory, address, code);
+        }
+    }
+
+    /// Encodes `instructions` and loads them like a ROM, returning how many bytes were written
+    pub fn load_instructions(&mut self, instructions: &[Instruction]) -> usize {
+        self.read_program(&assemble(instructions)[..]).expect("Reading from memory can't fail")
+    }
+
+    /// Warns (once per address) when about to execute bytes that look like data:
+    /// either static analysis didn't reach them, or the program stored them with FX33/FX55
+    fn check_executing_data(&mut self) {
+        let pc = self.pc;
+        if let Some(writer) = self.written_by[pc].or(self.written_by[pc + 1]) {
+            self.diagnose(Diagnostic::ExecutedWrittenData, self.cycles, pc, || {
+                format!("Executing {:#05x}, which was written as data by the instruction at {:#05x}", pc, writer)
+            });
+        }
+        if let Some(code) = &mut self.code {
+            if !code[pc] {
+                // Presumably reached by a computed jump; treat whatever follows as code too, so we only warn once
+                mark_code(&self.memory, pc, code);
+                self.diagnose(Diagnostic::ExecutedUnreachable, self.cycles, pc, || {
+                    format!("Executing {:#05x}, which static analysis classified as data", pc)
+                });
+            }
+        }
+    }
+
+    /// Warns (once per address) when about to execute memory marked no-execute
+    fn check_no_execute(&mut self) {
+        let pc = self.pc;
+        if self.regions.iter().any(|region| region.no_execute && region.range.contains(&pc)) {
+            self.diagnose(Diagnostic::NoExecute, self.cycles, pc, || format!("Executing {:#05x}, which is marked no-execute", pc));
+        }
+    }
+
+    /// Steps over the next instruction for a skip that was taken. On XO-CHIP that might be F000 and
+    /// its address, and on MEGA-CHIP, 01NN and its.
+    fn skip(&mut self) {
+        let next = self.instruction_at(self.pc).unwrap_or_default();
+        let long = match self.platform {
+            Platform::XoChip => next == 0xf000,
+            Platform::MegaChip => next >> 8 == 0x01,
+            _ => false,
+        };
+        self.pc += if long { 4 } else { 2 };
+    }
+
+    /// Scrolls the selected planes, or MEGA-CHIP's screen while it's on
+    fn scroll(&mut self, columns: isize, rows: isize) {
+        match &mut self.display.mega {
+            Some(mega) => mega.scroll(columns, rows),
+            None if rows != 0 => self.display.scroll_vertically(rows, self.planes),
+            None => self.display.scroll_sideways(columns, self.planes),
+        }
+    }
+
+    /// DXYN while MEGA-CHIP's screen is on: a sprite of palette indexes at the sprite size 03NN
+    /// and 04NN set, with N unused. VF says whether it collided.
+    fn draw_mega(&mut self, x_r: U4, y_r: U4) -> Cycle {
+        let (x, y) = (self.registers[x_r as usize].0 as usize, self.registers[y_r as usize].0 as usize);
+        let index = self.index_register.0 as usize;
+        // Sprites can be 64K, so one read stands for the lot in the xrefs, and the rest go straight to memory
+        self.read_memory(index);
+        let memory = &self.memory;
+        let sprite = |i: usize| memory[(index + i) % memory.len()];
+        let collided = self.display.mega.as_mut().is_some_and(|mega| mega.draw(x, y, sprite, &self.mega));
+        self.registers[0xf] = Wrapping(collided as u8);
+        Cycle::RedrawRequested
+    }
+
+    /// Where BNNN goes: NNN plus V0, or plus VX with `Quirks::jump_with_vx`
+    pub fn offset_target(&self, dest: U12) -> usize {
+        let register = if self.quirks.jump_with_vx { (dest >> 8) as usize } else { 0 };
+        dest as usize + self.registers[register].0 as usize
+    }
+
+    /// Applies the `odd_pc` policy to the jump or call just executed going to `target`
+    fn check_alignment(&mut self, target: usize) {
+        if target.is_multiple_of(2) {
+            return;
+        }
+        let pc = self.pc - 2;
+        match self.odd_pc {
+            Policy::Allow => {},
+            Policy::Warn => self.diagnose_executed(Diagnostic::OddJump, |pc| {
+                format!("Instruction at {:#05x} went to the odd address {:#05x}, so the instructions after are a byte out", pc, target)
+            }),
+            Policy::Error => self.crash(Diagnostic::OddJump, self.cycles.saturating_sub(1), pc, format!("Instruction at {:#05x} went to the odd address {:#05x}", pc, target)),
+        }
+    }
+
+    /// Wraps an address past the end of memory back to the start, as most interpreters do
+    fn wrap_address(&mut self, address: usize) -> usize {
+        if address >= self.memory.len() {
+            self.diagnose_executed(Diagnostic::MemoryWrapped, |pc| {
+                format!("Instruction at {:#05x} accessed {:#05x}, past the end of memory", pc, address)
+            });
+        }
+        address % self.memory.len()
+    }
+
+    /// Reads memory on behalf of the instruction just executed
+    fn read_memory(&mut self, address: usize) -> u8 {
+        let address = self.wrap_address(address);
+        self.xrefs.add(address, Xref { from: self.pc - 2, access: Access::Read });
+        self.log_access(address, Access::Read);
+        self.memory[address]
+    }
+
+    /// Writes memory on behalf of the instruction just executed, unless the address is read-only
+    fn write_memory(&mut self, address: usize, value: u8) {
+        let pc = self.pc - 2;
+        let address = self.wrap_address(address);
+        if self.regions.iter().any(|region| region.read_only && region.range.contains(&address)) {
+            self.diagnose_executed(Diagnostic::ReadOnlyWrite, |pc| {
+                format!("Instruction at {:#05x} tried to write to {:#05x}, which is read-only", pc, address)
+            });
+            return;
+        }
+        self.memory[address] = value;
+        self.written_by[address] = Some(pc as u16);
+        self.xrefs.add(address, Xref { from: pc, access: Access::Write });
+        self.log_access(address, Access::Write);
+    }
+
+    fn log_access(&mut self, address: usize, access: Access) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(MemoryAccess { cycle: self.cycles - 1, pc: self.pc - 2, address, access, value: self.memory[address] });
+        }
+    }
+
+    /// Called at the end of each 60Hz frame. Warns (once per address) about the draw
+    /// responsible for most of a frame's draws when there are too many to be intentional.
+    fn check_draw_storm(&mut self) {
+        let draws: u32 = self.frame_draws.values().sum();
+        self.draw_storm = draws > DRAW_STORM_THRESHOLD;
+        if self.draw_storm {
+            let (&pc, &pc_draws) = self.frame_draws.iter().max_by_key(|&(_, &count)| count).unwrap();
+            // Noticed as the frame ends, so the cycle is the one after the last draw
+            self.diagnose(Diagnostic::DrawStorm, self.cycles, pc, || {
+                format!("{} draws in one frame, {} of them by the instruction at {:#05x}", draws, pc_draws, pc)
+            });
+        }
+        self.frame_draws.clear();
+    }
+
+    /// The call stack, innermost frame first. Each frame names the subroutine it's in
+    /// (from the symbols, or `sub_XXX`) and where it is, and each caller shows what it will resume with.
+    pub fn stack_trace(&self) -> Vec<String> {
+        let describe = |address: usize| match self.instruction_at(address) {
+            Some(raw) => match self.platform.decode(raw) {
+                Some(instruction) => format!("{:?}", instruction),
+                None => format!("{:#06x}", raw),
+            },
+            None => String::from("out of memory"),
+        };
+        // Each return address follows the call that entered the frame inside it
+        let callee = |return_address: usize| {
+            match return_address.checked_sub(2).and_then(|call| self.instruction_at(call)).and_then(|raw| self.platform.decode(raw)) {
+                Some(Instruction::CallSubroutine { dest }) => self.symbols.name(dest as usize),
+                _ => String::from("???"),
+            }
+        };
+        let mut trace = Vec::new();
+        let mut location = self.pc;
+        let mut resumes_at = None;
+        for depth in 0..=self.stack.len() {
+            let function = match self.stack.len().checked_sub(depth + 1) {
+                Some(i) => callee(self.stack[i]),
+                None if self.symbols.has_name(INIT_INDEX) => self.symbols.name(INIT_INDEX),
+                None => String::from("start"),
+            };
+            let mut frame = format!("#{} {:#05x} in {}", depth, location, function);
+            if let Some(return_address) = resumes_at {
+                frame += &format!(", returns to {:#05x}: {}", return_address, describe(return_address));
+            }
+            trace.push(frame);
+            if let Some(i) = self.stack.len().checked_sub(depth + 1) {
+                resumes_at = Some(self.stack[i]);
+                location = self.stack[i].saturating_sub(2);
+            }
+        }
+        trace
+    }
+
+    /// The address of each subroutine being run, outermost first, from the calls the return addresses follow
+    pub fn call_chain(&self) -> Vec<usize> {
+        self.stack.iter()
+            .filter_map(|&return_address| match return_address.checked_sub(2).and_then(|call| self.instruction_at(call)).and_then(|raw| self.platform.decode(raw)) {
+                Some(Instruction::CallSubroutine { dest }) => Some(dest as usize),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Reports a diagnostic at its usual severity, logging it the first time it's from `pc`.
+    /// `cycle` is the count before the responsible instruction ran.
+    fn diagnose(&mut self, diagnostic: Diagnostic, cycle: u64, pc: usize, message: impl FnOnce() -> String) {
+        let severity = diagnostic.severity();
+        if self.diagnostics.report(diagnostic, severity, cycle, pc, message) {
+            let level = match severity {
+                Severity::Info => log::Level::Info,
+                Severity::Warning => log::Level::Warn,
+                Severity::Error => log::Level::Error,
+            };
+            log::log!(level, "{}", self.diagnostics.reports().last().expect("Just reported").message);
+        }
+    }
+
+    /// `diagnose` for the instruction just executed, which `message` is given the address of
+    fn diagnose_executed(&mut self, diagnostic: Diagnostic, message: impl FnOnce(usize) -> String) {
+        let pc = self.pc - 2;
+        // Tests execute instructions without cycling
+        let cycle = self.cycles.saturating_sub(1);
+        self.diagnose(diagnostic, cycle, pc, || message(pc));
+    }
+
+    /// Reports `message` as an error, so it's there for whoever catches the panic, then panics with
+    /// it and the stack trace
+    fn crash(&mut self, diagnostic: Diagnostic, cycle: u64, pc: usize, message: String) -> ! {
+        let panic = format!("{}\nCall stack:\n{}", message, self.stack_trace().join("\n"));
+        self.diagnostics.report(diagnostic, Severity::Error, cycle, pc, || message);
+        panic!("{}", panic)
+    }
+
+    /// The state is written as chunks (see `snapshot::write_chunk`), fixed size ones first
+    /// so that unchanged memory stays at the same offsets between snapshots:
+    /// * `PLAT` - the platform's name, e.g. `schip`, which the chunks after it are read for
+    /// * `QRKS` - the quirks as `Quirks::parse` reads them, naming every one
+    /// * `MEM ` - all of memory, 4096 bytes but for MEGA-CHIP
+    /// * `DISP` - width and height as u16, then the screen packed 8 pixels per byte
+    /// * `CORE` - registers, index, pc, delay, sound, stack length, stack
+    /// * `RPL ` - the 8 RPL flags
+    /// * `PLAN` - the selected planes, then the second plane packed like `DISP`. Left out when it's
+    ///   all still the default, which it always is for anything but XO-CHIP.
+    /// * `AUDI` - the pitch, then the audio pattern if one's been loaded. Left out at the default, like `PLAN`.
+    /// * `COLR` - CHIP-8X's background, then its foreground colours row by row. Left out for other platforms.
+    /// * `MEGA` - the top byte of I, MEGA-CHIP's registers, then its screen's buffers while it's on,
+    ///   as `mega::snapshot` writes them. Left out for other platforms.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut bytes = Vec::with_capacity(self.memory.len() + HIRES_WIDTH * HIRES_HEIGHT / 8 + 256);
+        write_chunk(&mut bytes, b"PLAT", self.platform.to_string().as_bytes());
+        write_chunk(&mut bytes, b"QRKS", self.quirks.to_string().as_bytes());
+        write_chunk(&mut bytes, b"MEM ", &self.memory);
+        let mut display = Vec::new();
+        display.extend_from_slice(&(self.display.width() as u16).to_be_bytes());
+        display.extend_from_slice(&(self.display.height() as u16).to_be_bytes());
+        display.extend(pack_screen(&self.display));
+        write_chunk(&mut bytes, b"DISP", &display);
+        let mut core = Vec::new();
+        core.extend(self.registers.iter().map(|reg| reg.0));
+        core.extend_from_slice(&(self.index_register.0 as u16).to_be_bytes());
+        core.extend_from_slice(&(self.pc as u16).to_be_bytes());
+        core.push(self.delay_timer);
+        core.push(self.sound_timer);
+        core.push(self.stack.len() as u8);
+        for &addr in &self.stack {
+            core.extend_from_slice(&(addr as u16).to_be_bytes());
+        }
+        write_chunk(&mut bytes, b"CORE", &core);
+        write_chunk(&mut bytes, b"RPL ", &self.rpl);
+        if self.planes != 1 || self.display.plane(1).contains(&true) {
+            let mut planes = vec![self.planes];
+            planes.extend(pack_pixels(self.display.plane(1), self.display.width()));
+            write_chunk(&mut bytes, b"PLAN", &planes);
+        }
+        if self.pitch != DEFAULT_PITCH || self.audio_pattern.is_some() {
+            let mut audio = vec![self.pitch];
+            audio.extend(self.audio_pattern.iter().flatten());
+            write_chunk(&mut bytes, b"AUDI", &audio);
+        }
+        if let Some(colors) = &self.display.colors {
+            let mut chunk = vec![colors.background];
+            chunk.extend_from_slice(&colors.foreground);
+            write_chunk(&mut bytes, b"COLR", &chunk);
+        }
+        if self.quirks.vip_timing {
+            write_chunk(&mut bytes, b"VIPT", &self.vip_clock.balance.to_be_bytes());
+        }
+        if self.platform == Platform::MegaChip {
+            let mut chunk = vec![(self.index_register.0 >> 16) as u8];
+            chunk.extend(mega::snapshot(&self.mega, self.display.mega.as_ref()));
+            write_chunk(&mut bytes, b"MEGA", &chunk);
+        }
+        Snapshot { bytes, rng: self.rng.clone() }
+    }
+
+    /// Restores every chunk it understands; unknown or malformed chunks are skipped.
+    /// Snapshots without `PLAT` or `QRKS` keep the machine's current platform and quirks.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        // Snapshots without a `PLAN` or `AUDI` chunk had these at the default
+        self.planes = 1;
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.mega = MegaRegisters::default();
+        self.vip_clock = VipClock::default();
+        for (tag, data) in read_chunks(&snapshot.bytes) {
+            match &tag {
+                b"PLAT" => match String::from_utf8_lossy(data).parse() {
+                    Ok(platform) => self.platform = platform,
+                    Err(e) => log::warn!("Skipping platform in snapshot: {}", e),
+                },
+                b"QRKS" => self.quirks = Quirks::parse(&String::from_utf8_lossy(data), self.quirks.clone()),
+                b"MEM " if data.len() == self.memory.len() => self.memory.copy_from_slice(data),
+                // MEGA-CHIP's memory is as big as its ROM needed, which a fresh machine doesn't know yet,
+                // and after `PLAT` a machine that was running something else may have the wrong size
+                b"MEM " if data.len() == MEMORY_SIZE
+                    || self.platform == Platform::MegaChip
+                        && data.len().is_power_of_two()
+                        && (MEMORY_SIZE..=MEGA_MEMORY_SIZE).contains(&data.len()) =>
+                {
+                    self.memory.clear();
+                    self.memory.extend_from_slice(data);
+                    self.written_by.resize(data.len(), None);
+                },
+                b"DISP" if data.len() >= 4 => {
+                    let width = u16::from_be_bytes([data[0], data[1]]) as usize;
+                    let height = u16::from_be_bytes([data[2], data[3]]) as usize;
+                    if Screen::valid_size(width, height) && data.len() - 4 == width * height / 8 {
+                        self.display = unpack_screen(&data[4..], width, height);
+                    } else {
+                        log::warn!("Skipping {}x{} display in snapshot", width, height);
+                    }
+                },
+                b"CORE" if data.len() >= 23 && data.len() == 23 + data[22] as usize * 2 => {
+                    for (reg, &value) in self.registers.iter_mut().zip(&data[0..16]) {
+                        *reg = Wrapping(value);
+                    }
+                    self.index_register = Wrapping(u16::from_be_bytes([data[16], data[17]]) as u32);
+                    self.pc = u16::from_be_bytes([data[18], data[19]]) as usize;
+                    self.delay_timer = data[20];
+                    self.sound_timer = data[21];
+                    self.stack = data[23..]
+                        .chunks(2)
+                        .map(|addr| u16::from_be_bytes([addr[0], addr[1]]) as usize)
+                        .collect();
+                },
+                b"RPL " if data.len() == RPL_FLAGS => self.rpl.copy_from_slice(data),
+                // After `DISP`, which sets the size and blanks this plane
+                b"PLAN" if data.len() == 1 + self.display.width() * self.display.height() / 8 => {
+                    self.planes = data[0];
+                    let width = self.display.width();
+                    unpack_pixels(&data[1..], width, self.display.plane_mut(1));
+                },
+                b"AUDI" if data.len() == 1 || data.len() == 17 => {
+                    self.pitch = data[0];
+                    self.audio_pattern = data[1..].try_into().ok();
+                },
+                // After `DISP` too, which leaves the screen without colours
+                b"COLR" if data.len() == 1 + self.display.width() / ZONE_WIDTH * self.display.height() => {
+                    self.display.colors = Some(Colors { background: data[0], foreground: data[1..].to_vec() });
+                },
+                // After `DISP` and `CORE`, which leave the screen without MEGA-CHIP's and I with 16 bits
+                b"MEGA" if !data.is_empty() => match mega::restore(&data[1..]) {
+                    Some((registers, screen)) if screen.is_none() || self.display.size() == (MEGA_WIDTH, MEGA_HEIGHT) => {
+                        self.index_register.0 |= (data[0] as u32) << 16;
+                        self.mega = registers;
+                        self.display.mega = screen;
+                    },
+                    _ => log::warn!("Skipping MEGA-CHIP state in snapshot"),
+                },
+                b"VIPT" => match data.try_into() {
+                    Ok(balance) => self.vip_clock = VipClock::with_balance(i32::from_be_bytes(balance)),
+                    Err(_) => log::warn!("Skipping VIP timing in snapshot"),
+                },
+                _ => log::debug!("Skipping chunk {} in snapshot", String::from_utf8_lossy(&tag)),
+            }
+        }
+        self.rng = snapshot.rng.clone();
+    }
+
+    /// Whether the pixel is lit, with anything off screen unlit
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.display.pixel(x, y)
+    }
+
+    pub fn count_lit_pixels(&self) -> usize {
+        let (width, height) = self.display.size();
+        (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).filter(|&(x, y)| self.pixel(x, y)).count()
+    }
+
+    /// FNV-1a of the pixels in `rect` as a byte each, row by row, so scripts can recognise
+    /// part of the screen (a score, a menu) without comparing whole frames
+    pub fn region_hash(&self, rect: Rect) -> u64 {
+        let pixels: Vec<u8> = (rect.y..rect.y + rect.height)
+            .flat_map(|y| (rect.x..rect.x + rect.width).map(move |x| (x, y)))
+            .map(|(x, y)| self.pixel(x, y) as u8)
+            .collect();
+        fnv1a(&pixels)
     }
 
     pub fn show_display(&self) -> impl Iterator<Item = String> + '_ {
-        self.display
-            .map(|row| 
-                row.map(|pixel| if pixel { 'Q' } else { ' ' })
-                    .iter()
-                    .collect()
-            ).into_iter()
+        let (width, height) = self.display.size();
+        (0..height).map(move |y| (0..width).map(|x| if self.pixel(x, y) { 'Q' } else { ' ' }).collect())
     }
 
     pub fn show_registers(&self) -> impl Iterator<Item = String> + '_ {
@@ -163,7 +1140,7 @@ impl Chip8 {
                 } else {
                     format!("{}", i)
                 };
-                format!("{}: {:#04x} => {:?}", index, raw, decode(raw))
+                format!("{}: {:#04x} => {:?}", index, raw, self.platform.decode(raw))
             })
     }
 
@@ -184,23 +1161,19 @@ impl Chip8 {
             for (&width, section) in widths.iter().zip(sections.iter()) {
                 print!("{:width$}|", section.title, width=width);
             }
-            println!("");
+            println!();
             let longest_section = sections.iter().map(|s| s.contents.len()).max().unwrap();
             for i in 0..longest_section {
                 for (&width, section) in widths.iter().zip(sections.iter()) {
                     print!("{:width$}|", 
-                        section.contents.iter()
-                            .nth(i).unwrap_or(&String::from("")),
+                        section.contents
+                            .get(i).unwrap_or(&String::from("")),
                         width=width
                     );
                 }
-                println!("");
+                println!();
             }
         }
-        let display = Section { 
-            title: String::from("Display"), 
-            contents: self.show_display().collect()
-        };
         let reg = Section { 
             title: String::from("Registers"), 
             contents: self.show_registers().collect()
@@ -213,62 +1186,226 @@ impl Chip8 {
             title: String::from("Stack"), 
             contents: self.show_stack().collect()
         };
-        print_screen(&self.display);
+        for row in self.show_display() {
+            println!("{}", row);
+        }
         side_by_side(&[reg, prog, stack]);
     }
     
     pub fn print_program(&self) {
         log::debug!("====Program=============================");
-        for i in (INIT_INDEX..4095).step_by(2) {
-            let val1 = self.memory[i];
-            let val2 = self.memory[i+1];
-            if val1 == 0 && val2 == 0 {
-                break;
-            }
+        let end = INIT_INDEX + trimmed_len(&self.memory[INIT_INDEX..]);
+        for i in (INIT_INDEX..min(end, 4095)).step_by(2) {
             let raw = self.memory[i + 1] as u16 | (self.memory[i] as u16) << 8;
             let index = if self.pc == i {
                 format!("[{}]", i)
             } else {
                 format!("{}", i)
             };
-            log::debug!("{}: {:#04x} => {:?}", index, raw, decode(raw));
+            log::debug!("{}: {:#04x} => {:?}", index, raw, self.platform.decode(raw));
+        }
+    }
+
+    /// The key whose number is in the register, for EX9E/EXA1. Only the low nibble is used, like the VIP did.
+    fn key_in(&mut self, register: U4) -> usize {
+        let key = self.registers[register as usize].0;
+        if key > 0xf {
+            self.diagnose_executed(Diagnostic::KeyOutOfRange, |pc| {
+                format!("Instruction at {:#05x} checked the key {:#x}, using {:#x}", pc, key, key & 0xf)
+            });
+        }
+        (key & 0xf) as usize
+    }
+
+    /// Done after the operation, so the flag wins when VF is the destination, as on the VIP
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.registers[0xf] = Wrapping(0);
         }
     }
 
     pub fn execute(&mut self, instruction: Instruction, key_pressed: [bool; 16]) -> Cycle {
         match instruction {
             Instruction::ClearScreen => {
-                self.display = BLANK_SCREEN;
+                if self.breakpoints.clear {
+                    self.hit = Some(Break::Clear { pc: self.pc - 2 });
+                }
+                match &mut self.display.mega {
+                    Some(mega) => mega.present(),
+                    None => self.display.clear(self.planes),
+                }
+                return Cycle::RedrawRequested;
+            },
+            // Switching clears the screen (every plane), as Octo does; the HP 48 kept whatever was drawn
+            Instruction::Lores => {
+                self.display = Screen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+                return Cycle::RedrawRequested;
+            },
+            Instruction::Hires => {
+                self.display = Screen::new(HIRES_WIDTH, HIRES_HEIGHT);
+                return Cycle::RedrawRequested;
+            },
+            // By the current screen's pixels, as Octo does. SCHIP 1.1 scrolled lores by half as far.
+            Instruction::ScrollDown { rows } => {
+                self.scroll(0, rows as isize);
+                return Cycle::RedrawRequested;
+            },
+            Instruction::ScrollUp { rows } => {
+                self.scroll(0, -(rows as isize));
+                return Cycle::RedrawRequested;
+            },
+            Instruction::ScrollRight => {
+                self.scroll(4, 0);
+                return Cycle::RedrawRequested;
+            },
+            Instruction::ScrollLeft => {
+                self.scroll(-4, 0);
+                return Cycle::RedrawRequested;
+            },
+            // Only the first two bits mean anything, since there are only two planes
+            Instruction::SelectPlanes { planes } => {
+                self.planes = planes & 0b11;
+            },
+            Instruction::LoadAudio => {
+                let mut pattern = [0; 16];
+                for (i, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read_memory(self.index_register.0 as usize + i);
+                }
+                self.audio_pattern = Some(pattern);
+            },
+            Instruction::SetPitch { register } => {
+                self.pitch = self.registers[register as usize].0;
+            },
+            Instruction::CycleBackground => {
+                if let Some(colors) = &mut self.display.colors {
+                    colors.background = (colors.background + 1) % CHIP8X_BACKGROUNDS.len() as u8;
+                }
+                return Cycle::RedrawRequested;
+            },
+            Instruction::AddNibbles { register1, register2 } => {
+                let (x, y) = (self.registers[register1 as usize].0, self.registers[register2 as usize].0);
+                self.registers[register1 as usize] = Wrapping(((x & 0x77) + (y & 0x77)) & 0x77);
+            },
+            Instruction::ColorZones { x_r, y_r } => {
+                let columns = self.registers[x_r as usize].0;
+                let rows = self.registers[(x_r as usize + 1) & 0xf].0;
+                let color = self.registers[y_r as usize].0 & 7;
+                let width = self.display.width / ZONE_WIDTH;
+                if let Some(colors) = &mut self.display.colors {
+                    for zone_row in (rows & 7)..=(rows >> 4 & 7) {
+                        for row in zone_row as usize * ZONE_HEIGHT..(zone_row as usize + 1) * ZONE_HEIGHT {
+                            for column in (columns & 7)..=(columns >> 4 & 7) {
+                                colors.foreground[row * width + column as usize] = color;
+                            }
+                        }
+                    }
+                }
+                return Cycle::RedrawRequested;
+            },
+            Instruction::ColorRows { x_r, y_r, rows } => {
+                let column = self.registers[x_r as usize].0 as usize / ZONE_WIDTH;
+                let top = self.registers[(x_r as usize + 1) & 0xf].0 as usize;
+                let color = self.registers[y_r as usize].0 & 7;
+                let (width, height) = (self.display.width / ZONE_WIDTH, self.display.height);
+                if let Some(colors) = &mut self.display.colors {
+                    // Off the bottom of the screen is cut off, like sprites
+                    for row in (top..top + rows as usize).filter(|&row| row < height) {
+                        colors.foreground[row * width + column % width] = color;
+                    }
+                }
                 return Cycle::RedrawRequested;
             },
+            Instruction::SkipPressed2 { register } => {
+                if self.second_keypad[self.key_in(register)] {
+                    self.skip();
+                }
+            },
+            Instruction::SkipNotPressed2 { register } => {
+                if !self.second_keypad[self.key_in(register)] {
+                    self.skip();
+                }
+            },
+            Instruction::MegaOff => {
+                self.display = Screen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+                return Cycle::RedrawRequested;
+            },
+            Instruction::MegaOn => {
+                self.display = Screen::mega();
+                return Cycle::RedrawRequested;
+            },
+            Instruction::LoadPalette { count } => {
+                let index = self.index_register.0 as usize;
+                for color in 1..=count as usize {
+                    let argb: [u8; 4] = std::array::from_fn(|i| self.read_memory(index + (color - 1) * 4 + i));
+                    self.mega.palette[color] = argb;
+                }
+            },
+            Instruction::SpriteWidth { width } => {
+                self.mega.sprite_width = width;
+            },
+            Instruction::SpriteHeight { height } => {
+                self.mega.sprite_height = height;
+            },
+            Instruction::Alpha { alpha } => {
+                self.mega.alpha = alpha;
+            },
+            // There's only the buzzer, so digitised sound isn't played
+            Instruction::PlaySample { .. } | Instruction::StopSample => {},
+            Instruction::BlendMode { mode } => {
+                self.mega.blend = Blend::from_mode(mode);
+            },
+            Instruction::CollisionColor { color } => {
+                self.mega.collision_color = color;
+            },
+            // Stays on the 00FD, so the machine is halted for anything that runs it on
+            Instruction::Exit => {
+                self.pc -= 2;
+                return Cycle::Exit;
+            },
             Instruction::Return => {
-                self.pc = self.stack.pop().expect("Program tried to return but stack was empty.");
+                self.pc = match self.stack.pop() {
+                    Some(pc) => pc,
+                    None => self.crash(Diagnostic::ReturnWithEmptyStack, self.cycles.saturating_sub(1), self.pc - 2, "Program tried to return but stack was empty.".to_string()),
+                };
             },
             Instruction::Jump { dest } => {
+                self.check_alignment(dest as usize);
                 self.pc = dest as usize;
             },
+            Instruction::JumpOffset { dest } => {
+                let from = self.pc - 2;
+                let target = self.offset_target(dest);
+                self.check_alignment(target);
+                self.pc = target;
+                self.xrefs.add(self.pc, Xref { from, access: Access::Jump });
+            },
             Instruction::CallSubroutine { dest} => {
+                self.check_alignment(dest as usize);
+                if self.stack.len() >= MAX_CALL_DEPTH {
+                    let message = format!("Program called more than {} subroutines deep.", MAX_CALL_DEPTH);
+                    self.crash(Diagnostic::StackOverflow, self.cycles.saturating_sub(1), self.pc - 2, message);
+                }
                 self.stack.push(self.pc);
                 self.pc = dest as usize;
             },
             Instruction::SkipEQ { register, value} => {
                 if self.registers[register as usize].0 == value {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             Instruction::SkipNEQ { register, value} => {
                 if self.registers[register as usize].0 != value {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             Instruction::SkipEQR { register1, register2} => {
                 if self.registers[register1 as usize] == self.registers[register2 as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             Instruction::SkipNEQR { register1, register2} => {
                 if self.registers[register1 as usize] != self.registers[register2 as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             Instruction::SetRegister { register, value } => {
@@ -282,12 +1419,15 @@ impl Chip8 {
             },
             Instruction::BinaryOr { register1, register2 } => {
                 self.registers[register1 as usize] |= self.registers[register2 as usize];
+                self.reset_vf();
             },
             Instruction::BinaryAnd { register1, register2 } => {
                 self.registers[register1 as usize] &= self.registers[register2 as usize];
+                self.reset_vf();
             },
             Instruction::BinaryXor { register1, register2 } => {
                 self.registers[register1 as usize] ^= self.registers[register2 as usize];
+                self.reset_vf();
             },
             Instruction::Add { register1, register2 } => {
                 let saved_val = self.registers[register1 as usize];
@@ -295,63 +1435,121 @@ impl Chip8 {
                 // handle overflow
                 self.registers[0xf] = Wrapping(if 
                     self.registers[register1 as usize] < saved_val
-                    || self.registers[register1 as usize] < self.registers[register1 as usize] 
                     { 1 } else { 0 }
                 )
             },
             Instruction::SubtractForward { register1, register2 } => {
-                self.registers[register1 as usize] -= self.registers[register2 as usize];
-                self.registers[0xf] = Wrapping(
-                    if self.registers[register1 as usize] < self.registers[register2 as usize] 
-                    { 0 } else { 1 }
-                )
+                let (x, y) = (self.registers[register1 as usize], self.registers[register2 as usize]);
+                self.registers[register1 as usize] = x - y;
+                // Set last, from the operands as they were, so the flag wins when VF is one of them
+                self.registers[0xf] = Wrapping((x >= y) as u8);
             },
             Instruction::SubtractBackward { register1, register2 } => {
-                self.registers[register1 as usize] = self.registers[register2 as usize] - self.registers[register1 as usize];
-                self.registers[0xf] = Wrapping(
-                    if self.registers[register1 as usize] > self.registers[register2 as usize] 
-                    { 0 } else { 1 }
-                )
+                let (x, y) = (self.registers[register1 as usize], self.registers[register2 as usize]);
+                self.registers[register1 as usize] = y - x;
+                self.registers[0xf] = Wrapping((y >= x) as u8);
             },
-            Instruction::ShiftRight { register1, register2: _ } => {
-                self.registers[register1 as usize] >>= 1; // different between chip and super-chip
+            Instruction::ShiftRight { register1, register2 } => {
+                let source = if self.quirks.shift_uses_vy { register2 } else { register1 };
+                let value = self.registers[source as usize];
+                self.registers[register1 as usize] = value >> 1;
+                // Set last, so the flag wins when shifting into VF
+                self.registers[0xf] = value & Wrapping(1);
             },
-            Instruction::ShiftLeft { register1, register2: _ } => {
-                self.registers[register1 as usize] <<= 1; // different between chip and super-chip
+            Instruction::ShiftLeft { register1, register2 } => {
+                let source = if self.quirks.shift_uses_vy { register2 } else { register1 };
+                let value = self.registers[source as usize];
+                self.registers[register1 as usize] = value << 1;
+                self.registers[0xf] = value >> 7;
             },
             Instruction::SetIndexRegister { value } => {
+                self.index_register = Wrapping(value as u32);
+            },
+            Instruction::LongIndex => {
+                let value = (self.read_memory(self.pc) as u32) << 8 | self.read_memory(self.pc + 1) as u32;
                 self.index_register = Wrapping(value);
+                self.pc += 2;
+            },
+            Instruction::MegaIndex { high } => {
+                let value = (self.read_memory(self.pc) as u32) << 8 | self.read_memory(self.pc + 1) as u32;
+                self.index_register = Wrapping((high as u32) << 16 | value);
+                self.pc += 2;
             },
             Instruction::Random { register, value } => {
                 let num: u8 = self.rng.next_u32() as u8;
                 self.registers[register as usize].0 = num & value;
             },
-            Instruction::Draw { x_r, y_r, height } => { // TODO: problem is probably here
-                let x = self.registers[x_r as usize].0 % SCREEN_WIDTH as u8;
-                let y = self.registers[y_r as usize].0 % SCREEN_HEIGHT as u8;
-                for row_index in 0..height {
-                    let mem_location = self.index_register.0 + row_index as u16;
-                    let sprite_row = self.memory[mem_location as usize];
-                    for bit_pos in 0..8 {
-                        if ((1_u8 << bit_pos) & sprite_row) != 0 {
-                            let pix_x = x + 7 - bit_pos;
-                            let pix_y = y + row_index;
-                            if pix_x < SCREEN_WIDTH as u8 && pix_y < SCREEN_HEIGHT as u8 {
-                                self.display[pix_y as usize][pix_x as usize] ^= true;
+            Instruction::Draw { x_r, y_r, height } => {
+                if self.quirks.display_wait {
+                    // Try again until the next frame, like FX0A waits for a key
+                    let frame = self.timestamp().frame();
+                    if self.last_draw_frame == Some(frame) {
+                        self.pc -= 2;
+                        return Cycle::Complete;
+                    }
+                    self.last_draw_frame = Some(frame);
+                }
+                *self.frame_draws.entry(self.pc - 2).or_insert(0) += 1;
+                if self.display.mega.is_some() {
+                    return self.draw_mega(x_r, y_r);
+                }
+                let (screen_width, screen_height) = self.display.size();
+                self.draw_order.start_draw(self.timestamp().frame(), screen_width * screen_height);
+                let x = self.registers[x_r as usize].0 as usize % screen_width;
+                let y = self.registers[y_r as usize].0 as usize % screen_height;
+                // DXY0 draws a 16x16 sprite, two bytes a row, in hires and on XO-CHIP
+                let wide = height == 0 && (self.display.hires() || self.platform == Platform::XoChip);
+                let (row_bytes, rows) = if wide { (2, 16) } else { (1, height as usize) };
+                // VF is set if the sprite erased any pixels, which is how games detect collisions
+                let mut collided = vec![false; rows];
+                let clipped_rows = if self.quirks.clip_sprites { (y + rows).saturating_sub(screen_height) as u8 } else { 0 };
+                // Each selected plane gets its own sprite, one after the other from I
+                let planes: Vec<usize> = (0..PLANES).filter(|plane| self.planes & 1 << plane != 0).collect();
+                for (n, plane) in planes.into_iter().enumerate() {
+                    let sprite = self.index_register.0 as usize + n * rows * row_bytes;
+                    for (row_index, collided) in collided.iter_mut().enumerate() {
+                        for byte_index in 0..row_bytes {
+                            let sprite_row = self.read_memory(sprite + row_index * row_bytes + byte_index);
+                            for bit_pos in 0..8 {
+                                if ((1_u8 << bit_pos) & sprite_row) != 0 {
+                                    let mut pix_x = x + byte_index * 8 + 7 - bit_pos as usize;
+                                    let mut pix_y = y + row_index;
+                                    if !self.quirks.clip_sprites {
+                                        pix_x %= screen_width;
+                                        pix_y %= screen_height;
+                                    }
+                                    if pix_x < screen_width && pix_y < screen_height {
+                                        let pixel = &mut self.display.plane_mut(plane)[pix_y * screen_width + pix_x];
+                                        *collided |= *pixel;
+                                        *pixel ^= true;
+                                        self.draw_order.pixels[pix_y * screen_width + pix_x] = self.draw_order.draws;
+                                        let touched = self.breakpoints.draws.iter().find(|rect| rect.contains(pix_x, pix_y));
+                                        if let Some(&rect) = touched {
+                                            self.hit = Some(Break::Draw { pc: self.pc - 2, rect });
+                                        }
+                                    }
+                                }
                             }
                         }
                     }
                 }
+                let collided_rows = collided.iter().filter(|&&collided| collided).count() as u8;
+                // SCHIP's hires counts the rows that collided or went off the bottom, where others just say whether any did
+                self.registers[0xf] = Wrapping(if self.platform == Platform::Schip && self.display.hires() {
+                    collided_rows + clipped_rows
+                } else {
+                    (collided_rows > 0) as u8
+                });
                 return Cycle::RedrawRequested;
             },
-            Instruction::SkipPressed { key } => {
-                if key_pressed[self.registers[key as usize].0 as usize] {
-                    self.pc += 2;
+            Instruction::SkipPressed { register } => {
+                if key_pressed[self.key_in(register)] {
+                    self.skip();
                 }
             },
-            Instruction::SkipNotPressed { key } => {
-                if !key_pressed[self.registers[key as usize].0 as usize] {
-                    self.pc += 2;
+            Instruction::SkipNotPressed { register } => {
+                if !key_pressed[self.key_in(register)] {
+                    self.skip();
                 }
             },
             Instruction::GetDelayTimer { register } => {
@@ -365,81 +1563,226 @@ impl Chip8 {
                 }
             },
             Instruction::FontChar { register } => {
-                self.index_register = Wrapping((self.registers[register as usize].0 as u16 & 0xf) * 5)
+                let digit = self.registers[register as usize].0;
+                if digit > 0xf {
+                    self.diagnose_executed(Diagnostic::FontDigitOutOfRange, |pc| {
+                        format!("Instruction at {:#05x} asked for the font digit {:#x}, using {:#x}", pc, digit, digit & 0xf)
+                    });
+                }
+                self.index_register = Wrapping((digit as u32 & 0xf) * 5)
+            },
+            Instruction::BigFontChar { register } => {
+                let digit = self.registers[register as usize].0;
+                if digit > 9 {
+                    self.diagnose_executed(Diagnostic::BigFontDigitOutOfRange, |pc| {
+                        format!("Instruction at {:#05x} asked for the big font digit {:#x}, which doesn't exist", pc, digit)
+                    });
+                }
+                self.index_register = Wrapping(BIG_FONT_ADDRESS as u32 + (digit as u32 & 0xf) * 10)
             },
             Instruction::SetDelayTimer { register } => {
                 self.delay_timer = self.registers[register as usize].0;
             },
             Instruction::SetSoundTimer { register } => {
                 self.sound_timer = self.registers[register as usize].0;
+                if self.breakpoints.sound {
+                    self.hit = Some(Break::SoundSet { pc: self.pc - 2, value: self.sound_timer });
+                }
             },
             Instruction::AddToIndex { register } => {
-                let saved_val = self.index_register;
-                self.index_register += Wrapping(self.registers[register as usize].0 as u16);
-                self.registers[0xf] = Wrapping(if self.index_register < saved_val { 1 } else { 0 })
+                // VF says whether I wrapped around, past 16 bits or MEGA-CHIP's 24
+                let max = if self.platform == Platform::MegaChip { 0xff_ffff } else { 0xffff };
+                let sum = self.index_register.0 + self.registers[register as usize].0 as u32;
+                self.index_register = Wrapping(sum & max);
+                self.registers[0xf] = Wrapping((sum > max) as u8)
             },
             Instruction::RegToDecimal { register } => {
                 let mut val = self.registers[register as usize].0;
                 for i in (0..3).rev() {
-                    self.memory[self.index_register.0 as usize + i] = val % 10;
+                    self.write_memory(self.index_register.0 as usize + i, val % 10);
                     val /= 10;
                 }
             },
             Instruction::StoreMemory { register } => {
                 for i in 0..=register as usize {
-                    self.memory[self.index_register.0 as usize + i] = 
-                        self.registers[i].0;
+                    self.write_memory(self.index_register.0 as usize + i, self.registers[i].0);
+                }
+                if self.quirks.increment_index {
+                    self.index_register += Wrapping(register as u32 + 1);
                 }
-                // self.index_register += Wrapping(register as u16 + 1); // TODO; this is original behavior, not modern
             },
             Instruction::LoadMemory { register } => {
                 for i in 0..=register as usize {
-                    self.registers[i].0 = 
-                        self.memory[self.index_register.0 as usize + i];
+                    self.registers[i].0 = self.read_memory(self.index_register.0 as usize + i);
+                }
+                if self.quirks.increment_index {
+                    self.index_register += Wrapping(register as u32 + 1);
+                }
+            },
+            Instruction::StoreRange { register1, register2 } => {
+                for (i, register) in register_range(register1, register2).enumerate() {
+                    self.write_memory(self.index_register.0 as usize + i, self.registers[register].0);
+                }
+            },
+            Instruction::LoadRange { register1, register2 } => {
+                for (i, register) in register_range(register1, register2).enumerate() {
+                    self.registers[register].0 = self.read_memory(self.index_register.0 as usize + i);
+                }
+            },
+            Instruction::SaveFlags { register } => {
+                let count = self.flag_count(register);
+                let saved: Vec<u8> = self.registers[..count].iter().map(|reg| reg.0).collect();
+                if self.rpl[..count] != saved[..] {
+                    self.rpl[..count].copy_from_slice(&saved);
+                    self.rpl_changed = true;
+                }
+            },
+            Instruction::LoadFlags { register } => {
+                let count = self.flag_count(register);
+                for (reg, &flag) in self.registers.iter_mut().zip(&self.rpl[..count]) {
+                    *reg = Wrapping(flag);
                 }
-                // self.index_register += Wrapping(register as u16 + 1); // TODO; this is original behavior, not modern
             }
         }
         Cycle::Complete
     }
 
-    fn update_timers(&mut self, now: Instant) {
-        let elapsed_frames = (now.duration_since(self.last_clock).as_secs_f32() * 60.0).floor() as u8;
-        self.delay_timer -= min(self.delay_timer, elapsed_frames);
-        self.sound_timer -= min(self.sound_timer, elapsed_frames); // TODO: beep
-        self.last_clock += Duration::from_secs_f32(elapsed_frames as f32 / 60.0);
+    /// How many RPL flags FX75/FX85 with `register` covers. There are only 8, so past V7 is reported.
+    fn flag_count(&mut self, register: U4) -> usize {
+        if register as usize >= RPL_FLAGS {
+            self.diagnose_executed(Diagnostic::FlagOutOfRange, |pc| {
+                format!("Instruction at {:#05x} used RPL flags up to V{:X}, but there are only 8", pc, register)
+            });
+        }
+        (register as usize + 1).min(RPL_FLAGS)
+    }
+
+    /// Moves emulated time on by `gap`, ticking the timers for each sixtieth of a second it completes
+    fn update_timers(&mut self, gap: Duration) {
+        let gap = gap.as_nanos() as u64;
+        self.elapsed += gap;
+        self.tick_accumulator += gap * 60;
+        let elapsed_frames = self.tick_accumulator / TICK;
+        self.tick_accumulator %= TICK;
+        let ticks = min(elapsed_frames, u8::MAX as u64) as u8;
+        if ticks > 0 {
+            self.check_draw_storm();
+        }
+        self.delay_timer -= min(self.delay_timer, ticks);
+        let sounding = self.sound_timer > 0;
+        self.sound_timer -= min(self.sound_timer, ticks);
+        if sounding && self.sound_timer == 0 && self.breakpoints.sound {
+            self.hit = Some(Break::SoundExpired { pc: self.pc });
+        }
+    }
+
+    /// How far into the current 60Hz timer tick emulated time is. Snapshots don't include this or
+    /// the time itself, so recordings keep them alongside.
+    pub fn timer_phase(&self) -> Duration {
+        Duration::from_nanos(self.tick_accumulator / 60)
+    }
+
+    /// Sets the emulated time since the machine was created, and how far into a timer tick that is
+    pub fn set_timer_phase(&mut self, elapsed: Duration, phase: Duration) {
+        self.elapsed = elapsed.as_nanos() as u64;
+        self.tick_accumulator = phase.as_nanos() as u64 * 60;
     }
 
-    pub fn cycle(&mut self, key_pressed: [bool; 16], now: Instant) -> Cycle {
+    /// The current point in emulated time
+    pub fn timestamp(&self) -> Timestamp {
+        Timestamp { cycle: self.cycles, nanos: self.elapsed }
+    }
+
+    /// Runs the next instruction `gap` of emulated time after the last. Emulated time is all the
+    /// core knows: the timers tick every sixtieth of a second of it, so they keep pace with the
+    /// instructions however fast the frontend actually runs them.
+    pub fn cycle(&mut self, key_pressed: [bool; 16], gap: Duration) -> Cycle {
         if !self.pc_inbounds() {
-            panic!("PC reached bad value: {}", self.pc);
+            self.crash(Diagnostic::PcOutOfBounds, self.cycles, self.pc, format!("PC reached bad value: {}", self.pc));
         }
-        self.update_timers(now);
+        self.update_timers(gap);
+        logging::stamp(self.timestamp());
+        self.check_executing_data();
+        self.check_no_execute();
         let raw_instruction: u16 = self.get_instruction();
+        let instruction = match self.platform.decode(raw_instruction) {
+            Some(instruction) => instruction,
+            None => {
+                let message = format!("Reached unimplemented or invalid instruction: {:#04x} at PC {} for {}", raw_instruction, self.pc, self.platform);
+                self.crash(Diagnostic::UnknownInstruction, self.cycles, self.pc, message)
+            },
+        };
+        // Held up until the VIP would have got to it, like FX0A waiting for a key
+        let held = self.quirks.vip_timing && !self.vip_clock.spend(self.timestamp().frame(), vip::machine_cycles(&instruction));
+        if held {
+            // The timers still ran, but no instruction did, so it doesn't count as one
+            self.stats.record(self.timestamp());
+            self.check_beep();
+            return Cycle::Complete;
+        }
         self.pc += 2;
-        if let Some(instruction) = decode(raw_instruction) {
-            return self.execute(instruction, key_pressed);
+        self.cycles += 1;
+        self.stats.record(self.timestamp());
+        let cycle = self.execute(instruction, key_pressed);
+        self.check_beep();
+        cycle
+    }
+
+    /// Cycles a second for the frontend to run: the platform's speed, or with `Quirks::vip_timing`,
+    /// often enough that the VIP's timing is what limits it
+    pub fn clock_speed(&self) -> u32 {
+        if self.quirks.vip_timing {
+            self.platform.clock_speed().max(VIP_TICK_SPEED)
         } else {
-            panic!("Reached unimplemented or invalid instruction: {:#04x} at PC {}", raw_instruction, self.pc);
+            self.platform.clock_speed()
         }
     }
+}
 
-    pub fn draw(&self, frame: &mut [u8]) {
-        for (y, row) in self.display.iter().enumerate() {
-            for (x, pixel) in row.iter().enumerate() {
-                let i = x * 4 + y * SCREEN_WIDTH * 4;
-                frame[i] = if *pixel { u8::MAX } else { 0 };
-            }
-        }
+/// The registers from `first` to `last` inclusive, in that order, for 5XY2/5XY3
+pub fn register_range(first: U4, last: U4) -> Box<dyn Iterator<Item = usize>> {
+    let (first, last) = (first as usize, last as usize);
+    if first <= last {
+        Box::new(first..=last)
+    } else {
+        Box::new((last..=first).rev())
     }
 }
 
-fn print_screen(display: &Screen) {
-    for row in display {
-        for pixel in row {
-            print!("{}", if *pixel { 'Q' } else { ' ' });
+/// Draws into an RGBA frame the size of the screen, each pixel in the palette's colour for the planes it's lit in.
+/// The alpha is left alone.
+pub fn draw_screen(display: &Screen, palette: &Palette, frame: &mut [u8]) {
+    let width = display.width();
+    for (i, pixel) in frame.chunks_mut(4).take(width * display.height()).enumerate() {
+        pixel[..3].copy_from_slice(&display.rgb(i % width, i / width, palette));
+    }
+}
+
+/// Packs the screen's first plane 8 pixels per byte, row by row, leftmost pixel in the high bit
+pub fn pack_screen(display: &Screen) -> Vec<u8> {
+    pack_pixels(display.plane(0), display.width())
+}
+
+fn pack_pixels(pixels: &[bool], width: usize) -> Vec<u8> {
+    pixels.chunks(width)
+        .flat_map(|row| row.chunks(8))
+        .map(|pixels| pixels.iter().fold(0, |byte, &pixel| byte << 1 | pixel as u8))
+        .collect()
+}
+
+pub fn unpack_screen(packed: &[u8], width: usize, height: usize) -> Screen {
+    let mut display = Screen::new(width, height);
+    unpack_pixels(packed, width, display.plane_mut(0));
+    display
+}
+
+fn unpack_pixels(packed: &[u8], width: usize, pixels: &mut [bool]) {
+    for (row, packed_row) in pixels.chunks_mut(width).zip(packed.chunks(width / 8)) {
+        for (pixels, &byte) in row.chunks_mut(8).zip(packed_row) {
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = byte & (0x80 >> i) != 0;
+            }
         }
-        println!("");
     }
 }
 
@@ -451,75 +1794,955 @@ mod tests {
             .parse_env(env_logger::Env::default()
                 .filter_or(env_logger::DEFAULT_FILTER_ENV, "debug"))
             .try_init()
-            .unwrap();
+            .ok();
     }
 
     use std::num::Wrapping;
-    use std::time::{Duration, Instant};
+    use std::time::Duration;
+
+    use crate::breakpoints::Break;
+    use crate::diagnostics::{Diagnostic, Policy, Severity};
+    use crate::platform::Platform;
+    use crate::symbols::Symbols;
+    use crate::testing::{Machine, TEST_CLOCK_GAP};
+    use crate::look::Palette;
+    use super::{AudioPattern, Beep, Chip8, Cycle, Instruction, Rect, CHIP8X_BACKGROUNDS, CHIP8X_COLORS, MAX_CALL_DEPTH};
 
-    use super::{Chip8, Instruction, print_screen};
     #[test]
     fn draw_tests() {
         init();
-        let mut chip8 = Chip8::new(Instant::now());
-        chip8.execute(Instruction::Draw { x_r: 0, y_r: 0, height: 5 }, None);
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.execute(Instruction::Draw { x_r: 0, y_r: 0, height: 5 }, [false; 16]);
         assert!(chip8.display[0][0]);
         assert!(chip8.display[1][0]);
         assert!(chip8.display[0][1]);
-        chip8.execute(Instruction::Draw { x_r: 0, y_r: 0, height: 5 }, None);
+        assert_eq!(chip8.registers[0xf].0, 0);
+        chip8.execute(Instruction::Draw { x_r: 0, y_r: 0, height: 5 }, [false; 16]);
         assert!(!chip8.display[0][0]);
         assert!(!chip8.display[1][0]);
         assert!(!chip8.display[0][1]);
+        assert_eq!(chip8.registers[0xf].0, 1);
+        // Drawing onto blank pixels clears the flag again
+        chip8.execute(Instruction::Draw { x_r: 0, y_r: 0, height: 5 }, [false; 16]);
+        assert_eq!(chip8.registers[0xf].0, 0);
     }
 
     #[test]
     fn num_tests() {
         init();
-        let mut chip8 = Chip8::new(Instant::now());
-        chip8.execute(Instruction::SetRegister { register: 0, value: 123 }, None);
-        chip8.execute(Instruction::SetIndexRegister { value: 0x400 }, None);
-        chip8.execute(Instruction::RegToDecimal { register: 0 }, None);
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.execute(Instruction::SetRegister { register: 0, value: 123 }, [false; 16]);
+        chip8.execute(Instruction::SetIndexRegister { value: 0x400 }, [false; 16]);
+        chip8.execute(Instruction::RegToDecimal { register: 0 }, [false; 16]);
         assert_eq!(chip8.memory[0x400], 1);
         assert_eq!(chip8.memory[0x401], 2);
         assert_eq!(chip8.memory[0x402], 3);
-        chip8.execute(Instruction::SetRegister { register: 0, value: 10 }, None);
-        chip8.execute(Instruction::SetIndexRegister { value: 0x400 }, None);
-        chip8.execute(Instruction::RegToDecimal { register: 0 }, None);
+        chip8.execute(Instruction::SetRegister { register: 0, value: 10 }, [false; 16]);
+        chip8.execute(Instruction::SetIndexRegister { value: 0x400 }, [false; 16]);
+        chip8.execute(Instruction::RegToDecimal { register: 0 }, [false; 16]);
         assert_eq!(chip8.memory[0x400], 0);
         assert_eq!(chip8.memory[0x401], 1);
         assert_eq!(chip8.memory[0x402], 0);
     }
 
+    #[test]
+    fn waits_for_input() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 2 },
+            Instruction::SetDelayTimer { register: 0 },
+            Instruction::GetKey { register: 1 },
+            Instruction::Jump { dest: 0x206 },
+        ]);
+        machine.run(2);
+        let mut keys = [false; 16];
+        // Still counting down the delay timer
+        assert!(!machine.chip8.waiting_for_input(keys));
+        machine.chip8.delay_timer = 0;
+        assert!(machine.chip8.waiting_for_input(keys));
+        keys[3] = true;
+        assert!(!machine.chip8.waiting_for_input(keys));
+        machine.keys = keys;
+        machine.run(1);
+        assert!(machine.chip8.waiting_for_input(keys));
+        assert!(machine.chip8.halted());
+    }
+
+    #[test]
+    fn reads_chorded_keys() {
+        let mut chip8 = Chip8::new(Platform::default());
+        let mut keys = [false; 16];
+        keys[0x5] = true;
+        keys[0xa] = true;
+        chip8.registers[0] = Wrapping(0x5);
+        chip8.registers[1] = Wrapping(0xa);
+        let pc = chip8.pc;
+        chip8.execute(Instruction::SkipPressed { register: 0 }, keys);
+        chip8.execute(Instruction::SkipPressed { register: 1 }, keys);
+        assert_eq!(chip8.pc, pc + 4);
+        // Letting go of one key leaves the other held
+        keys[0x5] = false;
+        chip8.execute(Instruction::SkipNotPressed { register: 0 }, keys);
+        chip8.execute(Instruction::SkipPressed { register: 1 }, keys);
+        assert_eq!(chip8.pc, pc + 8);
+        // Keys past F wrap around to their low nibble
+        chip8.registers[2] = Wrapping(0x1a);
+        chip8.execute(Instruction::SkipPressed { register: 2 }, keys);
+        assert_eq!(chip8.pc, pc + 10);
+        assert_eq!(chip8.diagnostics.pcs(Diagnostic::KeyOutOfRange), [0x206]);
+    }
+
+    #[test]
+    fn jumps_with_offset() {
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.execute(Instruction::SetRegister { register: 0, value: 0x10 }, [false; 16]);
+        chip8.execute(Instruction::SetRegister { register: 3, value: 0x20 }, [false; 16]);
+        chip8.execute(Instruction::JumpOffset { dest: 0x300 }, [false; 16]);
+        assert_eq!(chip8.pc, 0x310);
+        chip8.quirks.jump_with_vx = true;
+        chip8.execute(Instruction::JumpOffset { dest: 0x300 }, [false; 16]);
+        assert_eq!(chip8.pc, 0x320);
+    }
+
+    #[test]
+    fn patches_in_reachable_code() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::Jump { dest: 0x200 },
+            Instruction::SetRegister { register: 1, value: 1 },
+        ]);
+        machine.chip8.patch(0x200, &Instruction::SetRegister { register: 0, value: 7 });
+        assert_eq!(machine.chip8.memory[0x200..0x202], [0x60, 0x07]);
+        machine.run(2);
+        assert_eq!((machine.chip8.registers[0].0, machine.chip8.registers[1].0), (7, 1));
+        // The instruction after the patch was unreachable before it
+        assert!(machine.chip8.diagnostics.pcs(Diagnostic::ExecutedUnreachable).is_empty());
+    }
+
+    #[test]
+    fn shifts_set_vf_to_the_bit_shifted_out() {
+        for (quirk, source) in [("no_shift_uses_vy", 1), ("shift_uses_vy", 2)] {
+            let mut chip8 = Chip8::new(Platform::default());
+            chip8.quirks.apply(quirk).unwrap();
+            chip8.registers[source] = Wrapping(0b1000_0001);
+            chip8.execute(Instruction::ShiftRight { register1: 1, register2: 2 }, [false; 16]);
+            assert_eq!((chip8.registers[1].0, chip8.registers[0xf].0), (0b0100_0000, 1), "{}", quirk);
+            chip8.registers[source] = Wrapping(0b0100_0000);
+            chip8.execute(Instruction::ShiftLeft { register1: 1, register2: 2 }, [false; 16]);
+            assert_eq!((chip8.registers[1].0, chip8.registers[0xf].0), (0b1000_0000, 0), "{}", quirk);
+            chip8.registers[source] = Wrapping(0b1000_0000);
+            chip8.execute(Instruction::ShiftLeft { register1: 1, register2: 2 }, [false; 16]);
+            assert_eq!((chip8.registers[1].0, chip8.registers[0xf].0), (0, 1), "{}", quirk);
+        }
+        // Shifting VF itself leaves just the flag
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.registers[0xf] = Wrapping(0b10);
+        chip8.execute(Instruction::ShiftRight { register1: 0xf, register2: 0 }, [false; 16]);
+        assert_eq!(chip8.registers[0xf].0, 0);
+    }
+
+    #[test]
+    fn stops_runaway_recursion_before_snapshots_lose_the_stack() {
+        let mut machine = Machine::from_instructions(&[Instruction::CallSubroutine { dest: 0x200 }]);
+        machine.run(MAX_CALL_DEPTH as u64);
+        let snapshot = machine.chip8.snapshot();
+        let mut restored = Chip8::new(Platform::default());
+        restored.pc = 0x300;
+        restored.restore(&snapshot);
+        assert_eq!((restored.pc, restored.stack.len()), (0x200, MAX_CALL_DEPTH));
+        let crash = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| machine.step()));
+        let message = crash.expect_err("Should have crashed").downcast::<String>().unwrap();
+        assert!(message.starts_with("Program called more than 255 subroutines deep."), "{}", message);
+        assert_eq!(machine.chip8.diagnostics.pcs(Diagnostic::StackOverflow), [0x200]);
+    }
+
+    #[test]
+    fn subtractions_set_vf_when_nothing_is_borrowed() {
+        let subtract = |backward: bool, x: u8, y: u8| {
+            let instruction = if backward {
+                Instruction::SubtractBackward { register1: 1, register2: 2 }
+            } else {
+                Instruction::SubtractForward { register1: 1, register2: 2 }
+            };
+            let mut chip8 = Chip8::new(Platform::default());
+            chip8.registers[1] = Wrapping(x);
+            chip8.registers[2] = Wrapping(y);
+            chip8.execute(instruction, [false; 16]);
+            (chip8.registers[1].0, chip8.registers[0xf].0)
+        };
+        assert_eq!(subtract(false, 5, 3), (2, 1));
+        assert_eq!(subtract(false, 3, 5), (254, 0));
+        assert_eq!(subtract(false, 4, 4), (0, 1));
+        assert_eq!(subtract(true, 3, 5), (2, 1));
+        assert_eq!(subtract(true, 5, 3), (254, 0));
+        assert_eq!(subtract(true, 4, 4), (0, 1));
+
+        // With VF as an operand, the flag wins over the result
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.registers[0xf] = Wrapping(3);
+        chip8.registers[2] = Wrapping(5);
+        chip8.execute(Instruction::SubtractForward { register1: 0xf, register2: 2 }, [false; 16]);
+        assert_eq!(chip8.registers[0xf].0, 0);
+        chip8.registers[1] = Wrapping(9);
+        chip8.registers[0xf] = Wrapping(4);
+        chip8.execute(Instruction::SubtractForward { register1: 1, register2: 0xf }, [false; 16]);
+        assert_eq!((chip8.registers[1].0, chip8.registers[0xf].0), (5, 1));
+        chip8.registers[0xf] = Wrapping(4);
+        chip8.execute(Instruction::SubtractBackward { register1: 0xf, register2: 1 }, [false; 16]);
+        assert_eq!(chip8.registers[0xf].0, 1);
+    }
+
+    #[test]
+    fn quirks_change_behaviour() {
+        let quirks = |name: &str| {
+            let mut chip8 = Chip8::new(Platform::default());
+            chip8.quirks.apply(name).unwrap();
+            chip8.registers[1] = Wrapping(0b0110);
+            chip8.registers[2] = Wrapping(0b1001);
+            chip8.registers[0xf] = Wrapping(7);
+            chip8
+        };
+        let mut chip8 = quirks("no_shift_uses_vy");
+        chip8.execute(Instruction::ShiftRight { register1: 1, register2: 2 }, [false; 16]);
+        assert_eq!(chip8.registers[1].0, 0b0011);
+        let mut chip8 = quirks("shift_uses_vy");
+        chip8.execute(Instruction::ShiftLeft { register1: 1, register2: 2 }, [false; 16]);
+        assert_eq!(chip8.registers[1].0, 0b10010);
+
+        let mut chip8 = quirks("no_vf_reset");
+        chip8.execute(Instruction::BinaryOr { register1: 1, register2: 2 }, [false; 16]);
+        assert_eq!(chip8.registers[0xf].0, 7);
+        // VF is reset after the result is written, even when it's the destination
+        for (instruction, v1) in [
+            (Instruction::BinaryOr { register1: 1, register2: 2 }, 0b1111),
+            (Instruction::BinaryAnd { register1: 1, register2: 2 }, 0),
+            (Instruction::BinaryXor { register1: 1, register2: 2 }, 0b1111),
+            (Instruction::BinaryOr { register1: 0xf, register2: 2 }, 0b0110),
+        ] {
+            let mut chip8 = quirks("vf_reset");
+            chip8.execute(instruction, [false; 16]);
+            assert_eq!((chip8.registers[1].0, chip8.registers[0xf].0), (v1, 0));
+        }
+
+        // Programs like Animal Race walk through tables by loading from I over and over
+        for (name, index, v0) in [("no_increment_index", 0x400, 0), ("increment_index", 0x405, 9)] {
+            let mut chip8 = quirks(name);
+            chip8.index_register = Wrapping(0x400);
+            chip8.execute(Instruction::StoreMemory { register: 2 }, [false; 16]);
+            chip8.memory[0x403] = 9;
+            chip8.execute(Instruction::LoadMemory { register: 1 }, [false; 16]);
+            assert_eq!((chip8.index_register.0, chip8.registers[0].0), (index, v0), "{}", name);
+        }
+
+        for (name, wrapped) in [("clip_sprites", false), ("no_clip_sprites", true)] {
+            let mut chip8 = quirks(name);
+            chip8.registers[1] = Wrapping(62);
+            chip8.registers[2] = Wrapping(31);
+            // The 0 glyph is 4 pixels wide and 5 tall
+            chip8.execute(Instruction::Draw { x_r: 1, y_r: 2, height: 5 }, [false; 16]);
+            assert!(chip8.display[31][63]);
+            assert_eq!(chip8.display[0][1], wrapped, "{}", name);
+            assert_eq!(chip8.display[31][1], wrapped, "{}", name);
+        }
+
+        // Two draws in a row, then a jump to itself
+        let program = [
+            Instruction::Draw { x_r: 0, y_r: 0, height: 1 },
+            Instruction::Draw { x_r: 0, y_r: 0, height: 1 },
+            Instruction::Jump { dest: 0x204 },
+        ];
+        let mut machine = Machine::from_instructions(&program);
+        machine.run(2);
+        assert!(machine.chip8.halted());
+        let mut machine = Machine::from_instructions(&program);
+        machine.chip8.quirks.display_wait = true;
+        machine.run(2);
+        assert_eq!(machine.chip8.pc, 0x202);
+        // A frame is about 17 cycles at the test clock
+        machine.run(17);
+        assert!(machine.chip8.halted());
+    }
+
+    #[test]
+    fn loads_instructions() {
+        let mut chip8 = Chip8::new(Platform::default());
+        let len = chip8.load_instructions(&[
+            Instruction::SetRegister { register: 0xa, value: 0x42 },
+            Instruction::Jump { dest: 0x202 },
+        ]);
+        assert_eq!(len, 4);
+        assert_eq!(chip8.memory[0x200..0x206], [0x6a, 0x42, 0x12, 0x02, 0, 0]);
+        chip8.cycle([false; 16], TEST_CLOCK_GAP);
+        assert_eq!(chip8.registers[0xa].0, 0x42);
+    }
+
+    #[test]
+    fn ticks_timers_from_emulated_time() {
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.delay_timer = 180;
+        // A tick is three and a third of these, so it only comes out even if the remainders carry over
+        let gap = Duration::from_millis(5);
+        for _ in 0..599 {
+            chip8.update_timers(gap);
+        }
+        assert_eq!(chip8.delay_timer, 1);
+        assert_eq!(chip8.timer_phase(), Duration::from_nanos(11_666_666));
+        chip8.update_timers(gap);
+        assert_eq!((chip8.delay_timer, chip8.timer_phase()), (0, Duration::ZERO));
+        assert_eq!(chip8.timestamp().nanos, 3_000_000_000);
+    }
+
+    #[test]
+    fn switches_between_lores_and_hires() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::Hires,
+            Instruction::SetRegister { register: 0, value: 120 },
+            Instruction::SetRegister { register: 1, value: 60 },
+            Instruction::SetIndexRegister { value: 0 },
+            Instruction::Draw { x_r: 0, y_r: 1, height: 5 },
+            Instruction::Lores,
+        ]).on(Platform::Schip);
+        machine.run(5);
+        assert_eq!(machine.chip8.display.size(), (128, 64));
+        // The 0 is cut off at the bottom edge of the hires screen rather than the lores one
+        assert!(machine.chip8.pixel(120, 60) && machine.chip8.pixel(123, 63));
+        assert_eq!(machine.chip8.count_lit_pixels(), 10);
+
+        let mut restored = Chip8::new(Platform::Schip);
+        restored.restore(&machine.chip8.snapshot());
+        assert_eq!(restored.display, machine.chip8.display);
+
+        machine.step();
+        assert_eq!(machine.chip8.display.size(), (64, 32));
+        assert_eq!(machine.chip8.count_lit_pixels(), 0);
+    }
+
+    #[test]
+    fn scrolls_in_both_resolutions() {
+        for hires in [false, true] {
+            let mut program = vec![
+                Instruction::SetIndexRegister { value: 0 },
+                Instruction::Draw { x_r: 0, y_r: 0, height: 5 },
+                Instruction::ScrollDown { rows: 2 },
+                Instruction::ScrollRight,
+                Instruction::ScrollLeft,
+                Instruction::ScrollLeft,
+            ];
+            if hires {
+                program.insert(0, Instruction::Hires);
+            }
+            let mut machine = Machine::from_instructions(&program).on(Platform::Schip);
+            machine.run(program.len() as u64 - 2);
+            // The 0 glyph's top left corner, moved 2 down and 4 right
+            assert!(machine.chip8.pixel(4, 2) && !machine.chip8.pixel(0, 0));
+            assert_eq!(machine.chip8.count_lit_pixels(), 14);
+            machine.step();
+            assert!(machine.chip8.pixel(0, 2) && !machine.chip8.pixel(4, 2));
+            // Scrolling it off the left edge loses it
+            machine.step();
+            assert_eq!(machine.chip8.count_lit_pixels(), 0);
+            assert_eq!(machine.chip8.display.hires(), hires);
+        }
+    }
+
+    #[test]
+    fn runs_hires_roms_on_two_pages() {
+        let mut rom = vec![0; 0xca];
+        rom[..2].copy_from_slice(&[0x12, 0x60]);
+        rom[0xc0..].copy_from_slice(&[
+            0x61, 0x3c, // V1 = 60
+            0xf0, 0x29, // I = font 0
+            0xd0, 0x15, // draw it at (0, 60)
+            0x02, 0x30, // clear
+            0x12, 0xc8, // stay here
+        ]);
+        let mut chip8 = Chip8::new(Platform::Hires);
+        chip8.read_program(&rom[..]).unwrap();
+        assert_eq!((chip8.pc, chip8.display.size()), (0x2c0, (64, 64)));
+        let mut machine = Machine { chip8, clock_gap: TEST_CLOCK_GAP, keys: [false; 16] };
+        while machine.chip8.pc < 0x2c6 {
+            machine.step();
+        }
+        // The bottom of the second page, with the font's last row cut off rather than wrapping to the top
+        assert!(machine.chip8.pixel(3, 60) && machine.chip8.pixel(0, 63));
+        assert!(!machine.chip8.pixel(0, 0));
+        machine.step();
+        assert_eq!(machine.chip8.count_lit_pixels(), 0);
+    }
+
+    #[test]
+    fn draws_mega_chip_sprites() {
+        let mut rom = vec![0; 0x20000 - 0x200 + 6];
+        rom[..0x24].copy_from_slice(&[
+            0x00, 0x11, // MEGA-CHIP screen on
+            0x01, 0x02, 0x00, 0x00, // I = 0x20000
+            0x02, 0x01, // one colour from there
+            0x01, 0x02, 0x00, 0x04, // I = 0x20004
+            0x03, 0x02, // 2 wide
+            0x04, 0x01, // 1 high
+            0x09, 0x01, // colliding with colour 1
+            0x60, 0x03, // V0 = 3
+            0xd0, 0x10, // draw at (3, 0)
+            0x00, 0xe0, // show it
+            0xd0, 0x10, // draw again
+            0xd0, 0x10, // and over that
+            0x30, 0x03, // skip the whole of
+            0x01, 0x02, 0x00, 0x00, // I = 0x20000
+            0x12, 0x22, // stay here
+        ]);
+        rom[0x20000 - 0x200..].copy_from_slice(&[0xff, 0xff, 0x00, 0x00, 0x01, 0x00]);
+        let mut chip8 = Chip8::new(Platform::MegaChip);
+        chip8.read_program(&rom[..]).unwrap();
+        assert_eq!(chip8.memory.len(), 0x40000);
+        let mut machine = Machine { chip8, clock_gap: TEST_CLOCK_GAP, keys: [false; 16] };
+        machine.run(9);
+        // Drawn, but not shown until 00E0
+        assert_eq!(machine.chip8.display.size(), (256, 192));
+        assert!(!machine.chip8.pixel(3, 0));
+        machine.step();
+        let palette = Palette::default();
+        assert_eq!(machine.chip8.display.rgb(3, 0, &palette), [0xff, 0, 0]);
+        assert!(machine.chip8.pixel(3, 0) && !machine.chip8.pixel(4, 0));
+        machine.step();
+        assert_eq!(machine.chip8.registers[0xf].0, 0);
+        machine.run(2);
+        assert_eq!((machine.chip8.registers[0xf].0, machine.chip8.pc), (1, 0x222));
+        assert_eq!(machine.chip8.index_register.0, 0x20004);
+
+        let mut restored = Chip8::new(Platform::MegaChip);
+        restored.read_program(&rom[..]).unwrap();
+        restored.restore(&machine.chip8.snapshot());
+        assert_eq!(restored.display, machine.chip8.display);
+        assert_eq!(restored.mega, machine.chip8.mega);
+        assert_eq!(restored.index_register, machine.chip8.index_register);
+    }
+
+    #[test]
+    fn colours_chip8x_zones() {
+        let mut chip8 = Chip8::new(Platform::Chip8X);
+        chip8.load_instructions(&[
+            Instruction::SetRegister { register: 0, value: 0x21 }, // columns 1 to 2
+            Instruction::SetRegister { register: 1, value: 0x10 }, // zone rows 0 to 1
+            Instruction::SetRegister { register: 2, value: 4 },    // green
+            Instruction::ColorZones { x_r: 0, y_r: 2 },
+            Instruction::SetRegister { register: 0, value: 40 },
+            Instruction::SetRegister { register: 1, value: 30 },
+            Instruction::ColorRows { x_r: 0, y_r: 2, rows: 4 },
+            Instruction::CycleBackground,
+            Instruction::SetRegister { register: 3, value: 0x36 },
+            Instruction::SetRegister { register: 4, value: 0x15 },
+            Instruction::AddNibbles { register1: 3, register2: 4 },
+            Instruction::SetRegister { register: 5, value: 8 },
+            Instruction::FontChar { register: 6 },
+            Instruction::Draw { x_r: 5, y_r: 6, height: 5 },
+            Instruction::SkipPressed2 { register: 2 },
+            Instruction::Jump { dest: 0x31c },
+        ]);
+        assert_eq!(chip8.pc, 0x300);
+        chip8.second_keypad[4] = true;
+        let mut machine = Machine { chip8, clock_gap: TEST_CLOCK_GAP, keys: [false; 16] };
+        machine.run(15);
+        assert_eq!(machine.chip8.pc, 0x320);
+        // Each nibble is added on its own, without carrying into the other
+        assert_eq!(machine.chip8.registers[3].0, 0x43);
+
+        let display = &machine.chip8.display;
+        let colors = display.colors().unwrap();
+        assert_eq!(colors.background, 1);
+        assert_eq!((colors.foreground[1], colors.foreground[7 * 8 + 2], colors.foreground[8 * 8 + 1]), (4, 4, 1));
+        // Rows 30 and 31 of column 5, with the two after off the screen
+        assert_eq!((colors.foreground[29 * 8 + 5], colors.foreground[30 * 8 + 5], colors.foreground[31 * 8 + 5]), (1, 4, 4));
+        // The top of the 0 is lit in green, and the hole in it shows the background
+        let palette = Palette::default();
+        assert_eq!(display.rgb(8, 0, &palette), CHIP8X_COLORS[4]);
+        assert_eq!(display.rgb(9, 1, &palette), CHIP8X_BACKGROUNDS[1]);
+        assert_eq!(display.rgb(16, 0, &palette), CHIP8X_BACKGROUNDS[1]);
+
+        let mut restored = Chip8::new(Platform::Chip8X);
+        restored.restore(&machine.chip8.snapshot());
+        assert_eq!(restored.display.colors(), Some(colors));
+    }
+
+    #[test]
+    fn draws_16x16_sprites() {
+        let program = || [
+            Instruction::Hires,
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::Draw { x_r: 0, y_r: 1, height: 0 },
+            Instruction::Draw { x_r: 0, y_r: 1, height: 0 },
+            Instruction::SetRegister { register: 1, value: 56 },
+            Instruction::Draw { x_r: 0, y_r: 1, height: 0 },
+        ];
+        let mut machine = Machine::from_instructions(&program()).on(Platform::Schip);
+        // A 16x16 square with the top left pixel missing
+        machine.chip8.memory[0x300..0x320].fill(0xff);
+        machine.chip8.memory[0x300] = 0x7f;
+        machine.run(3);
+        assert_eq!(machine.chip8.count_lit_pixels(), 255);
+        assert!(!machine.chip8.pixel(0, 0) && machine.chip8.pixel(15, 15) && !machine.chip8.pixel(16, 0));
+        assert_eq!(machine.chip8.registers[0xf].0, 0);
+        // SCHIP counts the rows that collided, then the rows cut off at the bottom
+        machine.step();
+        assert_eq!(machine.chip8.count_lit_pixels(), 0);
+        assert_eq!(machine.chip8.registers[0xf].0, 16);
+        machine.run(2);
+        assert_eq!(machine.chip8.count_lit_pixels(), 128 - 1);
+        assert_eq!(machine.chip8.registers[0xf].0, 8);
+
+        let mut machine = Machine::from_instructions(&program()).on(Platform::XoChip);
+        machine.chip8.memory[0x300..0x320].fill(0xff);
+        machine.run(4);
+        assert_eq!(machine.chip8.registers[0xf].0, 1);
+
+        // CHIP-8 has no hires, and draws nothing for DXY0
+        let mut machine = Machine::from_instructions(&program()[1..]);
+        machine.chip8.memory[0x300..0x320].fill(0xff);
+        machine.run(2);
+        assert_eq!(machine.chip8.count_lit_pixels(), 0);
+    }
+
+    #[test]
+    fn points_at_big_digits() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 3, value: 8 },
+            Instruction::BigFontChar { register: 3 },
+            Instruction::Draw { x_r: 0, y_r: 0, height: 10 },
+            Instruction::SetRegister { register: 3, value: 0xa },
+            Instruction::BigFontChar { register: 3 },
+        ]).on(Platform::Schip);
+        machine.run(3);
+        assert_eq!(machine.chip8.index_register.0, 0x50 + 8 * 10);
+        let rows: Vec<u8> = (0..10)
+            .map(|y| (0..8).fold(0, |byte, x| byte << 1 | machine.chip8.pixel(x, y) as u8))
+            .collect();
+        assert_eq!(rows, [0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C]);
+        machine.run(2);
+        assert_eq!(machine.chip8.diagnostics.pcs(Diagnostic::BigFontDigitOutOfRange), [0x208]);
+    }
+
+    #[test]
+    fn loads_long_index() {
+        let mut machine = Machine::new(&[0xf0, 0x00, 0x12, 0x34, 0x60, 0x05]).on(Platform::XoChip);
+        machine.run(2);
+        assert_eq!(machine.chip8.index_register.0, 0x1234);
+        assert_eq!(machine.chip8.registers[0].0, 5);
+        assert_eq!(machine.chip8.pc, 0x206);
+        assert!(machine.chip8.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn draws_and_clears_planes() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::SelectPlanes { planes: 3 },
+            Instruction::Draw { x_r: 0, y_r: 0, height: 1 },
+            Instruction::SelectPlanes { planes: 2 },
+            Instruction::ScrollRight,
+            Instruction::ClearScreen,
+        ]).on(Platform::XoChip);
+        // One row for the first plane, then one for the second
+        machine.chip8.memory[0x300..0x302].copy_from_slice(&[0x80, 0xc0]);
+        machine.run(3);
+        let colors = |chip8: &Chip8| (0..6).map(|x| chip8.display.color(x, 0)).collect::<Vec<_>>();
+        assert_eq!(colors(&machine.chip8), [3, 2, 0, 0, 0, 0]);
+        assert_eq!(machine.chip8.registers[0xf].0, 0);
+
+        machine.run(2);
+        assert_eq!(colors(&machine.chip8), [1, 0, 0, 0, 2, 2]);
+        let mut restored = Machine::new(&[]).chip8;
+        restored.restore(&machine.chip8.snapshot());
+        assert_eq!((restored.planes, &restored.display), (2, &machine.chip8.display));
+
+        machine.step();
+        assert_eq!(colors(&machine.chip8), [1, 0, 0, 0, 0, 0]);
+        // Without a second plane in use there's nothing to keep
+        let snapshot = Machine::new(&[]).chip8.snapshot();
+        machine.chip8.restore(&snapshot);
+        assert_eq!((machine.chip8.planes, machine.chip8.display.plane(1).contains(&true)), (1, false));
+        assert_eq!(Platform::Schip.decode(0xf301), None);
+    }
+
+    #[test]
+    fn scrolls_up_selected_planes() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::SetRegister { register: 1, value: 3 },
+            Instruction::SelectPlanes { planes: 3 },
+            Instruction::Draw { x_r: 0, y_r: 1, height: 1 },
+            Instruction::SelectPlanes { planes: 2 },
+            Instruction::ScrollUp { rows: 2 },
+            Instruction::SelectPlanes { planes: 3 },
+            Instruction::ScrollUp { rows: 15 },
+        ]).on(Platform::XoChip);
+        machine.chip8.memory[0x300..0x302].copy_from_slice(&[0x80, 0x80]);
+        machine.run(6);
+        let column = |chip8: &Chip8| (0..5).map(|y| chip8.display.color(0, y)).collect::<Vec<_>>();
+        assert_eq!(column(&machine.chip8), [0, 2, 0, 1, 0]);
+        machine.run(2);
+        assert_eq!(machine.chip8.count_lit_pixels(), 0);
+        assert_eq!(Platform::Schip.decode(0x00d2), None);
+    }
+
+    #[test]
+    fn loads_audio_patterns() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::LoadAudio,
+            Instruction::SetRegister { register: 3, value: 112 },
+            Instruction::SetPitch { register: 3 },
+        ]).on(Platform::XoChip);
+        let pattern: Vec<u8> = (0..16).collect();
+        machine.chip8.memory[0x300..0x310].copy_from_slice(&pattern);
+        machine.run(4);
+        assert_eq!((machine.chip8.audio_pattern.unwrap().to_vec(), machine.chip8.pitch), (pattern, 112));
+        let mut restored = Machine::new(&[]).chip8;
+        restored.restore(&machine.chip8.snapshot());
+        assert_eq!((restored.audio_pattern, restored.pitch), (machine.chip8.audio_pattern, 112));
+        restored.restore(&Machine::new(&[]).chip8.snapshot());
+        assert_eq!((restored.audio_pattern, restored.pitch), (None, 64));
+        assert_eq!(Platform::Schip.decode(0xf002), None);
+    }
+
+    #[test]
+    fn reports_beeper_changes() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 3 },
+            Instruction::SetSoundTimer { register: 0 },
+            // The buzzer doesn't have a pitch, so this changes nothing
+            Instruction::SetRegister { register: 1, value: 112 },
+            Instruction::SetPitch { register: 1 },
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::LoadAudio,
+            Instruction::Jump { dest: 0x20c },
+        ]).on(Platform::XoChip);
+        machine.chip8.memory[0x300..0x310].copy_from_slice(&[0xf0; 16]);
+        let mut beeps = Vec::new();
+        let mut sounding = None;
+        for _ in 0..100 {
+            machine.step();
+            if let Some(beep) = machine.chip8.beep.take() {
+                beeps.push((machine.chip8.cycles, beep));
+            }
+            if machine.chip8.cycles == 3 {
+                sounding = Some(machine.chip8.snapshot());
+            }
+        }
+        let pattern = AudioPattern { bits: [0xf0; 16], pitch: 112 };
+        // Three ticks of the 60Hz timer at a cycle a millisecond
+        assert_eq!(beeps, [(2, Beep::Started(None)), (6, Beep::Started(Some(pattern))), (50, Beep::Stopped)]);
+        // A restore that starts it sounding again is reported on the next cycle
+        machine.chip8.restore(&sounding.unwrap());
+        machine.step();
+        assert_eq!(machine.chip8.beep, Some(Beep::Started(None)));
+    }
+
+    #[test]
+    fn saves_and_loads_register_ranges() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::StoreRange { register1: 2, register2: 4 },
+            Instruction::SetIndexRegister { value: 0x310 },
+            Instruction::StoreRange { register1: 4, register2: 2 },
+            Instruction::LoadRange { register1: 7, register2: 7 },
+        ]).on(Platform::XoChip);
+        machine.chip8.registers[2..5].copy_from_slice(&[Wrapping(1), Wrapping(2), Wrapping(3)]);
+        machine.run(5);
+        assert_eq!(machine.chip8.memory[0x300..0x304], [1, 2, 3, 0]);
+        assert_eq!(machine.chip8.memory[0x310..0x313], [3, 2, 1]);
+        assert_eq!((machine.chip8.registers[7].0, machine.chip8.index_register.0), (3, 0x310));
+        assert_eq!(Platform::Chip8.decode(0x5122), None);
+    }
+
+    #[test]
+    fn exits() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 1 },
+            Instruction::Exit,
+        ]).on(Platform::Schip);
+        assert_eq!(machine.step(), Cycle::Complete);
+        assert!(machine.chip8.halted());
+        assert_eq!(machine.step(), Cycle::Exit);
+        assert_eq!(machine.chip8.pc, 0x202);
+        assert!(machine.chip8.halted());
+        assert!(!machine.on(Platform::Chip8).chip8.halted());
+    }
+
+    #[test]
+    fn keeps_rpl_flags() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 4 },
+            Instruction::SetRegister { register: 1, value: 2 },
+            Instruction::SaveFlags { register: 1 },
+            Instruction::SetRegister { register: 0, value: 0 },
+            Instruction::LoadFlags { register: 0 },
+            Instruction::SaveFlags { register: 0xf },
+        ]).on(Platform::Schip);
+        machine.run(3);
+        assert_eq!(machine.chip8.rpl, [4, 2, 0, 0, 0, 0, 0, 0]);
+        assert!(std::mem::take(&mut machine.chip8.rpl_changed));
+        let snapshot = machine.chip8.snapshot();
+        machine.run(2);
+        assert_eq!(machine.chip8.registers[0].0, 4);
+        machine.run(1);
+        // Saving the same values again isn't a change, and only 8 registers fit
+        assert!(!machine.chip8.rpl_changed);
+        assert_eq!(machine.chip8.diagnostics.pcs(Diagnostic::FlagOutOfRange), [0x20a]);
+        machine.chip8.rpl = [0; 8];
+        machine.chip8.restore(&snapshot);
+        assert_eq!(machine.chip8.rpl, [4, 2, 0, 0, 0, 0, 0, 0]);
+    }
+
     #[test]
     fn rom_test() {
-        let mut chip8 = Chip8::new(Instant::now());
-        chip8.read_program(std::fs::File::open("/home/rachel/Downloads/Chip-8 Pack/Chip-8 Games/15 Puzzle [Roger Ivie].ch8").unwrap()).unwrap();
-        let mut now = Instant::now();
-        for i in 0..10000 {
-            now += Duration::from_secs(1);
-            chip8.cycle(Some(4), now);
-            print_screen(&chip8.display);
+        let mut machine = Machine::from_rom("test/ibm_logo.ch8").with_clock_gap(Duration::from_secs(1));
+        machine.press(4);
+        for _ in 0..10000 {
+            machine.step();
+            println!("{}", machine.screen());
+        }
+        assert!(machine.chip8.diagnostics.is_empty(), "{:?}", machine.chip8.diagnostics.summary());
+    }
+
+    #[test]
+    fn flags_executing_data() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetIndexRegister { value: 0x208 },
+            Instruction::SetRegister { register: 0, value: 0x12 },
+            Instruction::SetRegister { register: 1, value: 0x08 },
+            // store V0..V1 at 0x208, making it "jump 0x208"
+            Instruction::StoreMemory { register: 1 },
+        ]);
+        machine.run(4);
+        assert!(machine.chip8.diagnostics.is_empty());
+        machine.step();
+        assert_eq!(machine.chip8.pc, 0x208);
+        assert_eq!(machine.chip8.diagnostics.pcs(Diagnostic::ExecutedWrittenData), [0x208]);
+    }
+
+    #[test]
+    fn flags_draw_storms() {
+        let run = |clock_gap: Duration| {
+            let mut machine = Machine::from_instructions(&[
+                Instruction::Draw { x_r: 0, y_r: 0, height: 1 },
+                Instruction::Jump { dest: 0x200 },
+            ]).with_clock_gap(clock_gap);
+            machine.run(20_000);
+            machine.chip8
+        };
+        // 3333 instructions a frame, so 1666 draws
+        let storming = run(Duration::from_micros(5));
+        assert!(storming.draw_storm);
+        assert_eq!(storming.diagnostics.pcs(Diagnostic::DrawStorm), [0x200]);
+        // 833 draws a frame, fast but plausible
+        let fine = run(Duration::from_micros(10));
+        assert!(!fine.draw_storm);
+        assert!(fine.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn stack_traces_name_subroutines() {
+        let program: [u8; 12] = [
+            0x22, 0x06, // 200: call 206
+            0x12, 0x02, // 202: jump 202
+            0x00, 0x00, // 204: padding
+            0x22, 0x0a, // 206: call 20a
+            0x00, 0xee, // 208: return
+            0x00, 0x00, // 20a: invalid
+        ];
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.read_program(&program[..]).unwrap();
+        chip8.symbols = Symbols::parse("206 outer").unwrap();
+        chip8.cycle([false; 16], TEST_CLOCK_GAP);
+        chip8.cycle([false; 16], TEST_CLOCK_GAP);
+        assert_eq!(chip8.stack_trace(), [
+            "#0 0x20a in sub_20a",
+            "#1 0x206 in outer, returns to 0x208: Return",
+            "#2 0x200 in start, returns to 0x202: Jump { dest: 514 }",
+        ]);
+        let crash = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| chip8.cycle([false; 16], TEST_CLOCK_GAP)));
+        let message = crash.expect_err("Should have crashed").downcast::<String>().unwrap();
+        assert!(message.starts_with("Reached unimplemented or invalid instruction: 0x00 at PC 522"), "{}", message);
+        assert!(message.ends_with("in start, returns to 0x202: Jump { dest: 514 }"), "{}", message);
+        // And it's left as a diagnostic for whoever caught it
+        let report = chip8.diagnostics.reports().last().unwrap();
+        assert_eq!((report.diagnostic, report.severity, report.cycle, report.pc), (Diagnostic::UnknownInstruction, Severity::Error, 2, 0x20a));
+    }
+
+    #[test]
+    fn breaks_on_sound() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 2 },
+            Instruction::SetSoundTimer { register: 0 },
+            Instruction::Jump { dest: 0x204 },
+        ]);
+        machine.chip8.breakpoints.sound = true;
+        machine.run(2);
+        assert_eq!(machine.chip8.hit.take(), Some(Break::SoundSet { pc: 0x202, value: 2 }));
+        // Two 60Hz ticks at 1ms a cycle
+        let mut cycles = 0;
+        while machine.chip8.hit.is_none() {
+            machine.step();
+            cycles += 1;
+        }
+        assert_eq!(machine.chip8.hit, Some(Break::SoundExpired { pc: 0x204 }));
+        assert!((32..=34).contains(&cycles), "{}", cycles);
+    }
+
+    #[test]
+    fn breaks_on_clears_and_draws() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::ClearScreen,
+            // The 0 glyph at (2, 0), then at (8, 0)
+            Instruction::Draw { x_r: 1, y_r: 0, height: 5 },
+            Instruction::SetRegister { register: 1, value: 8 },
+            Instruction::Draw { x_r: 1, y_r: 0, height: 5 },
+        ]);
+        machine.chip8.registers[1].0 = 2;
+        let rect = Rect { x: 10, y: 4, width: 2, height: 1 };
+        machine.chip8.breakpoints.clear = true;
+        machine.chip8.breakpoints.draws.push(rect);
+        machine.step();
+        assert_eq!(machine.chip8.hit.take(), Some(Break::Clear { pc: 0x200 }));
+        // The first glyph's pixels stop at x = 5
+        machine.step();
+        assert_eq!(machine.chip8.hit.take(), None);
+        machine.run(2);
+        assert_eq!(machine.chip8.hit.take(), Some(Break::Draw { pc: 0x206, rect }));
+        assert_eq!("10,4,2,1".parse(), Ok(rect));
+        assert!("10,4,2".parse::<Rect>().is_err());
+    }
+
+    #[test]
+    fn queries_the_screen() {
+        let mut machine = Machine::from_instructions(&[
+            // The 1 glyph at (0, 0) and (8, 0)
+            Instruction::SetRegister { register: 0, value: 1 },
+            Instruction::FontChar { register: 0 },
+            Instruction::Draw { x_r: 1, y_r: 1, height: 5 },
+            Instruction::SetRegister { register: 1, value: 8 },
+            Instruction::Draw { x_r: 1, y_r: 2, height: 5 },
+        ]);
+        machine.run(5);
+        let chip8 = &machine.chip8;
+        // 0x20, 0x60, 0x20, 0x20, 0x70
+        assert!(chip8.pixel(2, 0));
+        assert!(!chip8.pixel(0, 0));
+        assert!(!chip8.pixel(100, 100));
+        assert_eq!(chip8.count_lit_pixels(), 2 * 8);
+        let left = chip8.region_hash(Rect { x: 0, y: 0, width: 8, height: 5 });
+        assert_eq!(left, chip8.region_hash(Rect { x: 8, y: 0, width: 8, height: 5 }));
+        assert_ne!(left, chip8.region_hash(Rect { x: 16, y: 0, width: 8, height: 5 }));
+        // Off screen pixels count as unlit
+        assert_eq!(
+            chip8.region_hash(Rect { x: 60, y: 30, width: 8, height: 2 }),
+            chip8.region_hash(Rect { x: 20, y: 20, width: 8, height: 2 }),
+        );
+    }
+
+    #[test]
+    fn enforces_protected_regions() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetIndexRegister { value: 0 },
+            Instruction::SetRegister { register: 0, value: 0x12 },
+            Instruction::StoreMemory { register: 0 },
+            Instruction::Jump { dest: 0x300 },
+        ]);
+        let chip8 = &mut machine.chip8;
+        chip8.memory[0x300..0x302].copy_from_slice(&[0x13, 0x00]); // jump 0x300
+        chip8.regions = vec!["0-1ff:ro".parse().unwrap(), "300-3ff:nx".parse().unwrap()];
+        machine.run(4);
+        assert_eq!(machine.chip8.memory[0], 0xf0, "Font was overwritten");
+        assert_eq!(machine.chip8.diagnostics.pcs(Diagnostic::ReadOnlyWrite), [0x204]);
+        machine.step();
+        assert_eq!(machine.chip8.diagnostics.pcs(Diagnostic::NoExecute), [0x300]);
+    }
+
+    #[test]
+    fn wraps_memory_past_the_end() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetIndexRegister { value: 0xfff },
+            Instruction::SetRegister { register: 0, value: 0x20 },
+            Instruction::SetRegister { register: 1, value: 0x21 },
+            Instruction::StoreMemory { register: 1 },
+            Instruction::FontChar { register: 0 },
+        ]);
+        machine.run(5);
+        assert_eq!(machine.chip8.memory[0xfff], 0x20);
+        assert_eq!(machine.chip8.memory[0], 0x21);
+        assert_eq!(machine.chip8.index_register.0, 0);
+        assert_eq!(machine.chip8.diagnostics.pcs(Diagnostic::MemoryWrapped), [0x206]);
+        assert_eq!(machine.chip8.diagnostics.pcs(Diagnostic::FontDigitOutOfRange), [0x208]);
+    }
+
+    #[test]
+    fn skips_over_long_index_loads() {
+        for platform in [Platform::XoChip, Platform::Schip] {
+            let mut machine = Machine::from_instructions(&[
+                Instruction::SkipEQ { register: 0, value: 0 },
+                Instruction::LongIndex,
+                Instruction::Jump { dest: 0x300 },
+            ]).on(platform);
+            machine.step();
+            // F000's address word, 0x1300, is a jump to 0x300 on its own
+            match platform {
+                Platform::XoChip => assert_eq!(machine.chip8.pc, 0x206),
+                _ => assert_eq!(machine.chip8.pc, 0x204),
+            }
         }
+
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SkipPressed { register: 0 },
+            Instruction::LongIndex,
+            Instruction::Jump { dest: 0x300 },
+        ]).on(Platform::XoChip);
+        // Skips that aren't taken run the F000 as usual
+        machine.run(2);
+        assert_eq!((machine.chip8.pc, machine.chip8.index_register.0), (0x206, 0x1300));
+    }
+
+    #[test]
+    fn handles_odd_jumps_by_policy() {
+        let program = [
+            Instruction::CallSubroutine { dest: 0x204 },
+            Instruction::Jump { dest: 0x200 },
+            Instruction::Jump { dest: 0x207 },
+        ];
+        let mut machine = Machine::from_instructions(&program);
+        machine.run(2);
+        assert_eq!(machine.chip8.pc, 0x207);
+        assert_eq!(machine.chip8.diagnostics.pcs(Diagnostic::OddJump), [0x204]);
+
+        let mut machine = Machine::from_instructions(&program);
+        machine.chip8.odd_pc = Policy::Allow;
+        machine.run(2);
+        assert_eq!(machine.chip8.pc, 0x207);
+        assert!(machine.chip8.diagnostics.is_empty());
+
+        let mut machine = Machine::from_instructions(&program);
+        machine.chip8.odd_pc = Policy::Error;
+        machine.step();
+        let crash = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| machine.step()));
+        let message = crash.expect_err("Should have stopped").downcast::<String>().unwrap();
+        assert!(message.starts_with("Instruction at 0x204 went to the odd address 0x207"), "{}", message);
     }
 
     use proptest::prelude::*;
-    use rand::prelude::*;
+    use rand_core::{RngCore, SeedableRng};
+    use rand_xoshiro::Xoroshiro64StarStar;
     proptest! {
         #[test]
         fn instruction_tests(
             a in 0..u8::MAX,
             b in 0..u8::MAX,
-            r1 in 0..15 as u8,
-            r2 in 0..15 as u8
+            r1 in 0..15_u8,
+            r2 in 0..15_u8
         ) {
-            let mut chip8 = Chip8::new(Instant::now());
-            chip8.execute(Instruction::SetRegister { register: r1, value: a as u8 }, None);
-            assert_eq!(chip8.registers[r1 as usize].0, a as u8);
-            chip8.execute(Instruction::SetRegister { register: r2, value: b as u8 }, None);
-            assert_eq!(chip8.registers[r2 as usize].0, b as u8);
-            chip8.execute(Instruction::MovRegister { register1: r1, register2: r2 }, None);
+            let mut chip8 = Chip8::new(Platform::default());
+            chip8.execute(Instruction::SetRegister { register: r1, value: a }, [false; 16]);
+            assert_eq!(chip8.registers[r1 as usize].0, a);
+            chip8.execute(Instruction::SetRegister { register: r2, value: b }, [false; 16]);
+            assert_eq!(chip8.registers[r2 as usize].0, b);
+            chip8.execute(Instruction::MovRegister { register1: r1, register2: r2 }, [false; 16]);
             assert_eq!(chip8.registers[r1 as usize], chip8.registers[r2 as usize]);
-            chip8.execute(Instruction::Add { register1: r1, register2: r2 }, None);
+            chip8.execute(Instruction::Add { register1: r1, register2: r2 }, [false; 16]);
             assert_eq!(chip8.registers[r1 as usize], Wrapping(b) + Wrapping(b));
         }
 
@@ -529,34 +2752,34 @@ mod tests {
             b in 0..(1 << 4),
             c in 0..(1 << 4),
         ) {
-            let mut chip8 = Chip8::new(Instant::now());
-            chip8.execute(Instruction::Draw {x_r: a as u8, y_r: b as u8, height:c as u8}, None);
+            let mut chip8 = Chip8::new(Platform::default());
+            chip8.execute(Instruction::Draw {x_r: a as u8, y_r: b as u8, height:c as u8}, [false; 16]);
         }
 
         #[test]
         fn memory_bothways(
             register in 0..(1 << 4) as u8,
             mem in 0..(1 << 11) as u16,
-            seed in 0..32 as u64
+            seed in 0..32_u64
         ) {
-            let mut rng = StdRng::seed_from_u64(seed);
-            let mut chip8 = Chip8::new(Instant::now());
+            let mut rng = Xoroshiro64StarStar::seed_from_u64(seed);
+            let mut chip8 = Chip8::new(Platform::default());
             let mut vals = Vec::new();
             for i in 0..=register {
-                let value = rng.gen();
+                let value = rng.next_u32() as u8;
                 vals.push(value);
-                chip8.execute(Instruction::SetRegister { register: i, value }, None);
+                chip8.execute(Instruction::SetRegister { register: i, value }, [false; 16]);
                 assert_eq!(chip8.registers[i as usize].0, value);
             }
-            chip8.execute(Instruction::SetIndexRegister { value: mem }, None);
-            assert_eq!(chip8.index_register.0, mem);
-            chip8.execute(Instruction::StoreMemory { register: register }, None);
+            chip8.execute(Instruction::SetIndexRegister { value: mem }, [false; 16]);
+            assert_eq!(chip8.index_register.0, mem as u32);
+            chip8.execute(Instruction::StoreMemory { register }, [false; 16]);
             for i in 0..=register {
                 assert_eq!(vals[i as usize], chip8.memory[(mem + i as u16) as usize]);
-                chip8.execute(Instruction::SetRegister { register: i , value: 0 }, None);
+                chip8.execute(Instruction::SetRegister { register: i , value: 0 }, [false; 16]);
                 assert_eq!(chip8.registers[i as usize].0, 0);
             }
-            chip8.execute(Instruction::LoadMemory { register: register }, None);
+            chip8.execute(Instruction::LoadMemory { register }, [false; 16]);
             for i in 0..=register {
                 assert_eq!(chip8.registers[i as usize].0, vals[i as usize]);
             }
@@ -566,14 +2789,12 @@ mod tests {
         fn timers_work(
             dur in 0..(1 << 4) as u8,
         ) {
-            let mut time = Instant::now();
-            let mut chip8 = Chip8::new(time);
-            chip8.execute(Instruction::SetRegister { register: 0, value: dur }, None);
-            chip8.execute(Instruction::SetDelayTimer { register: 0 }, None);
+            let mut chip8 = Chip8::new(Platform::default());
+            chip8.execute(Instruction::SetRegister { register: 0, value: dur }, [false; 16]);
+            chip8.execute(Instruction::SetDelayTimer { register: 0 }, [false; 16]);
             for _ in 0..dur {
                 assert!(chip8.delay_timer > 0);
-                time += Duration::from_secs_f32(1.0) / 60;
-                chip8.update_timers(time);
+                chip8.update_timers(Duration::from_nanos(16_666_667));
             }
             assert!(chip8.delay_timer == 0);
         }
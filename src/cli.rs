@@ -0,0 +1,845 @@
+use std::time::Duration;
+use crate::accesslog::Destination;
+use crate::audio::{parse_frequency, parse_volume, Buzzer};
+use crate::bench::Profile;
+use crate::breakpoints::Breakpoints;
+use crate::asm::parse_address;
+use crate::chip8::{Chip8, Rect};
+use crate::diagnostics::Policy;
+use crate::disasm::Format;
+use crate::platform::Platform;
+use crate::protection::Region;
+use crate::quirks::Quirks;
+use crate::rom::RomSource;
+use crate::savestate::SLOTS;
+use crate::trace::TraceFilter;
+
+pub const USAGE: &str = "\
+Usage:
+    chip8 <rom> [--max-frameskip <n>] [--record <file>] [--no-auto-speed] [--ips <n>]
+                [--protect <region>]... [--break-on-...] [--practice] [--checkpoint <addr>]...
+                                         Run a ROM, skipping at most n frames in a row
+                                         when running behind or in turbo (default 4),
+                                         and optionally save a replay on exit (recorded
+                                         since the last rewind or state load). The speed,
+                                         shown in the title bar, goes up if the program can't
+                                         keep up with the delay timer, unless turned off or
+                                         set with --ips or +/-.
+                                         F10 saves about the last 30 seconds as a replay
+                                         next to the ROM, to play or render-replay (e.g. to .gif).
+                                         F1 tints pixels by when they were drawn in the latest
+                                         frame, from blue for the first draw to red for the last.
+                                         Home logs how long each keypad press takes to show on
+                                         screen, in frames and milliseconds, until pressed again.
+                                         --practice turns on practice mode: End takes a
+                                         checkpoint, and Delete goes straight back to it to try
+                                         a hard part again. --checkpoint (which implies it) also
+                                         takes one whenever the program gets to an address, like
+                                         the start of a level. It's kept as <rom>.practice.
+                                         If <rom>.score gives the score's address and format
+                                         (address = 0x2f0, format = byte, word or bcd <digits>),
+                                         each session's best goes on the ROM's leaderboard
+    chip8 share <rom> [--slot <n>] [--embed-rom] [--output <file>]
+                                         Copy the state saved in a slot (default 1) to a file to
+                                         send with a bug report or as a challenge, <rom>.c8ss
+                                         unless given, with the ROM in it too if --embed-rom,
+                                         so it can be loaded without the ROM. Load it with --state
+    chip8 leaderboard <rom> [--output <file>]
+                                         List the best sessions' scores for the ROM, as kept in
+                                         <rom>.scores, and optionally write the list to a file
+    chip8 play <replay>                  Play a replay: Space pauses, 1/2/4 set the speed,
+                                         Left/Right step a frame, PageUp/PageDown jump a tenth
+                                         of the way, Home/End go to either end, or click the
+                                         timeline to seek
+    chip8 render-replay <replay> <output> [--scale <n>]
+                                         Encode a replay to a video file (e.g. .mp4 or .webm)
+                                         with ffmpeg, scaling pixels up n times (default 10)
+    chip8 trim <rom> [--output <file>] [--profile NAME]
+                                         Report the ROM's size without trailing zero padding,
+                                         warning if it's more than the profile (as below) can
+                                         load, and optionally write the trimmed ROM
+    chip8 disasm <rom> [--format text|json] [--profile NAME]
+                                         Disassemble the code reachable from the start, with
+                                         the rest as data, labels from <rom>.sym, and what jumps
+                                         to, calls, points I at, reads or writes each address,
+                                         as far as it can tell without running it. json gives an array
+                                         of objects with address, bytes, kind (code or data),
+                                         label, mnemonic, operands and xrefs, for other tools.
+                                         --profile (as below) says where it's loaded, e.g. 0x300
+                                         for chip8x
+    chip8 diff <old rom> <new rom> [--profile NAME]
+                                         Disassemble both ROMs and list the instructions and data
+                                         that differ, with their addresses in each
+    chip8 run-headless <rom> [--cycles <n>] [--hash] [--expect-hash <hex>] [--screenshot <file.ppm>]
+                       [--lit-pixels] [--region-hash X,Y,W,H]... [--read-digits X,Y,W,H]...
+                       [--trace] [--trace-only <scope>]... [--trace-skip <scope>]... [--progress] [--script]
+                       [--access-log <file>|tcp:HOST:PORT]
+                                         Run without a window until n cycles or the program
+                                         halts (jumps to itself), then print the screen's hash,
+                                         check it, and/or save it, and print the number of lit
+                                         pixels, hashes of parts of the screen, and the font
+                                         digits in parts of it (e.g. a score). --trace prints
+                                         each instruction to stderr as it runs; --trace-only and
+                                         --trace-skip narrow that to or leave out a hex range of
+                                         addresses like 200-2ff, or a subroutine by its symbol
+                                         (or sub_XXX), along with everything it calls. --progress
+                                         prints cycles, frames and emulated time every second.
+                                         --script runs commands from stdin instead of n cycles,
+                                         one a line, each answered with a line on stdout:
+                                         step [n] and frames [n] run n instructions or 60ths
+                                         of a second (ok, or halted if it stopped first),
+                                         key <0-f> down|up (ok), dump (cycles, PC, I, timers
+                                         and registers), hash, screen (WxH, then its rows),
+                                         and quit, or the end of stdin, to finish.
+                                         --access-log writes each byte instructions read or
+                                         write as CSV lines of cycle,pc,address,read|write,value,
+                                         gzipped to the file, or as they happen to the socket
+    chip8 bench <rom> [--cycles <n>] [--profile NAME=QUIRK,...]...
+                                         Time n cycles (default 1000000) under each profile of
+                                         quirks (by default none, then each quirk alone), and find
+                                         the first cycle each behaves differently from the first
+    chip8 soak <rom> [--hours <n>] [--seed <n>] [--record <file>]
+                                         Play for n hours of emulated time (default 1) without a
+                                         window, pressing keys like a player would, mostly ones the
+                                         program checks, and stop if the core crashes, the PC
+                                         leaves memory or the stack gets more than 16 calls deep.
+                                         Random numbers come from the seed, which is printed so a
+                                         run can be repeated, and --record saves a replay of a run
+                                         that found a problem
+    chip8 audit <rom> [--seconds <n>] [--seed <n>]
+                                         Check the core is deterministic: run the ROM twice at
+                                         once on separate threads, for n seconds of emulated time
+                                         (default 60) with the same seed and the same keys pressed
+                                         as soak would, and compare the whole state at the end of
+                                         every frame. Exits with 1 at the first frame that differs
+    chip8 latency <rom> [--key <0-f>] [--presses <n>] [--seconds <n>]
+                                         Measure input latency: run the ROM without a window for n
+                                         seconds of emulated time (default 3), then press the key
+                                         (default 5) n times (default 10), each once the screen has
+                                         been still for a moment, and print how many frames and
+                                         milliseconds it took the screen to change. Exits with 1
+                                         if it never did
+    chip8 thumbnails <dir> [--seconds <n>] [--scale <n>] [--profile ...] [--quirk ...]...
+                                         Run each ROM in the directory (.ch8, .c8, .sc8 and .xo8)
+                                         without a window for n seconds of emulated time (default 3),
+                                         with no keys pressed, and save the screen next to it as
+                                         <rom>.png, 128x64 times the scale (default 2) in either
+                                         resolution. .sc8 and .xo8 ROMs run as schip and xochip,
+                                         the rest as --profile. Exits with 1 if any ROM failed
+
+Every way of running (in a window, run-headless, soak, audit and latency) takes:
+    -                                    In place of the ROM (here or for trim), reads it from stdin,
+                                         e.g. assembler | chip8 run-headless - --hash
+    --stdin                              Read the ROM from stdin as hex or base64 text instead of
+                                         a file, e.g. xclip -o | chip8 --stdin
+    --protect START-END:FLAGS            Stop the program writing memory (flag ro) or warn when
+                                         it runs code there (flag nx), with addresses in hex,
+                                         e.g. --protect 0-1ff:ro,nx for the interpreter and font
+    --profile NAME                       The interpreter the ROM was written for: chip8 (default),
+                                         schip, xochip, hires, chip8x or megachip,
+                                         which sets the instructions it can use, the quirks it
+                                         starts with and the speed: chip8 runs at 500 instructions
+                                         a second, schip at 1000 with jump_with_vx, and xochip at
+                                         1200 with shift_uses_vy, increment_index and no_clip_sprites.
+                                         hires is the VIP's 64x64 two-page CHIP-8, with 0230 to clear
+                                         the screen and the VIP's quirks and speed. chip8 ROMs that
+                                         start with 1260, as hires ROMs do, run as hires.
+                                         chip8x is the VIP with its colour board, loading at 0x300,
+                                         with the colour opcodes, and the numpad as the second keypad.
+                                         megachip is SCHIP with MEGA-CHIP's 256x192 colour screen
+                                         and ROMs of up to 16M, at 3000
+    --ips <n>                            Run n instructions per second (60 to 20000) instead of the
+                                         profile's speed (not thumbnails). In a window, +/- still change it,
+                                         and there's no automatic speed
+    --quirk NAME                         Change an instruction to behave like another interpreter's
+                                         (repeatable), or no_NAME to turn a quirk off. These go on
+                                         top of the ROM's own quirks, kept as names separated by
+                                         spaces or commas in <rom>.quirks, e.g. no_clip_sprites:
+                                         shift_uses_vy    8XY6/8XYE shift VY into VX
+                                         increment_index  FX55/FX65 leave I past the registers
+                                         vf_reset         8XY1/8XY2/8XY3 reset VF
+                                         clip_sprites     Sprites are cut off at the edges (on)
+                                         display_wait     Draws wait for the next frame
+                                         jump_with_vx     BXNN jumps to XNN + VX
+                                         vip_timing       Instructions take as long as on the VIP
+    --odd-pc allow|warn|error            What to do when a jump or call goes to an odd address,
+                                         which reads every instruction after a byte out: nothing,
+                                         warn once per instruction (the default), or stop
+    --state <file>                       Start from a save state, e.g. one made by share, if it was
+                                         made with the ROM. Leave the ROM out to use the one in
+                                         the state, if it has one (not soak)
+    --strict                             Print counts of everything questionable the program did
+                                         on exit (except soak); run-headless also fails if there was anything.
+                                         Without it, run-headless still sums them up on stderr, errors
+                                         first, and a window shows warnings in its title bar as they happen
+    --slow-instruction <ms>              Print a SLOW: line for each instruction that takes longer
+                                         than this in real time, counting tracing, breakpoints,
+                                         snapshots and anything else done for it, with its cycle
+                                         and address (in a window and run-headless, which also
+                                         prints the slowest at the end)
+    --wav <file>                         Write the beeper to a WAV file, each beep starting and
+                                         stopping on the sample for its emulated time, to go with
+                                         a screen recording. run-headless writes the whole run
+                                         (but not with --script); a window writes what --record
+                                         would save, on exit, by playing it back
+
+Running in a window also takes these, which pause in the debugger when something happens:
+    --break-on-sound                     The sound timer is set, or runs out
+    --break-on-clear                     00E0 clears the screen
+    --break-on-draw X,Y,W,H              A draw changes a pixel in the rectangle (repeatable)
+
+Running in a window, run-headless and render-replay also take these, for the beeper:
+    --waveform square|triangle|sine      The shape of its wave (default square)
+    --beep-frequency <hz>                Its pitch (default 440)
+    --volume <percent>                   How loud it is (default 100)
+They can also be set in ~/.config/chip8/settings with lines like `waveform = sine`,
+`beep_frequency = 220` and `volume = 50`. XO-CHIP audio patterns keep their own pitch
+and shape, but play at the volume.
+
+Running a ROM or a replay in a window needs chip8 built with --features gui.
+The window closes by itself when a SCHIP program exits with 00FD.
+With `boot_animation = on` in ~/.config/chip8/settings, the window draws the IBM logo
+before starting the ROM; any key skips it. With `announce = on`, it prints a line like
+`ANNOUNCE: State saved to slot 1` whenever it pauses or resumes, hits a breakpoint, loads
+a ROM, saves or loads a state, takes or goes back to a practice checkpoint, changes speed, opens or closes a window, or the program
+does something questionable, for screen readers following the terminal. Everything in
+the window has a key, so no mouse is needed.
+The RPL flags SCHIP programs save with FX75 (often high scores) are kept next to the ROM
+as <rom>.rpl when running in a window, and loaded again next time.
+
+run-headless exits with 0 if it ran every cycle, 1 if --strict found anything,
+2 for bad arguments, 3 if the program halted (or exited) first, 4 if the program crashed the core,
+and 5 if the screen didn't match --expect-hash. soak exits with 0 if it played the whole
+time, 3 if the program halted first, and 4 for a crash or anything else it caught.
+
+Hotkeys while running can be rebound in ~/.config/chip8/hotkeys with lines like
+`pause = Space` or `faster = Equals, NumpadAdd`, using winit's key names. The actions are
+quit, pause, step, step_back, rewind, turbo, save_state, load_state, slot_picker, faster,
+slower, memory_view, mirror (F8 shows the display in a second window too, e.g. for a
+projector; fullscreen applies to whichever has focus), screenshot, fullscreen, paste_rom
+(load a hex or base64 ROM from the clipboard, Insert by default), and look, look_up and look_down (F7 picks phosphor
+persistence, brightness or bloom, PageUp/PageDown adjust it, and it's saved next to the ROM
+in <rom>.look, where a line like `palette = 000000 ff0000 40a0ff ffffff` sets the colours of
+unlit pixels, the first plane, XO-CHIP's second plane and both, and `effects = flash rainbow`
+lights the background while the sound plays and cycles the lit colours' hues),
+assemble (F3 pauses and takes a line like `0x220: LD V1, 0x05` in the title bar,
+patching that instruction into memory on Enter, or Escape to cancel; `0x300?` instead
+prints what jumps to, calls, points I at, reads or writes that address so far), and experiment
+(F4 pauses, then runs the next 5000 cycles twice for each quirk, with and without it
+flipped, and prints how the state and screen differ), and with --practice, checkpoint and retry.
+Keys the keypad uses can't be bound.";
+
+pub const DEFAULT_HEADLESS_CYCLES: u64 = 10_000;
+pub const DEFAULT_SOAK_TIME: Duration = Duration::from_secs(60 * 60);
+pub const DEFAULT_AUDIT_TIME: Duration = Duration::from_secs(60);
+pub const DEFAULT_BENCH_CYCLES: u64 = 1_000_000;
+pub const DEFAULT_MAX_FRAMESKIP: u32 = 4;
+/// The speeds --ips and the window's +/- keep to
+pub const MIN_CLOCK_SPEED: u32 = 60;
+pub const MAX_CLOCK_SPEED: u32 = 20_000;
+pub const DEFAULT_VIDEO_SCALE: u32 = 10;
+pub const DEFAULT_THUMBNAIL_WARM_UP: Duration = Duration::from_secs(3);
+pub const DEFAULT_THUMBNAIL_SCALE: u32 = 2;
+pub const DEFAULT_LATENCY_WARM_UP: Duration = Duration::from_secs(3);
+pub const DEFAULT_LATENCY_KEY: usize = 5;
+pub const DEFAULT_LATENCY_PRESSES: u32 = 10;
+
+/// How to set up the machine before running, for every way of running
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MachineOptions {
+    pub platform: Platform,
+    pub protect: Vec<Region>,
+    /// Quirks as for `Quirks::apply`, checked when parsed
+    pub quirks: Vec<String>,
+    pub odd_pc: Policy,
+    /// Instructions per second, instead of the speed the platform and quirks call for
+    pub ips: Option<u32>,
+}
+
+impl MachineOptions {
+    /// Call after the ROM and its quirks are loaded, since these override them
+    pub fn apply(&self, chip8: &mut Chip8) {
+        chip8.regions = self.protect.clone();
+        chip8.odd_pc = self.odd_pc;
+        self.apply_quirks(&mut chip8.quirks);
+    }
+
+    /// The speed to run `chip8` at once these are applied: `--ips`, or its own
+    pub fn clock_speed(&self, chip8: &Chip8) -> u32 {
+        self.ips.unwrap_or_else(|| chip8.clock_speed())
+    }
+
+    pub fn apply_quirks(&self, quirks: &mut Quirks) {
+        for spec in &self.quirks {
+            quirks.apply(spec).expect("Quirks are checked when parsed");
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Run {
+        rom: RomSource,
+        max_frameskip: u32,
+        record: Option<String>,
+        machine: MachineOptions,
+        strict: bool,
+        breakpoints: Breakpoints,
+        auto_speed: bool,
+        /// A save state to start from
+        state: Option<String>,
+        buzzer: Buzzer,
+        slow_instruction: Option<Duration>,
+        /// Where to write the beeper's audio as a WAV file
+        wav: Option<String>,
+        /// Practice mode, with the addresses that take a checkpoint when the program gets to them
+        practice: Option<Vec<usize>>,
+    },
+    Trim { rom: String, output: Option<String>, platform: Platform },
+    Leaderboard { rom: String, output: Option<String> },
+    Disasm { rom: String, format: Format, platform: Platform },
+    Diff { old: String, new: String, platform: Platform },
+    RunHeadless {
+        rom: RomSource,
+        cycles: u64,
+        hash: bool,
+        expect_hash: Option<u64>,
+        lit_pixels: bool,
+        region_hashes: Vec<Rect>,
+        digit_regions: Vec<Rect>,
+        screenshot: Option<String>,
+        trace: Option<TraceFilter>,
+        progress: bool,
+        access_log: Option<Destination>,
+        slow_instruction: Option<Duration>,
+        /// Take commands from stdin rather than running `cycles`
+        script: bool,
+        machine: MachineOptions,
+        strict: bool,
+        state: Option<String>,
+        buzzer: Buzzer,
+        wav: Option<String>,
+    },
+    Bench { rom: RomSource, cycles: u64, profiles: Vec<Profile> },
+    Soak { rom: RomSource, duration: Duration, seed: Option<u64>, record: Option<String>, machine: MachineOptions },
+    Audit { rom: RomSource, duration: Duration, seed: Option<u64>, machine: MachineOptions },
+    Latency { rom: RomSource, key: usize, presses: u32, warm_up: Duration, machine: MachineOptions },
+    Thumbnails { dir: String, warm_up: Duration, scale: u32, machine: MachineOptions },
+    Play { replay: String },
+    RenderReplay { replay: String, output: String, scale: u32, buzzer: Buzzer },
+    Share { rom: String, slot: u8, embed_rom: bool, output: Option<String> },
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    let first = args.peek().ok_or("No ROM given")?;
+    let command = match first.as_str() {
+        "trim" | "disasm" | "diff" | "run-headless" | "bench" | "soak" | "audit" | "latency" | "thumbnails" | "play" | "render-replay" | "share" | "leaderboard" => args.next().unwrap(),
+        _ => String::from("run"),
+    };
+    let mut rom = None;
+    let mut max_frameskip = DEFAULT_MAX_FRAMESKIP;
+    let mut output = None;
+    let mut other = None;
+    let mut format = Format::Text;
+    let mut cycles = None;
+    let mut hash = false;
+    let mut expect_hash = None;
+    let mut lit_pixels = false;
+    let mut region_hashes = Vec::new();
+    let mut digit_regions = Vec::new();
+    let mut screenshot = None;
+    let mut trace: Option<TraceFilter> = None;
+    let mut progress = false;
+    let mut access_log = None;
+    let mut script = false;
+    let mut record = None;
+    let mut scale = None;
+    let mut machine = MachineOptions::default();
+    let mut strict = false;
+    let mut stdin = false;
+    let mut breakpoints = Breakpoints::default();
+    let mut auto_speed = true;
+    let mut profiles = Vec::new();
+    let mut soak_time = DEFAULT_SOAK_TIME;
+    let mut seed = None;
+    let mut seconds = None;
+    let mut state = None;
+    let mut slot = 1;
+    let mut embed_rom = false;
+    let mut key = DEFAULT_LATENCY_KEY;
+    let mut presses = DEFAULT_LATENCY_PRESSES;
+    let mut buzzer = Buzzer::default();
+    let mut slow_instruction = None;
+    let mut wav = None;
+    let mut practice = None;
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("{} needs a value", flag));
+        match (command.as_str(), arg.as_str()) {
+            ("run", "--max-frameskip") => {
+                max_frameskip = value(&arg)?.parse().map_err(|e| format!("Bad --max-frameskip: {}", e))?;
+            },
+            ("run" | "soak", "--record") => record = Some(value(&arg)?),
+            ("run", "--no-auto-speed") => auto_speed = false,
+            ("run", "--practice") => {
+                practice.get_or_insert_with(Vec::new);
+            },
+            ("run", "--checkpoint") => {
+                let address = parse_address(&value(&arg)?).map_err(|e| format!("Bad --checkpoint: {}", e))?;
+                practice.get_or_insert_with(Vec::new).push(address);
+            },
+            ("run", "--break-on-sound") => breakpoints.sound = true,
+            ("run", "--break-on-clear") => breakpoints.clear = true,
+            ("run", "--break-on-draw") => {
+                breakpoints.draws.push(value(&arg)?.parse().map_err(|e| format!("Bad --break-on-draw: {}", e))?);
+            },
+            ("run" | "run-headless", "--strict") => strict = true,
+            ("run" | "run-headless" | "soak" | "audit" | "latency", "--stdin") => stdin = true,
+            ("run" | "run-headless" | "soak" | "audit" | "latency", "--protect") => {
+                machine.protect.push(value(&arg)?.parse().map_err(|e| format!("Bad --protect: {}", e))?);
+            },
+            ("run" | "run-headless" | "soak" | "audit" | "latency", "--odd-pc") => machine.odd_pc = value(&arg)?.parse()?,
+            ("run" | "run-headless" | "soak" | "audit" | "latency", "--ips") => {
+                let ips: u32 = value(&arg)?.parse().map_err(|e| format!("Bad --ips: {}", e))?;
+                if !(MIN_CLOCK_SPEED..=MAX_CLOCK_SPEED).contains(&ips) {
+                    return Err(format!("Bad --ips: expected {} to {}", MIN_CLOCK_SPEED, MAX_CLOCK_SPEED));
+                }
+                machine.ips = Some(ips);
+            },
+            ("run" | "run-headless" | "soak" | "audit" | "latency" | "thumbnails" | "trim" | "disasm" | "diff", "--profile") => machine.platform = value(&arg)?.parse()?,
+            ("run" | "run-headless" | "soak" | "audit" | "latency" | "thumbnails", "--quirk") => {
+                let spec = value(&arg)?;
+                Quirks::default().apply(&spec)?;
+                machine.quirks.push(spec);
+            },
+            ("run" | "run-headless", "--state") => state = Some(value(&arg)?),
+            ("run" | "run-headless", "--wav") => wav = Some(value(&arg)?),
+            ("run" | "run-headless", "--slow-instruction") => {
+                let millis: f64 = value(&arg)?.parse().map_err(|e| format!("Bad --slow-instruction: {}", e))?;
+                slow_instruction = Some(
+                    Duration::try_from_secs_f64(millis / 1000.0)
+                        .ok()
+                        .filter(|threshold| !threshold.is_zero())
+                        .ok_or_else(|| format!("Bad --slow-instruction: {}", millis))?,
+                );
+            },
+            ("trim" | "share" | "leaderboard", "-o" | "--output") => output = Some(value(&arg)?),
+            ("share", "--slot") => {
+                slot = value(&arg)?.parse().ok().filter(|slot| (1..=SLOTS).contains(slot)).ok_or(format!("Bad --slot, expected 1 to {}", SLOTS))?;
+            },
+            ("share", "--embed-rom") => embed_rom = true,
+            ("disasm", "--format") => format = value(&arg)?.parse()?,
+            ("run-headless" | "bench", "--cycles") => {
+                cycles = Some(value(&arg)?.parse().map_err(|e| format!("Bad --cycles: {}", e))?);
+            },
+            ("bench", "--profile") => {
+                profiles.push(value(&arg)?.parse().map_err(|e| format!("Bad --profile: {}", e))?);
+            },
+            ("run-headless", "--hash") => hash = true,
+            ("run-headless", "--expect-hash") => {
+                let value = value(&arg)?;
+                expect_hash = Some(u64::from_str_radix(&value, 16).map_err(|e| format!("Bad --expect-hash: {}", e))?);
+            },
+            ("run-headless", "--lit-pixels") => lit_pixels = true,
+            ("run-headless", "--region-hash") => {
+                region_hashes.push(value(&arg)?.parse().map_err(|e| format!("Bad --region-hash: {}", e))?);
+            },
+            ("run-headless", "--read-digits") => {
+                digit_regions.push(value(&arg)?.parse().map_err(|e| format!("Bad --read-digits: {}", e))?);
+            },
+            ("run-headless", "--screenshot") => screenshot = Some(value(&arg)?),
+            ("run-headless", "--trace") => {
+                trace.get_or_insert_with(TraceFilter::default);
+            },
+            ("run-headless", "--trace-only") => {
+                let scope = value(&arg)?.parse().map_err(|e| format!("Bad --trace-only: {}", e))?;
+                trace.get_or_insert_with(TraceFilter::default).only.push(scope);
+            },
+            ("run-headless", "--trace-skip") => {
+                let scope = value(&arg)?.parse().map_err(|e| format!("Bad --trace-skip: {}", e))?;
+                trace.get_or_insert_with(TraceFilter::default).skip.push(scope);
+            },
+            ("run-headless", "--progress") => progress = true,
+            ("run-headless", "--script") => script = true,
+            ("run-headless", "--access-log") => {
+                access_log = Some(value(&arg)?.parse().map_err(|e| format!("Bad --access-log: {}", e))?);
+            },
+            ("soak", "--hours") => {
+                let hours: f64 = value(&arg)?.parse().map_err(|e| format!("Bad --hours: {}", e))?;
+                soak_time = Duration::try_from_secs_f64(hours * 3600.0)
+                    .ok()
+                    .filter(|time| !time.is_zero())
+                    .ok_or_else(|| format!("Bad --hours: {}", hours))?;
+            },
+            ("soak" | "audit", "--seed") => seed = Some(value(&arg)?.parse().map_err(|e| format!("Bad --seed: {}", e))?),
+            ("latency", "--key") => {
+                key = value(&arg)?.parse::<char>().ok().and_then(|key| key.to_digit(16)).ok_or("Bad --key, expected 0 to f")? as usize;
+            },
+            ("latency", "--presses") => {
+                presses = value(&arg)?.parse().ok().filter(|&presses| presses > 0).ok_or("Bad --presses, expected at least 1")?;
+            },
+            ("thumbnails" | "audit" | "latency", "--seconds") => {
+                let value: f64 = value(&arg)?.parse().map_err(|e| format!("Bad --seconds: {}", e))?;
+                seconds = Some(Duration::try_from_secs_f64(value).map_err(|_| format!("Bad --seconds: {}", value))?);
+            },
+            ("run" | "run-headless" | "render-replay", "--waveform") => buzzer.waveform = Some(value(&arg)?.parse()?),
+            ("run" | "run-headless" | "render-replay", "--beep-frequency") => buzzer.frequency = Some(parse_frequency(&value(&arg)?)?),
+            ("run" | "run-headless" | "render-replay", "--volume") => buzzer.volume = Some(parse_volume(&value(&arg)?)?),
+            ("render-replay" | "thumbnails", "--scale") => {
+                scale = Some(value(&arg)?.parse().map_err(|e| format!("Bad --scale: {}", e))?);
+            },
+            (_, _) if rom.is_none() && !arg.starts_with("--") => rom = Some(arg),
+            ("diff", _) if other.is_none() && !arg.starts_with("--") => other = Some(arg),
+            ("render-replay", _) if output.is_none() && !arg.starts_with("--") => output = Some(arg),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    let source = || match (rom.as_deref(), stdin) {
+        (Some(path), false) => Ok(RomSource::from(path)),
+        (None, true) => Ok(RomSource::PastedStdin),
+        (Some(_), true) => Err("Give either a ROM or --stdin, not both"),
+        (None, false) => state.as_deref().map(|state| RomSource::Embedded(state.to_string())).ok_or("No ROM given"),
+    };
+    match command.as_str() {
+        "trim" => Ok(Command::Trim { rom: rom.ok_or("No ROM given")?, output, platform: machine.platform }),
+        "leaderboard" => Ok(Command::Leaderboard { rom: rom.ok_or("No ROM given")?, output }),
+        "disasm" => Ok(Command::Disasm { rom: rom.ok_or("No ROM given")?, format, platform: machine.platform }),
+        "diff" => Ok(Command::Diff { old: rom.ok_or("No ROMs given")?, new: other.ok_or("No second ROM given")?, platform: machine.platform }),
+        "run-headless" if script && (cycles.is_some() || trace.is_some() || progress || access_log.is_some() || slow_instruction.is_some() || wav.is_some()) => {
+            Err(String::from("--script can't be used with --cycles, --trace, --progress, --access-log, --slow-instruction or --wav"))
+        },
+        "run-headless" if script && matches!(source()?, RomSource::Stdin | RomSource::PastedStdin) => {
+            Err(String::from("--script reads commands from stdin, so the ROM can't come from there too"))
+        },
+        "run-headless" => Ok(Command::RunHeadless {
+            rom: source()?,
+            cycles: cycles.unwrap_or(DEFAULT_HEADLESS_CYCLES),
+            hash,
+            expect_hash,
+            lit_pixels,
+            region_hashes,
+            digit_regions,
+            screenshot,
+            trace,
+            progress,
+            access_log,
+            slow_instruction,
+            script,
+            machine,
+            strict,
+            state,
+            buzzer,
+            wav,
+        }),
+        "bench" => Ok(Command::Bench { rom: source()?, cycles: cycles.unwrap_or(DEFAULT_BENCH_CYCLES), profiles }),
+        "soak" => Ok(Command::Soak { rom: source()?, duration: soak_time, seed, record, machine }),
+        "audit" => Ok(Command::Audit { rom: source()?, duration: seconds.unwrap_or(DEFAULT_AUDIT_TIME), seed, machine }),
+        "latency" => Ok(Command::Latency { rom: source()?, key, presses, warm_up: seconds.unwrap_or(DEFAULT_LATENCY_WARM_UP), machine }),
+        "thumbnails" => Ok(Command::Thumbnails {
+            dir: rom.ok_or("No directory given")?,
+            warm_up: seconds.unwrap_or(DEFAULT_THUMBNAIL_WARM_UP),
+            scale: scale.unwrap_or(DEFAULT_THUMBNAIL_SCALE),
+            machine,
+        }),
+        "play" => Ok(Command::Play { replay: rom.ok_or("No replay given")? }),
+        "render-replay" => {
+            let replay = rom.ok_or("No replay given")?;
+            Ok(Command::RenderReplay { replay, output: output.ok_or("No output file given")?, scale: scale.unwrap_or(DEFAULT_VIDEO_SCALE), buzzer })
+        },
+        "share" => Ok(Command::Share { rom: rom.ok_or("No ROM given")?, slot, embed_rom, output }),
+        _ => Ok(Command::Run { rom: source()?, max_frameskip, record, machine, strict, breakpoints, auto_speed, state, buzzer, slow_instruction, wav, practice }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::accesslog::Destination;
+    use crate::audio::{Buzzer, Waveform};
+    use crate::breakpoints::Breakpoints;
+    use crate::chip8::Rect;
+    use crate::diagnostics::Policy;
+    use crate::disasm::Format;
+    use crate::platform::Platform;
+    use crate::protection::Region;
+    use crate::rom::RomSource;
+    use crate::trace::{Scope, TraceFilter};
+    use super::{
+        parse, Command, MachineOptions, DEFAULT_AUDIT_TIME, DEFAULT_BENCH_CYCLES, DEFAULT_HEADLESS_CYCLES, DEFAULT_LATENCY_PRESSES, DEFAULT_LATENCY_WARM_UP,
+        DEFAULT_MAX_FRAMESKIP, DEFAULT_VIDEO_SCALE,
+    };
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(
+            parse(args(&["pong.ch8"])),
+            Ok(Command::Run {
+                rom: "pong.ch8".into(),
+                max_frameskip: DEFAULT_MAX_FRAMESKIP,
+                record: None,
+                machine: MachineOptions::default(),
+                strict: false,
+                breakpoints: Breakpoints::default(),
+                auto_speed: true,
+                state: None,
+                buzzer: Buzzer::default(),
+                slow_instruction: None,
+                wav: None,
+                practice: None,
+            })
+        );
+        assert_eq!(
+            parse(args(&[
+                "pong.ch8", "--max-frameskip", "0", "--record", "pong.c8r", "--protect", "0-1ff:ro", "--protect", "300-3ff:nx",
+                "--break-on-sound", "--break-on-draw", "0,0,64,5", "--break-on-draw", "60,30,4,2", "--no-auto-speed", "--waveform", "sine",
+                "--volume", "30", "--wav", "pong.wav", "--checkpoint", "0x2a0", "--practice", "--checkpoint", "0x31e",
+            ])),
+            Ok(Command::Run {
+                rom: "pong.ch8".into(),
+                max_frameskip: 0,
+                record: Some("pong.c8r".into()),
+                machine: MachineOptions {
+                    protect: vec![
+                        Region { range: 0..0x200, read_only: true, no_execute: false },
+                        Region { range: 0x300..0x400, read_only: false, no_execute: true },
+                    ],
+                    quirks: vec![],
+                    ..MachineOptions::default()
+                },
+                strict: false,
+                breakpoints: Breakpoints {
+                    sound: true,
+                    clear: false,
+                    draws: vec![Rect { x: 0, y: 0, width: 64, height: 5 }, Rect { x: 60, y: 30, width: 4, height: 2 }],
+                },
+                auto_speed: false,
+                state: None,
+                buzzer: Buzzer { waveform: Some(Waveform::Sine), frequency: None, volume: Some(30) },
+                slow_instruction: None,
+                wav: Some("pong.wav".into()),
+                practice: Some(vec![0x2a0, 0x31e]),
+            })
+        );
+        let practice = |extra: &[&str]| match parse(args(&[&["pong.ch8"], extra].concat())) {
+            Ok(Command::Run { practice, .. }) => practice,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(practice(&["--practice"]), Some(vec![]));
+        assert!(parse(args(&["pong.ch8", "--checkpoint", "0x1000"])).is_err());
+        assert_eq!(parse(args(&["play", "pong.c8r"])), Ok(Command::Play { replay: "pong.c8r".into() }));
+        assert_eq!(parse(args(&["trim", "pong.ch8"])), Ok(Command::Trim { rom: "pong.ch8".into(), output: None, platform: Platform::Chip8 }));
+        assert_eq!(
+            parse(args(&["trim", "--output", "out.ch8", "--profile", "megachip", "pong.ch8"])),
+            Ok(Command::Trim { rom: "pong.ch8".into(), output: Some("out.ch8".into()), platform: Platform::MegaChip })
+        );
+        assert_eq!(
+            parse(args(&["run-headless", "pong.ch8", "--hash"])),
+            Ok(Command::RunHeadless {
+                rom: "pong.ch8".into(),
+                cycles: DEFAULT_HEADLESS_CYCLES,
+                hash: true,
+                expect_hash: None,
+                lit_pixels: false,
+                region_hashes: vec![],
+                digit_regions: vec![],
+                screenshot: None,
+                trace: None,
+                progress: false,
+                access_log: None,
+                slow_instruction: None,
+                script: false,
+                machine: MachineOptions::default(),
+                strict: false,
+                state: None,
+                buzzer: Buzzer::default(),
+                wav: None,
+            })
+        );
+        assert_eq!(
+            parse(args(&[
+                "run-headless", "--cycles", "5", "pong.ch8", "--screenshot", "out.ppm", "--strict", "--expect-hash", "6a28812bbb1e40cd",
+                "--lit-pixels", "--region-hash", "0,0,64,5", "--read-digits", "40,0,24,5", "--progress", "--profile", "schip", "--quirk", "jump_with_vx", "--quirk", "no_clip_sprites",
+                "--odd-pc", "error", "--ips", "2000", "--slow-instruction", "0.5", "--wav", "out.wav", "--beep-frequency", "880",
+            ])),
+            Ok(Command::RunHeadless {
+                rom: "pong.ch8".into(),
+                cycles: 5,
+                hash: false,
+                expect_hash: Some(0x6a28812bbb1e40cd),
+                lit_pixels: true,
+                region_hashes: vec![Rect { x: 0, y: 0, width: 64, height: 5 }],
+                digit_regions: vec![Rect { x: 40, y: 0, width: 24, height: 5 }],
+                screenshot: Some("out.ppm".into()),
+                trace: None,
+                progress: true,
+                access_log: None,
+                slow_instruction: Some(Duration::from_micros(500)),
+                script: false,
+                machine: MachineOptions {
+                    platform: Platform::Schip,
+                    protect: vec![],
+                    quirks: vec!["jump_with_vx".into(), "no_clip_sprites".into()],
+                    odd_pc: Policy::Error,
+                    ips: Some(2000),
+                },
+                strict: true,
+                state: None,
+                buzzer: Buzzer { frequency: Some(880), ..Buzzer::default() },
+                wav: Some("out.wav".into()),
+            })
+        );
+        let trace = |extra: &[&str]| match parse(args(&[&["run-headless", "pong.ch8"], extra].concat())) {
+            Ok(Command::RunHeadless { trace, .. }) => trace,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(trace(&[]), None);
+        assert_eq!(trace(&["--trace"]), Some(TraceFilter::default()));
+        assert_eq!(
+            trace(&["--trace-only", "draw", "--trace-skip", "300-3ff"]),
+            Some(TraceFilter { only: vec![Scope::Subroutine("draw".into())], skip: vec![Scope::Range(0x300..0x400)] })
+        );
+        assert!(parse(args(&["run-headless", "pong.ch8", "--trace-skip", "3ff-300"])).is_err());
+        assert!(matches!(parse(args(&["run-headless", "pong.ch8", "--script"])), Ok(Command::RunHeadless { script: true, .. })));
+        assert!(parse(args(&["run-headless", "pong.ch8", "--script", "--cycles", "5"])).is_err());
+        assert!(matches!(
+            parse(args(&["run-headless", "pong.ch8", "--access-log", "tcp:localhost:9000"])),
+            Ok(Command::RunHeadless { access_log: Some(Destination::Socket(_)), .. })
+        ));
+        assert!(parse(args(&["run-headless", "pong.ch8", "--access-log", "tcp:localhost"])).is_err());
+        assert!(parse(args(&["run-headless", "pong.ch8", "--script", "--access-log", "reads.csv.gz"])).is_err());
+        assert!(parse(args(&["run-headless", "-", "--script"])).is_err());
+        assert!(parse(args(&["run-headless", "pong.ch8", "--slow-instruction", "0"])).is_err());
+        assert!(parse(args(&["run-headless", "pong.ch8", "--script", "--slow-instruction", "1"])).is_err());
+        assert!(parse(args(&["run-headless", "pong.ch8", "--script", "--wav", "out.wav"])).is_err());
+        assert!(parse(args(&[])).is_err());
+        assert!(parse(args(&["trim"])).is_err());
+        assert!(parse(args(&["trim", "pong.ch8", "-o"])).is_err());
+        assert_eq!(parse(args(&["diff", "a.ch8", "b.ch8"])), Ok(Command::Diff { old: "a.ch8".into(), new: "b.ch8".into(), platform: Platform::Chip8 }));
+        assert!(parse(args(&["diff", "a.ch8"])).is_err());
+        assert_eq!(parse(args(&["disasm", "a.ch8"])), Ok(Command::Disasm { rom: "a.ch8".into(), format: Format::Text, platform: Platform::Chip8 }));
+        assert_eq!(
+            parse(args(&["disasm", "--format", "json", "--profile", "chip8x", "a.ch8"])),
+            Ok(Command::Disasm { rom: "a.ch8".into(), format: Format::Json, platform: Platform::Chip8X })
+        );
+        assert!(parse(args(&["disasm", "a.ch8", "--format", "xml"])).is_err());
+        assert!(parse(args(&["diff", "a.ch8", "b.ch8", "c.ch8"])).is_err());
+        assert!(parse(args(&["pong.ch8", "extra"])).is_err());
+        assert!(parse(args(&["run-headless", "pong.ch8", "--cycles", "many"])).is_err());
+        assert!(parse(args(&["run-headless", "pong.ch8", "--expect-hash", "nothex"])).is_err());
+        assert!(parse(args(&["trim", "pong.ch8", "--hash"])).is_err());
+        assert_eq!(
+            parse(args(&["render-replay", "pong.c8r", "pong.mp4"])),
+            Ok(Command::RenderReplay { replay: "pong.c8r".into(), output: "pong.mp4".into(), scale: DEFAULT_VIDEO_SCALE, buzzer: Buzzer::default() })
+        );
+        assert_eq!(
+            parse(args(&["render-replay", "--scale", "4", "pong.c8r", "pong.webm", "--beep-frequency", "220"])),
+            Ok(Command::RenderReplay {
+                replay: "pong.c8r".into(),
+                output: "pong.webm".into(),
+                scale: 4,
+                buzzer: Buzzer { frequency: Some(220), ..Buzzer::default() },
+            })
+        );
+        assert_eq!(
+            parse(args(&["bench", "pong.ch8"])),
+            Ok(Command::Bench { rom: "pong.ch8".into(), cycles: DEFAULT_BENCH_CYCLES, profiles: vec![] })
+        );
+        assert_eq!(
+            parse(args(&["bench", "pong.ch8", "--cycles", "100", "--profile", "plain", "--profile", "schip=jump_with_vx,no_clip_sprites"])),
+            Ok(Command::Bench { rom: "pong.ch8".into(), cycles: 100, profiles: vec!["plain".parse().unwrap(), "schip=jump_with_vx,no_clip_sprites".parse().unwrap()] })
+        );
+        assert!(parse(args(&["bench", "pong.ch8", "--profile", "schip=fast"])).is_err());
+        assert_eq!(
+            parse(args(&["soak", "pong.ch8", "--hours", "0.5", "--seed", "42", "--profile", "schip"])),
+            Ok(Command::Soak {
+                rom: "pong.ch8".into(),
+                duration: Duration::from_secs(30 * 60),
+                seed: Some(42),
+                record: None,
+                machine: MachineOptions { platform: Platform::Schip, ..MachineOptions::default() },
+            })
+        );
+        assert!(parse(args(&["soak", "pong.ch8", "--hours", "0"])).is_err());
+        assert_eq!(
+            parse(args(&["thumbnails", "roms", "--seconds", "1.5", "--scale", "4"])),
+            Ok(Command::Thumbnails { dir: "roms".into(), warm_up: Duration::from_millis(1500), scale: 4, machine: MachineOptions::default() })
+        );
+        assert!(parse(args(&["thumbnails", "roms", "--seconds", "-1"])).is_err());
+        assert_eq!(
+            parse(args(&["audit", "pong.ch8", "--seed", "42", "--quirk", "vip_timing"])),
+            Ok(Command::Audit {
+                rom: "pong.ch8".into(),
+                duration: DEFAULT_AUDIT_TIME,
+                seed: Some(42),
+                machine: MachineOptions { quirks: vec!["vip_timing".into()], ..MachineOptions::default() },
+            })
+        );
+        assert!(parse(args(&["audit", "pong.ch8", "--record", "run.c8r"])).is_err());
+        assert_eq!(
+            parse(args(&["latency", "pong.ch8", "--key", "C", "--profile", "schip"])),
+            Ok(Command::Latency {
+                rom: "pong.ch8".into(),
+                key: 0xc,
+                presses: DEFAULT_LATENCY_PRESSES,
+                warm_up: DEFAULT_LATENCY_WARM_UP,
+                machine: MachineOptions { platform: Platform::Schip, ..MachineOptions::default() },
+            })
+        );
+        for bad in [&["--key", "10"][..], &["--key", "g"], &["--presses", "0"]] {
+            assert!(parse(args(&[&["latency", "pong.ch8"][..], bad].concat())).is_err(), "{:?}", bad);
+        }
+        assert!(parse(args(&["play"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--protect", "0-1ff"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--break-on-draw", "0,0"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--quirk", "jump_with_v0"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--odd-pc", "ignore"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--ips", "0"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--ips", "59"])).is_err());
+        assert!(parse(args(&["run-headless", "pong.ch8", "--ips", "20001"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--ips", "2000000000"])).is_err());
+        assert!(parse(args(&["soak", "pong.ch8", "--ips", "20000"])).is_ok());
+        assert!(parse(args(&["thumbnails", "roms", "--ips", "700"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--profile", "superchip"])).is_err());
+        assert!(parse(args(&["render-replay", "pong.c8r"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--waveform", "saw"])).is_err());
+        assert!(parse(args(&["pong.ch8", "--volume", "150"])).is_err());
+        assert!(parse(args(&["soak", "pong.ch8", "--volume", "50"])).is_err());
+        assert!(matches!(parse(args(&["--stdin", "--strict"])), Ok(Command::Run { rom: RomSource::PastedStdin, strict: true, .. })));
+        assert!(matches!(parse(args(&["run-headless", "--stdin"])), Ok(Command::RunHeadless { rom: RomSource::PastedStdin, .. })));
+        assert!(parse(args(&["pong.ch8", "--stdin"])).is_err());
+        assert!(parse(args(&["trim", "--stdin"])).is_err());
+        assert!(matches!(parse(args(&["run-headless", "-", "--hash"])), Ok(Command::RunHeadless { rom: RomSource::Stdin, hash: true, .. })));
+        assert!(parse(args(&["-", "--stdin"])).is_err());
+    }
+
+    #[test]
+    fn parses_shared_states() {
+        assert_eq!(
+            parse(args(&["share", "pong.ch8", "--slot", "3", "--embed-rom"])),
+            Ok(Command::Share { rom: "pong.ch8".into(), slot: 3, embed_rom: true, output: None })
+        );
+        assert_eq!(
+            parse(args(&["share", "pong.ch8", "-o", "bug.c8ss"])),
+            Ok(Command::Share { rom: "pong.ch8".into(), slot: 1, embed_rom: false, output: Some("bug.c8ss".into()) })
+        );
+        assert!(parse(args(&["share", "pong.ch8", "--slot", "0"])).is_err());
+        assert!(parse(args(&["share", "pong.ch8", "--slot", "10"])).is_err());
+        assert!(parse(args(&["share"])).is_err());
+        assert_eq!(
+            parse(args(&["leaderboard", "pong.ch8", "--output", "pong.txt"])),
+            Ok(Command::Leaderboard { rom: "pong.ch8".into(), output: Some("pong.txt".into()) })
+        );
+        assert!(matches!(
+            parse(args(&["pong.ch8", "--state", "bug.c8ss"])),
+            Ok(Command::Run { rom: RomSource::File(_), state: Some(_), .. })
+        ));
+        let embedded = RomSource::Embedded("bug.c8ss".into());
+        assert!(matches!(parse(args(&["--state", "bug.c8ss"])), Ok(Command::Run { rom, .. }) if rom == embedded));
+        assert!(matches!(parse(args(&["run-headless", "--state", "bug.c8ss"])), Ok(Command::RunHeadless { rom, .. }) if rom == embedded));
+        assert!(parse(args(&["soak", "--state", "bug.c8ss"])).is_err());
+    }
+}
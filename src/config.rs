@@ -0,0 +1,11 @@
+use std::path::PathBuf;
+
+/// Where a settings file lives: `$XDG_CONFIG_HOME/chip8`, else `~/.config/chip8`,
+/// else the working directory if there's no home to speak of
+pub fn config_path(name: &str) -> PathBuf {
+    let dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map_or_else(PathBuf::new, |config| config.join("chip8"));
+    dir.join(name)
+}
@@ -1,11 +1,43 @@
 use crate::chip8::Instruction;
 use crate::bits::{get_nibble, get_nibbles};
 
+/// MEGA-CHIP's 0NNN opcodes, which mean other things, or nothing, everywhere else.
+/// Anything else is for `decode`.
+pub fn decode_mega(instruction: u16) -> Option<Instruction> {
+    let nn = instruction as u8;
+    match instruction >> 8 {
+        0x00 => match nn {
+            0x10 => Some(Instruction::MegaOff),
+            0x11 => Some(Instruction::MegaOn),
+            0xb0..=0xbf => Some(Instruction::ScrollUp { rows: nn & 0xf }),
+            _ => None,
+        },
+        0x01 => Some(Instruction::MegaIndex { high: nn }),
+        0x02 => Some(Instruction::LoadPalette { count: nn }),
+        0x03 => Some(Instruction::SpriteWidth { width: nn }),
+        0x04 => Some(Instruction::SpriteHeight { height: nn }),
+        0x05 => Some(Instruction::Alpha { alpha: nn }),
+        0x06 if nn <= 0xf => Some(Instruction::PlaySample { mode: nn }),
+        0x07 if nn == 0 => Some(Instruction::StopSample),
+        0x08 if nn <= 0xf => Some(Instruction::BlendMode { mode: nn }),
+        0x09 => Some(Instruction::CollisionColor { color: nn }),
+        _ => None,
+    }
+}
+
 pub fn decode(instruction: u16) -> Option<Instruction> {
     match get_nibble(instruction, 0) {
         0x0 => match get_nibbles(instruction, 1, 3) {
             0x0e0 => Some(Instruction::ClearScreen),
             0x0ee => Some(Instruction::Return),
+            0x0fe => Some(Instruction::Lores),
+            0x0ff => Some(Instruction::Hires),
+            0x0fd => Some(Instruction::Exit),
+            0x0fb => Some(Instruction::ScrollRight),
+            0x0fc => Some(Instruction::ScrollLeft),
+            rows @ 0x0c0..=0x0cf => Some(Instruction::ScrollDown { rows: (rows & 0xf) as u8 }),
+            rows @ 0x0d0..=0x0df => Some(Instruction::ScrollUp { rows: (rows & 0xf) as u8 }),
+            0x2a0 => Some(Instruction::CycleBackground),
             _ => None,
         },
         0x1 => {
@@ -26,10 +58,16 @@ pub fn decode(instruction: u16) -> Option<Instruction> {
             register: get_nibble(instruction, 1),
             value: get_nibbles(instruction, 2, 2) as u8
         }),
-        0x5 => Some(Instruction::SkipEQR {
-            register1: get_nibble(instruction, 1),
-            register2: get_nibble(instruction, 2),
-        }),
+        0x5 => {
+            let register1 = get_nibble(instruction, 1);
+            let register2 = get_nibble(instruction, 2);
+            match get_nibble(instruction, 3) {
+                2 => Some(Instruction::StoreRange { register1, register2 }),
+                3 => Some(Instruction::LoadRange { register1, register2 }),
+                // CHIP-8 interpreters never looked at the last nibble
+                _ => Some(Instruction::SkipEQR { register1, register2 }),
+            }
+        },
         0x6 => {
             let register = get_nibble(instruction, 1);
             let value = get_nibbles(instruction, 2, 2) as u8;
@@ -64,7 +102,7 @@ pub fn decode(instruction: u16) -> Option<Instruction> {
             let value = get_nibbles(instruction, 1, 3);
             Some(Instruction::SetIndexRegister { value })
         }
-        0xb => None,
+        0xb => Some(Instruction::JumpOffset { dest: get_nibbles(instruction, 1, 3) }),
         0xc => Some(Instruction::Random { 
             register: get_nibble(instruction, 1),
             value: get_nibbles(instruction, 2, 2) as u8
@@ -76,22 +114,31 @@ pub fn decode(instruction: u16) -> Option<Instruction> {
             Some(Instruction::Draw { x_r, y_r, height })
         },
         0xe => match get_nibbles(instruction, 2, 2) {
-            0x9e => Some(Instruction::SkipPressed { key: get_nibble(instruction, 1) }),
-            0xa1 => Some(Instruction::SkipNotPressed { key: get_nibble(instruction, 1) }),
+            0x9e => Some(Instruction::SkipPressed { register: get_nibble(instruction, 1) }),
+            0xa1 => Some(Instruction::SkipNotPressed { register: get_nibble(instruction, 1) }),
+            0xf2 => Some(Instruction::SkipPressed2 { register: get_nibble(instruction, 1) }),
+            0xf5 => Some(Instruction::SkipNotPressed2 { register: get_nibble(instruction, 1) }),
             _ => None
         },
         0xf => {
             let nib = get_nibble(instruction, 1);
             match get_nibbles(instruction, 2, 2) {
+                0x00 if nib == 0 => Some(Instruction::LongIndex),
+                0x01 => Some(Instruction::SelectPlanes { planes: nib }),
+                0x02 if nib == 0 => Some(Instruction::LoadAudio),
+                0x3a => Some(Instruction::SetPitch { register: nib }),
                 0x07 => Some(Instruction::GetDelayTimer { register: nib }),
                 0x0a => Some(Instruction::GetKey { register: nib }),
                 0x15 => Some(Instruction::SetDelayTimer { register: nib }),
                 0x18 => Some(Instruction::SetSoundTimer { register: nib }),
                 0x1e => Some(Instruction::AddToIndex { register: nib }), // TODO: set overflow
                 0x29 => Some(Instruction::FontChar { register: nib }),
+                0x30 => Some(Instruction::BigFontChar { register: nib }),
                 0x33 => Some(Instruction::RegToDecimal { register: nib }),
                 0x55 => Some(Instruction::StoreMemory { register: nib }),
                 0x65 => Some(Instruction::LoadMemory { register: nib }),
+                0x75 => Some(Instruction::SaveFlags { register: nib }),
+                0x85 => Some(Instruction::LoadFlags { register: nib }),
                 _ => None
             }
         },
@@ -102,20 +149,97 @@ pub fn decode(instruction: u16) -> Option<Instruction> {
 #[cfg(test)]
 mod tests {
     use crate::chip8::Instruction;
-    use super::decode;
+    use crate::encode::encode;
+    use super::{decode, decode_mega};
     #[test]
     fn working_instructions() {
         assert_eq!(decode(0xa2e0).unwrap(), Instruction::SetIndexRegister { value: 0x2e0 });
         assert_eq!(decode(0xdeaf).unwrap(), Instruction::Draw {x_r: 0xe, y_r: 0xa, height: 0xf });
         assert_eq!(decode(0x7abc).unwrap(), Instruction::AddToRegister { register: 0xa, value: 0xbc });
+        assert_eq!(decode(0xb3a0).unwrap(), Instruction::JumpOffset { dest: 0x3a0 });
+    }
+
+    #[test]
+    fn alu_family() {
+        let (register1, register2) = (0x1, 0x2);
+        assert_eq!(decode(0x8120), Some(Instruction::MovRegister { register1, register2 }));
+        assert_eq!(decode(0x8121), Some(Instruction::BinaryOr { register1, register2 }));
+        assert_eq!(decode(0x8122), Some(Instruction::BinaryAnd { register1, register2 }));
+        assert_eq!(decode(0x8123), Some(Instruction::BinaryXor { register1, register2 }));
+        assert_eq!(decode(0x8124), Some(Instruction::Add { register1, register2 }));
+        assert_eq!(decode(0x8125), Some(Instruction::SubtractForward { register1, register2 }));
+        assert_eq!(decode(0x8126), Some(Instruction::ShiftRight { register1, register2 }));
+        assert_eq!(decode(0x8127), Some(Instruction::SubtractBackward { register1, register2 }));
+        assert_eq!(decode(0x812e), Some(Instruction::ShiftLeft { register1, register2 }));
+        for unused in [0x8, 0x9, 0xa, 0xb, 0xc, 0xd, 0xf] {
+            assert_eq!(decode(0x8120 | unused), None);
+        }
+    }
+
+    #[test]
+    fn fx_family() {
+        assert_eq!(decode(0xf30a), Some(Instruction::GetKey { register: 3 }));
+        assert_eq!(decode(0xf318), Some(Instruction::SetSoundTimer { register: 3 }));
+        assert_eq!(decode(0xf329), Some(Instruction::FontChar { register: 3 }));
+        assert_eq!(decode(0xf333), Some(Instruction::RegToDecimal { register: 3 }));
+        assert_eq!(decode(0xf355), Some(Instruction::StoreMemory { register: 3 }));
+        assert_eq!(decode(0xf365), Some(Instruction::LoadMemory { register: 3 }));
+        assert_eq!(decode(0xf000), Some(Instruction::LongIndex));
+        assert_eq!(decode(0xf100), None);
+        assert_eq!(decode(0xf301), Some(Instruction::SelectPlanes { planes: 3 }));
+        assert_eq!(decode(0xf002), Some(Instruction::LoadAudio));
+        assert_eq!(decode(0xf102), None);
+        assert_eq!(decode(0xf53a), Some(Instruction::SetPitch { register: 5 }));
+        assert_eq!(decode(0xf375), Some(Instruction::SaveFlags { register: 3 }));
+        assert_eq!(decode(0xf385), Some(Instruction::LoadFlags { register: 3 }));
+        assert_eq!(decode(0xf300), None);
+    }
+
+    #[test]
+    fn zero_family() {
+        assert_eq!(decode(0x00fd), Some(Instruction::Exit));
+        assert_eq!(decode(0x00da), Some(Instruction::ScrollUp { rows: 0xa }));
+    }
+
+    #[test]
+    fn xo_chip_family() {
+        assert_eq!(decode(0x5412), Some(Instruction::StoreRange { register1: 4, register2: 1 }));
+        assert_eq!(decode(0x5143), Some(Instruction::LoadRange { register1: 1, register2: 4 }));
+    }
+
+    #[test]
+    fn chip8x_family() {
+        assert_eq!(decode(0x02a0), Some(Instruction::CycleBackground));
+        assert_eq!(decode(0xe4f5), Some(Instruction::SkipNotPressed2 { register: 4 }));
+    }
+
+    #[test]
+    fn decodes_mega_chip() {
+        assert_eq!(decode_mega(0x0011), Some(Instruction::MegaOn));
+        assert_eq!(decode_mega(0x0112), Some(Instruction::MegaIndex { high: 0x12 }));
+        assert_eq!(decode_mega(0x0304), Some(Instruction::SpriteWidth { width: 4 }));
+        assert_eq!(decode_mega(0x0801), Some(Instruction::BlendMode { mode: 1 }));
+        assert_eq!(decode_mega(0x0810), None);
+        assert_eq!(decode_mega(0x0701), None);
+        assert_eq!(decode_mega(0x00e0), None);
+        assert_eq!(decode_mega(0x1011), None);
     }
 
     use proptest::prelude::*;
     proptest! {
         #[test]
-        fn never_panics(instruction in 0..std::u16::MAX)
+        fn never_panics(instruction in 0..u16::MAX)
         {
             decode(instruction);
         }
+
+        #[test]
+        fn fx_roundtrips(
+            register in 0..16_u16,
+            suffix in prop::sample::select(vec![0x07, 0x0a, 0x15, 0x18, 0x1e, 0x29, 0x33, 0x55, 0x65]),
+        ) {
+            let raw = 0xf000 | register << 8 | suffix;
+            prop_assert_eq!(encode(&decode(raw).unwrap()), raw);
+        }
     }
 }
\ No newline at end of file
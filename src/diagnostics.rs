@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Questionable things a program did that the core tolerated, and the ones it couldn't
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Diagnostic {
+    /// Executed bytes stored by FX33/FX55
+    ExecutedWrittenData,
+    /// Executed bytes static analysis didn't reach from the entry point
+    ExecutedUnreachable,
+    /// Drew more than `DRAW_STORM_THRESHOLD` times in a frame
+    DrawStorm,
+    /// Tried to write a read-only region
+    ReadOnlyWrite,
+    /// Executed a no-execute region
+    NoExecute,
+    /// Accessed memory past the end through the index register, which wrapped around
+    MemoryWrapped,
+    /// FX29 with a value above 0xF, which only has its low nibble used
+    FontDigitOutOfRange,
+    /// FX30 with a value above 9, which has no big digit
+    BigFontDigitOutOfRange,
+    /// EX9E/EXA1 with a value above 0xF, which only has its low nibble used
+    KeyOutOfRange,
+    /// FX75/FX85 past V7, which has no RPL flag
+    FlagOutOfRange,
+    /// Jumped or called to an odd address, so every instruction after is read a byte out
+    OddJump,
+    /// The PC left the program's memory
+    PcOutOfBounds,
+    /// Reached bytes that aren't an instruction the platform has
+    UnknownInstruction,
+    /// 00EE with nothing on the stack
+    ReturnWithEmptyStack,
+    /// 2NNN with `MAX_CALL_DEPTH` calls on the stack already
+    StackOverflow,
+}
+
+impl Diagnostic {
+    fn describe(self) -> &'static str {
+        match self {
+            Diagnostic::ExecutedWrittenData => "executed data written by FX33/FX55",
+            Diagnostic::ExecutedUnreachable => "executed bytes static analysis classified as data",
+            Diagnostic::DrawStorm => "too many draws in one frame",
+            Diagnostic::ReadOnlyWrite => "wrote to read-only memory",
+            Diagnostic::NoExecute => "executed no-execute memory",
+            Diagnostic::MemoryWrapped => "accessed memory past 0xfff, wrapped around",
+            Diagnostic::FontDigitOutOfRange => "FX29 with a digit above 0xF",
+            Diagnostic::BigFontDigitOutOfRange => "FX30 with a digit above 9",
+            Diagnostic::KeyOutOfRange => "EX9E/EXA1 with a key above 0xF",
+            Diagnostic::FlagOutOfRange => "FX75/FX85 past V7",
+            Diagnostic::OddJump => "jumped or called to an odd address",
+            Diagnostic::PcOutOfBounds => "PC left the program's memory",
+            Diagnostic::UnknownInstruction => "reached an unknown instruction",
+            Diagnostic::ReturnWithEmptyStack => "returned with an empty stack",
+            Diagnostic::StackOverflow => "called too many subroutines deep",
+        }
+    }
+
+    /// How much it usually matters. An odd jump under `Policy::Error` is reported as an error instead.
+    pub fn severity(self) -> Severity {
+        match self {
+            // Static analysis misses computed jumps, and some programs really do draw that much
+            Diagnostic::ExecutedUnreachable | Diagnostic::DrawStorm => Severity::Info,
+            Diagnostic::PcOutOfBounds | Diagnostic::UnknownInstruction | Diagnostic::ReturnWithEmptyStack | Diagnostic::StackOverflow => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+/// How much a diagnostic matters, least first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Often intended, but worth knowing when something looks off
+    Info,
+    /// Most likely a bug in the program, which carried on
+    Warning,
+    /// The program couldn't go on
+    Error,
+}
+
+impl Severity {
+    fn plural(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warnings",
+            Severity::Error => "errors",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A diagnostic the first time it came from an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub diagnostic: Diagnostic,
+    pub severity: Severity,
+    /// Instructions run before the responsible one, the same count `--trace` prints
+    pub cycle: u64,
+    /// Address of the responsible instruction
+    pub pc: usize,
+    /// The particulars, e.g. `Instruction at 0x206 checked the key 0x12, using 0x2`
+    pub message: String,
+}
+
+/// e.g. `warning: Instruction at 0x206 checked the key 0x12, using 0x2 (cycle 3)`
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} (cycle {})", self.severity, self.message, self.cycle)
+    }
+}
+
+/// What to do when a program does something that's more likely a bug than intended
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Carry on without a word
+    Allow,
+    /// Carry on, warning once per address and counting it as a diagnostic
+    #[default]
+    Warn,
+    /// Stop, as for an instruction the core doesn't have
+    Error,
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Policy::Allow),
+            "warn" => Ok(Policy::Warn),
+            "error" => Ok(Policy::Error),
+            _ => Err(format!("Unknown policy {}, expected allow, warn or error", s)),
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Policy::Allow => "allow",
+            Policy::Warn => "warn",
+            Policy::Error => "error",
+        })
+    }
+}
+
+#[derive(Default)]
+struct Record {
+    count: u64,
+    /// The worst it's been reported as
+    severity: Option<Severity>,
+    /// Addresses of the responsible instructions, in order of first occurrence
+    pcs: Vec<usize>,
+}
+
+/// Where the core reports everything questionable a program does. Counts diagnostics by kind and
+/// by the instruction responsible, and keeps a report of the first of each kind from each
+/// instruction, which frontends take as they come, and can look back over.
+#[derive(Default)]
+pub struct Diagnostics {
+    records: BTreeMap<Diagnostic, Record>,
+    reports: Vec<Report>,
+    /// Reports already handed out by `take_new`
+    taken: usize,
+}
+
+impl Diagnostics {
+    /// Counts an occurrence, and the first time it's of its kind from `pc`, keeps a report with
+    /// `message()`, returning whether it did
+    pub fn report(&mut self, diagnostic: Diagnostic, severity: Severity, cycle: u64, pc: usize, message: impl FnOnce() -> String) -> bool {
+        let record = self.records.entry(diagnostic).or_default();
+        record.count += 1;
+        record.severity = record.severity.max(Some(severity));
+        let first = !record.pcs.contains(&pc);
+        if first {
+            record.pcs.push(pc);
+            self.reports.push(Report { diagnostic, severity, cycle, pc, message: message() });
+        }
+        first
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Everything reported so far, in order, once per kind and instruction
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+
+    /// Reports made since the last call
+    pub fn take_new(&mut self) -> Vec<Report> {
+        let new = self.reports[self.taken..].to_vec();
+        self.taken = self.reports.len();
+        new
+    }
+
+    /// The most serious thing reported, if anything was
+    pub fn worst(&self) -> Option<Severity> {
+        self.records.values().filter_map(|record| record.severity).max()
+    }
+
+    /// How many kinds were reported at each severity, most serious first, e.g. `1 error, 2 warnings`
+    pub fn counts(&self) -> String {
+        let counts: Vec<String> = [Severity::Error, Severity::Warning, Severity::Info].into_iter().filter_map(|severity| {
+            let kinds = self.records.values().filter(|record| record.severity == Some(severity)).count();
+            match kinds {
+                0 => None,
+                1 => Some(format!("1 {}", severity)),
+                _ => Some(format!("{} {}", kinds, severity.plural())),
+            }
+        }).collect();
+        counts.join(", ")
+    }
+
+    /// Addresses that caused `diagnostic`, in order of first occurrence
+    #[cfg(test)]
+    pub fn pcs(&self, diagnostic: Diagnostic) -> &[usize] {
+        self.records.get(&diagnostic).map_or(&[], |record| &record.pcs)
+    }
+
+    /// One line per kind, most serious first, e.g. `warning: executed no-execute memory: 3 times, from 0x300, 0x302`
+    pub fn summary(&self) -> Vec<String> {
+        let mut records: Vec<_> = self.records.iter().collect();
+        records.sort_by_key(|(_, record)| std::cmp::Reverse(record.severity));
+        records.into_iter().map(|(diagnostic, record)| {
+            let pcs: Vec<String> = record.pcs.iter().map(|pc| format!("{:#05x}", pc)).collect();
+            let times = if record.count == 1 { "time" } else { "times" };
+            let severity = record.severity.unwrap_or(Severity::Info);
+            format!("{}: {}: {} {}, from {}", severity, diagnostic.describe(), record.count, times, pcs.join(", "))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, Diagnostics, Policy, Severity};
+
+    fn report(diagnostics: &mut Diagnostics, diagnostic: Diagnostic, pc: usize) -> bool {
+        diagnostics.report(diagnostic, diagnostic.severity(), 0, pc, || format!("at {:#05x}", pc))
+    }
+
+    #[test]
+    fn counts_by_kind_and_address() {
+        let mut diagnostics = Diagnostics::default();
+        assert!(diagnostics.is_empty());
+        assert!(report(&mut diagnostics, Diagnostic::NoExecute, 0x300));
+        assert!(!report(&mut diagnostics, Diagnostic::NoExecute, 0x300));
+        assert!(report(&mut diagnostics, Diagnostic::NoExecute, 0x302));
+        assert!(report(&mut diagnostics, Diagnostic::ExecutedWrittenData, 0x300));
+        assert_eq!(diagnostics.pcs(Diagnostic::NoExecute), [0x300, 0x302]);
+        assert!(diagnostics.pcs(Diagnostic::DrawStorm).is_empty());
+        assert_eq!(diagnostics.summary(), [
+            "warning: executed data written by FX33/FX55: 1 time, from 0x300",
+            "warning: executed no-execute memory: 3 times, from 0x300, 0x302",
+        ]);
+    }
+
+    #[test]
+    fn keeps_reports_by_severity() {
+        let mut diagnostics = Diagnostics::default();
+        assert_eq!(diagnostics.worst(), None);
+        report(&mut diagnostics, Diagnostic::DrawStorm, 0x200);
+        report(&mut diagnostics, Diagnostic::KeyOutOfRange, 0x206);
+        assert_eq!(diagnostics.worst(), Some(Severity::Warning));
+        let new = diagnostics.take_new();
+        assert_eq!(new.iter().map(|report| report.diagnostic).collect::<Vec<_>>(), [Diagnostic::DrawStorm, Diagnostic::KeyOutOfRange]);
+        assert!(diagnostics.take_new().is_empty());
+
+        // Only the first from each address is kept, and the worst severity counts for the kind
+        report(&mut diagnostics, Diagnostic::KeyOutOfRange, 0x206);
+        diagnostics.report(Diagnostic::OddJump, Severity::Error, 7, 0x208, || "Instruction at 0x208 went to 0x20b".to_string());
+        let new = diagnostics.take_new();
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].to_string(), "error: Instruction at 0x208 went to 0x20b (cycle 7)");
+        assert_eq!(diagnostics.reports().len(), 3);
+        assert_eq!(diagnostics.worst(), Some(Severity::Error));
+        assert_eq!(diagnostics.counts(), "1 error, 1 warning, 1 info");
+        assert_eq!(diagnostics.summary()[0], "error: jumped or called to an odd address: 1 time, from 0x208");
+    }
+
+    #[test]
+    fn parses_policies() {
+        for policy in [Policy::Allow, Policy::Warn, Policy::Error] {
+            assert_eq!(policy.to_string().parse(), Ok(policy));
+        }
+        assert!("ignore".parse::<Policy>().is_err());
+    }
+}
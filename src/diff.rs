@@ -0,0 +1,115 @@
+use crate::chip8::INIT_INDEX;
+use crate::disasm::Line;
+
+/// A run of lines that differ, and where it starts in each ROM
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_address: usize,
+    pub new_address: usize,
+    pub removed: Vec<Line>,
+    pub added: Vec<Line>,
+}
+
+/// Address of the `i`th line, or just past the last one
+fn address_at(lines: &[Line], i: usize) -> usize {
+    match lines.get(i) {
+        Some(line) => line.address,
+        None => lines.last().map_or(INIT_INDEX, |line| line.address + line.item.size()),
+    }
+}
+
+/// Lines only in `old` or only in `new`, aligned by the longest run of items they have in common.
+/// Items are compared by their bytes, so inserting code also shows every jump past it as changed.
+pub fn diff(old: &[Line], new: &[Line]) -> Vec<Hunk> {
+    // Patches tend to be small, so most of both ROMs is skipped before the quadratic part
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a.item == b.item).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a.item == b.item)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    // common[i * width + j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let width = b.len() + 1;
+    let mut common = vec![0u16; (a.len() + 1) * width];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i * width + j] = if a[i].item == b[j].item {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let mut hunk: Option<Hunk> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i].item == b[j].item {
+            hunks.extend(hunk.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let hunk = hunk.get_or_insert_with(|| Hunk {
+            old_address: address_at(old, prefix + i),
+            new_address: address_at(new, prefix + j),
+            removed: Vec::new(),
+            added: Vec::new(),
+        });
+        if j == b.len() || (i < a.len() && common[(i + 1) * width + j] >= common[i * width + j + 1]) {
+            hunk.removed.push(a[i]);
+            i += 1;
+        } else {
+            hunk.added.push(b[j]);
+            j += 1;
+        }
+    }
+    hunks.extend(hunk);
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::disasm::{disassemble, Item, Line};
+    use crate::encode::assemble;
+    use crate::platform::Platform;
+    use super::diff;
+
+    #[test]
+    fn aligns_changed_code_and_data() {
+        let mut old = assemble(&[
+            Instruction::ClearScreen,
+            Instruction::SetRegister { register: 0, value: 1 },
+            Instruction::SetRegister { register: 1, value: 2 },
+            Instruction::Jump { dest: 0x206 },
+        ]);
+        old.extend([0xf0, 0x90, 0, 0]);
+        let mut new = assemble(&[
+            Instruction::ClearScreen,
+            Instruction::SetRegister { register: 1, value: 2 },
+            Instruction::SetRegister { register: 2, value: 3 },
+            Instruction::Jump { dest: 0x206 },
+        ]);
+        new.extend([0xf0, 0x80]);
+        let old = disassemble(&old, Platform::Chip8);
+        let new = disassemble(&new, Platform::Chip8);
+        // Padding is left out
+        assert_eq!(old.len(), 6);
+        assert_eq!(old[4], Line { address: 0x208, item: Item::Data(0xf0) });
+
+        let hunks = diff(&old, &new);
+        assert_eq!(hunks.len(), 3);
+        assert_eq!((hunks[0].old_address, hunks[0].new_address), (0x202, 0x202));
+        assert_eq!(hunks[0].removed, [old[1]]);
+        assert!(hunks[0].added.is_empty());
+        assert_eq!((hunks[1].old_address, hunks[1].new_address), (0x206, 0x204));
+        assert_eq!(hunks[1].added, [new[2]]);
+        assert_eq!(hunks[2].removed, [old[5]]);
+        assert_eq!(hunks[2].added, [new[5]]);
+        assert!(diff(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::analysis::mark_code;
+use crate::asm::mnemonic;
+use crate::chip8::MEMORY_SIZE;
+use crate::decode::decode;
+use crate::platform::Platform;
+use crate::rom::trimmed_len;
+use crate::symbols::Symbols;
+use crate::xref::{Xref, Xrefs};
+
+/// What's at an address of a ROM once it's disassembled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Item {
+    Instruction(u16),
+    /// A byte that isn't reachable as code
+    Data(u8),
+}
+
+impl Item {
+    /// Bytes the item takes up
+    pub fn size(&self) -> usize {
+        match self {
+            Item::Instruction(_) => 2,
+            Item::Data(_) => 1,
+        }
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        match *self {
+            Item::Instruction(raw) => raw.to_be_bytes().to_vec(),
+            Item::Data(byte) => vec![byte],
+        }
+    }
+
+    /// The mnemonic and operands, with data as `DB 0xff`
+    pub fn mnemonic(&self) -> (&'static str, Vec<String>) {
+        match *self {
+            Item::Instruction(raw) => mnemonic(&decode(raw).unwrap()),
+            Item::Data(byte) => ("DB", vec![format!("{:#04x}", byte)]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub item: Item,
+}
+
+/// e.g. `0x200: 6105  LD V1, 0x05`
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: String = self.item.bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        let (name, operands) = self.item.mnemonic();
+        write!(f, "{:#05x}: {:<4}  {} {}", self.address, bytes, name, operands.join(", "))
+    }
+}
+
+/// Splits a ROM into instructions and data the way the emulator's static analysis would,
+/// loaded where `platform` loads it, leaving out the trailing zero padding since it never changes what runs
+pub fn disassemble(rom: &[u8], platform: Platform) -> Vec<Line> {
+    let rom = &rom[..trimmed_len(rom).min(platform.max_rom_size())];
+    let start = platform.load_address();
+    let mut memory = vec![0; MEMORY_SIZE.max(start + rom.len())];
+    memory[start..start + rom.len()].copy_from_slice(rom);
+    let mut code = vec![false; memory.len()];
+    mark_code(&memory, start, &mut code);
+    let mut lines = Vec::new();
+    let mut address = start;
+    while address < start + rom.len() {
+        let item = if code[address] && code[address + 1] {
+            Item::Instruction((memory[address] as u16) << 8 | memory[address + 1] as u16)
+        } else {
+            Item::Data(memory[address])
+        };
+        lines.push(Line { address, item });
+        address += item.size();
+    }
+    lines
+}
+
+/// How `disasm` prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown format {}, expected text or json", s)),
+        }
+    }
+}
+
+/// A line each, with labels from the symbols above the lines they name and what refers to each line,
+/// e.g. `; jump from 0x202, read by 0x208`
+pub fn to_text(lines: &[Line], symbols: &Symbols) -> String {
+    let xrefs = Xrefs::analyze(lines);
+    let mut text = String::new();
+    for line in lines {
+        if symbols.has_name(line.address) {
+            text += &format!("{}:\n", symbols.name(line.address));
+        }
+        let line_text = line.to_string();
+        let refs: Vec<String> = xrefs.to(line.address).map(Xref::to_string).collect();
+        if refs.is_empty() {
+            text += &format!("{}\n", line_text.trim_end());
+        } else {
+            text += &format!("{:<32}; {}\n", line_text, refs.join(", "));
+        }
+    }
+    text
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            c if c.is_control() => json += &format!("\\u{:04x}", c as u32),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// An array with an object for each line, for other tools to read, e.g.
+/// `{"address": 512, "bytes": [0, 224], "kind": "code", "label": null, "mnemonic": "CLS", "operands": [], "xrefs": []}`,
+/// with xrefs like `{"from": 514, "kind": "jump"}`
+pub fn to_json(lines: &[Line], symbols: &Symbols) -> String {
+    let xrefs = Xrefs::analyze(lines);
+    let objects: Vec<String> = lines.iter().map(|line| {
+        let list = |items: Vec<String>| format!("[{}]", items.join(", "));
+        let (name, operands) = line.item.mnemonic();
+        let label = if symbols.has_name(line.address) { json_string(&symbols.name(line.address)) } else { String::from("null") };
+        format!(
+            "  {{\"address\": {}, \"bytes\": {}, \"kind\": \"{}\", \"label\": {}, \"mnemonic\": \"{}\", \"operands\": {}, \"xrefs\": {}}}",
+            line.address,
+            list(line.item.bytes().iter().map(u8::to_string).collect()),
+            if let Item::Instruction(_) = line.item { "code" } else { "data" },
+            label,
+            name,
+            list(operands.iter().map(|operand| json_string(operand)).collect()),
+            list(xrefs.to(line.address).map(|xref| format!("{{\"from\": {}, \"kind\": \"{}\"}}", xref.from, xref.access)).collect()),
+        )
+    }).collect();
+    format!("[\n{}\n]\n", objects.join(",\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::encode::assemble;
+    use crate::platform::Platform;
+    use crate::symbols::Symbols;
+    use super::{disassemble, to_json, to_text, Item, Line};
+
+    #[test]
+    fn prints_text_and_json() {
+        let mut rom = assemble(&[
+            Instruction::SetIndexRegister { value: 0x206 },
+            Instruction::Draw { x_r: 0, y_r: 0, height: 1 },
+            Instruction::Jump { dest: 0x204 },
+        ]);
+        rom.push(0xf0);
+        let lines = disassemble(&rom, Platform::Chip8);
+        let symbols = Symbols::parse("204 \"loop\"").unwrap();
+        assert_eq!(to_text(&lines, &symbols), concat!(
+            "0x200: a206  LD I, 0x206\n",
+            "0x202: d001  DRW V0, V0, 1\n",
+            "\"loop\":\n",
+            "0x204: 1204  JP 0x204           ; jump from 0x204\n",
+            "0x206: f0    DB 0xf0            ; I set by 0x200, read by 0x202\n",
+        ));
+        let json = to_json(&lines, &symbols);
+        assert!(json.starts_with("[\n  {\"address\": 512, \"bytes\": [162, 6], \"kind\": \"code\", \"label\": null, \"mnemonic\": \"LD\", \"operands\": [\"I\", \"0x206\"], \"xrefs\": []},\n"));
+        assert!(json.contains("\"label\": \"\\\"loop\\\"\""));
+        assert!(json.ends_with("{\"address\": 518, \"bytes\": [240], \"kind\": \"data\", \"label\": null, \"mnemonic\": \"DB\", \"operands\": [\"0xf0\"], \"xrefs\": [{\"from\": 512, \"kind\": \"index\"}, {\"from\": 514, \"kind\": \"read\"}]}\n]\n"));
+    }
+    #[test]
+    fn disassembles_where_the_platform_loads() {
+        let rom = assemble(&[Instruction::Jump { dest: 0x300 }]);
+        assert_eq!(disassemble(&rom, Platform::Chip8X), [Line { address: 0x300, item: Item::Instruction(0x1300) }]);
+        assert_eq!(disassemble(&rom, Platform::Chip8)[0].address, 0x200);
+    }
+
+    #[test]
+    fn keeps_mega_chip_roms_past_4k() {
+        let mut rom = vec![0; 0x2000];
+        rom[0x1fff] = 0xff;
+        assert_eq!(disassemble(&rom, Platform::MegaChip).last(), Some(&Line { address: 0x21ff, item: Item::Data(0xff) }));
+        assert!(disassemble(&rom, Platform::Chip8).iter().all(|line| line.address < 0x1000));
+    }
+}
@@ -0,0 +1,426 @@
+use std::io::{Error, Write};
+use crate::bits::{adler32, crc32, fnv1a};
+use crate::chip8::{draw_screen, DrawOrder, Screen, Timestamp, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::look::{Look, Palette};
+
+/// Somewhere to show frames, told when in emulated time each was produced
+pub trait DisplaySink {
+    fn present(&mut self, display: &Screen, at: Timestamp);
+}
+
+/// Software framebuffer in the same RGBA layout as the window's, for running without a GPU.
+/// It takes the size of whatever screen it was last shown.
+pub struct RgbaBuffer {
+    pub width: usize,
+    pub height: usize,
+    pub frame: Vec<u8>,
+    /// Frames presented so far
+    pub frames: u64,
+    /// When the current frame was produced
+    pub timestamp: Timestamp,
+    /// The colours frames are drawn in from the next one presented
+    pub palette: Palette,
+}
+
+impl RgbaBuffer {
+    pub fn new() -> Self {
+        RgbaBuffer {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            frame: black_frame(SCREEN_WIDTH, SCREEN_HEIGHT),
+            frames: 0,
+            timestamp: Timestamp::default(),
+            palette: Palette::default(),
+        }
+    }
+
+    /// The frame scaled up `factor` times, e.g. to show lores in a video that switches to hires
+    pub fn scaled(&self, factor: usize) -> RgbaBuffer {
+        let width = self.width * factor;
+        let frame = self.frame.chunks(self.width * 4)
+            .flat_map(|row| {
+                let row: Vec<u8> = row.chunks(4).flat_map(|pixel| pixel.repeat(factor)).collect();
+                row.repeat(factor)
+            })
+            .collect();
+        RgbaBuffer { width, height: self.height * factor, frame, frames: self.frames, timestamp: self.timestamp, palette: self.palette }
+    }
+
+    pub fn hash(&self) -> u64 {
+        fnv1a(&self.frame)
+    }
+
+    /// Writes a binary PPM, which needs no image library and most viewers open
+    pub fn write_ppm(&self, mut write: impl Write) -> Result<(), Error> {
+        write!(write, "P6\n{} {}\n255\n", self.width, self.height)?;
+        self.write_rgb(write)
+    }
+
+    /// Writes an RGB PNG. The pixel data is stored rather than compressed, which is simpler, and
+    /// small enough at CHIP-8 sizes.
+    pub fn write_png(&self, mut write: impl Write) -> Result<(), Error> {
+        let mut pixels = Vec::with_capacity(self.height * (self.width * 3 + 1));
+        for row in self.frame.chunks(self.width * 4) {
+            // Each row starts with its filter, 0 for none
+            pixels.push(0);
+            pixels.extend(row.chunks(4).flat_map(|pixel| &pixel[..3]));
+        }
+        // A zlib stream of deflate blocks that each store up to 65535 bytes as they are
+        let mut zlib = vec![0x78, 0x01];
+        let blocks = pixels.chunks(0xffff);
+        let last = blocks.len() - 1;
+        for (i, block) in blocks.enumerate() {
+            zlib.push((i == last) as u8);
+            zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        zlib.extend_from_slice(&adler32(&pixels).to_be_bytes());
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, then the only compression, filtering and (no) interlacing there are
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write.write_all(b"\x89PNG\r\n\x1a\n")?;
+        for (tag, data) in [(b"IHDR", &header), (b"IDAT", &zlib), (b"IEND", &Vec::new())] {
+            write.write_all(&(data.len() as u32).to_be_bytes())?;
+            let mut chunk = tag.to_vec();
+            chunk.extend_from_slice(data);
+            write.write_all(&chunk)?;
+            write.write_all(&crc32(&chunk).to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the bare pixels as 3 bytes each, row by row
+    pub fn write_rgb(&self, mut write: impl Write) -> Result<(), Error> {
+        for pixel in self.frame.chunks(4) {
+            write.write_all(&pixel[..3])?;
+        }
+        Ok(())
+    }
+
+    /// One line per row, `Q` for lit pixels like the debug view
+    #[cfg(test)]
+    pub fn to_ascii(&self) -> String {
+        self.frame.chunks(self.width * 4)
+            .map(|row| row.chunks(4).map(|pixel| if pixel[..3] == [0, 0, 0] { ' ' } else { 'Q' }).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl DisplaySink for RgbaBuffer {
+    fn present(&mut self, display: &Screen, at: Timestamp) {
+        if (self.width, self.height) != display.size() {
+            (self.width, self.height) = display.size();
+            self.frame = black_frame(self.width, self.height);
+        }
+        draw_screen(display, &self.palette, &mut self.frame);
+        self.frames += 1;
+        self.timestamp = at;
+    }
+}
+
+/// Opaque black RGBA pixels
+fn black_frame(width: usize, height: usize) -> Vec<u8> {
+    [0, 0, 0, u8::MAX].repeat(width * height)
+}
+
+/// Draws the screen like a CRT: pixels fade out over a few frames rather than turning off at once,
+/// and glow onto their neighbours. With the default `Look` it draws exactly what `draw_screen` does.
+pub struct Phosphor {
+    /// How brightly each pixel glows in red, green and blue, from 0 to 1
+    glow: Vec<[f32; 3]>,
+    width: usize,
+    height: usize,
+    /// Whether any unlit pixel hadn't faded to the background yet in the last frame
+    fading: bool,
+}
+
+impl Phosphor {
+    pub fn new() -> Self {
+        Phosphor::sized(SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    fn sized(width: usize, height: usize) -> Self {
+        Phosphor { glow: vec![[0.0; 3]; width * height], width, height, fading: false }
+    }
+
+    /// Draws a frame, advancing the fade by one frame. Switching between lores and hires starts the glow over.
+    /// Lit pixels glow in their colour from the look's palette, and fade to its background,
+    /// unless the screen has colours of its own (CHIP-8X), which are used instead.
+    pub fn draw(&mut self, display: &Screen, look: &Look, frame: &mut [u8]) {
+        let (width, height) = display.size();
+        if (self.width, self.height) != (width, height) {
+            *self = Phosphor::sized(width, height);
+        }
+        let to_float = |color: [u8; 3]| color.map(|channel| channel as f32 / u8::MAX as f32);
+        self.fading = false;
+        for (i, glow) in self.glow.iter_mut().enumerate() {
+            let (x, y) = (i % width, i / width);
+            let rgb = to_float(display.rgb(x, y, &look.palette));
+            if display.color(x, y) != 0 {
+                *glow = rgb;
+            } else {
+                let background = rgb;
+                for (channel, &unlit) in glow.iter_mut().zip(&background) {
+                    let faded = *channel * look.persistence;
+                    // Within half a level of the background, the difference can't be seen
+                    *channel = if faded - unlit < 0.5 / u8::MAX as f32 { unlit } else { faded };
+                }
+                self.fading |= *glow != background;
+            }
+        }
+        let glow_at = |x: usize, y: usize, channel: usize| self.glow[y * width + x][channel];
+        for (i, pixel) in frame.chunks_mut(4).take(self.glow.len()).enumerate() {
+            let (x, y) = (i % width, i / width);
+            for (channel, value) in pixel[..3].iter_mut().enumerate() {
+                let mut neighbours = 0.0;
+                if x > 0 { neighbours += glow_at(x - 1, y, channel) }
+                if x + 1 < width { neighbours += glow_at(x + 1, y, channel) }
+                if y > 0 { neighbours += glow_at(x, y - 1, channel) }
+                if y + 1 < height { neighbours += glow_at(x, y + 1, channel) }
+                let intensity = (self.glow[i][channel] + look.bloom * neighbours / 4.0).min(1.0) * look.brightness;
+                *value = (intensity * u8::MAX as f32).round() as u8;
+            }
+        }
+    }
+
+    /// Whether anything is still fading out, so more frames are needed even if the screen doesn't change
+    pub fn fading(&self) -> bool {
+        self.fading
+    }
+}
+
+/// Draws the screen tinted by the order things were drawn in the latest frame: from blue for the
+/// first draw to red for the last, dimmer where the draw erased the pixel, and grey for lit pixels no
+/// draw touched
+pub fn draw_heatmap(display: &Screen, order: &DrawOrder, frame: &mut [u8]) {
+    let (width, height) = display.size();
+    // From before a switch between lores and hires, so none of it applies
+    let untouched = vec![0; width * height];
+    let touched = if order.pixels.len() == width * height { &order.pixels } else { &untouched };
+    for (i, pixel) in frame.chunks_mut(4).take(width * height).enumerate() {
+        let lit = display.color(i % width, i / width) != 0;
+        let rgb = match touched[i] {
+            0 if lit => [0x60; 3],
+            0 => [0; 3],
+            draw => {
+                let late = if order.draws > 1 { (draw - 1) as f32 / (order.draws - 1) as f32 } else { 1.0 };
+                let level = if lit { u8::MAX as f32 } else { 0x60 as f32 };
+                [(late * level).round() as u8, 0, ((1.0 - late) * level).round() as u8]
+            },
+        };
+        pixel[..3].copy_from_slice(&rgb);
+        pixel[3] = u8::MAX;
+    }
+}
+
+/// Rows below the screen taken up by the replay timeline: a gap, then the bar
+pub const TIMELINE_HEIGHT: usize = 2;
+
+/// Draws a progress bar under the screen, in a frame `width` wide and `TIMELINE_HEIGHT` rows taller than the screen
+pub fn draw_timeline(frame: &mut [u8], width: usize, progress: f64) {
+    let played = (progress.clamp(0.0, 1.0) * width as f64).round() as usize;
+    let start = frame.len() - width * TIMELINE_HEIGHT * 4;
+    let rows = &mut frame[start..];
+    rows.fill(0);
+    let bar = &mut rows[width * (TIMELINE_HEIGHT - 1) * 4..];
+    for (x, pixel) in bar.chunks_mut(4).enumerate() {
+        let color: [u8; 4] = if x < played { [0x40, 0xc0, 0x40, 0xff] } else { [0x30, 0x30, 0x30, 0xff] };
+        pixel.copy_from_slice(&color);
+    }
+}
+
+/// Bytes per row of the memory view, each shown as its 8 bits side by side
+pub const MEMORY_VIEW_BYTES_PER_ROW: usize = 32;
+pub const MEMORY_VIEW_WIDTH: usize = MEMORY_VIEW_BYTES_PER_ROW * 8;
+pub const MEMORY_VIEW_HEIGHT: usize = 4096 / MEMORY_VIEW_BYTES_PER_ROW;
+
+/// Draws memory one pixel per bit, which makes sprites and fonts easy to spot. Only the first 4K
+/// fits, which is all of it but for MEGA-CHIP.
+/// The instruction at `pc` is tinted red and the byte at `index` green, even when they're zero.
+pub fn draw_memory(memory: &[u8], pc: usize, index: usize, frame: &mut [u8]) {
+    for (address, &byte) in memory.iter().enumerate().take(MEMORY_VIEW_HEIGHT * MEMORY_VIEW_BYTES_PER_ROW) {
+        let (lit, unlit): ([u8; 4], [u8; 4]) = if address == pc || address == pc + 1 {
+            ([0xff, 0x40, 0x40, 0xff], [0x50, 0, 0, 0xff])
+        } else if address == index {
+            ([0x40, 0xff, 0x40, 0xff], [0, 0x50, 0, 0xff])
+        } else {
+            ([0xff, 0xff, 0xff, 0xff], [0, 0, 0, 0xff])
+        };
+        let x = address % MEMORY_VIEW_BYTES_PER_ROW * 8;
+        let y = address / MEMORY_VIEW_BYTES_PER_ROW;
+        for bit in 0..8 {
+            let i = (y * MEMORY_VIEW_WIDTH + x + bit) * 4;
+            let color = if byte & (0x80 >> bit) != 0 { lit } else { unlit };
+            frame[i..i + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Decides which frames to skip rendering so emulation can keep up,
+/// never skipping more than `max_consecutive` in a row so the screen still updates
+pub struct FrameSkipper {
+    pub max_consecutive: u32,
+    skipped: u32,
+}
+
+impl FrameSkipper {
+    pub fn new(max_consecutive: u32) -> Self {
+        FrameSkipper { max_consecutive, skipped: 0 }
+    }
+
+    /// Call once per frame that would be rendered, with whether emulation is falling behind (or in turbo)
+    pub fn should_render(&mut self, behind: bool) -> bool {
+        if behind && self.skipped < self.max_consecutive {
+            self.skipped += 1;
+            false
+        } else {
+            self.skipped = 0;
+            true
+        }
+    }
+}
+
+impl Default for RgbaBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for Phosphor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::{draw_screen, Instruction, Screen, Timestamp, HIRES_HEIGHT, HIRES_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+    use crate::look::{Look, Palette};
+    use crate::testing::Machine;
+    use super::{draw_heatmap, draw_memory, DisplaySink, FrameSkipper, Phosphor, RgbaBuffer, MEMORY_VIEW_HEIGHT, MEMORY_VIEW_WIDTH};
+
+    #[test]
+    fn tints_pixels_by_draw_order() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::Draw { x_r: 0, y_r: 0, height: 1 },
+            Instruction::Draw { x_r: 1, y_r: 0, height: 1 },
+            Instruction::Draw { x_r: 2, y_r: 0, height: 1 },
+        ]);
+        machine.chip8.memory[0x300] = 0xc0;
+        machine.chip8.registers[1].0 = 1;
+        machine.chip8.registers[2].0 = 3;
+        machine.run(4);
+        assert_eq!((machine.chip8.draw_order.draws, &machine.chip8.draw_order.pixels[..6]), (3, &[1, 2, 2, 3, 3, 0][..]));
+        machine.chip8.display[1][0] = true;
+        let mut frame = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        draw_heatmap(&machine.chip8.display, &machine.chip8.draw_order, &mut frame);
+        let rgb = |x: usize, y: usize| &frame[(y * SCREEN_WIDTH + x) * 4..][..3];
+        // The first draw's pixel, the second's erased and lit ones, the third's, then nothing drawn but lit
+        assert_eq!(rgb(0, 0), [0, 0, 0xff]);
+        assert_eq!([rgb(1, 0), rgb(2, 0)], [[0x30, 0, 0x30], [0x80, 0, 0x80]]);
+        assert_eq!(rgb(3, 0), [0xff, 0, 0]);
+        assert_eq!([rgb(0, 1), rgb(5, 0)], [[0x60; 3], [0; 3]]);
+    }
+
+    #[test]
+    fn phosphor_fades_out() {
+        let mut screen = Screen::default();
+        screen[1][1] = true;
+        let mut expected = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        draw_screen(&screen, &Palette::default(), &mut expected);
+        let mut frame = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        let mut phosphor = Phosphor::new();
+        phosphor.draw(&screen, &Look::default(), &mut frame);
+        assert_eq!(frame, expected);
+
+        let look = Look { persistence: 0.5, brightness: 1.0, bloom: 0.4, ..Look::default() };
+        let red = |frame: &[u8], x: usize, y: usize| frame[(y * SCREEN_WIDTH + x) * 4];
+        phosphor.draw(&screen, &look, &mut frame);
+        assert_eq!((red(&frame, 1, 1), red(&frame, 2, 1), red(&frame, 2, 2)), (255, 26, 0));
+        assert!(!phosphor.fading());
+        phosphor.draw(&Screen::default(), &look, &mut frame);
+        assert_eq!((red(&frame, 1, 1), red(&frame, 2, 1)), (128, 13));
+        assert!(phosphor.fading());
+    }
+
+    #[test]
+    fn colours_pixels_by_plane() {
+        let mut screen = Screen::default();
+        screen.plane_mut(0)[0] = true;
+        screen.plane_mut(1)[1] = true;
+        screen.plane_mut(0)[2] = true;
+        screen.plane_mut(1)[2] = true;
+        let mut buffer = RgbaBuffer::new();
+        buffer.palette = Palette([[1, 1, 1], [2, 2, 2], [3, 3, 3], [4, 4, 4]]);
+        buffer.present(&screen, Timestamp::default());
+        let pixels: Vec<&[u8]> = buffer.frame.chunks(4).take(4).collect();
+        assert_eq!(pixels, [[2, 2, 2, 255], [3, 3, 3, 255], [4, 4, 4, 255], [1, 1, 1, 255]]);
+
+        // Unlit pixels fade to the background rather than black
+        let look = Look { persistence: 0.5, palette: Palette([[0, 0, 100], [200, 200, 200], [0; 3], [0; 3]]), ..Look::default() };
+        let mut phosphor = Phosphor::new();
+        let mut frame = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+        phosphor.draw(&screen, &look, &mut frame);
+        phosphor.draw(&Screen::default(), &look, &mut frame);
+        assert_eq!(frame[..3], [100, 100, 100]);
+        assert_eq!(frame[4..7], [0, 0, 100]);
+        assert!(phosphor.fading());
+        for _ in 0..10 {
+            phosphor.draw(&Screen::default(), &look, &mut frame);
+        }
+        assert_eq!(frame[..3], [0, 0, 100]);
+        assert!(!phosphor.fading());
+    }
+
+    #[test]
+    fn buffer_follows_the_screen_size() {
+        let mut buffer = RgbaBuffer::new();
+        let mut screen = Screen::new(HIRES_WIDTH, HIRES_HEIGHT);
+        screen[63][127] = true;
+        buffer.present(&screen, Timestamp::default());
+        assert_eq!((buffer.width, buffer.height, buffer.frame.len()), (128, 64, 128 * 64 * 4));
+        assert_eq!(buffer.frame[buffer.frame.len() - 4..], [255, 0, 0, 255]);
+
+        let mut screen = Screen::default();
+        screen[0][1] = true;
+        buffer.present(&screen, Timestamp::default());
+        let scaled = buffer.scaled(2);
+        assert_eq!((scaled.width, scaled.height), (128, 64));
+        assert_eq!(scaled.to_ascii().lines().next().unwrap().trim_end(), "  QQ");
+        assert_eq!(scaled.to_ascii().lines().nth(1).unwrap().trim_end(), "  QQ");
+    }
+
+    #[test]
+    fn memory_view_shows_bits() {
+        let mut memory = [0; 4096];
+        memory[0] = 0x81;
+        memory[33] = 0xff;
+        let mut frame = vec![0; MEMORY_VIEW_WIDTH * MEMORY_VIEW_HEIGHT * 4];
+        draw_memory(&memory, 0x200, 33, &mut frame);
+        let pixel = |x: usize, y: usize| &frame[(y * MEMORY_VIEW_WIDTH + x) * 4..][..4];
+        assert_eq!(pixel(0, 0), [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(pixel(1, 0), [0, 0, 0, 0xff]);
+        assert_eq!(pixel(7, 0), [0xff, 0xff, 0xff, 0xff]);
+        // Byte 33 is the second of the second row, and is where the index points
+        assert_eq!(pixel(8, 1), [0x40, 0xff, 0x40, 0xff]);
+        // The PC's instruction is at 0x200, the start of row 16
+        assert_eq!(pixel(0, 16), [0x50, 0, 0, 0xff]);
+        assert_eq!(pixel(15, 16), [0x50, 0, 0, 0xff]);
+        assert_eq!(pixel(16, 16), [0, 0, 0, 0xff]);
+    }
+
+    #[test]
+    fn skips_are_capped() {
+        let mut skipper = FrameSkipper::new(2);
+        let rendered: Vec<bool> = (0..7).map(|_| skipper.should_render(true)).collect();
+        assert_eq!(rendered, [false, false, true, false, false, true, false]);
+        assert!(skipper.should_render(false));
+        assert!(FrameSkipper::new(0).should_render(true));
+    }
+}
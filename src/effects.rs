@@ -0,0 +1,211 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::chip8::Chip8;
+use crate::look::Palette;
+
+/// Background while the sound timer runs, with the flash effect on
+const FLASH_COLOR: [u8; 3] = [0x50, 0x50, 0x50];
+/// Frames for the rainbow effect to take the lit colours once round the colour wheel
+const RAINBOW_PERIOD: u64 = 240;
+
+/// What an effect gets to go on for the frame being drawn
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// 60Hz frames of emulated time, as `Timestamp::frame` counts them
+    pub frame: u64,
+    /// Whether the sound timer is running
+    pub sound: bool,
+}
+
+impl FrameInfo {
+    pub fn of(chip8: &Chip8) -> Self {
+        FrameInfo { frame: chip8.timestamp().frame(), sound: chip8.should_beep() }
+    }
+}
+
+/// Changes the colours a frame is drawn in, e.g. to flash on sound. Effects only ever see a copy
+/// of the palette, never the screen, so nothing they do reaches the core or saved states.
+pub trait PaletteEffect {
+    fn apply(&mut self, palette: &mut Palette, frame: &FrameInfo);
+
+    /// Whether the effect changes from frame to frame by itself, so frames have to be drawn even
+    /// when the screen doesn't change
+    fn animated(&self) -> bool {
+        false
+    }
+}
+
+/// Lights up the background while the sound timer runs
+pub struct FlashOnSound {
+    pub color: [u8; 3],
+}
+
+impl PaletteEffect for FlashOnSound {
+    fn apply(&mut self, palette: &mut Palette, frame: &FrameInfo) {
+        if frame.sound {
+            palette.0[0] = self.color;
+        }
+    }
+}
+
+/// Turns the lit colours round the colour wheel, leaving the background be
+pub struct Rainbow {
+    /// Frames for one turn
+    pub period: u64,
+}
+
+impl PaletteEffect for Rainbow {
+    fn apply(&mut self, palette: &mut Palette, frame: &FrameInfo) {
+        let turn = (frame.frame % self.period) as f32 / self.period as f32;
+        for color in &mut palette.0[1..] {
+            *color = rotate_hue(*color, turn);
+        }
+    }
+
+    fn animated(&self) -> bool {
+        true
+    }
+}
+
+/// Moves a colour's hue by `turn` of the way round, keeping its saturation and value. Greys stay grey.
+fn rotate_hue(rgb: [u8; 3], turn: f32) -> [u8; 3] {
+    let [r, g, b] = rgb.map(|channel| channel as f32 / u8::MAX as f32);
+    let max = r.max(g).max(b);
+    let spread = max - r.min(g).min(b);
+    if spread == 0.0 {
+        return rgb;
+    }
+    let hue = if max == r {
+        (g - b) / spread
+    } else if max == g {
+        (b - r) / spread + 2.0
+    } else {
+        (r - g) / spread + 4.0
+    };
+    let hue = (hue / 6.0 + turn).rem_euclid(1.0) * 6.0;
+    let channel = |n: f32| {
+        let k = (n + hue) % 6.0;
+        let value = max - spread * k.min(4.0 - k).clamp(0.0, 1.0);
+        (value * u8::MAX as f32).round() as u8
+    };
+    [channel(5.0), channel(3.0), channel(1.0)]
+}
+
+/// The effects that come with the emulator, chosen in a ROM's look with e.g. `effects = flash rainbow`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Builtins {
+    pub flash: bool,
+    pub rainbow: bool,
+}
+
+const BUILTINS: [&str; 2] = ["flash", "rainbow"];
+
+/// Names separated by spaces or commas, or `none`
+impl FromStr for Builtins {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut builtins = Builtins::default();
+        for name in s.split([' ', ',']).filter(|name| !name.is_empty()) {
+            match name {
+                "flash" => builtins.flash = true,
+                "rainbow" => builtins.rainbow = true,
+                "none" => {},
+                _ => return Err(format!("Unknown effect {}, expected {}", name, BUILTINS.join(" or "))),
+            }
+        }
+        Ok(builtins)
+    }
+}
+
+impl fmt::Display for Builtins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on: Vec<&str> = BUILTINS.into_iter().zip([self.flash, self.rainbow]).filter(|&(_, on)| on).map(|(name, _)| name).collect();
+        if on.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&on.join(" "))
+        }
+    }
+}
+
+/// Runs every effect added over the palette for each frame, in order. With none, frames are
+/// drawn in the palette as it is.
+#[derive(Default)]
+pub struct Effects {
+    effects: Vec<Box<dyn PaletteEffect>>,
+    /// The frame the palette was last worked out for
+    last: Option<FrameInfo>,
+}
+
+impl Effects {
+    pub fn builtin(builtins: Builtins) -> Self {
+        let mut effects = Effects::default();
+        if builtins.rainbow {
+            effects.add(Rainbow { period: RAINBOW_PERIOD });
+        }
+        if builtins.flash {
+            effects.add(FlashOnSound { color: FLASH_COLOR });
+        }
+        effects
+    }
+
+    pub fn add(&mut self, effect: impl PaletteEffect + 'static) {
+        self.effects.push(Box::new(effect));
+    }
+
+    /// The palette to draw this frame in, from the look's
+    pub fn apply(&mut self, palette: &Palette, frame: &FrameInfo) -> Palette {
+        let mut palette = *palette;
+        for effect in &mut self.effects {
+            effect.apply(&mut palette, frame);
+        }
+        self.last = Some(*frame);
+        palette
+    }
+
+    /// Whether the last frame drawn would come out differently now, even if the screen hasn't changed
+    pub fn stale(&self, frame: &FrameInfo) -> bool {
+        let animated = self.effects.iter().any(|effect| effect.animated());
+        self.last.is_some_and(|last| last.sound != frame.sound || (animated && last.frame != frame.frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::look::Palette;
+    use super::{rotate_hue, Builtins, Effects, FrameInfo};
+
+    #[test]
+    fn rotates_hues() {
+        assert_eq!(rotate_hue([0xff, 0, 0], 0.0), [0xff, 0, 0]);
+        assert_eq!(rotate_hue([0xff, 0, 0], 1.0 / 3.0), [0, 0xff, 0]);
+        assert_eq!(rotate_hue([0xff, 0, 0], 2.0 / 3.0), [0, 0, 0xff]);
+        assert_eq!(rotate_hue([0x40, 0xa0, 0xff], 1.0), [0x40, 0xa0, 0xff]);
+        assert_eq!(rotate_hue([0x80; 3], 0.5), [0x80; 3]);
+    }
+
+    #[test]
+    fn applies_builtins_to_a_copy() {
+        assert_eq!("rainbow, flash".parse(), Ok(Builtins { flash: true, rainbow: true }));
+        assert_eq!("none".parse(), Ok(Builtins::default()));
+        assert!("sparkle".parse::<Builtins>().is_err());
+        assert_eq!(Builtins { flash: true, rainbow: false }.to_string(), "flash");
+
+        let look = Palette::default();
+        let mut effects = Effects::builtin(Builtins { flash: true, rainbow: false });
+        let quiet = FrameInfo { frame: 10, sound: false };
+        assert!(!effects.stale(&quiet));
+        assert_eq!(effects.apply(&look, &quiet), look);
+        assert!(!effects.stale(&FrameInfo { frame: 11, ..quiet }));
+        let beeping = FrameInfo { frame: 11, sound: true };
+        assert!(effects.stale(&beeping));
+        assert_eq!(effects.apply(&look, &beeping).0[0], super::FLASH_COLOR);
+        assert_eq!(look, Palette::default());
+
+        let mut effects = Effects::builtin(Builtins { flash: false, rainbow: true });
+        let turned = effects.apply(&look, &FrameInfo { frame: super::RAINBOW_PERIOD / 3, sound: false });
+        assert_eq!(turned.0[..2], [[0, 0, 0], [0, 0xff, 0]]);
+        assert!(effects.stale(&FrameInfo::default()));
+    }
+}
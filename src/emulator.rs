@@ -0,0 +1,421 @@
+use std::fmt;
+use std::time::Duration;
+use crate::chip8::{Beep, Chip8, Cycle, Instruction, Screen, Timestamp, MAX_CALL_DEPTH};
+use crate::diagnostics::{Diagnostic, Policy, Report, Severity};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// Why the emulator couldn't go on. The core panics in these cases; `Emulator` checks first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The ROM is longer than the memory after where it's loaded, which fits `max` bytes
+    RomTooLarge { size: usize, max: usize },
+    /// The PC left the program's memory
+    PcOutOfBounds { pc: usize },
+    /// The bytes at the PC aren't an instruction the platform has
+    UnknownInstruction { pc: usize, opcode: u16 },
+    /// 00EE with nothing on the stack
+    ReturnWithEmptyStack { pc: usize },
+    /// 2NNN with as many calls on the stack as the core holds, `MAX_CALL_DEPTH`
+    StackOverflow { pc: usize },
+    /// One call ran as many instructions as `Limits::max_cycles_per_call` allows
+    CycleLimit { limit: u64 },
+    /// A draw past `Limits::max_draws_per_frame` in the same frame
+    DrawLimit { pc: usize, limit: u32 },
+    /// A call past `Limits::max_stack_depth`
+    StackLimit { pc: usize, limit: usize },
+    /// A jump or call to an odd address, when `Chip8::odd_pc` is `Policy::Error`
+    OddJump { pc: usize, target: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RomTooLarge { size, max } => write!(f, "ROM is {} bytes, but only {} fit in memory", size, max),
+            Error::PcOutOfBounds { pc } => write!(f, "PC left the program's memory ({:#x})", pc),
+            Error::UnknownInstruction { pc, opcode } => write!(f, "Unknown instruction {:04x} at {:#05x}", opcode, pc),
+            Error::ReturnWithEmptyStack { pc } => write!(f, "Returned with an empty stack at {:#05x}", pc),
+            Error::StackOverflow { pc } => write!(f, "Called more than {} subroutines deep at {:#05x}", MAX_CALL_DEPTH, pc),
+            Error::CycleLimit { limit } => write!(f, "Ran the limit of {} instructions in one go", limit),
+            Error::DrawLimit { pc, limit } => write!(f, "Drew more than {} times in a frame, at {:#05x}", limit, pc),
+            Error::StackLimit { pc, limit } => write!(f, "Called more than {} subroutines deep at {:#05x}", limit, pc),
+            Error::OddJump { pc, target } => write!(f, "Went to the odd address {:#05x} at {:#05x}", target, pc),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Caps on the work a ROM can make the emulator do, for running ones that can't be trusted, e.g. on a
+/// server. Going over one is an `Error` like any other, and leaves the machine as it was, so it can be
+/// dropped or run on. `None` is no cap, which is the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Instructions one call to `run` or `run_frame` may run, however fast the clock is
+    pub max_cycles_per_call: Option<u64>,
+    /// Draws in one 60th of a second of emulated time
+    pub max_draws_per_frame: Option<u32>,
+    /// Subroutine calls deep
+    pub max_stack_depth: Option<usize>,
+}
+
+/// A machine on its own emulated clock, which only moves as it's stepped, so runs are repeatable
+/// and go as fast as the host can manage. Frontends call `run_frame` 60 times a second, and
+/// tests call it (or `step`) as often as they like.
+pub struct Emulator {
+    chip8: Chip8,
+    frames: u32,
+    clock_gap: Duration,
+    keys: [bool; 16],
+    limits: Limits,
+    /// Draws so far in the frame the machine's emulated time is in, and which frame that is
+    draws: u32,
+    draw_frame: u64,
+    /// Beeper changes not yet taken
+    beeps: Vec<(Beep, Timestamp)>,
+}
+
+impl Emulator {
+    /// An empty machine for `platform`, with its quirks and speed
+    pub fn new(platform: Platform) -> Self {
+        let chip8 = Chip8::new(platform);
+        Emulator {
+            clock_gap: Duration::from_secs(1) / chip8.clock_speed(),
+            chip8,
+            frames: 0,
+            keys: [false; 16],
+            limits: Limits::default(),
+            draws: 0,
+            draw_frame: 0,
+            beeps: Vec::new(),
+        }
+    }
+
+    /// Loads `rom` where the program starts, 0x200 on most platforms. Meant for a new emulator.
+    pub fn load(&mut self, rom: &[u8]) -> Result<(), Error> {
+        let max = self.chip8.platform.max_rom_size();
+        if rom.len() > max {
+            return Err(Error::RomTooLarge { size: rom.len(), max });
+        }
+        self.chip8.read_program(rom).expect("Reading from memory can't fail");
+        Ok(())
+    }
+
+    /// Replaces the platform's quirks, e.g. for a ROM that expects another interpreter's
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.chip8.quirks = quirks;
+    }
+
+    /// Instructions per emulated second, in place of the platform's. Panics if it's 0.
+    pub fn set_clock_speed(&mut self, clock_speed: u32) {
+        assert!(clock_speed > 0, "The clock speed has to be at least 1 instruction per second");
+        self.clock_gap = Duration::from_secs(1) / clock_speed;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Makes CXNN's random numbers the same every run
+    pub fn seed(&mut self, seed: u64) {
+        self.chip8.seed_rng(seed);
+    }
+
+    /// Holds or lets go of a key, 0 to F, for the following cycles
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        self.keys[key] = pressed;
+    }
+
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        self.keys = keys;
+    }
+
+    /// Runs one instruction
+    pub fn step(&mut self) -> Result<Cycle, Error> {
+        let pc = self.chip8.pc;
+        if !self.chip8.pc_inbounds() {
+            return self.fail(Error::PcOutOfBounds { pc });
+        }
+        let opcode = self.chip8.get_instruction();
+        // The frame the instruction will run in, once the core moves its clock on
+        let now = self.elapsed() + self.clock_gap;
+        let frame = (now.as_nanos() / FRAME.as_nanos()) as u64;
+        if frame != self.draw_frame {
+            self.draw_frame = frame;
+            self.draws = 0;
+        }
+        let decoded = self.chip8.platform.decode(opcode);
+        let target = match decoded {
+            Some(Instruction::Jump { dest } | Instruction::CallSubroutine { dest }) => Some(dest as usize),
+            Some(Instruction::JumpOffset { dest }) => Some(self.chip8.offset_target(dest)),
+            _ => None,
+        };
+        if let Some(target) = target.filter(|target| !target.is_multiple_of(2) && self.chip8.odd_pc == Policy::Error) {
+            return self.fail(Error::OddJump { pc, target });
+        }
+        match decoded {
+            None => return self.fail(Error::UnknownInstruction { pc, opcode }),
+            Some(Instruction::Return) if self.chip8.stack.is_empty() => return self.fail(Error::ReturnWithEmptyStack { pc }),
+            Some(Instruction::CallSubroutine { .. }) if self.chip8.stack.len() >= MAX_CALL_DEPTH => return self.fail(Error::StackOverflow { pc }),
+            Some(Instruction::CallSubroutine { .. }) => match self.limits.max_stack_depth {
+                Some(limit) if self.chip8.stack.len() >= limit => return Err(Error::StackLimit { pc, limit }),
+                _ => {},
+            },
+            Some(Instruction::Draw { .. }) => match self.limits.max_draws_per_frame {
+                Some(limit) if self.draws >= limit => return Err(Error::DrawLimit { pc, limit }),
+                _ => self.draws += 1,
+            },
+            Some(_) => {},
+        }
+        let cycle = self.chip8.cycle(self.keys, self.clock_gap);
+        if let Some(beep) = self.chip8.beep.take() {
+            self.beeps.push((beep, self.chip8.timestamp()));
+        }
+        Ok(cycle)
+    }
+
+    /// Reports an error the core would have stopped on to its diagnostics, as it would have, and returns it
+    fn fail(&mut self, error: Error) -> Result<Cycle, Error> {
+        let (diagnostic, pc) = match error {
+            Error::PcOutOfBounds { pc } => (Diagnostic::PcOutOfBounds, pc),
+            Error::UnknownInstruction { pc, .. } => (Diagnostic::UnknownInstruction, pc),
+            Error::ReturnWithEmptyStack { pc } => (Diagnostic::ReturnWithEmptyStack, pc),
+            Error::StackOverflow { pc } => (Diagnostic::StackOverflow, pc),
+            Error::OddJump { pc, .. } => (Diagnostic::OddJump, pc),
+            _ => return Err(error),
+        };
+        let message = error.to_string();
+        self.chip8.diagnostics.report(diagnostic, Severity::Error, self.chip8.cycles, pc, || message);
+        Err(error)
+    }
+
+    /// Runs up to `cycles` instructions, stopping early if the program halts
+    pub fn run(&mut self, cycles: u64) -> Result<(), Error> {
+        for ran in 0..cycles {
+            if self.chip8.halted() {
+                break;
+            }
+            self.check_budget(ran)?;
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Runs a 60th of a second's worth of instructions, returning whether the screen changed
+    pub fn run_frame(&mut self) -> Result<bool, Error> {
+        self.frames += 1;
+        let frame_end = self.frames * FRAME;
+        let mut ran = 0;
+        let mut redrawn = false;
+        while self.elapsed() + self.clock_gap <= frame_end {
+            self.check_budget(ran)?;
+            ran += 1;
+            redrawn |= matches!(self.step()?, Cycle::RedrawRequested);
+        }
+        Ok(redrawn)
+    }
+
+    /// Fails once a call has run `Limits::max_cycles_per_call` instructions
+    fn check_budget(&self, ran: u64) -> Result<(), Error> {
+        match self.limits.max_cycles_per_call {
+            Some(limit) if ran >= limit => Err(Error::CycleLimit { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Times `run_frame` has been called
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Emulated time run so far, as the machine keeps it
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.chip8.timestamp().nanos)
+    }
+
+    /// Whether the program has jumped to itself or exited, after which nothing can change
+    pub fn halted(&self) -> bool {
+        self.chip8.halted()
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.chip8.display
+    }
+
+    /// Whether the buzzer should sound
+    pub fn sound_on(&self) -> bool {
+        self.chip8.should_beep()
+    }
+
+    /// Each time the beeper started or stopped since the last call, with when in emulated time,
+    /// so sound can be played in step with the frames rather than checking `sound_on` after them
+    pub fn take_beeps(&mut self) -> Vec<(Beep, Timestamp)> {
+        std::mem::take(&mut self.beeps)
+    }
+
+    /// Everything questionable the program has done so far, like reading past the end of memory
+    /// or the error that stopped it, once per kind and instruction, in order
+    pub fn diagnostics(&self) -> &[Report] {
+        self.chip8.diagnostics.reports()
+    }
+
+    /// Diagnostics reported since the last call, e.g. to show them as they come
+    pub fn take_diagnostics(&mut self) -> Vec<Report> {
+        self.chip8.diagnostics.take_new()
+    }
+
+    /// The machine itself, for registers, memory and the rest
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chip8::{Beep, Instruction, Timestamp, MAX_CALL_DEPTH};
+    use crate::diagnostics::{Diagnostic, Policy, Severity};
+    use crate::encode::assemble;
+    use crate::platform::Platform;
+    use super::{Emulator, Error, Limits};
+
+    #[test]
+    fn runs_frames_on_emulated_time() {
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.load(&assemble(&[
+            Instruction::FontChar { register: 0 },
+            Instruction::Draw { x_r: 0, y_r: 0, height: 5 },
+            Instruction::Jump { dest: 0x204 },
+        ])).unwrap();
+        assert!(emulator.run_frame().unwrap());
+        assert!(emulator.screen().pixel(0, 0));
+        assert!(!emulator.run_frame().unwrap());
+        assert_eq!(emulator.frames(), 2);
+        // 8 cycles of 2ms fit in each 16.7ms frame
+        assert_eq!(emulator.chip8().cycles, 16);
+        emulator.run(1000).unwrap();
+        assert!(emulator.halted());
+        assert!(emulator.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn keeps_time_with_the_core_across_clock_changes() {
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.load(&assemble(&[
+            Instruction::Jump { dest: 0x202 },
+            Instruction::Jump { dest: 0x200 },
+        ])).unwrap();
+        emulator.run(10).unwrap();
+        emulator.set_clock_speed(1000);
+        emulator.run(10).unwrap();
+        assert_eq!(emulator.elapsed(), Duration::from_nanos(emulator.chip8().timestamp().nanos));
+        assert_eq!(emulator.elapsed(), Duration::from_millis(20 + 10));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_a_stopped_clock() {
+        Emulator::new(Platform::Chip8).set_clock_speed(0);
+    }
+
+    #[test]
+    fn times_beeps_in_emulated_time() {
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.load(&assemble(&[
+            Instruction::SetRegister { register: 0, value: 6 },
+            Instruction::SetSoundTimer { register: 0 },
+            Instruction::Jump { dest: 0x204 },
+        ])).unwrap();
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.take_beeps(), [(Beep::Started(None), Timestamp { cycle: 2, nanos: 4_000_000 })]);
+        assert!(emulator.sound_on());
+        for _ in 0..10 {
+            emulator.run_frame().unwrap();
+        }
+        // Six ticks of the 60Hz timer, which count from when the machine started
+        assert_eq!(emulator.take_beeps(), [(Beep::Stopped, Timestamp { cycle: 50, nanos: 100_000_000 })]);
+        assert!(emulator.take_beeps().is_empty());
+    }
+
+    #[test]
+    fn reports_what_the_core_would_panic_at() {
+        let mut emulator = Emulator::new(Platform::Chip8);
+        assert_eq!(emulator.load(&[0; 4000]), Err(Error::RomTooLarge { size: 4000, max: 3584 }));
+        emulator.load(&assemble(&[Instruction::Return])).unwrap();
+        assert_eq!(emulator.step(), Err(Error::ReturnWithEmptyStack { pc: 0x200 }));
+
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.load(&assemble(&[Instruction::CallSubroutine { dest: 0x200 }])).unwrap();
+        emulator.run(MAX_CALL_DEPTH as u64).unwrap();
+        assert_eq!(emulator.step(), Err(Error::StackOverflow { pc: 0x200 }));
+        assert_eq!(emulator.chip8().stack.len(), MAX_CALL_DEPTH);
+
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.load(&[0x00, 0xff]).unwrap();
+        assert_eq!(emulator.step(), Err(Error::UnknownInstruction { pc: 0x200, opcode: 0x00ff }));
+
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.chip8_mut().odd_pc = Policy::Error;
+        emulator.load(&assemble(&[Instruction::SetRegister { register: 0, value: 3 }, Instruction::JumpOffset { dest: 0x200 }])).unwrap();
+        assert_eq!(emulator.run(2), Err(Error::OddJump { pc: 0x202, target: 0x203 }));
+    }
+
+    #[test]
+    fn collects_diagnostics() {
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.set_limits(Limits { max_cycles_per_call: Some(2), ..Limits::default() });
+        let mut rom = assemble(&[
+            Instruction::SetRegister { register: 0, value: 0x12 },
+            Instruction::FontChar { register: 0 },
+        ]);
+        rom.extend([0x00, 0xff]);
+        emulator.load(&rom).unwrap();
+        assert_eq!(emulator.run(2), Ok(()));
+        let reported = emulator.take_diagnostics();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].to_string(), "warning: Instruction at 0x202 asked for the font digit 0x12, using 0x2 (cycle 1)");
+        assert!(emulator.take_diagnostics().is_empty());
+
+        assert_eq!(emulator.step(), Err(Error::UnknownInstruction { pc: 0x204, opcode: 0x00ff }));
+        let reported = emulator.take_diagnostics();
+        assert_eq!((reported[0].diagnostic, reported[0].severity, reported[0].pc), (Diagnostic::UnknownInstruction, Severity::Error, 0x204));
+        assert_eq!(emulator.diagnostics().len(), 2);
+        // Limits are the frontend's, not anything wrong with the program
+        emulator.chip8_mut().pc = 0x200;
+        assert_eq!(emulator.run(10), Err(Error::CycleLimit { limit: 2 }));
+        assert_eq!(emulator.diagnostics().len(), 2);
+    }
+
+    #[test]
+    fn stops_at_limits() {
+        let limits = Limits { max_cycles_per_call: Some(100), max_draws_per_frame: Some(3), max_stack_depth: Some(4) };
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.set_limits(limits);
+        emulator.load(&assemble(&[Instruction::CallSubroutine { dest: 0x200 }])).unwrap();
+        assert_eq!(emulator.run(10), Err(Error::StackLimit { pc: 0x200, limit: 4 }));
+        assert_eq!(emulator.chip8().stack.len(), 4);
+
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.set_limits(limits);
+        emulator.load(&assemble(&[Instruction::Draw { x_r: 0, y_r: 0, height: 1 }, Instruction::Jump { dest: 0x200 }])).unwrap();
+        assert_eq!(emulator.run_frame(), Err(Error::DrawLimit { pc: 0x200, limit: 3 }));
+        assert_eq!(emulator.chip8().cycles, 6);
+
+        let mut emulator = Emulator::new(Platform::Chip8);
+        emulator.set_limits(limits);
+        emulator.set_clock_speed(1_000_000);
+        emulator.load(&assemble(&[Instruction::AddToRegister { register: 0, value: 1 }, Instruction::Jump { dest: 0x200 }])).unwrap();
+        assert_eq!(emulator.run_frame(), Err(Error::CycleLimit { limit: 100 }));
+        assert_eq!(emulator.chip8().cycles, 100);
+        // Nothing's lost: the rest of the frame runs on the next call
+        emulator.set_limits(Limits::default());
+        assert_eq!(emulator.run(50), Ok(()));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::chip8::Instruction;
+
+/// The inverse of `decode`. Fields wider than their slot in the opcode are truncated.
+pub fn encode(instruction: &Instruction) -> u16 {
+    let xy = |prefix: u16, x: u8, y: u8, suffix: u16| prefix << 12 | (x as u16 & 0xf) << 8 | (y as u16 & 0xf) << 4 | suffix;
+    let xnn = |prefix: u16, x: u8, value: u8| prefix << 12 | (x as u16 & 0xf) << 8 | value as u16;
+    let nnn = |prefix: u16, value: u16| prefix << 12 | value & 0xfff;
+    let fx = |x: u8, suffix: u16| 0xf000 | (x as u16 & 0xf) << 8 | suffix;
+    match *instruction {
+        Instruction::ClearScreen => 0x00e0,
+        Instruction::Return => 0x00ee,
+        Instruction::Lores => 0x00fe,
+        Instruction::Exit => 0x00fd,
+        Instruction::LongIndex => 0xf000,
+        Instruction::LoadAudio => 0xf002,
+        Instruction::Hires => 0x00ff,
+        Instruction::ScrollDown { rows } => 0x00c0 | (rows as u16 & 0xf),
+        Instruction::ScrollUp { rows } => 0x00d0 | (rows as u16 & 0xf),
+        Instruction::ScrollRight => 0x00fb,
+        Instruction::ScrollLeft => 0x00fc,
+        Instruction::Jump { dest } => nnn(0x1, dest),
+        Instruction::CallSubroutine { dest } => nnn(0x2, dest),
+        Instruction::SkipEQ { register, value } => xnn(0x3, register, value),
+        Instruction::SkipNEQ { register, value } => xnn(0x4, register, value),
+        Instruction::SkipEQR { register1, register2 } => xy(0x5, register1, register2, 0),
+        Instruction::StoreRange { register1, register2 } => xy(0x5, register1, register2, 2),
+        Instruction::LoadRange { register1, register2 } => xy(0x5, register1, register2, 3),
+        Instruction::SetRegister { register, value } => xnn(0x6, register, value),
+        Instruction::AddToRegister { register, value } => xnn(0x7, register, value),
+        Instruction::MovRegister { register1, register2 } => xy(0x8, register1, register2, 0),
+        Instruction::BinaryOr { register1, register2 } => xy(0x8, register1, register2, 1),
+        Instruction::BinaryAnd { register1, register2 } => xy(0x8, register1, register2, 2),
+        Instruction::BinaryXor { register1, register2 } => xy(0x8, register1, register2, 3),
+        Instruction::Add { register1, register2 } => xy(0x8, register1, register2, 4),
+        Instruction::SubtractForward { register1, register2 } => xy(0x8, register1, register2, 5),
+        Instruction::ShiftRight { register1, register2 } => xy(0x8, register1, register2, 6),
+        Instruction::SubtractBackward { register1, register2 } => xy(0x8, register1, register2, 7),
+        Instruction::ShiftLeft { register1, register2 } => xy(0x8, register1, register2, 0xe),
+        Instruction::SkipNEQR { register1, register2 } => xy(0x9, register1, register2, 0),
+        Instruction::SetIndexRegister { value } => nnn(0xa, value),
+        Instruction::JumpOffset { dest } => nnn(0xb, dest),
+        Instruction::Random { register, value } => xnn(0xc, register, value),
+        Instruction::Draw { x_r, y_r, height } => xy(0xd, x_r, y_r, height as u16 & 0xf),
+        Instruction::SkipPressed { register } => xnn(0xe, register, 0x9e),
+        Instruction::SkipNotPressed { register } => xnn(0xe, register, 0xa1),
+        Instruction::GetDelayTimer { register } => fx(register, 0x07),
+        Instruction::GetKey { register } => fx(register, 0x0a),
+        Instruction::SetDelayTimer { register } => fx(register, 0x15),
+        Instruction::SetSoundTimer { register } => fx(register, 0x18),
+        Instruction::SetPitch { register } => fx(register, 0x3a),
+        Instruction::AddToIndex { register } => fx(register, 0x1e),
+        Instruction::FontChar { register } => fx(register, 0x29),
+        Instruction::BigFontChar { register } => fx(register, 0x30),
+        Instruction::RegToDecimal { register } => fx(register, 0x33),
+        Instruction::StoreMemory { register } => fx(register, 0x55),
+        Instruction::LoadMemory { register } => fx(register, 0x65),
+        Instruction::SaveFlags { register } => fx(register, 0x75),
+        Instruction::LoadFlags { register } => fx(register, 0x85),
+        Instruction::SelectPlanes { planes } => fx(planes, 0x01),
+        Instruction::CycleBackground => 0x02a0,
+        Instruction::AddNibbles { register1, register2 } => xy(0x5, register1, register2, 1),
+        Instruction::ColorZones { x_r, y_r } => xy(0xb, x_r, y_r, 0),
+        Instruction::ColorRows { x_r, y_r, rows } => xy(0xb, x_r, y_r, rows as u16 & 0xf),
+        Instruction::SkipPressed2 { register } => xnn(0xe, register, 0xf2),
+        Instruction::SkipNotPressed2 { register } => xnn(0xe, register, 0xf5),
+        Instruction::MegaOff => 0x0010,
+        Instruction::MegaOn => 0x0011,
+        Instruction::MegaIndex { high } => 0x0100 | high as u16,
+        Instruction::LoadPalette { count } => 0x0200 | count as u16,
+        Instruction::SpriteWidth { width } => 0x0300 | width as u16,
+        Instruction::SpriteHeight { height } => 0x0400 | height as u16,
+        Instruction::Alpha { alpha } => 0x0500 | alpha as u16,
+        Instruction::PlaySample { mode } => 0x0600 | mode as u16 & 0xf,
+        Instruction::StopSample => 0x0700,
+        Instruction::BlendMode { mode } => 0x0800 | mode as u16 & 0xf,
+        Instruction::CollisionColor { color } => 0x0900 | color as u16,
+    }
+}
+
+/// Encodes instructions into ROM bytes
+pub fn assemble(instructions: &[Instruction]) -> Vec<u8> {
+    instructions.iter().flat_map(|instruction| encode(instruction).to_be_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::decode;
+    use crate::chip8::Instruction;
+    use super::{assemble, encode};
+
+    #[test]
+    fn assembles_programs() {
+        assert_eq!(assemble(&[Instruction::ClearScreen, Instruction::Jump { dest: 0x202 }]), [0x00, 0xe0, 0x12, 0x02]);
+    }
+
+    use proptest::prelude::*;
+    proptest! {
+        #[test]
+        fn inverts_decode(raw in 0..u16::MAX) {
+            if let Some(instruction) = decode(raw) {
+                prop_assert_eq!(decode(encode(&instruction)), Some(instruction));
+            }
+        }
+    }
+}
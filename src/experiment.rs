@@ -0,0 +1,127 @@
+use std::fmt;
+use std::time::Duration;
+use crate::bench::state;
+use crate::chip8::Chip8;
+
+/// How long each side of an experiment runs, 10 seconds at the default speed
+pub const EXPERIMENT_CYCLES: u64 = 5000;
+
+/// The same state run twice, once as it is and once with a quirk flipped
+pub struct Experiment {
+    pub quirk: &'static str,
+    /// What the quirk was flipped to
+    pub enabled: bool,
+    pub cycles: u64,
+    pub control: Chip8,
+    pub variant: Chip8,
+    /// The first cycle after which the states differed, and the address of the instruction it ran
+    pub divergence: Option<(u64, usize)>,
+    pub crashed: bool,
+}
+
+impl Experiment {
+    /// Forks `chip8` into two new cores and runs them side by side with no keys held
+    pub fn run(chip8: &Chip8, quirk: &'static str, cycles: u64, clock_gap: Duration) -> Self {
+        let snapshot = chip8.snapshot();
+        let fork = |enabled: bool| {
+            let mut fork = Chip8::new(chip8.platform);
+            fork.restore(&snapshot);
+            fork.quirks = chip8.quirks.clone();
+            fork.quirks.set(quirk, enabled).unwrap();
+            fork
+        };
+        let enabled = !chip8.quirks.get(quirk).unwrap();
+        let mut control = fork(!enabled);
+        let mut variant = fork(enabled);
+        let mut divergence = None;
+        // The panic has already been printed with the call stack
+        let run = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            for cycle in 0..cycles {
+                let pc = control.pc;
+                for chip8 in [&mut control, &mut variant] {
+                    if !chip8.halted() {
+                        chip8.cycle([false; 16], clock_gap);
+                    }
+                }
+                if divergence.is_none() && state(&control) != state(&variant) {
+                    divergence = Some((cycle, pc));
+                }
+            }
+        }));
+        Experiment { quirk, enabled, cycles, control, variant, divergence, crashed: run.is_err() }
+    }
+}
+
+/// Where the states ended up differing, with the screen drawn as `+` for pixels lit only
+/// with the quirk flipped, `-` for those lit only without it, and `#` for both
+impl fmt::Display for Experiment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = if self.enabled { self.quirk.to_string() } else { format!("no_{}", self.quirk) };
+        let (control, variant) = (&self.control, &self.variant);
+        match self.divergence {
+            None if !self.crashed => return writeln!(f, "{}: no difference in {} cycles", name, self.cycles),
+            None => writeln!(f, "{}: crashed", name)?,
+            Some((cycle, pc)) => writeln!(f, "{}: differs after cycle {} ({:#05x}){}", name, cycle, pc, if self.crashed { ", then crashed" } else { "" })?,
+        }
+        for (i, (a, b)) in control.registers.iter().zip(&variant.registers).enumerate().filter(|(_, (a, b))| a != b) {
+            writeln!(f, "    V{:X} {:#04x} -> {:#04x}", i, a, b)?;
+        }
+        if control.index_register != variant.index_register {
+            writeln!(f, "    I {:#05x} -> {:#05x}", control.index_register, variant.index_register)?;
+        }
+        if control.pc != variant.pc {
+            writeln!(f, "    pc {:#05x} -> {:#05x}", control.pc, variant.pc)?;
+        }
+        if control.stack != variant.stack {
+            writeln!(f, "    stack {:x?} -> {:x?}", control.stack, variant.stack)?;
+        }
+        let changed: Vec<usize> = (0..control.memory.len()).filter(|&i| control.memory[i] != variant.memory[i]).collect();
+        if let (Some(first), Some(last)) = (changed.first(), changed.last()) {
+            writeln!(f, "    {} bytes of memory between {:#05x} and {:#05x}", changed.len(), first, last)?;
+        }
+        let (width, height) = control.display.size();
+        if control.display.size() != variant.display.size() {
+            let (variant_width, variant_height) = variant.display.size();
+            writeln!(f, "    screen {}x{} -> {}x{}", width, height, variant_width, variant_height)?;
+        } else if control.display != variant.display {
+            writeln!(f, "    screen:")?;
+            for y in 0..height {
+                let row: String = (0..width).map(|x| match (control.display[y][x], variant.display[y][x]) {
+                    (true, true) => '#',
+                    (false, true) => '+',
+                    (true, false) => '-',
+                    (false, false) => ' ',
+                }).collect();
+                writeln!(f, "    |{}|", row)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::testing::{Machine, TEST_CLOCK_GAP};
+    use super::Experiment;
+
+    #[test]
+    fn finds_what_a_quirk_changes() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 1, value: 0b100 },
+            Instruction::SetRegister { register: 2, value: 0b1 },
+            Instruction::ShiftLeft { register1: 1, register2: 2 },
+            Instruction::Jump { dest: 0x206 },
+        ]);
+        machine.run(1);
+        let experiment = Experiment::run(&machine.chip8, "shift_uses_vy", 100, TEST_CLOCK_GAP);
+        assert_eq!(experiment.divergence, Some((1, 0x204)));
+        assert!(!experiment.crashed);
+        assert_eq!(experiment.to_string(), "shift_uses_vy: differs after cycle 1 (0x204)\n    V1 0x08 -> 0x02\n");
+        // The original is left alone
+        assert_eq!(machine.chip8.pc, 0x202);
+
+        let experiment = Experiment::run(&machine.chip8, "vf_reset", 100, TEST_CLOCK_GAP);
+        assert_eq!(experiment.to_string(), "vf_reset: no difference in 100 cycles\n");
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+use crate::chip8::{Chip8, Cycle};
+use crate::display::DisplaySink;
+
+/// Why a headless run stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// Ran every cycle asked for
+    CyclesReached,
+    /// Reached a jump to itself or SCHIP's 00FD exit, after which nothing can change
+    Halted,
+}
+
+/// How `run-headless` ended, as its process exit code, so scripts can branch on the outcome.
+/// 2 is left for bad arguments, like every other command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    CyclesReached = 0,
+    StrictFailed = 1,
+    Halted = 3,
+    CoreError = 4,
+    HashMismatch = 5,
+}
+
+/// Runs `cycles` instructions as fast as possible, or until the program halts, pretending `clock_speed`
+/// instructions take a second.
+/// Frames go to `sink` at most 60 times a simulated second, plus a final one if the screen changed since the last.
+/// `observe` sees the machine before every cycle, e.g. for tracing or taking what the last cycle recorded.
+pub fn run(
+    chip8: &mut Chip8,
+    cycles: u64,
+    clock_speed: u32,
+    keys: [bool; 16],
+    sink: &mut impl DisplaySink,
+    observe: &mut impl FnMut(&mut Chip8),
+) -> Stop {
+    let clock_gap = Duration::from_secs(1) / clock_speed;
+    let frame_gap = Duration::from_nanos(16_666_667);
+    let mut now = Duration::ZERO;
+    let mut next_frame = frame_gap;
+    let mut redraw = false;
+    let mut stop = Stop::CyclesReached;
+    for _ in 0..cycles {
+        if chip8.halted() {
+            stop = Stop::Halted;
+            break;
+        }
+        now += clock_gap;
+        observe(chip8);
+        if let Cycle::RedrawRequested = chip8.cycle(keys, clock_gap) {
+            redraw = true;
+        }
+        if now >= next_frame {
+            next_frame += frame_gap;
+            if redraw {
+                sink.present(&chip8.display, chip8.timestamp());
+                redraw = false;
+            }
+        }
+    }
+    if redraw {
+        sink.present(&chip8.display, chip8.timestamp());
+    }
+    stop
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::{Chip8, Screen, Timestamp};
+    use crate::display::{DisplaySink, RgbaBuffer};
+    use crate::platform::Platform;
+    use super::{run, Stop};
+
+    fn render<Sink: DisplaySink + Default>(rom: &str, cycles: u64) -> Sink {
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.read_program(std::fs::File::open(rom).unwrap()).unwrap();
+        let mut sink = Sink::default();
+        run(&mut chip8, cycles, 500, [false; 16], &mut sink, &mut |_| {});
+        sink
+    }
+
+    #[derive(Default)]
+    struct Timestamps(Vec<Timestamp>);
+
+    impl DisplaySink for Timestamps {
+        fn present(&mut self, _display: &Screen, at: Timestamp) {
+            self.0.push(at);
+        }
+    }
+
+    #[test]
+    fn ibm_logo_golden() {
+        let buffer: RgbaBuffer = render("test/ibm_logo.ch8", 1000);
+        assert_eq!(buffer.to_ascii(), std::fs::read_to_string("test/golden/ibm_logo.txt").unwrap().trim_end_matches('\n'));
+    }
+
+    #[test]
+    fn presents_at_most_once_per_frame() {
+        // 1000 cycles at 500Hz is 2 seconds, so at most 120 frames plus the final one
+        let buffer: RgbaBuffer = render("test/ibm_logo.ch8", 1000);
+        assert!(buffer.frames >= 1);
+        assert!(buffer.frames <= 121);
+    }
+
+    #[test]
+    fn frames_are_timestamped() {
+        let Timestamps(timestamps) = render("test/ibm_logo.ch8", 1000);
+        assert!(!timestamps.is_empty());
+        for at in &timestamps {
+            // 2ms per cycle at 500Hz
+            assert_eq!(at.nanos, at.cycle * 2_000_000);
+        }
+        // Apart from the final frame, each falls in a different 60th of a second
+        let slots: Vec<u64> = timestamps[..timestamps.len() - 1].iter().map(|at| at.frame()).collect();
+        assert!(slots.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", timestamps);
+    }
+
+    #[test]
+    fn stops_when_halted() {
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.read_program(std::fs::File::open("test/ibm_logo.ch8").unwrap()).unwrap();
+        let mut buffer = RgbaBuffer::new();
+        assert_eq!(run(&mut chip8, 5, 500, [false; 16], &mut buffer, &mut |_| {}), Stop::CyclesReached);
+        assert_eq!(chip8.cycles, 5);
+        // The logo ends by jumping to itself at 0x228
+        assert_eq!(run(&mut chip8, 1000, 500, [false; 16], &mut buffer, &mut |_| {}), Stop::Halted);
+        assert_eq!(chip8.pc, 0x228);
+        assert!(chip8.cycles < 1000);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use winit::event::VirtualKeyCode;
+use winit_input_helper::WinitInputHelper;
+use crate::config::config_path;
+
+const HOTKEYS_FILE: &str = "hotkeys";
+
+/// Things the emulator (rather than the program) does when a key is pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Pause,
+    Step,
+    StepBack,
+    Rewind,
+    Turbo,
+    SaveState,
+    LoadState,
+    SlotPicker,
+    Faster,
+    Slower,
+    MemoryView,
+    Mirror,
+    Screenshot,
+    InstantReplay,
+    Fullscreen,
+    PasteRom,
+    Look,
+    LookUp,
+    LookDown,
+    Assemble,
+    Experiment,
+    Heatmap,
+    Latency,
+    Checkpoint,
+    Retry,
+}
+
+const ACTIONS: [(&str, Action, &[VirtualKeyCode]); 26] = [
+    ("quit", Action::Quit, &[VirtualKeyCode::Escape]),
+    ("pause", Action::Pause, &[VirtualKeyCode::P]),
+    ("step", Action::Step, &[VirtualKeyCode::N]),
+    ("step_back", Action::StepBack, &[VirtualKeyCode::B]),
+    ("rewind", Action::Rewind, &[VirtualKeyCode::Back]),
+    ("turbo", Action::Turbo, &[VirtualKeyCode::Tab]),
+    ("save_state", Action::SaveState, &[VirtualKeyCode::F5]),
+    ("load_state", Action::LoadState, &[VirtualKeyCode::F9]),
+    ("slot_picker", Action::SlotPicker, &[VirtualKeyCode::F6]),
+    ("faster", Action::Faster, &[VirtualKeyCode::Equals, VirtualKeyCode::NumpadAdd]),
+    ("slower", Action::Slower, &[VirtualKeyCode::Minus, VirtualKeyCode::NumpadSubtract]),
+    ("memory_view", Action::MemoryView, &[VirtualKeyCode::F2]),
+    ("mirror", Action::Mirror, &[VirtualKeyCode::F8]),
+    ("screenshot", Action::Screenshot, &[VirtualKeyCode::F12]),
+    ("instant_replay", Action::InstantReplay, &[VirtualKeyCode::F10]),
+    ("fullscreen", Action::Fullscreen, &[VirtualKeyCode::F11]),
+    ("paste_rom", Action::PasteRom, &[VirtualKeyCode::Insert]),
+    ("look", Action::Look, &[VirtualKeyCode::F7]),
+    ("look_up", Action::LookUp, &[VirtualKeyCode::PageUp]),
+    ("look_down", Action::LookDown, &[VirtualKeyCode::PageDown]),
+    ("assemble", Action::Assemble, &[VirtualKeyCode::F3]),
+    ("experiment", Action::Experiment, &[VirtualKeyCode::F4]),
+    ("heatmap", Action::Heatmap, &[VirtualKeyCode::F1]),
+    ("latency", Action::Latency, &[VirtualKeyCode::Home]),
+    ("checkpoint", Action::Checkpoint, &[VirtualKeyCode::End]),
+    ("retry", Action::Retry, &[VirtualKeyCode::Delete]),
+];
+
+macro_rules! key_names {
+    ($($key:ident)*) => { [$((stringify!($key), VirtualKeyCode::$key)),*] };
+}
+
+/// Keys that can be named in the hotkeys file, by their winit names
+const KEY_NAMES: [(&str, VirtualKeyCode); 88] = key_names![
+    Key1 Key2 Key3 Key4 Key5 Key6 Key7 Key8 Key9 Key0
+    A B C D E F G H I J K L M N O P Q R S T U V W X Y Z
+    F1 F2 F3 F4 F5 F6 F7 F8 F9 F10 F11 F12
+    Escape Tab Back Return Space Insert Delete Home End PageUp PageDown Left Right Up Down
+    Minus Equals LBracket RBracket Semicolon Apostrophe Grave Backslash Comma Period Slash
+    Numpad0 Numpad1 Numpad2 Numpad3 Numpad4 Numpad5 Numpad6 Numpad7 Numpad8 Numpad9
+    NumpadAdd NumpadSubtract NumpadMultiply NumpadDivide
+];
+
+fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    KEY_NAMES.iter().find(|(key_name, _)| key_name.eq_ignore_ascii_case(name)).map(|&(_, key)| key)
+}
+
+/// Which keys trigger each action
+pub struct Hotkeys {
+    bindings: HashMap<Action, Vec<VirtualKeyCode>>,
+}
+
+impl Hotkeys {
+    /// Defaults overridden by `action = Key, Key` lines, e.g. `pause = Space`.
+    /// Bad lines are skipped with a warning, and so are keys the keypad already uses
+    /// or that are bound to another action.
+    pub fn parse(text: &str, keypad: &[VirtualKeyCode]) -> Self {
+        let mut bindings: HashMap<Action, Vec<VirtualKeyCode>> =
+            ACTIONS.iter().map(|&(_, action, keys)| (action, keys.to_vec())).collect();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = line.split_once('=').and_then(|(name, keys)| {
+                let &(_, action, _) = ACTIONS.iter().find(|(action_name, _, _)| *action_name == name.trim())?;
+                let keys: Option<Vec<VirtualKeyCode>> = keys.split(',').map(|key| key_from_name(key.trim())).collect();
+                Some((action, keys?))
+            });
+            match parsed {
+                Some((action, keys)) => {
+                    bindings.insert(action, keys);
+                },
+                None => log::warn!("Skipping bad hotkey line: {}", line),
+            }
+        }
+
+        // Keypad first, then actions in the order they're listed, so overridden defaults lose
+        let mut taken: Vec<VirtualKeyCode> = keypad.to_vec();
+        for (name, action, _) in ACTIONS {
+            let keys = bindings.get_mut(&action).unwrap();
+            keys.retain(|key| {
+                let conflict = taken.contains(key);
+                if conflict {
+                    let user = if keypad.contains(key) { "the keypad" } else { "another hotkey" };
+                    log::warn!("Not binding {:?} to {}, since {} uses it", key, name, user);
+                } else {
+                    taken.push(*key);
+                }
+                !conflict
+            });
+        }
+        Hotkeys { bindings }
+    }
+
+    /// Reads the hotkeys file if there is one
+    pub fn load(keypad: &[VirtualKeyCode]) -> Self {
+        let text = std::fs::read_to_string(config_path(HOTKEYS_FILE)).unwrap_or_default();
+        Hotkeys::parse(&text, keypad)
+    }
+
+    fn keys(&self, action: Action) -> &[VirtualKeyCode] {
+        &self.bindings[&action]
+    }
+
+    pub fn pressed(&self, input: &WinitInputHelper, action: Action) -> bool {
+        self.keys(action).iter().any(|&key| input.key_pressed(key))
+    }
+
+    pub fn released(&self, input: &WinitInputHelper, action: Action) -> bool {
+        self.keys(action).iter().any(|&key| input.key_released(key))
+    }
+
+    pub fn held(&self, input: &WinitInputHelper, action: Action) -> bool {
+        self.keys(action).iter().any(|&key| input.key_held(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::VirtualKeyCode;
+    use super::{Action, Hotkeys};
+
+    #[test]
+    fn overrides_defaults() {
+        let hotkeys = Hotkeys::parse("pause = space # easier to hit\nfaster = PageUp, Up\nnonsense = A\nturbo = Nope", &[]);
+        assert_eq!(hotkeys.keys(Action::Pause), [VirtualKeyCode::Space]);
+        assert_eq!(hotkeys.keys(Action::Faster), [VirtualKeyCode::PageUp, VirtualKeyCode::Up]);
+        assert_eq!(hotkeys.keys(Action::Turbo), [VirtualKeyCode::Tab]);
+        assert_eq!(hotkeys.keys(Action::Slower), [VirtualKeyCode::Minus, VirtualKeyCode::NumpadSubtract]);
+    }
+
+    #[test]
+    fn skips_conflicts() {
+        // P is on the keypad in this layout, and F5 is bound twice
+        let hotkeys = Hotkeys::parse("step_back = F5", &[VirtualKeyCode::P, VirtualKeyCode::X]);
+        assert!(hotkeys.keys(Action::Pause).is_empty());
+        assert_eq!(hotkeys.keys(Action::StepBack), [VirtualKeyCode::F5]);
+        assert!(hotkeys.keys(Action::SaveState).is_empty());
+    }
+}
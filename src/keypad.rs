@@ -0,0 +1,75 @@
+/// The keypad as the frontend sees it between instructions. A key pressed and released
+/// before the next instruction runs still counts as down for that instruction, so quick taps
+/// aren't lost however long an instruction's wakeup takes to come round.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Keypad {
+    held: [bool; 16],
+    /// Keys pressed since the last instruction took its keys
+    tapped: [bool; 16],
+}
+
+impl Keypad {
+    pub fn press(&mut self, key: usize) {
+        self.held[key] = true;
+        self.tapped[key] = true;
+    }
+
+    pub fn release(&mut self, key: usize) {
+        self.held[key] = false;
+    }
+
+    /// Keys physically down right now, leaving out taps already released
+    pub fn held(&self) -> [bool; 16] {
+        self.held
+    }
+
+    /// Keys the next instruction will see, without using up the taps
+    pub fn peek(&self) -> [bool; 16] {
+        std::array::from_fn(|key| self.held[key] || self.tapped[key])
+    }
+
+    /// Keys for the instruction about to run; taps count for this one instruction only
+    pub fn take(&mut self) -> [bool; 16] {
+        let keys = self.peek();
+        self.tapped = [false; 16];
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chip8::{Chip8, Instruction};
+    use crate::platform::Platform;
+    use crate::replay::{Player, Recording};
+    use super::Keypad;
+
+    #[test]
+    fn taps_between_instructions_are_recorded() {
+        let clock_gap = Duration::from_millis(2);
+        let mut chip8 = Chip8::new(Platform::Chip8);
+        chip8.load_instructions(&[
+            Instruction::GetKey { register: 1 },
+            Instruction::Jump { dest: 0x202 },
+        ]);
+        let mut keypad = Keypad::default();
+        let mut recording = Recording::start(&chip8, keypad.held(), clock_gap);
+        for cycle in 0..10 {
+            // Pressed and released between two wakeups, with no instruction seeing it held
+            if cycle == 5 {
+                keypad.press(0xa);
+                keypad.release(0xa);
+            }
+            let keys = keypad.take();
+            assert_eq!(keys[0xa], cycle == 5);
+            recording.record_cycle(keys, clock_gap);
+            chip8.cycle(keys, clock_gap);
+        }
+        assert_eq!(chip8.registers[1].0, 0xa);
+        assert_eq!(keypad.peek(), [false; 16]);
+
+        let mut player = Player::new(recording);
+        player.step(10);
+        assert_eq!(player.chip8.registers[1].0, 0xa);
+    }
+}
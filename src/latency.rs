@@ -0,0 +1,140 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::chip8::{Chip8, Screen};
+
+/// Emulated time to wait for the screen to answer a press before giving up on it
+pub const MAX_WAIT: Duration = Duration::from_secs(2);
+/// Frames the screen has to stay the same before the next press, so the change is the press's doing
+const SETTLED_FRAMES: u64 = 10;
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// How long a key press took to show on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latency {
+    /// 60Hz frames of emulated time
+    pub frames: u64,
+    /// Real time, which takes in everything the frontend does along the way
+    pub host: Duration,
+}
+
+/// e.g. `3 frames, 1.2ms`
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.frames == 1 { "" } else { "s" };
+        write!(f, "{} frame{}, {:.1}ms", self.frames, plural, self.host.as_secs_f64() * 1000.0)
+    }
+}
+
+/// Times key presses to the first change on screen after them
+#[derive(Debug, Default)]
+pub struct LatencyProbe {
+    /// The frame and real time of the press being timed, and the screen as it was
+    pressed: Option<(u64, Instant, Screen)>,
+}
+
+impl LatencyProbe {
+    /// A key went down. Presses while the last is still being timed don't count.
+    pub fn press(&mut self, chip8: &Chip8, host: Instant) {
+        if self.pressed.is_none() {
+            self.pressed = Some((chip8.timestamp().frame(), host, chip8.display.clone()));
+        }
+    }
+
+    pub fn waiting(&self) -> bool {
+        self.pressed.is_some()
+    }
+
+    pub fn cancel(&mut self) {
+        self.pressed = None;
+    }
+
+    /// Call whenever the screen might have changed, e.g. after each cycle or as it's shown.
+    /// Returns how long the press being timed took, the first time the screen differs.
+    pub fn check(&mut self, chip8: &Chip8, host: Instant) -> Option<Latency> {
+        let (frame, pressed_at, before) = self.pressed.as_ref()?;
+        if chip8.display == *before {
+            return None;
+        }
+        let latency = Latency { frames: chip8.timestamp().frame() - frame, host: host.saturating_duration_since(*pressed_at) };
+        self.pressed = None;
+        Some(latency)
+    }
+}
+
+/// Runs `chip8` without a window for `warm_up` of emulated time, then presses `key` `presses`
+/// times, each once the screen has settled, timing it to the first change and letting go. Real
+/// time here is only the core's, so it's the emulated frames that say how responsive the program
+/// is. Presses the screen didn't answer within `MAX_WAIT` are `None`.
+pub fn measure(chip8: &mut Chip8, clock_speed: u32, key: usize, presses: u32, warm_up: Duration) -> Vec<Option<Latency>> {
+    let clock_gap = Duration::from_secs(1) / clock_speed;
+    let mut elapsed = Duration::ZERO;
+    let mut keys = [false; 16];
+    let mut cycle = |chip8: &mut Chip8, keys: [bool; 16]| {
+        elapsed += clock_gap;
+        chip8.cycle(keys, clock_gap);
+        elapsed
+    };
+    while cycle(chip8, keys) < warm_up && !chip8.halted() {}
+    let mut probe = LatencyProbe::default();
+    let mut results = Vec::new();
+    for _ in 0..presses {
+        // Wait for the screen to be still, or as long as a press gets, whichever's first
+        let (mut still_since, mut screen) = (chip8.timestamp().frame(), chip8.display.clone());
+        let give_up = chip8.timestamp().frame() + frames(MAX_WAIT);
+        while chip8.timestamp().frame() < (still_since + SETTLED_FRAMES).min(give_up) && !chip8.halted() {
+            cycle(chip8, keys);
+            if chip8.display != screen {
+                (still_since, screen) = (chip8.timestamp().frame(), chip8.display.clone());
+            }
+        }
+        keys[key] = true;
+        probe.press(chip8, Instant::now());
+        let give_up = chip8.timestamp().frame() + frames(MAX_WAIT);
+        let mut latency = None;
+        while latency.is_none() && chip8.timestamp().frame() < give_up && !chip8.halted() {
+            cycle(chip8, keys);
+            latency = probe.check(chip8, Instant::now());
+        }
+        probe.cancel();
+        keys[key] = false;
+        results.push(latency);
+    }
+    results
+}
+
+fn frames(time: Duration) -> u64 {
+    (time.as_nanos() / FRAME.as_nanos()) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chip8::{Chip8, Instruction};
+    use crate::platform::Platform;
+    use super::measure;
+
+    #[test]
+    fn times_presses_to_the_screen_changing() {
+        let mut chip8 = Chip8::new(Platform::default());
+        // Waits for a key, then a second before drawing it, toggling it on and off each press
+        chip8.load_instructions(&[
+            Instruction::GetKey { register: 0 },
+            Instruction::SetRegister { register: 1, value: 60 },
+            Instruction::SetDelayTimer { register: 1 },
+            Instruction::GetDelayTimer { register: 1 },
+            Instruction::SkipEQ { register: 1, value: 0 },
+            Instruction::Jump { dest: 0x206 },
+            Instruction::FontChar { register: 0 },
+            Instruction::Draw { x_r: 2, y_r: 2, height: 5 },
+            Instruction::Jump { dest: 0x200 },
+        ]);
+        let results = measure(&mut chip8, 1000, 5, 2, Duration::from_millis(100));
+        let frames: Vec<Option<u64>> = results.iter().map(|latency| latency.map(|latency| latency.frames)).collect();
+        assert_eq!(frames, [Some(60), Some(60)]);
+
+        // Nothing ever draws
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.load_instructions(&[Instruction::GetKey { register: 0 }, Instruction::Jump { dest: 0x200 }]);
+        assert_eq!(measure(&mut chip8, 1000, 5, 1, Duration::ZERO), [None]);
+    }
+}
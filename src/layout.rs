@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Error;
+use crate::config::config_path;
+
+const LAYOUT_FILE: &str = "layout";
+
+/// Where a window is, in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where each window was, so they open in the same place next time.
+/// A window missing from the layout was closed (or never opened).
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    windows: BTreeMap<String, Placement>,
+}
+
+impl Layout {
+    /// One `name x y width height` line per window. Lines that don't fit are skipped.
+    pub fn parse(text: &str) -> Self {
+        let mut windows = BTreeMap::new();
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let placement = match fields[..] {
+                [_, x, y, width, height] => (|| Some(Placement {
+                    x: x.parse().ok()?,
+                    y: y.parse().ok()?,
+                    width: width.parse().ok()?,
+                    height: height.parse().ok()?,
+                }))(),
+                _ => None,
+            };
+            match placement {
+                Some(placement) => {
+                    windows.insert(fields[0].to_string(), placement);
+                },
+                None if line.trim().is_empty() => {},
+                None => log::warn!("Skipping bad line in window layout: {}", line),
+            }
+        }
+        Layout { windows }
+    }
+
+    pub fn load() -> Self {
+        std::fs::read_to_string(config_path(LAYOUT_FILE)).map_or_else(|_| Layout::default(), |text| Layout::parse(&text))
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = config_path(LAYOUT_FILE);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_string())
+    }
+
+    pub fn get(&self, window: &str) -> Option<Placement> {
+        self.windows.get(window).copied()
+    }
+
+    /// `None` records the window as closed
+    pub fn set(&mut self, window: &str, placement: Option<Placement>) {
+        match placement {
+            Some(placement) => self.windows.insert(window.to_string(), placement),
+            None => self.windows.remove(window),
+        };
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, Placement { x, y, width, height }) in &self.windows {
+            writeln!(f, "{} {} {} {} {}", name, x, y, width, height)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Layout, Placement};
+
+    #[test]
+    fn roundtrips() {
+        let mut layout = Layout::default();
+        layout.set("display", Some(Placement { x: -1920, y: 40, width: 1280, height: 640 }));
+        layout.set("memory", Some(Placement { x: 10, y: 20, width: 512, height: 256 }));
+        let text = layout.to_string();
+        assert_eq!(text, "display -1920 40 1280 640\nmemory 10 20 512 256\n");
+        assert_eq!(Layout::parse(&text), layout);
+        layout.set("memory", None);
+        assert_eq!(layout.get("memory"), None);
+        assert_eq!(Layout::parse("display 1 2 3\n\nmemory 10 20 512 256"), Layout::parse("memory 10 20 512 256"));
+    }
+}
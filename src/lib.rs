@@ -0,0 +1,136 @@
+//! A CHIP-8 emulator core, which the `chip8` binary runs in a window, and other programs can
+//! run however they like.
+//!
+//! [`Emulator`] is the way in: load a ROM, then run a frame at a time (or a cycle at a time),
+//! setting keys in between and reading the screen after. [`Limits`] caps the work a ROM that
+//! can't be trusted can make it do. [`testing`] has helpers for testing ROMs against the core.
+//! The items re-exported here and the `testing` module follow semver. The other modules are
+//! the binary's, and public only so it can reach them; they change freely.
+//!
+//! ```
+//! use chip8::{Emulator, Platform};
+//!
+//! let mut emulator = Emulator::new(Platform::Chip8);
+//! // Point I at the 0 glyph, draw it, then wait forever
+//! emulator.load(&[0xf0, 0x29, 0xd0, 0x05, 0x12, 0x04])?;
+//! emulator.run_frame()?;
+//! assert!(emulator.screen().pixel(0, 0));
+//! # Ok::<(), chip8::Error>(())
+//! ```
+//!
+//! See `examples/` for a headless run, a terminal frontend, and a scripted test.
+
+#[doc(hidden)]
+pub mod decode;
+#[doc(hidden)]
+pub mod chip8;
+#[doc(hidden)]
+pub mod bits;
+#[doc(hidden)]
+pub mod snapshot;
+#[doc(hidden)]
+pub mod savestate;
+#[doc(hidden)]
+pub mod rom;
+#[doc(hidden)]
+pub mod cli;
+#[doc(hidden)]
+pub mod analysis;
+#[doc(hidden)]
+pub mod asm;
+#[doc(hidden)]
+pub mod audio;
+#[doc(hidden)]
+pub mod display;
+#[doc(hidden)]
+pub mod headless;
+#[doc(hidden)]
+pub mod replay;
+#[doc(hidden)]
+pub mod keypad;
+#[doc(hidden)]
+pub mod symbols;
+#[doc(hidden)]
+pub mod stats;
+#[doc(hidden)]
+pub mod trace;
+#[doc(hidden)]
+pub mod accesslog;
+#[doc(hidden)]
+pub mod slowlog;
+#[doc(hidden)]
+pub mod platform;
+#[doc(hidden)]
+pub mod protection;
+#[doc(hidden)]
+pub mod quirks;
+#[doc(hidden)]
+pub mod diagnostics;
+#[doc(hidden)]
+pub mod diff;
+#[doc(hidden)]
+pub mod disasm;
+#[doc(hidden)]
+pub mod experiment;
+#[doc(hidden)]
+pub mod xref;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod layout;
+#[doc(hidden)]
+pub mod calibrate;
+#[doc(hidden)]
+pub mod ocr;
+#[doc(hidden)]
+pub mod breakpoints;
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "gui")]
+#[doc(hidden)]
+pub mod hotkeys;
+#[doc(hidden)]
+pub mod logging;
+#[doc(hidden)]
+pub mod look;
+#[doc(hidden)]
+pub mod effects;
+#[doc(hidden)]
+pub mod mega;
+#[doc(hidden)]
+pub mod vip;
+#[doc(hidden)]
+pub mod encode;
+#[doc(hidden)]
+pub mod video;
+#[doc(hidden)]
+pub mod soak;
+#[doc(hidden)]
+pub mod audit;
+#[doc(hidden)]
+pub mod latency;
+#[doc(hidden)]
+pub mod emulator;
+#[doc(hidden)]
+pub mod settings;
+#[doc(hidden)]
+pub mod boot;
+#[doc(hidden)]
+pub mod notify;
+#[doc(hidden)]
+pub mod script;
+#[doc(hidden)]
+pub mod practice;
+#[doc(hidden)]
+pub mod score;
+#[doc(hidden)]
+pub mod rpl;
+#[doc(hidden)]
+pub mod thumbnails;
+pub mod testing;
+
+pub use crate::chip8::{AudioPattern, Beep, Chip8, Cycle, Instruction, Screen, Timestamp};
+pub use crate::diagnostics::{Diagnostic, Report, Severity};
+pub use crate::emulator::{Emulator, Error, Limits};
+pub use crate::platform::Platform;
+pub use crate::quirks::Quirks;
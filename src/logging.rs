@@ -0,0 +1,31 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::chip8::Timestamp;
+
+/// Where emulation was when the latest instruction started, for stamping log lines
+static CYCLE: AtomicU64 = AtomicU64::new(0);
+static FRAME: AtomicU64 = AtomicU64::new(0);
+
+/// Marks log lines from here on as coming from `at`
+pub fn stamp(at: Timestamp) {
+    CYCLE.store(at.cycle, Ordering::Relaxed);
+    FRAME.store(at.frame(), Ordering::Relaxed);
+}
+
+/// Sets up `env_logger` to prefix every line with the cycle and frame, e.g.
+/// `[c1234 f20 DEBUG chip8::audio] Beep started`, matching replay positions and screenshot names
+pub fn init() {
+    env_logger::builder()
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "[c{} f{} {} {}] {}",
+                CYCLE.load(Ordering::Relaxed),
+                FRAME.load(Ordering::Relaxed),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        })
+        .init();
+}
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::effects::Builtins;
+
+/// How much each press of the adjust keys changes a setting
+const STEP: f32 = 0.1;
+
+/// How the screen is rendered in the window, for imitating a COSMAC VIP on a CRT television
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Look {
+    /// Fraction of a pixel's glow left after each frame once it's turned off, 0 for none
+    pub persistence: f32,
+    /// Intensity of a lit pixel
+    pub brightness: f32,
+    /// Fraction of each pixel's glow that spills onto its neighbours
+    pub bloom: f32,
+    pub palette: Palette,
+    /// Built-in palette effects, applied over the palette as each frame is drawn
+    pub effects: Builtins,
+}
+
+impl Default for Look {
+    fn default() -> Self {
+        Look { persistence: 0.0, brightness: 1.0, bloom: 0.0, palette: Palette::default(), effects: Builtins::default() }
+    }
+}
+
+/// RGB colours for pixels lit in no plane, the first, the second, and both. Only XO-CHIP uses the last two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette(pub [[u8; 3]; 4]);
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette([[0, 0, 0], [0xff, 0, 0], [0x40, 0xa0, 0xff], [0xff, 0xff, 0xff]])
+    }
+}
+
+/// Four colours as hex, separated by spaces or commas, e.g. `000000 ff0000 40a0ff #ffffff`
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colors: Vec<[u8; 3]> = s.split([' ', ',']).filter(|color| !color.is_empty()).map(|color| {
+            let hex = color.strip_prefix('#').unwrap_or(color);
+            match u32::from_str_radix(hex, 16) {
+                Ok(rgb) if hex.len() == 6 => Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]),
+                _ => Err(format!("Bad colour {}, expected 6 hex digits like ff0000", color)),
+            }
+        }).collect::<Result<_, _>>()?;
+        let colors = colors.try_into().map_err(|colors: Vec<_>| format!("Expected 4 colours, got {}", colors.len()))?;
+        Ok(Palette(colors))
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let colors: Vec<String> = self.0.iter().map(|[r, g, b]| format!("{:02x}{:02x}{:02x}", r, g, b)).collect();
+        f.write_str(&colors.join(" "))
+    }
+}
+
+/// A setting of `Look` that can be adjusted while running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Persistence,
+    Brightness,
+    Bloom,
+}
+
+const SETTINGS: [(&str, Setting); 3] = [
+    ("persistence", Setting::Persistence),
+    ("brightness", Setting::Brightness),
+    ("bloom", Setting::Bloom),
+];
+
+impl Setting {
+    /// The setting after this one, or `None` after the last, for cycling through them with one key
+    pub fn next(setting: Option<Setting>) -> Option<Setting> {
+        match setting {
+            None => Some(Setting::Persistence),
+            Some(Setting::Persistence) => Some(Setting::Brightness),
+            Some(Setting::Brightness) => Some(Setting::Bloom),
+            Some(Setting::Bloom) => None,
+        }
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = SETTINGS.iter().find(|&&(_, setting)| setting == *self).unwrap().0;
+        f.write_str(name)
+    }
+}
+
+/// The look for a ROM is kept next to it, like its save states
+pub fn look_path(rom_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.look", rom_path))
+}
+
+impl Look {
+    /// `setting = value` lines, with anything missing left at the default.
+    /// Bad lines are skipped with a warning.
+    pub fn parse(text: &str) -> Self {
+        let mut look = Look::default();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match line.split_once('=').map(|(name, value)| (name.trim(), value)) {
+                Some(("palette", colors)) => {
+                    match colors.parse() {
+                        Ok(palette) => look.palette = palette,
+                        Err(e) => log::warn!("Skipping look setting {:?}: {}", line, e),
+                    }
+                    continue;
+                },
+                Some(("effects", names)) => {
+                    match names.parse() {
+                        Ok(effects) => look.effects = effects,
+                        Err(e) => log::warn!("Skipping look setting {:?}: {}", line, e),
+                    }
+                    continue;
+                },
+                _ => {},
+            }
+            let parsed = line.split_once('=').and_then(|(name, value)| {
+                let setting = SETTINGS.iter().find(|(setting_name, _)| *setting_name == name.trim())?.1;
+                Some((setting, value.trim().parse::<f32>().ok()?))
+            });
+            match parsed {
+                Some((setting, value)) => look.set(setting, value),
+                None => log::warn!("Skipping look setting {:?}, expected a line like `bloom = 0.2`", line),
+            }
+        }
+        look
+    }
+
+    pub fn load(rom_path: &str) -> Self {
+        match std::fs::read_to_string(look_path(rom_path)) {
+            Ok(text) => Look::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Look::default(),
+            Err(e) => {
+                log::warn!("Couldn't read look settings for {}: {}", rom_path, e);
+                Look::default()
+            },
+        }
+    }
+
+    pub fn save(&self, rom_path: &str) -> std::io::Result<()> {
+        std::fs::write(look_path(rom_path), self.to_string())
+    }
+
+    pub fn get(&self, setting: Setting) -> f32 {
+        match setting {
+            Setting::Persistence => self.persistence,
+            Setting::Brightness => self.brightness,
+            Setting::Bloom => self.bloom,
+        }
+    }
+
+    /// Sets a setting, clamped to what makes sense for it
+    pub fn set(&mut self, setting: Setting, value: f32) {
+        match setting {
+            // Full persistence would never let a pixel go dark
+            Setting::Persistence => self.persistence = value.clamp(0.0, 0.9),
+            Setting::Brightness => self.brightness = value.clamp(STEP, 1.0),
+            Setting::Bloom => self.bloom = value.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Moves a setting a step up or down, rounding to the step so repeated presses don't drift
+    pub fn adjust(&mut self, setting: Setting, up: bool) {
+        let value = self.get(setting) + if up { STEP } else { -STEP };
+        self.set(setting, (value / STEP).round() * STEP);
+    }
+}
+
+impl fmt::Display for Look {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, setting) in SETTINGS {
+            writeln!(f, "{} = {}", name, self.get(setting))?;
+        }
+        writeln!(f, "palette = {}", self.palette)?;
+        if self.effects != Builtins::default() {
+            writeln!(f, "effects = {}", self.effects)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::effects::Builtins;
+    use super::{Look, Palette, Setting};
+
+    #[test]
+    fn roundtrips_and_clamps() {
+        let mut look = Look::default();
+        look.adjust(Setting::Persistence, true);
+        look.adjust(Setting::Persistence, true);
+        look.adjust(Setting::Brightness, true);
+        look.adjust(Setting::Bloom, false);
+        assert_eq!(look, Look { persistence: 0.2, brightness: 1.0, bloom: 0.0, ..Look::default() });
+        assert_eq!(Look::parse(&look.to_string()), look);
+        look.palette = Palette([[0x10, 0x20, 0x30], [0xff; 3], [0; 3], [0xab, 0xcd, 0xef]]);
+        assert_eq!(Look::parse(&look.to_string()), look);
+        look.effects = Builtins { flash: true, rainbow: true };
+        assert_eq!(Look::parse(&look.to_string()), look);
+        assert_eq!(Look::parse("effects = glitter").effects, Builtins::default());
+        assert_eq!(Look::parse("palette = #102030, ffffff, 000000, abcdef").palette, look.palette);
+        assert_eq!(Look::parse("palette = 000000 ff0000\npalette = red").palette, Palette::default());
+        assert_eq!(Look::parse("bloom = 3\nglare = 1\nbrightness: 0.5\n"), Look { bloom: 1.0, ..Look::default() });
+    }
+}
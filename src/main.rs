@@ -1,175 +1,587 @@
-mod decode;
-mod chip8;
-mod bits;
+#[cfg(feature = "gui")]
+mod window;
 
-use chip8::{Chip8, Cycle, SCREEN_HEIGHT, SCREEN_WIDTH};
+use chip8::{audit, bench, cli, diff, disasm, headless, latency, logging, script, soak, thumbnails, video};
+use chip8::accesslog::{AccessLog, Destination, Output};
+use chip8::audio::{Beeper, Buzzer, PcmSink, Tone, WAV_SAMPLE_RATE};
+use chip8::bits::fnv1a;
+use chip8::bench::{default_profiles, Profile};
+use chip8::cli::{Command, MachineOptions};
+use chip8::disasm::Format;
+use chip8::display::RgbaBuffer;
+use chip8::headless::{ExitCode, Stop};
+use chip8::ocr::read_digits;
+use chip8::platform::Platform;
+use chip8::quirks::Quirks;
+use chip8::replay::{Player, Recording};
+use chip8::rom::{trimmed_len, RomSource};
+use chip8::savestate::{slot_path, SaveState};
+use chip8::score::{score_path, Leaderboard, ScoreSpec};
+use chip8::slowlog::SlowLog;
+use chip8::settings::Settings;
+use chip8::chip8::{Chip8, Rect};
+use chip8::symbols::Symbols;
+use chip8::trace::{TraceFilter, Tracer};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::time::Instant;
-use pixels::{Pixels, SurfaceTexture};
-use winit::dpi::{LogicalPosition, LogicalSize, PhysicalSize};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::event::{Event, StartCause, VirtualKeyCode};
-use winit_input_helper::WinitInputHelper;
 use std::time::{Duration};
-use env_logger;
 
-fn load_rom(chip8: &mut Chip8) {
-    let rom_path = std::env::args().nth(1).expect("No ROM given");
-    let file = std::fs::File::open(rom_path).expect("Couldn't find ROM path given");
-    chip8.read_program(file).expect("Failed to read ROM");
+/// Loads `rom`, with symbols from next to `rom_path` if there are any, and returns its hash
+fn load_rom(chip8: &mut Chip8, rom: &[u8], rom_path: &str) -> u64 {
+    chip8.read_program(rom).expect("Failed to read ROM");
+    chip8.symbols = Symbols::load(rom_path);
+    chip8.quirks = Quirks::load(rom_path, chip8.platform.quirks());
     chip8.print_program();
+    fnv1a(rom)
 }
 
-const KEY_MAPPING: [(VirtualKeyCode, usize); 16] = [
-    (VirtualKeyCode::Key1, 1),
-    (VirtualKeyCode::Key2, 2),
-    (VirtualKeyCode::Key3, 3),
-    (VirtualKeyCode::Key4, 0xc),
-    (VirtualKeyCode::Q, 4),
-    (VirtualKeyCode::W, 5),
-    (VirtualKeyCode::E, 6),
-    (VirtualKeyCode::R, 0xd),
-    (VirtualKeyCode::A, 7),
-    (VirtualKeyCode::S, 8),
-    (VirtualKeyCode::D, 9),
-    (VirtualKeyCode::F, 0xe),
-    (VirtualKeyCode::Z, 0xa),
-    (VirtualKeyCode::X, 0),
-    (VirtualKeyCode::C, 0xb),
-    (VirtualKeyCode::V, 0xf),
-];
+fn read_rom(source: &RomSource) -> (Vec<u8>, String) {
+    source.read().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// The ROM to run, and a save state to start from with `--state`
+struct Launch {
+    rom: RomSource,
+    state: Option<String>,
+}
+
+/// Restores the save state at `path` for `--state`, returning its play time
+fn load_state(chip8: &mut Chip8, path: &str, rom: &[u8]) -> Duration {
+    let state = std::fs::File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| SaveState::read(file).map_err(|e| e.to_string()))
+        .and_then(|state| state.check_rom(rom).map(|()| state))
+        .unwrap_or_else(|e| {
+            eprintln!("Couldn't load save state {}: {}", path, e);
+            std::process::exit(1);
+        });
+    chip8.restore(&state.snapshot);
+    state.play_time
+}
+
+/// Copies a slot's state to a file of its own, with the ROM if `embed_rom`
+fn share(rom_path: &str, slot: u8, embed_rom: bool, output: Option<&str>) {
+    let (rom, _) = read_rom(&RomSource::from(rom_path));
+    let path = slot_path(rom_path, slot);
+    let mut state = std::fs::File::open(&path)
+        .and_then(SaveState::read)
+        .unwrap_or_else(|e| {
+            eprintln!("Couldn't read save state {}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+    if let Err(e) = state.check_rom(&rom) {
+        eprintln!("Can't share slot {}: {}", slot, e);
+        std::process::exit(1);
+    }
+    state.rom = embed_rom.then_some(rom);
+    let output = output.map_or_else(|| format!("{}.c8ss", rom_path), String::from);
+    match std::fs::File::create(&output).and_then(|file| state.write(std::io::BufWriter::new(file))) {
+        Ok(()) if embed_rom => println!("Wrote {} with the ROM in it", output),
+        Ok(()) => println!("Wrote {}, which needs the ROM to load", output),
+        Err(e) => {
+            eprintln!("Couldn't write {}: {}", output, e);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn leaderboard(rom_path: &str, output: Option<&str>) {
+    let leaderboard = Leaderboard::load(rom_path);
+    if leaderboard.entries.is_empty() {
+        match ScoreSpec::load(rom_path) {
+            Some(_) => println!("No scores for {} yet", rom_path),
+            None => println!("No scores for {}, which needs {} to say where its score is", rom_path, score_path(rom_path).display()),
+        }
+        return;
+    }
+    print!("{}", leaderboard);
+    if let Some(output) = output {
+        if let Err(e) = std::fs::write(output, leaderboard.to_string()) {
+            eprintln!("Couldn't write {}: {}", output, e);
+            std::process::exit(1);
+        }
+        println!("Wrote {}", output);
+    }
+}
+
+fn trim(rom_path: &str, output: Option<&str>, platform: Platform) {
+    let (rom, _) = read_rom(&RomSource::from(rom_path));
+    let len = trimmed_len(&rom);
+    println!("{}: {} bytes, {} without trailing zero padding", rom_path, rom.len(), len);
+    if len > platform.max_rom_size() {
+        println!("Warning: only the first {} bytes fit in memory, the rest is never loaded", platform.max_rom_size());
+    }
+    if let Some(output) = output {
+        std::fs::write(output, &rom[..len]).expect("Couldn't write trimmed ROM");
+        println!("Wrote {}", output);
+    }
+}
+
+fn disasm(rom_path: &str, format: Format, platform: Platform) {
+    let (rom, rom_path) = read_rom(&RomSource::from(rom_path));
+    let lines = disasm::disassemble(&rom, platform);
+    let symbols = Symbols::load(&rom_path);
+    match format {
+        Format::Text => print!("{}", disasm::to_text(&lines, &symbols)),
+        Format::Json => print!("{}", disasm::to_json(&lines, &symbols)),
+    }
+}
+
+/// Exits with 1 if the ROMs differ, like diff(1)
+fn diff(old_path: &str, new_path: &str, platform: Platform) {
+    let (old, _) = read_rom(&RomSource::from(old_path));
+    let (new, _) = read_rom(&RomSource::from(new_path));
+    let old = disasm::disassemble(&old, platform);
+    let new = disasm::disassemble(&new, platform);
+    let hunks = diff::diff(&old, &new);
+    for hunk in &hunks {
+        println!("@@ {:#05x} {:#05x} @@", hunk.old_address, hunk.new_address);
+        for line in &hunk.removed {
+            println!("- {}", line);
+        }
+        for line in &hunk.added {
+            println!("+ {}", line);
+        }
+    }
+    if hunks.is_empty() {
+        println!("{} and {} run the same program", old_path, new_path);
+    } else {
+        let changed: usize = hunks.iter().map(|hunk| hunk.removed.len().max(hunk.added.len())).sum();
+        println!("{} lines differ in {} places", changed, hunks.len());
+        std::process::exit(1);
+    }
+}
+
+fn bench(rom: RomSource, cycles: u64, profiles: Vec<Profile>) {
+    let profiles = if profiles.is_empty() { default_profiles() } else { profiles };
+    let (rom, _) = read_rom(&rom);
+    let reports = bench::bench(&rom, &profiles, cycles, DEFAULT_CLOCK_SPEED);
+    let width = reports.iter().map(|report| report.name.len()).max().unwrap_or(0);
+    for (i, report) in reports.iter().enumerate() {
+        let (ips, stop) = match (report.ips, report.stop) {
+            (Some(ips), Some(Stop::CyclesReached)) => (format!("{:.0} ips", ips), "ran every cycle"),
+            (Some(ips), Some(Stop::Halted)) => (format!("{:.0} ips", ips), "halted"),
+            _ => (String::from("-"), "crashed"),
+        };
+        let divergence = match report.divergence {
+            _ if i == 0 => String::from("baseline"),
+            Some((cycle, pc)) => format!("diverges at cycle {} (pc {:#05x})", cycle, pc),
+            None if report.ips.is_some() && reports[0].ips.is_some() => String::from("same throughout"),
+            None => String::from("-"),
+        };
+        println!("{:width$}  {:>14}  {:15}  {}", report.name, ips, stop, divergence, width = width);
+    }
+}
 
 fn main() {
-    env_logger::builder().init();
-    let mut time = Instant::now();
-    let mut chip8 = Chip8::new(time);
-    load_rom(&mut chip8);
-    chip8.print_program();
-    let clock_speed: u32 = 500; // TODO: make configurable
-    let clock_gap: Duration = Duration::from_secs_f32(1.0) / clock_speed;
-    let event_loop = EventLoop::new();
-    let mut input = WinitInputHelper::new();
-    let (window, width, height, mut _hidpi_factor) = create_window("CHIP-8 Emulator", &event_loop);
-    let surface_texture = SurfaceTexture::new(width, height, &window);
-    let mut pixels = Pixels::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, surface_texture).expect("Failed to start graphics library");
-    println!("Starting CHIP-8 emulator");
-
-    let mut key_pressed: [bool; 16] = [false; 16];
-    let mut debugging = true;
-    let mut next_cycle = false;
-    let mut last_render = time;
-    let mut wanna_render = Cycle::Complete;
-    event_loop.run(move |event, _, control_flow| {
-        if input.update(&event) {
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
-                *control_flow = ControlFlow::Exit;
-                return;
+    logging::init();
+    let command = cli::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, cli::USAGE);
+        std::process::exit(2);
+    });
+    match command {
+        #[cfg(feature = "gui")]
+        Command::Run { rom, max_frameskip, record, machine, strict, breakpoints, auto_speed, state, buzzer, slow_instruction, wav, practice } => {
+            let presentation = window::Presentation { max_frameskip, buzzer };
+            let checks = window::Checks { strict, breakpoints, slow_instruction };
+            window::run(Launch { rom, state }, presentation, window::Capture { replay: record, wav }, machine, checks, auto_speed, practice)
+        },
+        #[cfg(feature = "gui")]
+        Command::Play { replay } => window::play(&replay),
+        #[cfg(not(feature = "gui"))]
+        Command::Run { .. } | Command::Play { .. } => {
+            eprintln!("This chip8 was built without windows; rebuild it with --features gui");
+            std::process::exit(2);
+        },
+        Command::Trim { rom, output, platform } => trim(&rom, output.as_deref(), platform),
+        Command::Disasm { rom, format, platform } => disasm(&rom, format, platform),
+        Command::Diff { old, new, platform } => diff(&old, &new, platform),
+        Command::RunHeadless {
+            rom,
+            cycles,
+            hash,
+            expect_hash,
+            lit_pixels,
+            region_hashes,
+            digit_regions,
+            screenshot,
+            trace,
+            progress,
+            access_log,
+            slow_instruction,
+            script,
+            machine,
+            strict,
+            state,
+            buzzer,
+            wav,
+        } => {
+            let queries = ScreenQueries { hash, lit_pixels, region_hashes, digit_regions, screenshot };
+            let driver = if script { Driver::Script } else { Driver::Cycles(cycles) };
+            let wav = wav.map(|path| (path, buzzer.or(Settings::load().buzzer).tone()));
+            let monitoring = Monitoring { trace, progress, access_log, slow_instruction, wav };
+            let code = run_headless(Launch { rom, state }, driver, expect_hash, queries, monitoring, machine, strict);
+            std::process::exit(code as i32);
+        },
+        Command::Bench { rom, cycles, profiles } => bench(rom, cycles, profiles),
+        Command::Soak { rom, duration, seed, record, machine } => {
+            let code = soak(rom, duration, seed, record, machine);
+            std::process::exit(code as i32);
+        },
+        Command::Audit { rom, duration, seed, machine } => audit(rom, duration, seed, machine),
+        Command::Latency { rom, key, presses, warm_up, machine } => latency(rom, key, presses, warm_up, machine),
+        Command::Thumbnails { dir, warm_up, scale, machine } => thumbnails(&dir, warm_up, scale, machine),
+        Command::RenderReplay { replay, output, scale, buzzer } => render_replay(&replay, &output, scale, buzzer),
+        Command::Share { rom, slot, embed_rom, output } => share(&rom, slot, embed_rom, output.as_deref()),
+        Command::Leaderboard { rom, output } => leaderboard(&rom, output.as_deref()),
+    }
+}
+
+fn read_recording(path: &str) -> Recording {
+    std::fs::File::open(path)
+        .and_then(Recording::read)
+        .unwrap_or_else(|e| {
+            eprintln!("Couldn't read replay {}: {}", path, e);
+            std::process::exit(1);
+        })
+}
+
+fn render_replay(replay_path: &str, output: &str, scale: u32, buzzer: Buzzer) {
+    let mut player = Player::new(read_recording(replay_path));
+    let tone = buzzer.or(Settings::load().buzzer).tone();
+    match video::render_replay(&mut player, output.as_ref(), scale, tone) {
+        Ok(()) => println!("Wrote {}", output),
+        Err(e) => {
+            eprintln!("Couldn't render {}: {}", output, e);
+            std::process::exit(1);
+        },
+    }
+}
+
+fn save_recording(recording: &Recording, path: &str) {
+    match std::fs::File::create(path).and_then(|file| recording.write(std::io::BufWriter::new(file))) {
+        Ok(()) => println!("Saved {} cycles of replay to {}", recording.len(), path),
+        Err(e) => log::error!("Couldn't write replay {}: {}", path, e),
+    }
+}
+
+/// Reports on stderr, since run-headless's stdout is for its results
+fn save_wav(audio: &PcmSink, path: &str) {
+    match std::fs::File::create(path).and_then(|file| audio.write_wav(std::io::BufWriter::new(file))) {
+        Ok(()) => eprintln!("Saved {:.1}s of audio to {}", audio.samples.len() as f64 / audio.sample_rate as f64, path),
+        Err(e) => log::error!("Couldn't write audio {}: {}", path, e),
+    }
+}
+
+/// Prints what `--strict` collected
+fn print_diagnostics(chip8: &Chip8) {
+    if chip8.diagnostics.is_empty() {
+        println!("No diagnostics");
+    } else {
+        println!("Diagnostics ({}):", chip8.diagnostics.counts());
+        for line in chip8.diagnostics.summary() {
+            println!("    {}", line);
+        }
+    }
+}
+
+/// What to report about the screen after running headless
+struct ScreenQueries {
+    hash: bool,
+    lit_pixels: bool,
+    region_hashes: Vec<Rect>,
+    digit_regions: Vec<Rect>,
+    screenshot: Option<String>,
+}
+
+/// How far to run headless
+enum Driver {
+    Cycles(u64),
+    /// Run as commands on stdin say, with `script::run`
+    Script,
+}
+
+/// What to report while running headless
+struct Monitoring {
+    trace: Option<TraceFilter>,
+    progress: bool,
+    access_log: Option<Destination>,
+    slow_instruction: Option<Duration>,
+    /// Where to write the beeper's audio, and how it sounds
+    wav: Option<(String, Tone)>,
+}
+
+/// How often `--progress` reports
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+fn run_headless(
+    launch: Launch,
+    driver: Driver,
+    expect_hash: Option<u64>,
+    queries: ScreenQueries,
+    monitoring: Monitoring,
+    machine: MachineOptions,
+    strict: bool,
+) -> ExitCode {
+    let (rom, rom_path) = read_rom(&launch.rom);
+    let mut chip8 = Chip8::new(machine.platform.for_rom(&rom));
+    load_rom(&mut chip8, &rom, &rom_path);
+    machine.apply(&mut chip8);
+    if let Some(state) = &launch.state {
+        load_state(&mut chip8, state, &rom);
+    }
+    let mut buffer = RgbaBuffer::new();
+    let mut tracer = match monitoring.trace.map(|filter| Tracer::new(&filter, &chip8.symbols, std::io::BufWriter::new(std::io::stderr()))) {
+        Some(Ok(tracer)) => Some(tracer),
+        Some(Err(e)) => {
+            eprintln!("Can't trace: {}", e);
+            std::process::exit(2);
+        },
+        None => None,
+    };
+    let mut access_log = monitoring.access_log.map(|destination| {
+        let log = Output::open(&destination).and_then(AccessLog::new).unwrap_or_else(|e| {
+            eprintln!("Can't write the access log to {:?}: {}", destination, e);
+            std::process::exit(2);
+        });
+        chip8.accesses = Some(Vec::new());
+        log
+    });
+    let mut slow_log = monitoring.slow_instruction.map(SlowLog::new);
+    let mut wav = monitoring.wav.map(|(path, tone)| (path, Beeper::new(tone), PcmSink::new(WAV_SAMPLE_RATE)));
+    // Reports from its own thread, reading the counters the core keeps as it goes
+    let (stop_progress, stopped) = mpsc::channel::<()>();
+    let progress = monitoring.progress.then(|| {
+        let stats = chip8.stats.clone();
+        std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(PROGRESS_INTERVAL) {
+                eprintln!("{} cycles, {} frames, {:.1}s emulated", stats.cycles(), stats.frames(), stats.emulated_time().as_secs_f64());
             }
-            if let Some(factor) = input.scale_factor_changed() {
-                _hidpi_factor = factor;
+        })
+    });
+    // The panic has already been printed with the call stack
+    let run = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut observe = |chip8: &mut Chip8| {
+            // Everything here is done for the instruction about to run, so it's timed with it
+            if let Some(slow_log) = &mut slow_log {
+                if let Some(slow) = slow_log.finish(Instant::now()) {
+                    eprintln!("SLOW: {}", slow);
+                }
+                slow_log.start(chip8, Instant::now());
             }
-            if let Some(size) = input.window_resized() {
-                pixels.resize_surface(size.width, size.height);
+            if let Some(tracer) = &mut tracer {
+                tracer.observe(chip8);
             }
-
-            for (key, num) in KEY_MAPPING {
-                if input.key_pressed(key) {
-                    key_pressed[num] = true;
-                }
-                if input.key_released(key) {
-                    key_pressed[num] = false;
+            // Before the next cycle, so the change is timed at the cycle that made it
+            if let Some((_, beeper, audio)) = &mut wav {
+                beeper.update(chip8, audio);
+            }
+            if let (Some(log), Some(accesses)) = (&mut access_log, &mut chip8.accesses) {
+                if let Err(e) = log.write(accesses) {
+                    log::error!("Couldn't write the access log, so stopped: {}", e);
+                    chip8.accesses = None;
+                } else {
+                    accesses.clear();
                 }
             }
+        };
+        let clock_speed = machine.clock_speed(&chip8);
+        match driver {
+            Driver::Cycles(cycles) => headless::run(&mut chip8, cycles, clock_speed, [false; 16], &mut buffer, &mut observe),
+            Driver::Script => {
+                let stdin = std::io::stdin();
+                script::run(&mut chip8, clock_speed, &mut buffer, stdin.lock(), std::io::stdout()).unwrap_or_else(|e| {
+                    eprintln!("Script stopped: {}", e);
+                    Stop::CyclesReached
+                })
+            },
+        }
+    }));
+    drop(stop_progress);
+    if let Some(mut slow_log) = slow_log {
+        if let Some(slow) = slow_log.finish(Instant::now()) {
+            eprintln!("SLOW: {}", slow);
+        }
+        match slow_log.slowest {
+            Some(slowest) => {
+                let plural = if slow_log.found == 1 { "" } else { "s" };
+                eprintln!("{} slow instruction{}, the slowest {}", slow_log.found, plural, slowest);
+            },
+            None => eprintln!("No instructions took over {:?}", slow_log.threshold),
+        }
+    }
+    if let Some(progress) = progress {
+        progress.join().expect("Progress thread panicked");
+    }
+    // Up to wherever it stopped, crash or not
+    if let Some((path, mut beeper, mut audio)) = wav {
+        beeper.update(&mut chip8, &mut audio);
+        audio.fill_until(Duration::from_nanos(chip8.timestamp().nanos));
+        save_wav(&audio, &path);
+    }
+    if let Some(mut log) = access_log {
+        let accesses = chip8.accesses.take();
+        match log.write(accesses.as_deref().unwrap_or_default()).and_then(|()| {
+            let written = log.written;
+            log.into_inner().finish().map(|()| written)
+        }) {
+            Ok(written) => eprintln!("Logged {} memory accesses", written),
+            Err(e) => log::error!("Couldn't finish the access log: {}", e),
+        }
+    }
+    // --strict prints them with the results. Otherwise they're summed up here, including whatever
+    // stopped the core.
+    if !strict && !chip8.diagnostics.is_empty() {
+        eprintln!("Diagnostics ({}):", chip8.diagnostics.counts());
+        for line in chip8.diagnostics.summary() {
+            eprintln!("    {}", line);
+        }
+    }
+    let stop = match run {
+        Ok(stop) => stop,
+        Err(_) => return ExitCode::CoreError,
+    };
+    if queries.hash {
+        println!("{:016x}", buffer.hash());
+    }
+    if queries.lit_pixels {
+        println!("{} lit", chip8.count_lit_pixels());
+    }
+    for rect in queries.region_hashes {
+        println!("{},{},{},{} {:016x}", rect.x, rect.y, rect.width, rect.height, chip8.region_hash(rect));
+    }
+    for rect in queries.digit_regions {
+        println!("{},{},{},{} {}", rect.x, rect.y, rect.width, rect.height, read_digits(&chip8.display, rect));
+    }
+    if let Some(path) = queries.screenshot {
+        std::fs::File::create(path)
+            .and_then(|file| buffer.write_ppm(std::io::BufWriter::new(file)))
+            .expect("Couldn't write screenshot");
+    }
+    if let Some(expected) = expect_hash.filter(|&expected| expected != buffer.hash()) {
+        eprintln!("Screen hash {:016x} doesn't match {:016x}", buffer.hash(), expected);
+        return ExitCode::HashMismatch;
+    }
+    if strict {
+        print_diagnostics(&chip8);
+        if !chip8.diagnostics.is_empty() {
+            return ExitCode::StrictFailed;
+        }
+    }
+    match stop {
+        Stop::CyclesReached => ExitCode::CyclesReached,
+        Stop::Halted => ExitCode::Halted,
+    }
+}
 
-            if input.key_pressed(VirtualKeyCode::P) {
-                debugging ^= true;
+fn soak(rom: RomSource, duration: Duration, seed: Option<u64>, record: Option<String>, machine: MachineOptions) -> ExitCode {
+    let (rom, rom_path) = read_rom(&rom);
+    let mut chip8 = Chip8::new(machine.platform.for_rom(&rom));
+    load_rom(&mut chip8, &rom, &rom_path);
+    machine.apply(&mut chip8);
+    let seed = seed.unwrap_or_else(soak::random_seed);
+    chip8.seed_rng(seed);
+    let clock_speed = machine.clock_speed(&chip8);
+    let mut recording = Recording::start(&chip8, [false; 16], Duration::from_secs(1) / clock_speed);
+    let result = soak::soak(&mut chip8, duration, clock_speed, seed, &mut recording);
+    let played = format!("{} cycles ({:.1}s emulated) with --seed {}", result.cycles, result.elapsed.as_secs_f64(), seed);
+    match result.violation {
+        Some(violation) => {
+            eprintln!("After {}, {}", played, violation);
+            if let Some(path) = record {
+                save_recording(&recording, &path);
             }
+            ExitCode::CoreError
+        },
+        None if result.halted => {
+            println!("Halted after {}", played);
+            ExitCode::Halted
+        },
+        None => {
+            println!("Played {} without problems", played);
+            ExitCode::CyclesReached
+        },
+    }
+}
 
-            if input.key_released(VirtualKeyCode::N) {
-                next_cycle = true;
-            }
+fn audit(rom: RomSource, duration: Duration, seed: Option<u64>, machine: MachineOptions) {
+    let (rom, rom_path) = read_rom(&rom);
+    let setup = || {
+        let mut chip8 = Chip8::new(machine.platform.for_rom(&rom));
+        load_rom(&mut chip8, &rom, &rom_path);
+        machine.apply(&mut chip8);
+        chip8
+    };
+    let clock_speed = machine.clock_speed(&setup());
+    let seed = seed.unwrap_or_else(soak::random_seed);
+    let result = audit::audit(setup, duration, clock_speed, seed);
+    println!("{}, with --seed {}", result, seed);
+    if !matches!(result, audit::Audit::Matched { .. }) {
+        std::process::exit(1);
+    }
+}
+
+fn latency(rom: RomSource, key: usize, presses: u32, warm_up: Duration, machine: MachineOptions) {
+    let (rom, rom_path) = read_rom(&rom);
+    let mut chip8 = Chip8::new(machine.platform.for_rom(&rom));
+    load_rom(&mut chip8, &rom, &rom_path);
+    machine.apply(&mut chip8);
+    let clock_speed = machine.clock_speed(&chip8);
+    let results = latency::measure(&mut chip8, clock_speed, key, presses, warm_up);
+    for (press, result) in results.iter().enumerate() {
+        match result {
+            Some(latency) => println!("Press {}: {}", press + 1, latency),
+            None => println!("Press {}: no change within {} seconds", press + 1, latency::MAX_WAIT.as_secs()),
         }
+    }
+    let answered: Vec<_> = results.iter().flatten().collect();
+    if answered.is_empty() {
+        println!("{} never changed the screen after pressing {:X}", rom_path, key);
+        std::process::exit(1);
+    }
+    let frames = answered.iter().map(|latency| latency.frames).sum::<u64>() as f64 / answered.len() as f64;
+    let host = answered.iter().map(|latency| latency.host).sum::<Duration>() / answered.len() as u32;
+    println!("{}: {:.1} frames, {:.1}ms on average over {} presses", rom_path, frames, host.as_secs_f64() * 1000.0, answered.len());
+}
 
-        match event {
-            Event::RedrawRequested(_) => {
-                chip8.draw(pixels.get_frame());
-                pixels.render().expect("Failed to render");
+fn thumbnails(dir: &str, warm_up: Duration, scale: u32, machine: MachineOptions) {
+    let roms = thumbnails::roms_in(dir.as_ref(), machine.platform).unwrap_or_else(|e| {
+        eprintln!("Couldn't read {}: {}", dir, e);
+        std::process::exit(1);
+    });
+    let mut failed = false;
+    for (path, platform) in roms {
+        let rom_path = path.to_string_lossy();
+        let thumbnail_path = thumbnails::thumbnail_path(&path);
+        // The panic has already been printed with the call stack
+        let made = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let rom = std::fs::read(&path)?;
+            let mut chip8 = Chip8::new(platform.for_rom(&rom));
+            load_rom(&mut chip8, &rom, &rom_path);
+            machine.apply(&mut chip8);
+            // Programs that start on something random look the same every time
+            chip8.seed_rng(0);
+            let buffer = thumbnails::thumbnail(&mut chip8, warm_up, scale as usize);
+            std::fs::File::create(&thumbnail_path).and_then(|file| buffer.write_png(std::io::BufWriter::new(file)))
+        }));
+        match made {
+            Ok(Ok(())) => println!("{}", thumbnail_path.display()),
+            Ok(Err(e)) => {
+                eprintln!("Couldn't make a thumbnail for {}: {}", rom_path, e);
+                failed = true;
             },
-            Event::NewEvents(StartCause::Init) => {
-                *control_flow = ControlFlow::WaitUntil(time + clock_gap);
+            Err(_) => {
+                eprintln!("Skipped {}, which crashed the core", rom_path);
+                failed = true;
             },
-            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
-                if !debugging || next_cycle {
-                    let now = Instant::now();
-                    if let Cycle::RedrawRequested = chip8.cycle(key_pressed, now) {
-                        wanna_render = Cycle::RedrawRequested;
-                    }
-                    if debugging {
-                        next_cycle = false;
-                        print!("DEBUGGING: {}", debugging);
-                        chip8.print_debug_view();
-                    }
-                    if let Cycle::RedrawRequested = wanna_render {
-                        if now.duration_since(last_render) >= Duration::from_secs_f32(1.0 / 60.0) {
-                            wanna_render = Cycle::Complete;
-                            last_render = now;
-                            window.request_redraw();
-                        }
-                    }
-                }
-                time += clock_gap;
-                *control_flow = ControlFlow::WaitUntil(time);
-            },
-            _ => {}
         }
-    });
+    }
+    if failed {
+        std::process::exit(1);
+    }
 }
 
-/// Tuple of `(window, surface, width, height, hidpi_factor)`
-/// `width` and `height` are in `PhysicalSize` units.
-fn create_window(
-    title: &str,
-    event_loop: &EventLoop<()>,
-) -> (winit::window::Window, u32, u32, f64) {
-    // Create a hidden window so we can estimate a good default window size
-    let window = winit::window::WindowBuilder::new()
-        .with_visible(false)
-        .with_title(title)
-        .build(event_loop)
-        .unwrap();
-    let hidpi_factor = window.scale_factor();
-
-    // Get dimensions
-    let width = SCREEN_WIDTH as f64;
-    let height = SCREEN_HEIGHT as f64;
-    let (monitor_width, monitor_height) = {
-        if let Some(monitor) = window.current_monitor() {
-            let size = monitor.size().to_logical(hidpi_factor);
-            (size.width, size.height)
-        } else {
-            (width, height)
-        }
-    };
-    let scale = (monitor_height / height * 2.0 / 3.0).round().max(1.0);
-
-    // Resize, center, and display the window
-    let min_size: winit::dpi::LogicalSize<f64> =
-        PhysicalSize::new(width, height).to_logical(hidpi_factor);
-    let default_size = LogicalSize::new(width * scale, height * scale);
-    let center = LogicalPosition::new(
-        (monitor_width - width * scale) / 2.0,
-        (monitor_height - height * scale) / 2.0,
-    );
-    window.set_inner_size(default_size);
-    window.set_min_inner_size(Some(min_size));
-    window.set_outer_position(center);
-    window.set_visible(true);
-
-    let size = default_size.to_physical::<f64>(hidpi_factor);
-
-    (
-        window,
-        size.width.round() as u32,
-        size.height.round() as u32,
-        hidpi_factor,
-    )
-}
\ No newline at end of file
+const DEFAULT_CLOCK_SPEED: u32 = Platform::Chip8.clock_speed();
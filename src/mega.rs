@@ -0,0 +1,264 @@
+/// MEGA-CHIP's screen size, switched to with 0011
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+/// As much memory as MEGA-CHIP's 24-bit I can reach
+pub const MEGA_MEMORY_SIZE: usize = 1 << 24;
+
+/// How a sprite's colours mix with what's drawn under them, from 080N
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Blend {
+    #[default]
+    Normal,
+    /// The sprite at 25% opacity
+    Quarter,
+    /// The sprite at 50% opacity
+    Half,
+    Add,
+    Multiply,
+}
+
+impl Blend {
+    /// 080N's N, with anything past 4 taken as normal
+    pub fn from_mode(mode: u8) -> Self {
+        match mode {
+            1 => Blend::Quarter,
+            2 => Blend::Half,
+            3 => Blend::Add,
+            4 => Blend::Multiply,
+            _ => Blend::Normal,
+        }
+    }
+
+    pub fn mode(self) -> u8 {
+        match self {
+            Blend::Normal => 0,
+            Blend::Quarter => 1,
+            Blend::Half => 2,
+            Blend::Add => 3,
+            Blend::Multiply => 4,
+        }
+    }
+
+    /// `over` drawn on `under`
+    pub fn mix(self, under: [u8; 3], over: [u8; 3]) -> [u8; 3] {
+        std::array::from_fn(|channel| {
+            let (under, over) = (under[channel] as u16, over[channel] as u16);
+            (match self {
+                Blend::Normal => over,
+                Blend::Quarter => (under * 3 + over) / 4,
+                Blend::Half => (under + over) / 2,
+                Blend::Add => (under + over).min(0xff),
+                Blend::Multiply => under * over / 0xff,
+            }) as u8
+        })
+    }
+}
+
+/// What MEGA-CHIP's 02NN to 09NN set up for drawing. These outlast switching the MEGA screen on and off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MegaRegisters {
+    /// ARGB colours from 02NN, which loads from index 1 up. Index 0 is transparent.
+    pub palette: [[u8; 4]; 256],
+    /// From 03NN and 04NN, with 0 meaning 256
+    pub sprite_width: u8,
+    pub sprite_height: u8,
+    pub blend: Blend,
+    /// Drawing over a pixel of this palette index sets VF. Nothing collides with blank pixels.
+    pub collision_color: u8,
+    /// The screen's opacity from 05NN. Kept, but not shown.
+    pub alpha: u8,
+}
+
+impl Default for MegaRegisters {
+    fn default() -> Self {
+        MegaRegisters { palette: [[0; 4]; 256], sprite_width: 0, sprite_height: 0, blend: Blend::Normal, collision_color: 0, alpha: 0xff }
+    }
+}
+
+// Sprite width, height, blend, collision colour and alpha, then the palette
+const REGISTERS_LEN: usize = 5 + 256 * 4;
+const PIXELS: usize = MEGA_WIDTH * MEGA_HEIGHT;
+
+impl MegaRegisters {
+    pub fn sprite_size(&self) -> (usize, usize) {
+        let size = |size: u8| if size == 0 { 256 } else { size as usize };
+        (size(self.sprite_width), size(self.sprite_height))
+    }
+
+    pub fn rgb(&self, index: u8) -> [u8; 3] {
+        let [_, r, g, b] = self.palette[index as usize];
+        [r, g, b]
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.sprite_width, self.sprite_height, self.blend.mode(), self.collision_color, self.alpha];
+        bytes.extend(self.palette.iter().flatten());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != REGISTERS_LEN {
+            return None;
+        }
+        let mut palette = [[0; 4]; 256];
+        for (color, argb) in palette.iter_mut().zip(bytes[5..].chunks(4)) {
+            color.copy_from_slice(argb);
+        }
+        Some(MegaRegisters {
+            palette,
+            sprite_width: bytes[0],
+            sprite_height: bytes[1],
+            blend: Blend::from_mode(bytes[2]),
+            collision_color: bytes[3],
+            alpha: bytes[4],
+        })
+    }
+}
+
+/// MEGA-CHIP's screen. Sprites are drawn to a back buffer, which 00E0 shows and then blanks,
+/// so what's on screen is the last finished frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MegaScreen {
+    /// The palette index last drawn at each pixel since the last 00E0, row by row, for collisions
+    pub indexes: Vec<u8>,
+    /// What's been drawn since the last 00E0, blended
+    pub drawing: Vec<[u8; 3]>,
+    /// What 00E0 last showed
+    pub shown: Vec<[u8; 3]>,
+}
+
+impl MegaScreen {
+    pub fn new() -> Self {
+        MegaScreen { indexes: vec![0; PIXELS], drawing: vec![[0; 3]; PIXELS], shown: vec![[0; 3]; PIXELS] }
+    }
+
+    /// 00E0: shows what's been drawn, then starts the next frame blank
+    pub fn present(&mut self) {
+        self.shown.copy_from_slice(&self.drawing);
+        self.drawing.fill([0; 3]);
+        self.indexes.fill(0);
+    }
+
+    /// Draws a sprite of palette indexes at (x, y), row by row at the registers' sprite size,
+    /// with `sprite` giving its bytes in order. Index 0 is transparent, and anything off the
+    /// screen is cut off. Returns whether it drew over the collision colour.
+    pub fn draw(&mut self, x: usize, y: usize, sprite: impl Fn(usize) -> u8, registers: &MegaRegisters) -> bool {
+        let (width, height) = registers.sprite_size();
+        let mut collided = false;
+        for row in 0..height.min(MEGA_HEIGHT.saturating_sub(y)) {
+            for column in 0..width.min(MEGA_WIDTH.saturating_sub(x)) {
+                let index = sprite(row * width + column);
+                if index == 0 {
+                    continue;
+                }
+                let pixel = (y + row) * MEGA_WIDTH + x + column;
+                let under = self.indexes[pixel];
+                collided |= under != 0 && under == registers.collision_color;
+                self.indexes[pixel] = index;
+                self.drawing[pixel] = registers.blend.mix(self.drawing[pixel], registers.rgb(index));
+            }
+        }
+        collided
+    }
+
+    /// Moves what's been drawn by `columns` right and `rows` down (left and up if negative), blanking what's uncovered
+    pub fn scroll(&mut self, columns: isize, rows: isize) {
+        shift(&mut self.indexes, columns, rows, 0);
+        shift(&mut self.drawing, columns, rows, [0; 3]);
+    }
+
+    /// The bytes of all three buffers, for snapshots
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.indexes.clone();
+        bytes.extend(self.drawing.iter().chain(&self.shown).flatten());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != PIXELS * 7 {
+            return None;
+        }
+        let (indexes, colors) = bytes.split_at(PIXELS);
+        let colors: Vec<[u8; 3]> = colors.chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+        let (drawing, shown) = colors.split_at(PIXELS);
+        Some(MegaScreen { indexes: indexes.to_vec(), drawing: drawing.to_vec(), shown: shown.to_vec() })
+    }
+}
+
+impl Default for MegaScreen {
+    fn default() -> Self {
+        MegaScreen::new()
+    }
+}
+
+/// Registers plus, while the MEGA screen is on, the screen, as `Chip8::snapshot` writes them in its `MEGA` chunk
+pub fn snapshot(registers: &MegaRegisters, screen: Option<&MegaScreen>) -> Vec<u8> {
+    let mut bytes = registers.to_bytes();
+    bytes.extend(screen.iter().flat_map(|screen| screen.to_bytes()));
+    bytes
+}
+
+/// The inverse of `snapshot`, or `None` if the chunk's the wrong size
+pub fn restore(bytes: &[u8]) -> Option<(MegaRegisters, Option<MegaScreen>)> {
+    let (registers, screen) = bytes.split_at(REGISTERS_LEN.min(bytes.len()));
+    let registers = MegaRegisters::from_bytes(registers)?;
+    match screen {
+        [] => Some((registers, None)),
+        screen => Some((registers, Some(MegaScreen::from_bytes(screen)?))),
+    }
+}
+
+fn shift<T: Copy>(pixels: &mut [T], columns: isize, rows: isize, blank: T) {
+    let moved: Vec<T> = (0..PIXELS)
+        .map(|i| {
+            let x = (i % MEGA_WIDTH) as isize - columns;
+            let y = (i / MEGA_WIDTH) as isize - rows;
+            let inside = (0..MEGA_WIDTH as isize).contains(&x) && (0..MEGA_HEIGHT as isize).contains(&y);
+            if inside { pixels[y as usize * MEGA_WIDTH + x as usize] } else { blank }
+        })
+        .collect();
+    pixels.copy_from_slice(&moved);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{restore, snapshot, Blend, MegaRegisters, MegaScreen, MEGA_WIDTH};
+
+    #[test]
+    fn blends_colours() {
+        let (under, over) = ([200, 100, 0], [0, 100, 200]);
+        assert_eq!(Blend::Normal.mix(under, over), over);
+        assert_eq!(Blend::Quarter.mix(under, over), [150, 100, 50]);
+        assert_eq!(Blend::Half.mix(under, over), [100, 100, 100]);
+        assert_eq!(Blend::Add.mix(under, over), [200, 200, 200]);
+        assert_eq!(Blend::Multiply.mix([255, 128, 0], [128, 255, 255]), [128, 128, 0]);
+        assert_eq!(Blend::from_mode(9), Blend::Normal);
+    }
+
+    #[test]
+    fn draws_to_the_back_buffer() {
+        let mut registers = MegaRegisters { sprite_width: 2, sprite_height: 2, collision_color: 2, ..MegaRegisters::default() };
+        registers.palette[1] = [0xff, 0xff, 0, 0];
+        registers.palette[2] = [0xff, 0, 0xff, 0];
+        let mut screen = MegaScreen::new();
+        // A transparent corner, and cut off at the right edge
+        assert!(!screen.draw(MEGA_WIDTH - 1, 0, |i| [0, 1, 2, 2][i], &registers));
+        assert_eq!(screen.drawing[MEGA_WIDTH - 1], [0; 3]);
+        assert_eq!(screen.drawing[MEGA_WIDTH * 2 - 1], [0, 0xff, 0]);
+        assert_eq!(screen.shown, vec![[0; 3]; screen.shown.len()]);
+        // Drawing over colour 2 collides
+        assert!(screen.draw(MEGA_WIDTH - 2, 1, |_| 1, &registers));
+        screen.present();
+        assert_eq!(screen.shown[MEGA_WIDTH * 2 - 1], [0xff, 0, 0]);
+        assert_eq!(screen.drawing[MEGA_WIDTH * 2 - 1], [0; 3]);
+
+        screen.draw(0, 0, |_| 1, &registers);
+        screen.scroll(1, 1);
+        assert_eq!((screen.drawing[0], screen.drawing[MEGA_WIDTH * 2 + 2]), ([0; 3], [0xff, 0, 0]));
+        assert_eq!((screen.indexes[0], screen.indexes[MEGA_WIDTH * 2 + 2]), (0, 1));
+        let restored = restore(&snapshot(&registers, Some(&screen))).unwrap();
+        assert_eq!(restored, (registers.clone(), Some(screen)));
+        assert_eq!(restore(&snapshot(&registers, None)), Some((registers, None)));
+        assert_eq!(restore(&[0; 3]), None);
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+use crate::breakpoints::Break;
+use crate::diagnostics::Report;
+
+/// A change to the emulator's state that someone who can't see the window, or isn't looking at
+/// it, should hear about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Announcement {
+    Paused,
+    Resumed,
+    Breakpoint(Break),
+    RomLoaded { path: String },
+    StateSaved { slot: u8 },
+    StateLoaded { slot: u8 },
+    /// Practice mode took a checkpoint, on a key or at a marked address
+    CheckpointTaken,
+    /// Practice mode went back to the checkpoint, for the nth time since it was taken
+    Retry { count: u32 },
+    /// The score went past the best on the ROM's leaderboard
+    NewBest { score: u32 },
+    ClockSpeed(u32),
+    /// A window other than the display, by name, opened or closed
+    Window { name: &'static str, open: bool },
+    /// The program did something questionable, the first time it did from there
+    Diagnostic(Report),
+}
+
+impl fmt::Display for Announcement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Announcement::Paused => write!(f, "Paused"),
+            Announcement::Resumed => write!(f, "Running"),
+            Announcement::Breakpoint(hit) => write!(f, "Paused, {}", hit),
+            Announcement::RomLoaded { path } => write!(f, "Loaded {}", path),
+            Announcement::StateSaved { slot } => write!(f, "State saved to slot {}", slot),
+            Announcement::StateLoaded { slot } => write!(f, "State loaded from slot {}", slot),
+            Announcement::CheckpointTaken => write!(f, "Checkpoint taken"),
+            Announcement::Retry { count } => write!(f, "Back to the checkpoint, retry {}", count),
+            Announcement::NewBest { score } => write!(f, "New best score, {}", score),
+            Announcement::ClockSpeed(speed) => write!(f, "Speed {} instructions per second", speed),
+            Announcement::Window { name, open: true } => write!(f, "{} window open", name),
+            Announcement::Window { name, open: false } => write!(f, "{} window closed", name),
+            Announcement::Diagnostic(report) => write!(f, "{}: {}", report.severity, report.message),
+        }
+    }
+}
+
+/// Somewhere announcements go, e.g. a screen reader or on-screen toasts
+pub trait Notifier {
+    fn announce(&mut self, announcement: &Announcement);
+}
+
+/// Prints `ANNOUNCE: ...` lines, which a screen reader following the terminal reads out
+pub struct PrintNotifier;
+
+impl Notifier for PrintNotifier {
+    fn announce(&mut self, announcement: &Announcement) {
+        println!("ANNOUNCE: {}", announcement);
+    }
+}
+
+/// Hands each announcement to every notifier added, in order. With none, announcing does nothing.
+#[derive(Default)]
+pub struct Announcer {
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Announcer {
+    pub fn add(&mut self, notifier: impl Notifier + 'static) {
+        self.notifiers.push(Box::new(notifier));
+    }
+
+    pub fn announce(&mut self, announcement: Announcement) {
+        for notifier in &mut self.notifiers {
+            notifier.announce(&announcement);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::breakpoints::Break;
+    use super::{Announcement, Announcer, Notifier};
+
+    struct Heard(Rc<RefCell<Vec<String>>>);
+
+    impl Notifier for Heard {
+        fn announce(&mut self, announcement: &Announcement) {
+            self.0.borrow_mut().push(announcement.to_string());
+        }
+    }
+
+    #[test]
+    fn tells_every_notifier() {
+        let heard = Rc::new(RefCell::new(Vec::new()));
+        let mut announcer = Announcer::default();
+        announcer.announce(Announcement::Paused);
+        announcer.add(Heard(heard.clone()));
+        announcer.add(Heard(heard.clone()));
+        announcer.announce(Announcement::StateSaved { slot: 2 });
+        announcer.announce(Announcement::Breakpoint(Break::Clear { pc: 0x204 }));
+        announcer.announce(Announcement::Window { name: "Memory", open: false });
+        assert_eq!(*heard.borrow(), [
+            "State saved to slot 2",
+            "State saved to slot 2",
+            "Paused, screen cleared at 0x204",
+            "Paused, screen cleared at 0x204",
+            "Memory window closed",
+            "Memory window closed",
+        ]);
+    }
+}
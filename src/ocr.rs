@@ -0,0 +1,72 @@
+use crate::chip8::{Rect, Screen, FONT};
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 5;
+
+/// The font digit drawn with its top left corner at `(x, y)`, if there is one.
+/// The columns either side have to be blank, so parts of bigger sprites don't count.
+fn glyph_at(screen: &Screen, x: usize, y: usize) -> Option<u8> {
+    let rows = y..y + GLYPH_HEIGHT;
+    let blank_column = |column: Option<usize>| column.is_none_or(|column| rows.clone().all(|y| !screen.pixel(column, y)));
+    if !blank_column(x.checked_sub(1)) || !blank_column(Some(x + GLYPH_WIDTH)) {
+        return None;
+    }
+    let drawn: Vec<u8> = rows.clone()
+        .map(|y| (0..GLYPH_WIDTH).fold(0, |byte, column| byte | (screen.pixel(x + column, y) as u8) << (7 - column)))
+        .collect();
+    if drawn.iter().all(|&row| row == 0) {
+        return None;
+    }
+    FONT.chunks(GLYPH_HEIGHT).position(|glyph| glyph == drawn).map(|digit| digit as u8)
+}
+
+/// Reads the font digits inside `rect` (e.g. a score) as hex, top to bottom, then left to right
+pub fn read_digits(screen: &Screen, rect: Rect) -> String {
+    let mut digits = String::new();
+    for y in rect.y..(rect.y + rect.height + 1).saturating_sub(GLYPH_HEIGHT) {
+        let mut x = rect.x;
+        while x + GLYPH_WIDTH <= rect.x + rect.width {
+            match glyph_at(screen, x, y) {
+                Some(digit) => {
+                    digits.push(char::from_digit(digit as u32, 16).unwrap().to_ascii_uppercase());
+                    x += GLYPH_WIDTH;
+                },
+                None => x += 1,
+            }
+        }
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::{Instruction, Rect};
+    use crate::testing::Machine;
+    use super::read_digits;
+
+    #[test]
+    fn reads_font_digits() {
+        // 4, 2 and then A, spaced out like a score counter
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 4 },
+            Instruction::FontChar { register: 0 },
+            Instruction::Draw { x_r: 1, y_r: 2, height: 5 },
+            Instruction::SetRegister { register: 0, value: 2 },
+            Instruction::FontChar { register: 0 },
+            Instruction::SetRegister { register: 1, value: 5 },
+            Instruction::Draw { x_r: 1, y_r: 2, height: 5 },
+            Instruction::SetRegister { register: 0, value: 0xa },
+            Instruction::FontChar { register: 0 },
+            Instruction::SetRegister { register: 1, value: 40 },
+            Instruction::SetRegister { register: 2, value: 20 },
+            Instruction::Draw { x_r: 1, y_r: 2, height: 5 },
+        ]);
+        machine.run(12);
+        let screen = &machine.chip8.display;
+        assert_eq!(read_digits(screen, Rect { x: 0, y: 0, width: 64, height: 32 }), "42A");
+        assert_eq!(read_digits(screen, Rect { x: 0, y: 0, width: 10, height: 5 }), "42");
+        // Only part of the 2 is inside
+        assert_eq!(read_digits(screen, Rect { x: 0, y: 0, width: 8, height: 5 }), "4");
+        assert_eq!(read_digits(screen, Rect { x: 0, y: 10, width: 64, height: 5 }), "");
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::bits::get_nibble;
+use crate::chip8::{Instruction, INIT_INDEX, MEMORY_SIZE};
+use crate::decode::{decode, decode_mega};
+use crate::mega::MEGA_MEMORY_SIZE;
+use crate::quirks::Quirks;
+
+/// The interpreter a ROM was written for, which decides the instructions it has, the quirks
+/// they start with, and how fast it runs unless the speed is changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// CHIP-8 as this emulator has always run it, which is mostly how CHIP-48 did
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1 on the HP 48
+    Schip,
+    /// Octo's XO-CHIP
+    XoChip,
+    /// CHIP-8 with the COSMAC VIP's two-page 64x64 display, for ROMs that start with `1260`
+    Hires,
+    /// CHIP-8X, for the VIP with its colour board and a second keypad
+    Chip8X,
+    /// MEGA-CHIP, SCHIP with a 256x192 colour screen and 24-bit I
+    MegaChip,
+}
+
+impl Platform {
+    pub const NAMES: [&'static str; 6] = ["chip8", "schip", "xochip", "hires", "chip8x", "megachip"];
+
+    /// The platform to run `rom` on when asked for this one, which is this one
+    /// except that CHIP-8 ROMs that start like hires ROMs run as hires
+    pub fn for_rom(self, rom: &[u8]) -> Platform {
+        if self == Platform::Chip8 && starts_two_page(rom) {
+            Platform::Hires
+        } else {
+            self
+        }
+    }
+
+    /// Where the ROM's quirks file and `--quirk` start from
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::Schip | Platform::MegaChip => Quirks { jump_with_vx: true, ..Quirks::default() },
+            Platform::XoChip => Quirks {
+                shift_uses_vy: true,
+                increment_index: true,
+                clip_sprites: false,
+                ..Quirks::default()
+            },
+            // These ROMs ran on the VIP itself
+            Platform::Hires | Platform::Chip8X => Quirks {
+                shift_uses_vy: true,
+                increment_index: true,
+                vf_reset: true,
+                display_wait: true,
+                vip_timing: true,
+                ..Quirks::default()
+            },
+        }
+    }
+
+    /// Instructions per second
+    pub const fn clock_speed(self) -> u32 {
+        match self {
+            Platform::Chip8 | Platform::Hires | Platform::Chip8X => 500,
+            Platform::Schip => 1000,
+            Platform::XoChip => 1200,
+            // Its demos were written for PC interpreters, and draw whole-screen sprites every frame
+            Platform::MegaChip => 3000,
+        }
+    }
+
+    /// Where programs are loaded and start. CHIP-8X's interpreter is bigger, so its programs start at 0x300.
+    pub const fn load_address(self) -> usize {
+        match self {
+            Platform::Chip8X => 0x300,
+            _ => INIT_INDEX,
+        }
+    }
+
+    /// How much memory programs can reach: 4K, except MEGA-CHIP's 24-bit I reaches 16M
+    pub const fn memory_size(self) -> usize {
+        match self {
+            Platform::MegaChip => MEGA_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    /// The biggest ROM that fits in memory from the load address
+    pub const fn max_rom_size(self) -> usize {
+        self.memory_size() - self.load_address()
+    }
+
+    /// Whether the platform has this instruction. SCHIP and XO-CHIP kept all of CHIP-8's.
+    pub fn supports(self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::Lores
+            | Instruction::Hires
+            | Instruction::Exit
+            | Instruction::ScrollDown { .. }
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::BigFontChar { .. }
+            | Instruction::SaveFlags { .. }
+            | Instruction::LoadFlags { .. } => matches!(self, Platform::Schip | Platform::XoChip | Platform::MegaChip),
+            Instruction::LongIndex
+            | Instruction::SelectPlanes { .. }
+            | Instruction::ScrollUp { .. }
+            | Instruction::LoadAudio
+            | Instruction::SetPitch { .. }
+            | Instruction::StoreRange { .. }
+            | Instruction::LoadRange { .. } => self == Platform::XoChip,
+            Instruction::CycleBackground
+            | Instruction::AddNibbles { .. }
+            | Instruction::ColorZones { .. }
+            | Instruction::ColorRows { .. }
+            | Instruction::SkipPressed2 { .. }
+            | Instruction::SkipNotPressed2 { .. } => self == Platform::Chip8X,
+            Instruction::MegaOff
+            | Instruction::MegaOn
+            | Instruction::MegaIndex { .. }
+            | Instruction::LoadPalette { .. }
+            | Instruction::SpriteWidth { .. }
+            | Instruction::SpriteHeight { .. }
+            | Instruction::Alpha { .. }
+            | Instruction::PlaySample { .. }
+            | Instruction::StopSample
+            | Instruction::BlendMode { .. }
+            | Instruction::CollisionColor { .. } => self == Platform::MegaChip,
+            // CHIP-8X colours with BXYN instead
+            Instruction::JumpOffset { .. } => self != Platform::Chip8X,
+            _ => true,
+        }
+    }
+
+    /// Like `decode::decode`, but only for instructions the platform has, and with the meanings
+    /// some give opcodes that mean something else everywhere else. The hires interpreter also clears
+    /// the screen with 0230, which hires ROMs use instead of 00E0, CHIP-8X has 5XY1 and BXYN, and
+    /// MEGA-CHIP has the 0NNN opcodes `decode::decode_mega` knows.
+    pub fn decode(self, raw: u16) -> Option<Instruction> {
+        let (x, y, n) = (get_nibble(raw, 1), get_nibble(raw, 2), get_nibble(raw, 3));
+        match (self, get_nibble(raw, 0)) {
+            (Platform::Hires, 0x0) if raw == 0x0230 => Some(Instruction::ClearScreen),
+            (Platform::MegaChip, 0x0) if decode_mega(raw).is_some() => decode_mega(raw),
+            (Platform::Chip8X, 0x5) if n == 1 => Some(Instruction::AddNibbles { register1: x, register2: y }),
+            (Platform::Chip8X, 0xb) if n == 0 => Some(Instruction::ColorZones { x_r: x, y_r: y }),
+            (Platform::Chip8X, 0xb) => Some(Instruction::ColorRows { x_r: x, y_r: y, rows: n }),
+            _ => decode(raw).filter(|instruction| self.supports(instruction)),
+        }
+    }
+}
+
+/// Hires ROMs start by jumping to VIP machine code at 0x260 that sets up the two-page display
+pub fn starts_two_page(rom: &[u8]) -> bool {
+    rom.starts_with(&[0x12, 0x60])
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" => Ok(Platform::Schip),
+            "xochip" => Ok(Platform::XoChip),
+            "hires" => Ok(Platform::Hires),
+            "chip8x" => Ok(Platform::Chip8X),
+            "megachip" => Ok(Platform::MegaChip),
+            _ => Err(format!("Unknown profile {}, expected one of {}", s, Platform::NAMES.join(", "))),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(Platform::NAMES[*self as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use super::Platform;
+
+    #[test]
+    fn parses_names() {
+        for name in Platform::NAMES {
+            assert_eq!(name.parse::<Platform>().unwrap().to_string(), name);
+        }
+        assert!("superchip".parse::<Platform>().is_err());
+        assert_eq!(Platform::XoChip.decode(0x00e0), Some(Instruction::ClearScreen));
+        assert_eq!(Platform::Chip8.decode(0x0123), None);
+        assert_eq!(Platform::Chip8.decode(0x00ff), None);
+        assert_eq!(Platform::Schip.decode(0x00ff), Some(Instruction::Hires));
+        assert_eq!(Platform::Chip8.decode(0x00c3), None);
+        assert_eq!(Platform::XoChip.decode(0x00c3), Some(Instruction::ScrollDown { rows: 3 }));
+        assert_eq!(Platform::Schip.decode(0xf000), None);
+        assert_eq!(Platform::XoChip.decode(0xf000), Some(Instruction::LongIndex));
+        assert_eq!(Platform::Hires.decode(0x0230), Some(Instruction::ClearScreen));
+        assert_eq!(Platform::Chip8.decode(0x0230), None);
+        assert_eq!(Platform::Hires.decode(0x00ff), None);
+        assert_eq!(Platform::Chip8X.decode(0x5121), Some(Instruction::AddNibbles { register1: 1, register2: 2 }));
+        assert_eq!(Platform::Chip8.decode(0x5121), Some(Instruction::SkipEQR { register1: 1, register2: 2 }));
+        assert_eq!(Platform::Chip8X.decode(0xb120), Some(Instruction::ColorZones { x_r: 1, y_r: 2 }));
+        assert_eq!(Platform::Chip8X.decode(0xb124), Some(Instruction::ColorRows { x_r: 1, y_r: 2, rows: 4 }));
+        assert_eq!(Platform::Chip8.decode(0xb124), Some(Instruction::JumpOffset { dest: 0x124 }));
+        assert_eq!(Platform::Chip8X.decode(0x02a0), Some(Instruction::CycleBackground));
+        assert_eq!(Platform::Chip8.decode(0x02a0), None);
+        assert_eq!(Platform::Chip8X.decode(0xe3f2), Some(Instruction::SkipPressed2 { register: 3 }));
+        assert_eq!(Platform::MegaChip.decode(0x02a0), Some(Instruction::LoadPalette { count: 0xa0 }));
+        assert_eq!(Platform::MegaChip.decode(0x00b3), Some(Instruction::ScrollUp { rows: 3 }));
+        assert_eq!(Platform::MegaChip.decode(0x00d3), None);
+        assert_eq!(Platform::MegaChip.decode(0x00fb), Some(Instruction::ScrollRight));
+        assert_eq!(Platform::Schip.decode(0x0011), None);
+    }
+
+    #[test]
+    fn detects_hires_roms() {
+        assert_eq!(Platform::Chip8.for_rom(&[0x12, 0x60, 0x00, 0xe0]), Platform::Hires);
+        assert_eq!(Platform::Schip.for_rom(&[0x12, 0x60, 0x00, 0xe0]), Platform::Schip);
+        assert_eq!(Platform::Chip8.for_rom(&[0x12, 0x02]), Platform::Chip8);
+        assert_eq!(Platform::Chip8.for_rom(&[0x12]), Platform::Chip8);
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::chip8::Chip8;
+use crate::savestate::SaveState;
+
+/// Frames a marked address has to wait after the last checkpoint to take another, so a mark
+/// in a loop doesn't take one every time round, and going back to one doesn't replace it
+const MARK_GAP_FRAMES: u64 = 60;
+
+/// The checkpoint for a ROM is kept next to it, like its save states, so practice carries on next time
+pub fn practice_path(rom_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.practice", rom_path))
+}
+
+/// Practice mode: a save state taken with a key, or whenever the program gets to a marked
+/// address, that another key goes straight back to, for trying a hard part of a game again
+/// and again
+pub struct Practice {
+    /// Addresses that take a checkpoint when the program gets to them, e.g. the start of a level
+    pub marks: Vec<usize>,
+    checkpoint: Option<SaveState>,
+    /// The frame the checkpoint was taken on, if it was taken this run
+    taken_at: Option<u64>,
+    /// Times the latest checkpoint has been gone back to
+    pub retries: u32,
+}
+
+impl Practice {
+    pub fn new(marks: Vec<usize>) -> Self {
+        Practice { marks, checkpoint: None, taken_at: None, retries: 0 }
+    }
+
+    /// Carries on from the checkpoint saved for the ROM, if it was made with this one
+    pub fn load(rom_path: &str, rom_hash: u64, marks: Vec<usize>) -> Self {
+        let mut practice = Practice::new(marks);
+        let path = practice_path(rom_path);
+        match std::fs::File::open(&path).and_then(SaveState::read) {
+            Ok(state) if state.rom_hash == rom_hash => practice.checkpoint = Some(state),
+            Ok(_) => log::warn!("Ignoring the practice checkpoint in {}, which was made with a different ROM", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => log::warn!("Couldn't read the practice checkpoint {}: {}", path.display(), e),
+        }
+        practice
+    }
+
+    pub fn save(&self, rom_path: &str) -> std::io::Result<()> {
+        match &self.checkpoint {
+            Some(state) => state.write(std::io::BufWriter::new(std::fs::File::create(practice_path(rom_path))?)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn checkpoint(&self) -> Option<&SaveState> {
+        self.checkpoint.as_ref()
+    }
+
+    /// Makes the machine as it is now the checkpoint to go back to
+    pub fn take(&mut self, chip8: &Chip8, rom_hash: u64, play_time: Duration) {
+        self.checkpoint = Some(SaveState::new(chip8.snapshot(), &chip8.display, rom_hash, play_time));
+        self.taken_at = Some(chip8.timestamp().frame());
+        self.retries = 0;
+    }
+
+    /// Call before each instruction. Takes a checkpoint if it's at a marked address and the last
+    /// wasn't just now, returning whether it did.
+    pub fn check(&mut self, chip8: &Chip8, rom_hash: u64, play_time: Duration) -> bool {
+        if !self.marks.contains(&chip8.pc) {
+            return false;
+        }
+        let frame = chip8.timestamp().frame();
+        if self.taken_at.is_some_and(|taken| taken.abs_diff(frame) < MARK_GAP_FRAMES) {
+            return false;
+        }
+        self.take(chip8, rom_hash, play_time);
+        true
+    }
+
+    /// Puts the machine back to the checkpoint, returning the play time it was taken at
+    pub fn retry(&mut self, chip8: &mut Chip8) -> Option<Duration> {
+        let state = self.checkpoint.as_ref()?;
+        chip8.restore(&state.snapshot);
+        // Emulated time carries on through a restore, so this is when it was gone back to
+        self.taken_at = Some(chip8.timestamp().frame());
+        self.retries += 1;
+        Some(state.play_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chip8::Instruction;
+    use crate::testing::Machine;
+    use super::Practice;
+
+    #[test]
+    fn takes_checkpoints_at_marks_and_goes_back() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 0 },
+            Instruction::AddToRegister { register: 0, value: 1 },
+            Instruction::Jump { dest: 0x202 },
+        ]);
+        let mut practice = Practice::new(vec![0x202]);
+        assert_eq!(practice.retry(&mut machine.chip8), None);
+        machine.step();
+        assert!(practice.check(&machine.chip8, 1, Duration::from_secs(3)));
+        machine.run(2);
+        assert_eq!(machine.chip8.pc, 0x202);
+        // Going round the loop again is too soon for another
+        assert!(!practice.check(&machine.chip8, 1, Duration::ZERO));
+        assert_eq!(machine.chip8.registers[0].0, 1);
+        assert_eq!(practice.retry(&mut machine.chip8), Some(Duration::from_secs(3)));
+        assert_eq!((machine.chip8.registers[0].0, machine.chip8.pc, practice.retries), (0, 0x202, 1));
+        assert!(!practice.check(&machine.chip8, 1, Duration::ZERO));
+
+        // A second later it's at the mark again, and that's the checkpoint now
+        machine.run(1000);
+        assert_eq!(machine.chip8.pc, 0x202);
+        assert!(practice.check(&machine.chip8, 1, Duration::ZERO));
+        assert_eq!(practice.retries, 0);
+        practice.retry(&mut machine.chip8);
+        // Back where it was 500 times round the loop after the last
+        assert_eq!(machine.chip8.registers[0].0, 500u32 as u8);
+    }
+}
@@ -0,0 +1,53 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+/// A range of memory with restrictions on what the program may do with it.
+/// Writes to read-only memory are dropped, and executing no-execute memory is allowed but reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub range: Range<usize>,
+    pub read_only: bool,
+    pub no_execute: bool,
+}
+
+/// `START-END:FLAGS`, with an inclusive range in hex and comma separated flags `ro` and `nx`,
+/// e.g. `0-1ff:ro,nx` for the interpreter area, font included
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (range, flags) = s.split_once(':').ok_or("Expected START-END:FLAGS")?;
+        let (start, end) = range.split_once('-').ok_or("Expected a range like 0-1ff")?;
+        let address = |hex: &str| {
+            usize::from_str_radix(hex.trim_start_matches("0x"), 16).map_err(|e| format!("Bad address {}: {}", hex, e))
+        };
+        let (start, end) = (address(start)?, address(end)?);
+        if start > end || end >= 4096 {
+            return Err(format!("{:#x}-{:#x} isn't a range of memory", start, end));
+        }
+        let mut region = Region { range: start..end + 1, read_only: false, no_execute: false };
+        for flag in flags.split(',') {
+            match flag {
+                "ro" => region.read_only = true,
+                "nx" => region.no_execute = true,
+                _ => return Err(format!("Unknown flag {}, expected ro or nx", flag)),
+            }
+        }
+        Ok(region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Region;
+
+    #[test]
+    fn parses_regions() {
+        assert_eq!("0-1ff:ro,nx".parse(), Ok(Region { range: 0..0x200, read_only: true, no_execute: true }));
+        assert_eq!("0x300-0x3ff:nx".parse(), Ok(Region { range: 0x300..0x400, read_only: false, no_execute: true }));
+        assert!("0-1ff".parse::<Region>().is_err());
+        assert!("200-100:ro".parse::<Region>().is_err());
+        assert!("0-1000:ro".parse::<Region>().is_err());
+        assert!("0-1ff:rw".parse::<Region>().is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Behaviours that differ between CHIP-8 interpreters, which ROMs written for one of them can depend on.
+/// The defaults are how this emulator has always behaved, which is mostly CHIP-48's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY into VX, as on the COSMAC VIP, rather than shifting VX in place
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I just past the last register they touched, as on the COSMAC VIP
+    pub increment_index: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0, as on the COSMAC VIP
+    pub vf_reset: bool,
+    /// Sprites are cut off at the edges of the screen rather than wrapping around to the other side
+    pub clip_sprites: bool,
+    /// DXYN waits for the next 60Hz frame like the COSMAC VIP, so there's at most one draw per frame
+    pub display_wait: bool,
+    /// BXNN jumps to XNN + VX, as on CHIP-48 and SCHIP, rather than to XNN + V0
+    pub jump_with_vx: bool,
+    /// Each instruction takes about as long as on the COSMAC VIP, out of what its 1802 had to spare each
+    /// frame, rather than all taking the same time. See `vip::machine_cycles`.
+    pub vip_timing: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            increment_index: false,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+            jump_with_vx: false,
+            vip_timing: false,
+        }
+    }
+}
+
+/// The quirks a ROM needs are kept next to it, like its look and save states
+pub fn quirks_path(rom_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.quirks", rom_path))
+}
+
+impl Quirks {
+    pub const NAMES: [&'static str; 7] = [
+        "shift_uses_vy",
+        "increment_index",
+        "vf_reset",
+        "clip_sprites",
+        "display_wait",
+        "jump_with_vx",
+        "vip_timing",
+    ];
+
+    fn flag(&mut self, name: &str) -> Result<&mut bool, String> {
+        Ok(match name {
+            "shift_uses_vy" => &mut self.shift_uses_vy,
+            "increment_index" => &mut self.increment_index,
+            "vf_reset" => &mut self.vf_reset,
+            "clip_sprites" => &mut self.clip_sprites,
+            "display_wait" => &mut self.display_wait,
+            "jump_with_vx" => &mut self.jump_with_vx,
+            "vip_timing" => &mut self.vip_timing,
+            _ => return Err(format!("Unknown quirk {}, expected one of {}", name, Quirks::NAMES.join(", "))),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Result<bool, String> {
+        self.clone().flag(name).map(|flag| *flag)
+    }
+
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        *self.flag(name)? = value;
+        Ok(())
+    }
+
+    /// Turns on the quirk with this name, from `NAMES`, or turns it off if the name starts with `no_`,
+    /// e.g. `no_clip_sprites`
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        match spec.strip_prefix("no_") {
+            Some(name) => self.set(name, false),
+            None => self.set(spec, true),
+        }
+    }
+
+    /// Quirks as for `apply` on top of `base`, separated by commas or whitespace, with `#` starting a comment,
+    /// e.g. `no_clip_sprites # VERTICAL BRIX wraps the ball around`. Unknown ones are skipped with a warning.
+    pub fn parse(text: &str, base: Quirks) -> Self {
+        let mut quirks = base;
+        let specs = text.lines()
+            .map(|line| line.split('#').next().unwrap())
+            .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|spec| !spec.is_empty());
+        for spec in specs {
+            if let Err(e) = quirks.apply(spec) {
+                log::warn!("Skipping quirk: {}", e);
+            }
+        }
+        quirks
+    }
+
+    /// The ROM's quirks file on top of `base`, usually the platform's, or just `base` if it has none
+    pub fn load(rom_path: &str, base: Quirks) -> Self {
+        match std::fs::read_to_string(quirks_path(rom_path)) {
+            Ok(text) => Quirks::parse(&text, base),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => base,
+            Err(e) => {
+                log::warn!("Couldn't read quirks for {}: {}", rom_path, e);
+                base
+            },
+        }
+    }
+}
+
+/// Every quirk by name as `apply` takes it, e.g. `no_shift_uses_vy, ..., clip_sprites`, so `parse` reads it back whatever the base
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let specs: Vec<String> = Quirks::NAMES
+            .iter()
+            .map(|&name| if self.get(name) == Ok(true) { name.to_string() } else { format!("no_{}", name) })
+            .collect();
+        write!(f, "{}", specs.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Quirks;
+
+    #[test]
+    fn applies_by_name() {
+        let mut quirks = Quirks::default();
+        quirks.apply("vf_reset").unwrap();
+        quirks.apply("no_clip_sprites").unwrap();
+        assert_eq!(quirks, Quirks { vf_reset: true, clip_sprites: false, ..Quirks::default() });
+        assert_eq!(quirks.get("vf_reset"), Ok(true));
+        assert!(quirks.apply("no_such_quirk").is_err());
+        assert!(quirks.apply("wrap").is_err());
+        assert_eq!(Quirks::parse("# VERTICAL BRIX\nno_clip_sprites, vf_reset\nwrap  # unknown\n", Quirks::default()), quirks);
+        let base = Quirks { shift_uses_vy: true, clip_sprites: true, ..Quirks::default() };
+        assert_eq!(Quirks::parse(&quirks.to_string(), base), quirks);
+    }
+}
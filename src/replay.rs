@@ -0,0 +1,608 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::time::Duration;
+use crate::chip8::Chip8;
+use crate::platform::Platform;
+use crate::snapshot::{read_chunks, write_chunk, Snapshot};
+
+const MAGIC: &[u8; 4] = b"C8RP";
+pub const FORMAT_VERSION: u16 = 1;
+/// How often the player keeps a full snapshot to seek from
+const KEYFRAME_INTERVAL: u64 = 1000;
+/// How many recordings an `InstantReplay` window is split into
+const INSTANT_REPLAY_SEGMENTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Keys([bool; 16]),
+    ClockGap(Duration),
+}
+
+/// The starting state plus every input change, which is enough to get back to any later state
+/// by running the program again, since the core is deterministic given its inputs and emulated time.
+///
+/// A replay file is `C8RP`, a u16 format version, then chunks (see `snapshot::write_chunk`):
+/// * `RPLY` - starting cycle count, timer phase in nanoseconds, starting keys as a bitmask,
+///   starting clock gap in nanoseconds, and length in cycles (u64 apart from the u16 keys)
+/// * `EVNT` - each event as its cycle (u64), a kind byte (0 for keys, 1 for clock gap), then its value as a u64
+/// * `RNG ` - the starting rng state, serialized with bincode
+/// * `PLAT` - the platform's name, e.g. `schip`; replays without one are `chip8`
+/// * every chunk of the starting `Snapshot`, which includes the ROM since it's in memory
+#[derive(Clone)]
+pub struct Recording {
+    initial: Snapshot,
+    platform: Platform,
+    initial_cycles: u64,
+    /// The machine's emulated time when recording started, which isn't saved, so read recordings start from zero
+    start_time: Duration,
+    timer_phase: Duration,
+    start_keys: [bool; 16],
+    start_clock_gap: Duration,
+    /// Each event applies from the cycle it's tagged with (counted from the start of the recording)
+    events: Vec<(u64, Event)>,
+    keys: [bool; 16],
+    clock_gap: Duration,
+    len: u64,
+}
+
+impl Recording {
+    pub fn start(chip8: &Chip8, keys: [bool; 16], clock_gap: Duration) -> Self {
+        Recording {
+            initial: chip8.snapshot(),
+            platform: chip8.platform,
+            initial_cycles: chip8.cycles,
+            start_time: Duration::from_nanos(chip8.timestamp().nanos),
+            timer_phase: chip8.timer_phase(),
+            start_keys: keys,
+            start_clock_gap: clock_gap,
+            events: Vec::new(),
+            keys,
+            clock_gap,
+            len: 0,
+        }
+    }
+
+    /// Number of cycles recorded
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Call before each cycle with the inputs it's about to run with, `clock_gap` being the gap it's run with
+    pub fn record_cycle(&mut self, keys: [bool; 16], clock_gap: Duration) {
+        if keys != self.keys {
+            self.keys = keys;
+            self.events.push((self.len, Event::Keys(keys)));
+        }
+        if clock_gap != self.clock_gap {
+            self.clock_gap = clock_gap;
+            self.events.push((self.len, Event::ClockGap(clock_gap)));
+        }
+        self.len += 1;
+    }
+
+    /// Forgets everything after `len` cycles, to carry on recording from an earlier point
+    pub fn truncate(&mut self, len: u64) {
+        self.len = self.len.min(len);
+        self.events.retain(|&(cycle, _)| cycle < len);
+        let (keys, clock_gap) = self.inputs_at(len);
+        self.keys = keys;
+        self.clock_gap = clock_gap;
+    }
+
+    /// Carries on with `next`, which has to have started where this one ends
+    pub fn append(&mut self, next: &Recording) {
+        if next.start_keys != self.keys {
+            self.events.push((self.len, Event::Keys(next.start_keys)));
+        }
+        if next.start_clock_gap != self.clock_gap {
+            self.events.push((self.len, Event::ClockGap(next.start_clock_gap)));
+        }
+        self.events.extend(next.events.iter().map(|&(cycle, event)| (self.len + cycle, event)));
+        self.keys = next.keys;
+        self.clock_gap = next.clock_gap;
+        self.len += next.len;
+    }
+
+    /// Puts `chip8` into the state it was in after `target` cycles of the recording,
+    /// and returns its emulated time then
+    pub fn reconstruct(&self, chip8: &mut Chip8, target: u64) -> Duration {
+        assert!(target <= self.len, "Can't reconstruct past the end of the recording");
+        chip8.restore(&self.initial);
+        chip8.cycles = self.initial_cycles;
+        chip8.set_timer_phase(self.start_time, self.timer_phase);
+        let mut time = self.start_time;
+        let mut keys = self.start_keys;
+        let mut clock_gap = self.start_clock_gap;
+        let mut events = self.events.iter().peekable();
+        for cycle in 0..target {
+            while let Some((_, event)) = events.next_if(|&&(event_cycle, _)| event_cycle == cycle) {
+                match *event {
+                    Event::Keys(new_keys) => keys = new_keys,
+                    Event::ClockGap(gap) => clock_gap = gap,
+                }
+            }
+            time += clock_gap;
+            chip8.cycle(keys, clock_gap);
+        }
+        time
+    }
+
+    pub fn write(&self, mut write: impl Write) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        let mut header = Vec::new();
+        header.extend_from_slice(&self.initial_cycles.to_be_bytes());
+        header.extend_from_slice(&(self.timer_phase.as_nanos() as u64).to_be_bytes());
+        header.extend_from_slice(&pack_keys(self.start_keys).to_be_bytes());
+        header.extend_from_slice(&(self.start_clock_gap.as_nanos() as u64).to_be_bytes());
+        header.extend_from_slice(&self.len.to_be_bytes());
+        write_chunk(&mut bytes, b"RPLY", &header);
+        let mut events = Vec::with_capacity(self.events.len() * 17);
+        for &(cycle, event) in &self.events {
+            events.extend_from_slice(&cycle.to_be_bytes());
+            let (kind, value) = match event {
+                Event::Keys(keys) => (0, pack_keys(keys) as u64),
+                Event::ClockGap(gap) => (1, gap.as_nanos() as u64),
+            };
+            events.push(kind);
+            events.extend_from_slice(&value.to_be_bytes());
+        }
+        write_chunk(&mut bytes, b"EVNT", &events);
+        let rng = bincode::serialize(&self.initial.rng).map_err(Error::other)?;
+        write_chunk(&mut bytes, b"RNG ", &rng);
+        write_chunk(&mut bytes, b"PLAT", self.platform.to_string().as_bytes());
+        bytes.extend_from_slice(&self.initial.bytes);
+        write.write_all(&bytes)
+    }
+
+    /// Unlike save states, a replay missing any of its chunks is useless, so they're all required
+    pub fn read(mut read: impl Read) -> Result<Self, Error> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        let mut bytes = Vec::new();
+        read.read_to_end(&mut bytes)?;
+        if bytes.len() < 6 || &bytes[0..4] != MAGIC {
+            return Err(invalid("Not a replay"));
+        }
+        let version = u16::from_be_bytes([bytes[4], bytes[5]]);
+        if version > FORMAT_VERSION {
+            log::warn!("Replay is format version {}, newer than {}; some of it may be ignored", version, FORMAT_VERSION);
+        }
+        let mut header = None;
+        let mut events = None;
+        let mut rng = None;
+        let mut platform = Platform::default();
+        let mut snapshot_bytes = Vec::new();
+        for (tag, data) in read_chunks(&bytes[6..]) {
+            let u64_at = |i: usize| u64::from_be_bytes(data[i..i + 8].try_into().unwrap());
+            match &tag {
+                b"RPLY" if data.len() >= 34 => {
+                    header = Some((
+                        u64_at(0),
+                        Duration::from_nanos(u64_at(8)),
+                        unpack_keys(u16::from_be_bytes([data[16], data[17]])),
+                        Duration::from_nanos(u64_at(18)),
+                        u64_at(26),
+                    ));
+                },
+                b"EVNT" if data.len() % 17 == 0 => {
+                    let parsed: Option<Vec<_>> = data.chunks(17).map(|event| {
+                        let value = u64::from_be_bytes(event[9..17].try_into().unwrap());
+                        let event_cycle = u64::from_be_bytes(event[0..8].try_into().unwrap());
+                        match event[8] {
+                            0 => Some((event_cycle, Event::Keys(unpack_keys(value as u16)))),
+                            1 => Some((event_cycle, Event::ClockGap(Duration::from_nanos(value)))),
+                            _ => None,
+                        }
+                    }).collect();
+                    events = parsed;
+                },
+                b"RNG " => rng = bincode::deserialize(data).ok(),
+                b"PLAT" => {
+                    platform = String::from_utf8_lossy(data).parse().map_err(|e: String| invalid(&e))?;
+                },
+                b"RPLY" | b"EVNT" => return Err(invalid("Replay has a malformed chunk")),
+                _ => write_chunk(&mut snapshot_bytes, &tag, data),
+            }
+        }
+        let (initial_cycles, timer_phase, start_keys, start_clock_gap, len) = header.ok_or_else(|| invalid("Replay has no header"))?;
+        let events = events.ok_or_else(|| invalid("Replay has no usable events"))?;
+        let rng = rng.ok_or_else(|| invalid("Replay has no usable rng state"))?;
+        let mut recording = Recording {
+            initial: Snapshot { bytes: snapshot_bytes, rng },
+            platform,
+            initial_cycles,
+            start_time: Duration::ZERO,
+            timer_phase,
+            start_keys,
+            start_clock_gap,
+            events,
+            keys: start_keys,
+            clock_gap: start_clock_gap,
+            len,
+        };
+        recording.truncate(len);
+        Ok(recording)
+    }
+
+    fn inputs_at(&self, cycle: u64) -> ([bool; 16], Duration) {
+        let mut keys = self.start_keys;
+        let mut clock_gap = self.start_clock_gap;
+        for &(_, event) in self.events.iter().take_while(|&&(event_cycle, _)| event_cycle < cycle) {
+            match event {
+                Event::Keys(new_keys) => keys = new_keys,
+                Event::ClockGap(gap) => clock_gap = gap,
+            }
+        }
+        (keys, clock_gap)
+    }
+}
+
+fn pack_keys(keys: [bool; 16]) -> u16 {
+    keys.iter().enumerate().fold(0, |mask, (i, &pressed)| mask | (pressed as u16) << i)
+}
+
+fn unpack_keys(mask: u16) -> [bool; 16] {
+    let mut keys = [false; 16];
+    for (i, key) in keys.iter_mut().enumerate() {
+        *key = mask & 1 << i != 0;
+    }
+    keys
+}
+
+/// The last `window` or so of emulated time, for saving after something worth keeping happens
+/// rather than recording a whole session. It's kept as a few recordings each a fraction of the
+/// window long, starting a new one as the latest fills up and dropping the oldest once the rest
+/// cover the window, so it holds between one and one and a third windows.
+pub struct InstantReplay {
+    window: Duration,
+    /// Each recording with the emulated time it covers, oldest first
+    segments: VecDeque<(Recording, Duration)>,
+}
+
+impl InstantReplay {
+    pub fn start(window: Duration, chip8: &Chip8, keys: [bool; 16], clock_gap: Duration) -> Self {
+        let mut instant_replay = InstantReplay { window, segments: VecDeque::new() };
+        instant_replay.restart(chip8, keys, clock_gap);
+        instant_replay
+    }
+
+    /// Forgets everything so far, for when the state jumps somewhere playing couldn't get to
+    pub fn restart(&mut self, chip8: &Chip8, keys: [bool; 16], clock_gap: Duration) {
+        self.segments.clear();
+        self.segments.push_back((Recording::start(chip8, keys, clock_gap), Duration::ZERO));
+    }
+
+    /// Call before each cycle, like `Recording::record_cycle`, with the state before it
+    pub fn record_cycle(&mut self, chip8: &Chip8, keys: [bool; 16], clock_gap: Duration) {
+        if self.segments.back().unwrap().1 >= self.window / INSTANT_REPLAY_SEGMENTS {
+            self.segments.push_back((Recording::start(chip8, keys, clock_gap), Duration::ZERO));
+            while self.segments.iter().skip(1).map(|&(_, duration)| duration).sum::<Duration>() >= self.window {
+                self.segments.pop_front();
+            }
+        }
+        let (recording, duration) = self.segments.back_mut().unwrap();
+        recording.record_cycle(keys, clock_gap);
+        *duration += clock_gap;
+    }
+
+    /// Emulated time held
+    pub fn duration(&self) -> Duration {
+        self.segments.iter().map(|&(_, duration)| duration).sum()
+    }
+
+    /// Everything held as one recording, to write out
+    pub fn export(&self) -> Recording {
+        let mut segments = self.segments.iter();
+        let mut recording = segments.next().unwrap().0.clone();
+        for (segment, _) in segments {
+            recording.append(segment);
+        }
+        recording
+    }
+}
+
+struct Keyframe {
+    snapshot: Snapshot,
+    cycles: u64,
+    time: Duration,
+    timer_phase: Duration,
+}
+
+/// Plays a recording back with seeking, keeping a snapshot every `KEYFRAME_INTERVAL` cycles
+/// so any point is at most that many cycles of re-execution away
+pub struct Player {
+    recording: Recording,
+    /// `keyframes[i]` is the state after `i * KEYFRAME_INTERVAL` cycles, filled in as playback first gets there
+    keyframes: Vec<Keyframe>,
+    pub chip8: Chip8,
+    position: u64,
+    /// The machine's emulated time
+    time: Duration,
+    next_event: usize,
+    keys: [bool; 16],
+    clock_gap: Duration,
+}
+
+impl Player {
+    pub fn new(mut recording: Recording) -> Self {
+        // Played on its own, the machine's time starts with the recording
+        recording.start_time = Duration::ZERO;
+        let mut chip8 = Chip8::new(recording.platform);
+        let time = recording.reconstruct(&mut chip8, 0);
+        let mut player = Player {
+            keys: recording.start_keys,
+            clock_gap: recording.start_clock_gap,
+            recording,
+            keyframes: Vec::new(),
+            chip8,
+            position: 0,
+            time,
+            next_event: 0,
+        };
+        player.save_keyframe();
+        player
+    }
+
+    /// Cycles played so far
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn len(&self) -> u64 {
+        self.recording.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recording.is_empty()
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.position == self.len()
+    }
+
+    /// Emulated time played so far
+    pub fn elapsed(&self) -> Duration {
+        self.time - self.recording.start_time
+    }
+
+    /// Clock gap of the next cycle, or the last one at the end
+    pub fn clock_gap(&self) -> Duration {
+        self.next_clock_gap()
+    }
+
+    /// Runs up to `cycles` more cycles, stopping at the end
+    pub fn step(&mut self, cycles: u64) {
+        let target = (self.position + cycles).min(self.len());
+        while self.position < target {
+            self.run_cycle();
+        }
+    }
+
+    /// Runs every cycle that falls within the next `duration` of emulated time
+    pub fn advance(&mut self, duration: Duration) {
+        let target = self.time + duration;
+        while !self.at_end() && self.time + self.next_clock_gap() <= target {
+            self.run_cycle();
+        }
+    }
+
+    /// Jumps to the state after `target` cycles, clamped to the recording
+    pub fn seek(&mut self, target: u64) {
+        let target = target.min(self.len());
+        let keyframe = ((target / KEYFRAME_INTERVAL) as usize).min(self.keyframes.len() - 1);
+        let keyframe_cycle = keyframe as u64 * KEYFRAME_INTERVAL;
+        // Carrying on from here is never slower than going back to the keyframe
+        if (keyframe_cycle..=target).contains(&self.position) {
+            return self.step(target - self.position);
+        }
+        let Keyframe { snapshot, cycles, time, timer_phase } = &self.keyframes[keyframe];
+        self.chip8.restore(snapshot);
+        self.chip8.cycles = *cycles;
+        self.chip8.set_timer_phase(*time, *timer_phase);
+        self.time = *time;
+        self.position = keyframe_cycle;
+        self.next_event = self.recording.events.partition_point(|&(cycle, _)| cycle < keyframe_cycle);
+        let (keys, clock_gap) = self.recording.inputs_at(keyframe_cycle);
+        self.keys = keys;
+        self.clock_gap = clock_gap;
+        self.step(target - keyframe_cycle);
+    }
+
+    fn next_clock_gap(&self) -> Duration {
+        self.recording.events[self.next_event..]
+            .iter()
+            .take_while(|&&(cycle, _)| cycle == self.position)
+            .fold(self.clock_gap, |gap, &(_, event)| match event {
+                Event::ClockGap(new_gap) => new_gap,
+                Event::Keys(_) => gap,
+            })
+    }
+
+    fn run_cycle(&mut self) {
+        while let Some(&(cycle, event)) = self.recording.events.get(self.next_event) {
+            if cycle != self.position {
+                break;
+            }
+            match event {
+                Event::Keys(keys) => self.keys = keys,
+                Event::ClockGap(gap) => self.clock_gap = gap,
+            }
+            self.next_event += 1;
+        }
+        self.time += self.clock_gap;
+        self.chip8.cycle(self.keys, self.clock_gap);
+        self.position += 1;
+        if self.position == self.keyframes.len() as u64 * KEYFRAME_INTERVAL {
+            self.save_keyframe();
+        }
+    }
+
+    fn save_keyframe(&mut self) {
+        self.keyframes.push(Keyframe {
+            snapshot: self.chip8.snapshot(),
+            cycles: self.chip8.cycles,
+            time: self.time,
+            timer_phase: self.chip8.timer_phase(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use rand_core::{RngCore, SeedableRng};
+    use rand_xoshiro::Xoroshiro64StarStar;
+    use crate::chip8::Chip8;
+    use crate::platform::Platform;
+    use super::{InstantReplay, Player, Recording};
+
+    /// Records 2000 cycles of keypad.ch8 with random key presses and a clock change halfway,
+    /// returning the recording, the state after each cycle, and the final emulated time and cycle count
+    fn record_keypad() -> (Recording, Vec<Vec<u8>>, Duration, u64) {
+        let mut time = Duration::ZERO;
+        let mut chip8 = Chip8::new(Platform::Schip);
+        chip8.read_program(std::fs::File::open("test/keypad.ch8").unwrap()).unwrap();
+        let mut rng = Xoroshiro64StarStar::seed_from_u64(7);
+        let mut keys = [false; 16];
+        let mut clock_gap = Duration::from_millis(2);
+
+        // Warm up first so the recording doesn't start from a fresh machine
+        for _ in 0..100 {
+            time += clock_gap;
+            chip8.cycle(keys, clock_gap);
+        }
+        let mut recording = Recording::start(&chip8, keys, clock_gap);
+        let mut expected = vec![chip8.snapshot().bytes];
+        for i in 0..2000 {
+            if rng.next_u32() % 50 == 0 {
+                keys[rng.next_u32() as usize % 16] ^= true;
+            }
+            if i == 1000 {
+                clock_gap = Duration::from_millis(1);
+            }
+            recording.record_cycle(keys, clock_gap);
+            time += clock_gap;
+            chip8.cycle(keys, clock_gap);
+            expected.push(chip8.snapshot().bytes);
+        }
+        (recording, expected, time, chip8.cycles)
+    }
+
+    #[test]
+    fn reconstructs_every_cycle() {
+        let (mut recording, expected, time, cycles) = record_keypad();
+        let mut replayed = Chip8::new(Platform::default());
+        for target in [0, 1, 999, 1000, 1001, 2000] {
+            let replayed_time = recording.reconstruct(&mut replayed, target);
+            assert_eq!(replayed.snapshot().bytes, expected[target as usize], "Diverged by cycle {}", target);
+            if target == 2000 {
+                assert_eq!(replayed_time, time);
+                assert_eq!(replayed.cycles, cycles);
+            }
+        }
+
+        recording.truncate(500);
+        assert_eq!(recording.len(), 500);
+        recording.reconstruct(&mut replayed, 500);
+        assert_eq!(replayed.snapshot().bytes, expected[500]);
+    }
+
+    #[test]
+    fn player_seeks_both_ways() {
+        let (recording, expected, _, cycles) = record_keypad();
+        let mut file = Vec::new();
+        recording.write(&mut file).unwrap();
+        let mut player = Player::new(Recording::read(&file[..]).unwrap());
+        assert_eq!(player.len(), 2000);
+        assert_eq!(player.chip8.platform, Platform::Schip);
+        for target in [1500, 200, 2000, 999, 1001, 1000, 0, 5000] {
+            player.seek(target);
+            let position = target.min(2000);
+            assert_eq!(player.position(), position);
+            assert_eq!(player.chip8.snapshot().bytes, expected[position as usize], "Diverged seeking to {}", target);
+        }
+        assert_eq!(player.chip8.cycles, cycles);
+
+        // 1000 cycles at 2ms, then 1000 at 1ms
+        player.seek(0);
+        player.advance(Duration::from_millis(2100));
+        assert_eq!(player.position(), 1100);
+        assert_eq!(player.clock_gap(), Duration::from_millis(1));
+        player.step(10_000);
+        assert!(player.at_end());
+        assert_eq!(player.elapsed(), Duration::from_millis(3000));
+    }
+
+    #[test]
+    fn instant_replay_keeps_the_last_window() {
+        let mut time = Duration::ZERO;
+        let mut chip8 = Chip8::new(Platform::Schip);
+        chip8.read_program(std::fs::File::open("test/keypad.ch8").unwrap()).unwrap();
+        let mut rng = Xoroshiro64StarStar::seed_from_u64(3);
+        let mut keys = [false; 16];
+        let mut clock_gap = Duration::from_millis(2);
+        let window = Duration::from_millis(600);
+        let mut instant_replay = InstantReplay::start(window, &chip8, keys, clock_gap);
+        for i in 0..2000 {
+            if rng.next_u32() % 50 == 0 {
+                keys[rng.next_u32() as usize % 16] ^= true;
+            }
+            if i == 1900 {
+                clock_gap = Duration::from_millis(1);
+            }
+            instant_replay.record_cycle(&chip8, keys, clock_gap);
+            time += clock_gap;
+            chip8.cycle(keys, clock_gap);
+        }
+        // 3.9s in all, of which the last 600-800ms are held
+        assert_eq!(chip8.timestamp().nanos, time.as_nanos() as u64);
+        let held = instant_replay.duration();
+        assert!(held >= window && held <= window * 4 / 3, "{:?}", held);
+
+        let exported = instant_replay.export();
+        let mut file = Vec::new();
+        exported.write(&mut file).unwrap();
+        let mut player = Player::new(Recording::read(&file[..]).unwrap());
+        assert!(player.len() < 2000);
+        player.step(10_000);
+        assert_eq!(player.elapsed(), held);
+        assert_eq!(player.chip8.snapshot().bytes, chip8.snapshot().bytes);
+        assert_eq!(player.chip8.cycles, chip8.cycles);
+    }
+
+    #[test]
+    fn plays_mega_chip_roms_bigger_than_4k() {
+        // A counting loop at the start of a ROM that needs 8K of memory
+        let mut rom = vec![0; 0x1800];
+        rom[..6].copy_from_slice(&[0x60, 0x00, 0x70, 0x01, 0x12, 0x02]);
+        rom[0x17ff] = 0xff;
+        let mut chip8 = Chip8::new(Platform::MegaChip);
+        chip8.read_program(&rom[..]).unwrap();
+        assert_eq!(chip8.memory.len(), 0x2000);
+        let clock_gap = Duration::from_millis(1);
+        let mut recording = Recording::start(&chip8, [false; 16], clock_gap);
+        for _ in 0..10 {
+            recording.record_cycle([false; 16], clock_gap);
+            chip8.cycle([false; 16], clock_gap);
+        }
+        let mut file = Vec::new();
+        recording.write(&mut file).unwrap();
+        let mut player = Player::new(Recording::read(&file[..]).unwrap());
+        assert_eq!(player.chip8.memory.len(), 0x2000);
+        player.step(10);
+        assert!(player.at_end());
+        assert_eq!(player.chip8.registers[0].0, 5);
+        assert_eq!(player.chip8.snapshot().bytes, chip8.snapshot().bytes);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(Recording::read(&b"C8SS\0\x01"[..]).is_err());
+        assert!(Recording::read(&b"C8RP\0\x01"[..]).is_err());
+    }
+}
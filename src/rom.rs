@@ -0,0 +1,148 @@
+use std::io::Read;
+use crate::bits::fnv1a;
+use crate::savestate::SaveState;
+
+/// Length of the ROM without trailing zero bytes.
+/// Memory after the ROM is zeroed when it's loaded, so stripping them never changes what runs.
+pub fn trimmed_len(rom: &[u8]) -> usize {
+    rom.iter().rposition(|&byte| byte != 0).map_or(0, |i| i + 1)
+}
+
+/// Where to get the program from
+#[derive(Debug, PartialEq, Eq)]
+pub enum RomSource {
+    File(String),
+    /// Raw bytes on stdin, given as the path `-`, e.g. `assembler | chip8 run-headless -`
+    Stdin,
+    /// Hex or base64 text on stdin, e.g. `xclip -o | chip8 --stdin`
+    PastedStdin,
+    /// The ROM embedded in a shared save state, e.g. `chip8 --state bug.c8ss`
+    Embedded(String),
+}
+
+impl From<&str> for RomSource {
+    fn from(path: &str) -> Self {
+        match path {
+            "-" => RomSource::Stdin,
+            _ => RomSource::File(path.to_string()),
+        }
+    }
+}
+
+impl RomSource {
+    /// The ROM, and a path to keep its save states, symbols and screenshots next to
+    pub fn read(&self) -> Result<(Vec<u8>, String), String> {
+        match self {
+            RomSource::File(path) => {
+                let rom = std::fs::read(path).map_err(|e| format!("Couldn't read ROM {}: {}", path, e))?;
+                Ok((rom, path.clone()))
+            },
+            RomSource::Stdin => {
+                let mut rom = Vec::new();
+                std::io::stdin().read_to_end(&mut rom).map_err(|e| format!("Couldn't read stdin: {}", e))?;
+                let path = pasted_path(&rom);
+                Ok((rom, path))
+            },
+            RomSource::PastedStdin => {
+                let mut text = String::new();
+                std::io::stdin().read_to_string(&mut text).map_err(|e| format!("Couldn't read stdin: {}", e))?;
+                let rom = decode_pasted(&text)?;
+                let path = pasted_path(&rom);
+                Ok((rom, path))
+            },
+            RomSource::Embedded(state_path) => {
+                let state = std::fs::File::open(state_path)
+                    .and_then(SaveState::read)
+                    .map_err(|e| format!("Couldn't read save state {}: {}", state_path, e))?;
+                let rom = state.rom.ok_or(format!("{} has no ROM in it, so give the ROM too", state_path))?;
+                let path = pasted_path(&rom);
+                Ok((rom, path))
+            },
+        }
+    }
+}
+
+/// ROMs from stdin or the clipboard have no file, so they're named after their hash, which keeps save states apart
+pub fn pasted_path(rom: &[u8]) -> String {
+    format!("pasted-{:016x}.ch8", fnv1a(rom))
+}
+
+/// Decodes a ROM copied as text: hex bytes or words, optionally with `0x` or `$` prefixes and
+/// separated by whitespace or commas (e.g. `00E0 A22A` or `0x00, 0xe0`), or else base64
+pub fn decode_pasted(text: &str) -> Result<Vec<u8>, String> {
+    let hex: String = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|token| token.trim_start_matches("0x").trim_start_matches("0X").trim_start_matches('$'))
+        .collect();
+    let rom = if hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        if !hex.len().is_multiple_of(2) {
+            return Err(String::from("Pasted hex has an odd number of digits"));
+        }
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    } else {
+        let base64: String = text.split_whitespace().collect();
+        decode_base64(&base64).ok_or("Pasted text is neither hex nor base64")?
+    };
+    if rom.is_empty() {
+        return Err(String::from("Pasted ROM is empty"));
+    }
+    Ok(rom)
+}
+
+/// Standard or URL-safe alphabet, padding optional
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut rom = Vec::new();
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            rom.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+    Some(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_pasted, trimmed_len};
+
+    #[test]
+    fn trims_only_trailing_zeroes() {
+        assert_eq!(trimmed_len(&[]), 0);
+        assert_eq!(trimmed_len(&[0, 0, 0]), 0);
+        assert_eq!(trimmed_len(&[0x12, 0x00, 0x00, 0x00, 0xa2, 0x00, 0x00]), 5);
+        assert_eq!(trimmed_len(&[0x12, 0x00]), 1);
+    }
+
+    #[test]
+    fn padded_rom() {
+        let rom = std::fs::read("test/chip8_logo.ch8").unwrap();
+        assert_eq!(rom.len(), 288);
+        assert_eq!(trimmed_len(&rom), 271);
+    }
+
+    #[test]
+    fn decodes_pasted_roms() {
+        let logo = [0x00, 0xe0, 0xa2, 0x2a];
+        assert_eq!(decode_pasted("00E0 A22A\n"), Ok(logo.to_vec()));
+        assert_eq!(decode_pasted("0x00, 0xe0, 0xa2, 0x2a"), Ok(logo.to_vec()));
+        assert_eq!(decode_pasted("$00 $E0\n$A2 $2A"), Ok(logo.to_vec()));
+        assert_eq!(decode_pasted("AOCiKg=="), Ok(logo.to_vec()));
+        assert_eq!(decode_pasted("AOCi\nKg"), Ok(logo.to_vec()));
+        assert!(decode_pasted("00E0 A22").is_err());
+        assert!(decode_pasted("not a rom!").is_err());
+        assert!(decode_pasted("  \n").is_err());
+    }
+}
@@ -0,0 +1,26 @@
+use std::path::PathBuf;
+use crate::chip8::RPL_FLAGS;
+
+/// A ROM's RPL flags are kept next to it, as `<rom>.rpl`, the 8 bytes as they are
+pub fn rpl_path(rom_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.rpl", rom_path))
+}
+
+/// The flags saved for the ROM, or all 0 if there aren't any (or they can't be read)
+pub fn load(rom_path: &str) -> [u8; RPL_FLAGS] {
+    match std::fs::read(rpl_path(rom_path)) {
+        Ok(bytes) => bytes.try_into().unwrap_or_else(|bytes: Vec<u8>| {
+            log::warn!("Ignoring RPL flags for {}, which are {} bytes rather than {}", rom_path, bytes.len(), RPL_FLAGS);
+            [0; RPL_FLAGS]
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => [0; RPL_FLAGS],
+        Err(e) => {
+            log::warn!("Couldn't read RPL flags for {}: {}", rom_path, e);
+            [0; RPL_FLAGS]
+        },
+    }
+}
+
+pub fn save(rom_path: &str, flags: &[u8; RPL_FLAGS]) -> std::io::Result<()> {
+    std::fs::write(rpl_path(rom_path), flags)
+}
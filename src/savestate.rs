@@ -0,0 +1,252 @@
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::bits::fnv1a;
+use crate::chip8::{pack_screen, unpack_screen, Screen};
+use crate::snapshot::{read_chunks, write_chunk, Snapshot};
+use rand_core::SeedableRng;
+use rand_xoshiro::Xoroshiro64StarStar;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+/// Bump when a chunk's layout changes incompatibly. Adding chunks doesn't need a bump,
+/// since readers skip chunks they don't know.
+pub const FORMAT_VERSION: u16 = 1;
+pub const SLOTS: u8 = 9;
+
+/// A save state file is `C8SS`, a u16 format version, then chunks (see `snapshot::write_chunk`):
+/// * `META` - ROM hash, save time in unix seconds, and play time in milliseconds, all u64
+/// * `THMB` - the screen at save time, as in the `DISP` snapshot chunk
+/// * `RNG ` - the rng state, serialized with bincode
+/// * `ROM ` - optionally, the whole ROM, so a shared state can be loaded without it
+/// * every chunk of the core `Snapshot` (`PLAT`, `QRKS`, `MEM `, `DISP`, `CORE`, and whatever later versions add)
+pub struct SaveState {
+    pub rom_hash: u64,
+    /// Seconds since the unix epoch when the state was saved
+    pub timestamp: u64,
+    pub play_time: Duration,
+    /// The screen at save time, if the file had a usable one
+    pub thumbnail: Option<Screen>,
+    /// The ROM itself, for sharing a state with someone who may not have it. Always matches `rom_hash`.
+    pub rom: Option<Vec<u8>>,
+    pub snapshot: Snapshot,
+}
+
+impl SaveState {
+    pub fn new(snapshot: Snapshot, display: &Screen, rom_hash: u64, play_time: Duration) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        SaveState { rom_hash, timestamp, play_time, thumbnail: Some(display.clone()), rom: None, snapshot }
+    }
+
+    pub fn write(&self, mut write: impl Write) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        let mut meta = Vec::new();
+        meta.extend_from_slice(&self.rom_hash.to_be_bytes());
+        meta.extend_from_slice(&self.timestamp.to_be_bytes());
+        meta.extend_from_slice(&(self.play_time.as_millis() as u64).to_be_bytes());
+        write_chunk(&mut bytes, b"META", &meta);
+        if let Some(screen) = &self.thumbnail {
+            let mut thumbnail = Vec::new();
+            thumbnail.extend_from_slice(&(screen.width() as u16).to_be_bytes());
+            thumbnail.extend_from_slice(&(screen.height() as u16).to_be_bytes());
+            thumbnail.extend(pack_screen(screen));
+            write_chunk(&mut bytes, b"THMB", &thumbnail);
+        }
+        let rng = bincode::serialize(&self.snapshot.rng).map_err(Error::other)?;
+        write_chunk(&mut bytes, b"RNG ", &rng);
+        if let Some(rom) = &self.rom {
+            write_chunk(&mut bytes, b"ROM ", rom);
+        }
+        bytes.extend_from_slice(&self.snapshot.bytes);
+        write.write_all(&bytes)
+    }
+
+    /// Reads a state written by any format version.
+    /// Only `META` is required; the core skips what it doesn't understand when restoring.
+    /// An embedded ROM that doesn't match the ROM hash is an error, since it can't be what the state was made with.
+    pub fn read(mut read: impl Read) -> Result<Self, Error> {
+        let mut bytes = Vec::new();
+        read.read_to_end(&mut bytes)?;
+        if bytes.len() < 6 || &bytes[0..4] != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a save state"));
+        }
+        let version = u16::from_be_bytes([bytes[4], bytes[5]]);
+        if version > FORMAT_VERSION {
+            log::warn!("Save state is format version {}, newer than {}; some of it may be ignored", version, FORMAT_VERSION);
+        }
+        let mut meta = None;
+        let mut thumbnail = None;
+        let mut rng = None;
+        let mut rom = None;
+        let mut snapshot_bytes = Vec::new();
+        for (tag, data) in read_chunks(&bytes[6..]) {
+            match &tag {
+                b"META" if data.len() >= 24 => {
+                    let field = |i: usize| u64::from_be_bytes(data[i * 8..i * 8 + 8].try_into().unwrap());
+                    meta = Some((field(0), field(1), Duration::from_millis(field(2))));
+                },
+                b"THMB" if data.len() >= 4 => {
+                    let width = u16::from_be_bytes([data[0], data[1]]) as usize;
+                    let height = u16::from_be_bytes([data[2], data[3]]) as usize;
+                    if Screen::valid_size(width, height) && data.len() - 4 == width * height / 8 {
+                        thumbnail = Some(unpack_screen(&data[4..], width, height));
+                    }
+                },
+                b"RNG " => rng = bincode::deserialize(data).ok(),
+                b"ROM " => rom = Some(data.to_vec()),
+                b"META" | b"THMB" => log::warn!("Skipping malformed {} chunk", String::from_utf8_lossy(&tag)),
+                _ => write_chunk(&mut snapshot_bytes, &tag, data),
+            }
+        }
+        let (rom_hash, timestamp, play_time) = meta
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Save state has no metadata"))?;
+        if rom.as_deref().is_some_and(|rom| fnv1a(rom) != rom_hash) {
+            return Err(Error::new(ErrorKind::InvalidData, "Save state's ROM doesn't match its hash"));
+        }
+        let rng = rng.unwrap_or_else(|| {
+            log::warn!("Save state has no usable rng state, random numbers won't match");
+            Xoroshiro64StarStar::from_entropy()
+        });
+        Ok(SaveState { rom_hash, timestamp, play_time, thumbnail, rom, snapshot: Snapshot { bytes: snapshot_bytes, rng } })
+    }
+
+    /// Checks the state was made with `rom`, e.g. before loading one someone else shared
+    pub fn check_rom(&self, rom: &[u8]) -> Result<(), String> {
+        let hash = fnv1a(rom);
+        if hash == self.rom_hash {
+            Ok(())
+        } else {
+            Err(format!("The state was made with a different ROM (hash {:016x}, not {:016x})", self.rom_hash, hash))
+        }
+    }
+
+    /// One-line summary for the slot picker, e.g. `2021-11-02 18:04 UTC, played 1:02:09`
+    pub fn describe(&self) -> String {
+        let secs = self.play_time.as_secs();
+        format!("{}, played {}:{:02}:{:02}", format_timestamp(self.timestamp), secs / 3600, secs / 60 % 60, secs % 60)
+    }
+}
+
+/// Save states live next to the ROM, as `<rom>.ss1` through `<rom>.ss9`
+pub fn slot_path(rom_path: &str, slot: u8) -> PathBuf {
+    assert!((1..=SLOTS).contains(&slot));
+    PathBuf::from(format!("{}.ss{}", rom_path, slot))
+}
+
+pub fn format_timestamp(timestamp: u64) -> String {
+    // Days since the epoch to a civil date, from Howard Hinnant's date algorithms
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    let secs_of_day = timestamp % 86400;
+    format!("{}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, secs_of_day / 3600, secs_of_day / 60 % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::bits::fnv1a;
+    use crate::chip8::{Chip8, Instruction, SCREEN_HEIGHT, SCREEN_WIDTH};
+    use crate::platform::Platform;
+    use crate::snapshot::write_chunk;
+    use crate::testing::Machine;
+    use super::{format_timestamp, SaveState};
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00 UTC");
+        assert_eq!(format_timestamp(1_635_876_240), "2021-11-02 18:04 UTC");
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut chip8 = Chip8::new(Platform::Schip);
+        chip8.execute(Instruction::Hires, [false; 16]);
+        chip8.execute(Instruction::Draw { x_r: 0, y_r: 0, height: 5 }, [false; 16]);
+        let state = SaveState::new(chip8.snapshot(), &chip8.display, 0xdead, Duration::from_secs(3729));
+        let mut file = Vec::new();
+        state.write(&mut file).unwrap();
+        let read = SaveState::read(&file[..]).unwrap();
+        assert_eq!(read.rom_hash, 0xdead);
+        assert_eq!(read.timestamp, state.timestamp);
+        assert_eq!(read.play_time, state.play_time);
+        assert_eq!(read.thumbnail, state.thumbnail);
+        assert_eq!(read.snapshot.bytes, state.snapshot.bytes);
+        assert!(read.describe().ends_with("played 1:02:09"));
+        assert!(SaveState::read(&file[1..]).is_err());
+    }
+
+    #[test]
+    fn restores_random_numbers() {
+        let program = [
+            Instruction::Random { register: 0, value: 0xff },
+            Instruction::Random { register: 1, value: 0xff },
+            Instruction::Jump { dest: 0x200 },
+        ];
+        let mut machine = Machine::from_instructions(&program);
+        machine.run(30);
+        let state = SaveState::new(machine.chip8.snapshot(), &machine.chip8.display, 0, Duration::ZERO);
+        let mut file = Vec::new();
+        state.write(&mut file).unwrap();
+        let mut restored = Machine::from_instructions(&program);
+        restored.chip8.restore(&SaveState::read(&file[..]).unwrap().snapshot);
+        for _ in 0..30 {
+            machine.step();
+            restored.step();
+            assert_eq!(machine.chip8.registers, restored.chip8.registers);
+        }
+    }
+
+    #[test]
+    fn embeds_the_rom() {
+        let rom = [0x60, 0x2a, 0x12, 0x02];
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.read_program(&rom[..]).unwrap();
+        let mut state = SaveState::new(chip8.snapshot(), &chip8.display, fnv1a(&rom), Duration::ZERO);
+        state.rom = Some(rom.to_vec());
+        let mut file = Vec::new();
+        state.write(&mut file).unwrap();
+        let read = SaveState::read(&file[..]).unwrap();
+        assert_eq!(read.rom.as_deref(), Some(&rom[..]));
+        assert!(read.check_rom(&rom).is_ok());
+        assert!(read.check_rom(&[0x12, 0x00]).is_err());
+
+        // A ROM changed after the state was made doesn't load
+        state.rom = Some(vec![0x12, 0x00]);
+        let mut file = Vec::new();
+        state.write(&mut file).unwrap();
+        assert!(SaveState::read(&file[..]).is_err());
+    }
+
+    #[test]
+    fn tolerates_unknown_and_missing_chunks() {
+        let mut chip8 = Chip8::new(Platform::default());
+        chip8.execute(Instruction::SetRegister { register: 2, value: 42 }, [false; 16]);
+        let state = SaveState::new(chip8.snapshot(), &chip8.display, 7, Duration::ZERO);
+        let mut file = Vec::new();
+        state.write(&mut file).unwrap();
+        // Pretend a future version added a chunk, and drop the thumbnail
+        file[5] = 99;
+        write_chunk(&mut file, b"XTRA", &[1, 2, 3]);
+        let thumbnail_start = 6 + 8 + 24;
+        let thumbnail_end = thumbnail_start + 8 + 4 + SCREEN_WIDTH * SCREEN_HEIGHT / 8;
+        file.drain(thumbnail_start..thumbnail_end);
+
+        let read = SaveState::read(&file[..]).unwrap();
+        assert_eq!(read.rom_hash, 7);
+        assert!(read.thumbnail.is_none());
+        let mut restored = Chip8::new(Platform::default());
+        restored.restore(&read.snapshot);
+        assert_eq!(restored.registers[2].0, 42);
+    }
+}
@@ -0,0 +1,306 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::asm::parse_address;
+use crate::chip8::Chip8;
+use crate::savestate::format_timestamp;
+
+/// Sessions the leaderboard keeps, best first
+pub const LEADERBOARD_SIZE: usize = 10;
+/// Digits a BCD score has unless it says, as FX33 stores a byte
+const DEFAULT_BCD_DIGITS: usize = 3;
+
+/// Where a ROM says its score is kept, next to it like its look
+pub fn score_path(rom_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.score", rom_path))
+}
+
+/// The best scores seen playing a ROM, kept next to it
+pub fn leaderboard_path(rom_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.scores", rom_path))
+}
+
+/// How a score is laid out in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreFormat {
+    Byte,
+    /// Two bytes, high byte first
+    Word,
+    /// A decimal digit a byte, most significant first, as FX33 stores them
+    Bcd(usize),
+}
+
+impl ScoreFormat {
+    fn len(self) -> usize {
+        match self {
+            ScoreFormat::Byte => 1,
+            ScoreFormat::Word => 2,
+            ScoreFormat::Bcd(digits) => digits,
+        }
+    }
+}
+
+/// `byte`, `word`, or `bcd` with the number of digits, e.g. `bcd 4`
+impl FromStr for ScoreFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            ["byte"] => Ok(ScoreFormat::Byte),
+            ["word"] => Ok(ScoreFormat::Word),
+            ["bcd"] => Ok(ScoreFormat::Bcd(DEFAULT_BCD_DIGITS)),
+            ["bcd", digits] => digits.parse().ok()
+                .filter(|digits| (1..=9).contains(digits))
+                .map(ScoreFormat::Bcd)
+                .ok_or(format!("Bad number of digits {}, expected 1 to 9", digits)),
+            _ => Err(format!("Unknown score format {:?}, expected byte, word or bcd <digits>", s)),
+        }
+    }
+}
+
+impl fmt::Display for ScoreFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScoreFormat::Byte => write!(f, "byte"),
+            ScoreFormat::Word => write!(f, "word"),
+            ScoreFormat::Bcd(digits) => write!(f, "bcd {}", digits),
+        }
+    }
+}
+
+/// Where in memory a ROM keeps its score, from `<rom>.score`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScoreSpec {
+    pub address: usize,
+    pub format: ScoreFormat,
+}
+
+impl ScoreSpec {
+    /// `address = 0x2f0` and `format = bcd 3` lines, with the format `byte` if left out
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut address = None;
+        let mut format = ScoreFormat::Byte;
+        for line in text.lines().map(|line| line.split('#').next().unwrap().trim()).filter(|line| !line.is_empty()) {
+            match line.split_once('=').map(|(name, value)| (name.trim(), value.trim())) {
+                Some(("address", value)) => address = Some(parse_address(value)?),
+                Some(("format", value)) => format = value.parse()?,
+                _ => return Err(format!("Unexpected line {:?}, expected address = ADDRESS or format = FORMAT", line)),
+            }
+        }
+        Ok(ScoreSpec { address: address.ok_or("No score address given")?, format })
+    }
+
+    /// The ROM's score spec, if it has one that makes sense
+    pub fn load(rom_path: &str) -> Option<Self> {
+        let path = score_path(rom_path);
+        match std::fs::read_to_string(&path).map(|text| ScoreSpec::parse(&text)) {
+            Ok(Ok(spec)) => Some(spec),
+            Ok(Err(e)) => {
+                log::warn!("Ignoring the score spec in {}: {}", path.display(), e);
+                None
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                log::warn!("Couldn't read the score spec {}: {}", path.display(), e);
+                None
+            },
+        }
+    }
+
+    /// The score as the program has it now, or `None` if it's not a score yet, like BCD digits over 9
+    pub fn read(&self, chip8: &Chip8) -> Option<u32> {
+        let bytes = chip8.memory.get(self.address..self.address + self.format.len())?;
+        match self.format {
+            ScoreFormat::Byte => Some(bytes[0] as u32),
+            ScoreFormat::Word => Some(u16::from_be_bytes([bytes[0], bytes[1]]) as u32),
+            ScoreFormat::Bcd(_) => bytes.iter().try_fold(0, |score, &digit| (digit <= 9).then_some(score * 10 + digit as u32)),
+        }
+    }
+}
+
+/// A session's best score, and when the session started, in seconds since the Unix epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub score: u32,
+    pub timestamp: u64,
+}
+
+/// The best sessions playing a ROM, best first, with the earlier of two equal scores ahead
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Leaderboard {
+    pub entries: Vec<Entry>,
+}
+
+impl Leaderboard {
+    /// `score timestamp` lines. Bad lines are skipped with a warning.
+    pub fn parse(text: &str) -> Self {
+        let mut leaderboard = Leaderboard::default();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let entry = line.split_once(' ').and_then(|(score, timestamp)| {
+                Some(Entry { score: score.parse().ok()?, timestamp: timestamp.trim().parse().ok()? })
+            });
+            match entry {
+                Some(entry) => leaderboard.record(entry),
+                None => log::warn!("Skipping leaderboard line {:?}, expected SCORE TIMESTAMP", line),
+            }
+        }
+        leaderboard
+    }
+
+    pub fn load(rom_path: &str) -> Self {
+        match std::fs::read_to_string(leaderboard_path(rom_path)) {
+            Ok(text) => Leaderboard::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Leaderboard::default(),
+            Err(e) => {
+                log::warn!("Couldn't read the leaderboard for {}: {}", rom_path, e);
+                Leaderboard::default()
+            },
+        }
+    }
+
+    pub fn save(&self, rom_path: &str) -> std::io::Result<()> {
+        let lines: String = self.entries.iter().map(|entry| format!("{} {}\n", entry.score, entry.timestamp)).collect();
+        std::fs::write(leaderboard_path(rom_path), lines)
+    }
+
+    pub fn best(&self) -> Option<u32> {
+        self.entries.first().map(|entry| entry.score)
+    }
+
+    /// Puts a session's score in its place, replacing what the same session had before, and drops
+    /// whatever falls off the end
+    pub fn record(&mut self, entry: Entry) {
+        self.entries.retain(|other| other.timestamp != entry.timestamp);
+        let place = self.entries.partition_point(|other| {
+            other.score > entry.score || (other.score == entry.score && other.timestamp <= entry.timestamp)
+        });
+        self.entries.insert(place, entry);
+        self.entries.truncate(LEADERBOARD_SIZE);
+    }
+}
+
+/// Ranked lines like `1. 1250  2024-03-01 18:04 UTC`, for reading or sharing
+impl fmt::Display for Leaderboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.entries.iter().map(|entry| entry.score.to_string().len()).max().unwrap_or(0);
+        for (rank, entry) in self.entries.iter().enumerate() {
+            writeln!(f, "{:>2}. {:>width$}  {}", rank + 1, entry.score, format_timestamp(entry.timestamp), width = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a change in the score came to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// The best this session so far, which is on the leaderboard now
+    Improved,
+    /// Better than the best session before, the first time this session it is
+    NewBest(u32),
+}
+
+/// Watches a ROM's score while it's played, keeping the session's best on the leaderboard
+pub struct ScoreTracker {
+    pub spec: ScoreSpec,
+    pub leaderboard: Leaderboard,
+    /// When the session started, which is how its entry is told apart from the rest
+    started: u64,
+    session_best: Option<u32>,
+    /// The best from before this session, until it's been beaten
+    to_beat: Option<u32>,
+}
+
+impl ScoreTracker {
+    pub fn new(spec: ScoreSpec, leaderboard: Leaderboard) -> Self {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let to_beat = leaderboard.best();
+        ScoreTracker { spec, leaderboard, started, session_best: None, to_beat }
+    }
+
+    /// Tracks the ROM's score if it has a spec, carrying on its leaderboard
+    pub fn load(rom_path: &str) -> Option<Self> {
+        Some(ScoreTracker::new(ScoreSpec::load(rom_path)?, Leaderboard::load(rom_path)))
+    }
+
+    /// Call as the program runs. Says if the score is the best this session, so the leaderboard
+    /// wants saving. Scores of 0 don't count, since that's where every game starts.
+    pub fn observe(&mut self, chip8: &Chip8) -> Option<Progress> {
+        let score = self.spec.read(chip8).filter(|&score| score > 0)?;
+        if self.session_best.is_some_and(|best| best >= score) {
+            return None;
+        }
+        self.session_best = Some(score);
+        self.leaderboard.record(Entry { score, timestamp: self.started });
+        if self.to_beat.is_some_and(|best| score > best) {
+            self.to_beat = None;
+            return Some(Progress::NewBest(score));
+        }
+        Some(Progress::Improved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::testing::Machine;
+    use super::{Entry, Leaderboard, Progress, ScoreFormat, ScoreSpec, ScoreTracker, LEADERBOARD_SIZE};
+
+    #[test]
+    fn reads_scores_from_memory() {
+        assert_eq!(ScoreSpec::parse("address = 0x300\nformat = bcd 4  # thousands first\n"), Ok(ScoreSpec { address: 0x300, format: ScoreFormat::Bcd(4) }));
+        assert_eq!(ScoreSpec::parse("address = 0x300").unwrap().format, ScoreFormat::Byte);
+        assert_eq!("bcd".parse(), Ok(ScoreFormat::Bcd(3)));
+        assert!("bcd 12".parse::<ScoreFormat>().is_err());
+        assert!(ScoreSpec::parse("format = word").is_err());
+        assert!(ScoreSpec::parse("address = 0x300\nhighscore = 1").is_err());
+
+        // 142 stored as BCD by FX33
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 142 },
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::RegToDecimal { register: 0 },
+        ]);
+        let bcd = ScoreSpec { address: 0x300, format: ScoreFormat::Bcd(3) };
+        assert_eq!(bcd.read(&machine.chip8), Some(0));
+        machine.run(3);
+        assert_eq!(bcd.read(&machine.chip8), Some(142));
+        assert_eq!(ScoreSpec { format: ScoreFormat::Word, ..bcd }.read(&machine.chip8), Some(0x0104));
+        machine.chip8.memory[0x301] = 0xff;
+        assert_eq!(bcd.read(&machine.chip8), None);
+        assert_eq!(ScoreSpec { address: machine.chip8.memory.len() - 1, ..bcd }.read(&machine.chip8), None);
+    }
+
+    #[test]
+    fn keeps_the_best_sessions() {
+        let mut leaderboard = Leaderboard::default();
+        for (score, timestamp) in [(50, 1), (70, 2), (50, 3), (60, 2)] {
+            leaderboard.record(Entry { score, timestamp });
+        }
+        // Session 2 went back down, which a tracker never records, but it only has the one place
+        assert_eq!(leaderboard.entries, [Entry { score: 60, timestamp: 2 }, Entry { score: 50, timestamp: 1 }, Entry { score: 50, timestamp: 3 }]);
+        let saved: String = leaderboard.entries.iter().map(|entry| format!("{} {}\n", entry.score, entry.timestamp)).collect();
+        assert_eq!(Leaderboard::parse(&(saved + "lots\n")), leaderboard);
+        assert_eq!(leaderboard.to_string().lines().next(), Some(" 1. 60  1970-01-01 00:00 UTC"));
+        for timestamp in 10..30 {
+            leaderboard.record(Entry { score: 1, timestamp });
+        }
+        assert_eq!(leaderboard.entries.len(), LEADERBOARD_SIZE);
+        assert_eq!(leaderboard.best(), Some(60));
+
+        let mut machine = Machine::from_instructions(&[]);
+        let spec = ScoreSpec { address: 0x300, format: ScoreFormat::Byte };
+        let mut tracker = ScoreTracker::new(spec, leaderboard);
+        assert_eq!(tracker.observe(&machine.chip8), None);
+        machine.chip8.memory[0x300] = 40;
+        assert_eq!(tracker.observe(&machine.chip8), Some(Progress::Improved));
+        assert_eq!(tracker.observe(&machine.chip8), None);
+        machine.chip8.memory[0x300] = 61;
+        assert_eq!(tracker.observe(&machine.chip8), Some(Progress::NewBest(61)));
+        machine.chip8.memory[0x300] = 62;
+        assert_eq!(tracker.observe(&machine.chip8), Some(Progress::Improved));
+        machine.chip8.memory[0x300] = 0;
+        assert_eq!(tracker.observe(&machine.chip8), None);
+        assert_eq!((tracker.leaderboard.best(), tracker.leaderboard.entries[1].score), (Some(62), 60));
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use std::time::Duration;
+use crate::chip8::Chip8;
+use crate::display::{DisplaySink, RgbaBuffer};
+use crate::headless::Stop;
+
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// A line of a script, e.g. `step 100` or `key 5 down`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Run this many instructions, or until the program halts
+    Step(u32),
+    /// Run this many 60ths of a second of emulated time, or until the program halts
+    Frames(u32),
+    /// Hold or let go of a key, 0 to F, for the instructions after
+    Key { key: usize, pressed: bool },
+    /// Print the cycle count, PC, index, timers and registers
+    Dump,
+    /// Print the screen's hash, the same as `--hash` would
+    Hash,
+    /// Print the screen's size, then its rows with `Q` for lit pixels like the debugger
+    Screen,
+    Quit,
+}
+
+/// Words separated by spaces, with the count for `step` and `frames` optional (default 1)
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let count = |word: Option<&str>| word.map_or(Ok(1), |n| n.parse().map_err(|_| format!("Bad count {}", n)));
+        let words: Vec<&str> = s.split_whitespace().collect();
+        match words[..] {
+            ["step"] | ["step", _] => Ok(Command::Step(count(words.get(1).copied())?)),
+            ["frames"] | ["frames", _] => Ok(Command::Frames(count(words.get(1).copied())?)),
+            ["key", key, state] => {
+                let key = usize::from_str_radix(key, 16).ok().filter(|&key| key < 16).ok_or(format!("Bad key {}, expected 0 to f", key))?;
+                let pressed = match state {
+                    "down" => true,
+                    "up" => false,
+                    _ => return Err(format!("Expected down or up, not {}", state)),
+                };
+                Ok(Command::Key { key, pressed })
+            },
+            ["dump"] => Ok(Command::Dump),
+            ["hash"] => Ok(Command::Hash),
+            ["screen"] => Ok(Command::Screen),
+            ["quit"] => Ok(Command::Quit),
+            _ => Err(format!("Unknown command {:?}, expected step, frames, key, dump, hash, screen or quit", s)),
+        }
+    }
+}
+
+/// Runs `chip8` a command at a time from `input`, on emulated time like `headless::run`, so another
+/// program can drive it through a pipe. Every command but `quit` answers with a line on `output`:
+/// `ok` (or `halted`, if the program halted first) for the ones that run or press keys, `error: ...`
+/// for ones that can't be understood, and what was asked for otherwise. Blank lines and
+/// anything after `#` are ignored. Stops at `quit` or the end of the input, presenting the
+/// final screen to `buffer`.
+pub fn run(
+    chip8: &mut Chip8,
+    clock_speed: u32,
+    buffer: &mut RgbaBuffer,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> std::io::Result<Stop> {
+    let clock_gap = Duration::from_secs(1) / clock_speed;
+    let mut elapsed = Duration::ZERO;
+    let mut keys = [false; 16];
+    for line in input.lines() {
+        let line = line?;
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.parse() {
+            Ok(Command::Step(cycles)) => {
+                let end = elapsed.saturating_add(clock_gap.saturating_mul(cycles));
+                let reply = run_until(chip8, clock_gap, keys, &mut elapsed, end);
+                writeln!(output, "{}", reply)?;
+            },
+            Ok(Command::Frames(frames)) => {
+                let frame = (elapsed.as_nanos() / FRAME.as_nanos()) as u32;
+                let end = FRAME.saturating_mul(frame.saturating_add(frames));
+                let reply = run_until(chip8, clock_gap, keys, &mut elapsed, end);
+                writeln!(output, "{}", reply)?;
+            },
+            Ok(Command::Key { key, pressed }) => {
+                keys[key] = pressed;
+                writeln!(output, "ok")?;
+            },
+            Ok(Command::Dump) => {
+                let registers: Vec<String> = chip8.registers.iter().map(|reg| format!("{:02x}", reg.0)).collect();
+                writeln!(
+                    output,
+                    "cycles={} pc={:#05x} i={:#05x} dt={} st={} v={}",
+                    chip8.cycles, chip8.pc, chip8.index_register.0, chip8.delay_timer, chip8.sound_timer, registers.join(","),
+                )?;
+            },
+            Ok(Command::Hash) => {
+                buffer.present(&chip8.display, chip8.timestamp());
+                writeln!(output, "{:016x}", buffer.hash())?;
+            },
+            Ok(Command::Screen) => {
+                let (width, height) = chip8.display.size();
+                writeln!(output, "{}x{}", width, height)?;
+                for row in chip8.show_display() {
+                    writeln!(output, "{}", row)?;
+                }
+            },
+            Ok(Command::Quit) => break,
+            Err(e) => writeln!(output, "error: {}", e)?,
+        }
+        output.flush()?;
+    }
+    buffer.present(&chip8.display, chip8.timestamp());
+    Ok(if chip8.halted() { Stop::Halted } else { Stop::CyclesReached })
+}
+
+/// Runs cycles until `elapsed` reaches `end`, answering `halted` if the program halts first
+fn run_until(chip8: &mut Chip8, clock_gap: Duration, keys: [bool; 16], elapsed: &mut Duration, end: Duration) -> &'static str {
+    while *elapsed + clock_gap <= end {
+        if chip8.halted() {
+            return "halted";
+        }
+        *elapsed += clock_gap;
+        chip8.cycle(keys, clock_gap);
+    }
+    "ok"
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::display::RgbaBuffer;
+    use crate::headless::Stop;
+    use crate::testing::Machine;
+    use super::{run, Command};
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!("step".parse(), Ok(Command::Step(1)));
+        assert_eq!("step 100".parse(), Ok(Command::Step(100)));
+        assert_eq!(" frames  3 ".parse(), Ok(Command::Frames(3)));
+        assert_eq!("key a down".parse(), Ok(Command::Key { key: 0xa, pressed: true }));
+        assert_eq!("key 5 up".parse(), Ok(Command::Key { key: 5, pressed: false }));
+        for bad in ["", "step -1", "key 10 down", "key 5 held", "dump 2", "jump"] {
+            assert!(bad.parse::<Command>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn answers_each_command() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 5 },
+            Instruction::SkipPressed { register: 0 },
+            Instruction::Jump { dest: 0x202 },
+            Instruction::FontChar { register: 0 },
+            Instruction::Draw { x_r: 1, y_r: 1, height: 5 },
+            Instruction::Jump { dest: 0x20a },
+        ]);
+        let script = "step 3 # waits for 5\nkey 5 down\n\nstep 2\ndump\nfly\nframes 10\nscreen\nhash\nquit\nstep\n";
+        let mut output = Vec::new();
+        let mut buffer = RgbaBuffer::new();
+        let stop = run(&mut machine.chip8, 500, &mut buffer, script.as_bytes(), &mut output).unwrap();
+        assert_eq!(stop, Stop::Halted);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[..4], [
+            "ok",
+            "ok",
+            "ok",
+            "cycles=5 pc=0x208 i=0x019 dt=0 st=0 v=05,00,00,00,00,00,00,00,00,00,00,00,00,00,00,00",
+        ]);
+        assert!(lines[4].starts_with("error: Unknown command \"fly\""));
+        assert_eq!(lines[5..7], ["halted", "64x32"]);
+        assert_eq!(lines[7..9].iter().map(|row| row.trim_end()).collect::<Vec<_>>(), ["QQQQ", "Q"]);
+        assert_eq!(lines[7 + 32], format!("{:016x}", buffer.hash()));
+        assert_eq!(lines.len(), 7 + 32 + 1);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::audio::{parse_frequency, parse_volume, Buzzer};
+use crate::config::config_path;
+
+const SETTINGS_FILE: &str = "settings";
+
+/// Preferences for the windowed emulator as a whole, as opposed to a ROM's look or quirks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Settings {
+    /// Draw the IBM logo on the core before starting the ROM
+    pub boot_animation: bool,
+    /// Print an `ANNOUNCE:` line for each change of state, for screen readers
+    pub announce: bool,
+    /// `waveform`, `beep_frequency` and `volume`, which the command line overrides
+    pub buzzer: Buzzer,
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value {
+        "on" | "true" | "yes" => Some(true),
+        "off" | "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+impl Settings {
+    /// `setting = value` lines, with anything missing left at the default.
+    /// Bad lines are skipped with a warning.
+    pub fn parse(text: &str) -> Self {
+        let mut settings = Settings::default();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let applied = line.split_once('=').and_then(|(name, value)| {
+                let value = value.trim();
+                match name.trim() {
+                    "boot_animation" => settings.boot_animation = parse_switch(value)?,
+                    "announce" => settings.announce = parse_switch(value)?,
+                    "waveform" => settings.buzzer.waveform = Some(value.parse().ok()?),
+                    "beep_frequency" => settings.buzzer.frequency = Some(parse_frequency(value).ok()?),
+                    "volume" => settings.buzzer.volume = Some(parse_volume(value).ok()?),
+                    _ => return None,
+                }
+                Some(())
+            });
+            if applied.is_none() {
+                log::warn!("Skipping setting {:?}, expected a line like `boot_animation = on`", line);
+            }
+        }
+        settings
+    }
+
+    pub fn load() -> Self {
+        let path = config_path(SETTINGS_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Settings::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => {
+                log::warn!("Couldn't read {}: {}", path.display(), e);
+                Settings::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::{Buzzer, Waveform};
+    use super::Settings;
+
+    #[test]
+    fn parses_switches() {
+        assert_eq!(Settings::parse(""), Settings::default());
+        assert!(Settings::parse("# Show the IBM logo first\nboot_animation = on # for now\n").boot_animation);
+        assert!(!Settings::parse("boot_animation = on\nboot_animation=off").boot_animation);
+        assert!(!Settings::parse("boot_animation = maybe\nvolume = on").boot_animation);
+        assert_eq!(Settings::parse("announce = yes"), Settings { announce: true, ..Settings::default() });
+    }
+
+    #[test]
+    fn parses_the_buzzer() {
+        let settings = Settings::parse("waveform = sine\nbeep_frequency = 220\nvolume = 40%");
+        assert_eq!(settings.buzzer, Buzzer { waveform: Some(Waveform::Sine), frequency: Some(220), volume: Some(40) });
+        assert_eq!(Settings::parse("waveform = saw\nbeep_frequency = high\nvolume = 200"), Settings::default());
+    }
+}
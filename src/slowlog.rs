@@ -0,0 +1,100 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::asm::mnemonic;
+use crate::chip8::Chip8;
+use crate::decode::decode;
+
+/// An instruction that took longer than the threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowInstruction {
+    /// Instructions run before this one, the same count `--trace` prints
+    pub cycle: u64,
+    pub pc: usize,
+    /// Its two bytes, unless the PC was past the end of memory
+    pub raw: Option<u16>,
+    /// Real time, with everything done for it
+    pub took: Duration,
+}
+
+/// e.g. `cycle 1234 at 0x2a0 (DRW V0, V1, 15) took 3.20ms`
+impl fmt::Display for SlowInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cycle {} at {:#05x}", self.cycle, self.pc)?;
+        match self.raw.and_then(decode) {
+            Some(instruction) => {
+                let (name, operands) = mnemonic(&instruction);
+                write!(f, " ({})", format!("{} {}", name, operands.join(", ")).trim_end())?;
+            },
+            None => write!(f, " ({:04x})", self.raw.unwrap_or_default())?,
+        }
+        write!(f, " took {:.2}ms", self.took.as_secs_f64() * 1000.0)
+    }
+}
+
+/// Times each instruction in real time along with whatever runs for it, like tracing, breakpoints,
+/// the access log or rewind snapshots, and picks out the ones over `threshold`, to find what's
+/// slowing a run down
+#[derive(Debug)]
+pub struct SlowLog {
+    pub threshold: Duration,
+    /// The cycle count, PC, instruction and start of the instruction being timed
+    running: Option<(u64, usize, Option<u16>, Instant)>,
+    /// How many were over the threshold
+    pub found: u64,
+    pub slowest: Option<SlowInstruction>,
+}
+
+impl SlowLog {
+    pub fn new(threshold: Duration) -> Self {
+        SlowLog { threshold, running: None, found: 0, slowest: None }
+    }
+
+    /// Call before the instruction at the PC runs, and before anything done for it
+    pub fn start(&mut self, chip8: &Chip8, now: Instant) {
+        self.running = Some((chip8.cycles, chip8.pc, chip8.instruction_at(chip8.pc), now));
+    }
+
+    /// Call once the instruction and everything done for it has finished. Returns it if it was slow.
+    pub fn finish(&mut self, now: Instant) -> Option<SlowInstruction> {
+        let (cycle, pc, raw, started) = self.running.take()?;
+        let took = now.saturating_duration_since(started);
+        if took <= self.threshold {
+            return None;
+        }
+        let slow = SlowInstruction { cycle, pc, raw, took };
+        self.found += 1;
+        if self.slowest.is_none_or(|slowest| took > slowest.took) {
+            self.slowest = Some(slow);
+        }
+        Some(slow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::chip8::Instruction;
+    use crate::testing::Machine;
+    use super::SlowLog;
+
+    #[test]
+    fn logs_instructions_over_the_threshold() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 1 },
+            Instruction::Draw { x_r: 0, y_r: 0, height: 15 },
+        ]);
+        let mut log = SlowLog::new(Duration::from_millis(1));
+        let now = Instant::now();
+        assert_eq!(log.finish(now), None);
+        log.start(&machine.chip8, now);
+        machine.step();
+        assert_eq!(log.finish(now + Duration::from_micros(500)), None);
+        log.start(&machine.chip8, now);
+        machine.step();
+        let slow = log.finish(now + Duration::from_millis(3)).unwrap();
+        assert_eq!(slow.to_string(), "cycle 1 at 0x202 (DRW V0, V0, 15) took 3.00ms");
+        assert_eq!((log.found, log.slowest), (1, Some(slow)));
+        // Each start is timed once
+        assert_eq!(log.finish(now + Duration::from_millis(5)), None);
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+use rand_xoshiro::Xoroshiro64StarStar;
+
+/// A full copy of the emulator state at one point in time.
+/// `bytes` holds everything except the rng as a sequence of chunks, written by `Chip8::snapshot`.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub bytes: Vec<u8>,
+    pub rng: Xoroshiro64StarStar,
+}
+
+pub type ChunkTag = [u8; 4];
+
+/// Appends a chunk: 4 byte tag, big-endian u32 length, then the data
+pub fn write_chunk(out: &mut Vec<u8>, tag: &ChunkTag, data: &[u8]) {
+    out.extend_from_slice(tag);
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+/// Splits bytes into chunks, stopping at the first truncated one
+pub fn read_chunks(mut bytes: &[u8]) -> Vec<(ChunkTag, &[u8])> {
+    let mut chunks = Vec::new();
+    while bytes.len() >= 8 {
+        let tag = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let len = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        if bytes.len() - 8 < len {
+            log::warn!("Chunk {} is truncated", String::from_utf8_lossy(&tag));
+            break;
+        }
+        chunks.push((tag, &bytes[8..8 + len]));
+        bytes = &bytes[8 + len..];
+    }
+    chunks
+}
+
+const PAGE_SIZE: usize = 256;
+// Differences closer than this are merged into one run, since each run costs more than a few bytes
+const RUN_MERGE_GAP: usize = 8;
+
+enum Entry {
+    /// Full state split into pages, shared with the previous keyframe where unchanged
+    Key { pages: Vec<Rc<[u8]>>, len: usize, rng: Xoroshiro64StarStar },
+    /// Byte runs that differ from the previous entry
+    Delta { runs: Vec<(usize, Vec<u8>)>, len: usize, rng: Xoroshiro64StarStar },
+}
+
+/// Bounded history of snapshots for rewinding.
+/// Every `keyframe_interval`th snapshot is stored whole (with unchanged pages shared),
+/// the rest are stored as deltas against the snapshot before them.
+pub struct History {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    keyframe_interval: usize,
+    since_keyframe: usize,
+    last_key_pages: Vec<Rc<[u8]>>,
+    newest: Option<Vec<u8>>,
+}
+
+impl History {
+    pub fn new(capacity: usize, keyframe_interval: usize) -> Self {
+        assert!(capacity >= 1);
+        assert!(keyframe_interval >= 1);
+        History {
+            entries: VecDeque::new(),
+            capacity,
+            keyframe_interval,
+            since_keyframe: 0,
+            last_key_pages: Vec::new(),
+            newest: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn push(&mut self, snapshot: Snapshot) {
+        let entry = match &self.newest {
+            Some(prev) if self.since_keyframe < self.keyframe_interval => {
+                self.since_keyframe += 1;
+                Entry::Delta { runs: diff(prev, &snapshot.bytes), len: snapshot.bytes.len(), rng: snapshot.rng }
+            },
+            _ => {
+                self.since_keyframe = 1;
+                self.keyframe(&snapshot.bytes, snapshot.rng)
+            }
+        };
+        self.entries.push_back(entry);
+        self.newest = Some(snapshot.bytes);
+        while self.entries.len() > self.capacity {
+            self.drop_oldest();
+        }
+    }
+
+    /// Removes and returns the newest snapshot
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        let bytes = self.newest.take()?;
+        let rng = match self.entries.pop_back()? {
+            Entry::Key { rng, .. } => {
+                self.since_keyframe = self.keyframe_interval;
+                rng
+            },
+            Entry::Delta { rng, .. } => {
+                self.since_keyframe = self.since_keyframe.saturating_sub(1);
+                rng
+            },
+        };
+        if !self.entries.is_empty() {
+            self.newest = Some(self.reconstruct(self.entries.len() - 1));
+        }
+        Some(Snapshot { bytes, rng })
+    }
+
+    /// Rough number of bytes used by the stored history
+    pub fn memory_usage(&self) -> usize {
+        self.entries.iter().map(|entry| match entry {
+            Entry::Key { pages, .. } => pages.iter()
+                .map(|page| page.len() / Rc::strong_count(page) + std::mem::size_of::<Rc<[u8]>>())
+                .sum::<usize>(),
+            Entry::Delta { runs, .. } => runs.iter()
+                .map(|(_, run)| run.len() + std::mem::size_of::<(usize, Vec<u8>)>())
+                .sum(),
+        } + std::mem::size_of::<Entry>()).sum()
+    }
+
+    fn keyframe(&mut self, bytes: &[u8], rng: Xoroshiro64StarStar) -> Entry {
+        let pages = share_pages(bytes, &self.last_key_pages);
+        self.last_key_pages = pages.clone();
+        Entry::Key { pages, len: bytes.len(), rng }
+    }
+
+    fn drop_oldest(&mut self) {
+        // The entry after the oldest may be a delta against it, so promote that one to a keyframe first
+        if let Some(Entry::Delta { rng, .. }) = self.entries.get(1) {
+            let rng = rng.clone();
+            let bytes = self.reconstruct(1);
+            let pages = match &self.entries[0] {
+                Entry::Key { pages, .. } => share_pages(&bytes, pages),
+                Entry::Delta { .. } => unreachable!(),
+            };
+            self.entries[1] = Entry::Key { pages, len: bytes.len(), rng };
+        }
+        self.entries.pop_front();
+    }
+
+    fn reconstruct(&self, index: usize) -> Vec<u8> {
+        let key_index = (0..=index).rev()
+            .find(|&i| matches!(self.entries[i], Entry::Key { .. }))
+            .expect("History always starts with a keyframe");
+        let mut bytes = match &self.entries[key_index] {
+            Entry::Key { pages, len, .. } => {
+                let mut bytes: Vec<u8> = pages.iter().flat_map(|page| page.iter().copied()).collect();
+                bytes.truncate(*len);
+                bytes
+            },
+            Entry::Delta { .. } => unreachable!(),
+        };
+        for entry in self.entries.range(key_index + 1..=index) {
+            if let Entry::Delta { runs, len, .. } = entry {
+                apply(&mut bytes, runs, *len);
+            }
+        }
+        bytes
+    }
+}
+
+fn share_pages(bytes: &[u8], existing: &[Rc<[u8]>]) -> Vec<Rc<[u8]>> {
+    bytes.chunks(PAGE_SIZE)
+        .enumerate()
+        .map(|(i, chunk)| match existing.get(i) {
+            Some(page) if page[..] == *chunk => page.clone(),
+            _ => Rc::from(chunk),
+        })
+        .collect()
+}
+
+fn diff(prev: &[u8], next: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+    for (i, &byte) in next.iter().enumerate() {
+        if prev.get(i) == Some(&byte) {
+            continue;
+        }
+        match runs.last_mut() {
+            Some((start, run)) if i - (*start + run.len()) <= RUN_MERGE_GAP => {
+                let run_end = *start + run.len();
+                run.extend_from_slice(&next[run_end..=i]);
+            },
+            _ => runs.push((i, vec![byte])),
+        }
+    }
+    runs
+}
+
+fn apply(bytes: &mut Vec<u8>, runs: &[(usize, Vec<u8>)], len: usize) {
+    bytes.resize(len, 0);
+    for (start, run) in runs {
+        bytes[*start..*start + run.len()].copy_from_slice(run);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::{Chip8, Instruction};
+    use crate::platform::Platform;
+    use super::{read_chunks, write_chunk, History, Snapshot};
+
+    fn chip8_with_registers(value: u8) -> Chip8 {
+        let mut chip8 = Chip8::new(Platform::default());
+        for register in 0..16 {
+            chip8.execute(Instruction::SetRegister { register, value }, [false; 16]);
+        }
+        chip8
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let mut chip8 = chip8_with_registers(7);
+        chip8.execute(Instruction::Draw { x_r: 0, y_r: 0, height: 5 }, [false; 16]);
+        chip8.stack.push(0x234);
+        let snapshot = chip8.snapshot();
+        let mut restored = Chip8::new(Platform::default());
+        restored.restore(&snapshot);
+        assert_eq!(restored.snapshot().bytes, snapshot.bytes);
+        assert_eq!(restored.display, chip8.display);
+        assert_eq!(restored.stack, vec![0x234]);
+    }
+
+    #[test]
+    fn restores_platform_and_quirks() {
+        let mut chip8 = Chip8::new(Platform::Schip);
+        chip8.quirks.apply("no_clip_sprites").unwrap();
+        let snapshot = chip8.snapshot();
+        let mut restored = Chip8::new(Platform::Chip8);
+        restored.restore(&snapshot);
+        assert_eq!(restored.platform, Platform::Schip);
+        assert_eq!(restored.quirks, chip8.quirks);
+
+        // Older snapshots have neither, and leave the machine's as they are
+        let mut bytes = Vec::new();
+        for (tag, data) in read_chunks(&snapshot.bytes) {
+            if &tag == b"PLAT" || &tag == b"QRKS" {
+                continue;
+            }
+            write_chunk(&mut bytes, &tag, data);
+        }
+        let mut restored = Chip8::new(Platform::Chip8);
+        restored.quirks.apply("vf_reset").unwrap();
+        let quirks = restored.quirks.clone();
+        restored.restore(&Snapshot { bytes, rng: snapshot.rng });
+        assert_eq!(restored.platform, Platform::Chip8);
+        assert_eq!(restored.quirks, quirks);
+    }
+
+    #[test]
+    fn pops_newest_first() {
+        let mut history = History::new(100, 4);
+        for value in 0..10 {
+            history.push(chip8_with_registers(value).snapshot());
+        }
+        let mut chip8 = Chip8::new(Platform::default());
+        for value in (0..10).rev() {
+            chip8.restore(&history.pop().unwrap());
+            assert_eq!(chip8.registers[3].0, value);
+        }
+        assert!(history.pop().is_none());
+    }
+
+    #[test]
+    fn evicts_oldest() {
+        let mut history = History::new(5, 3);
+        for value in 0..12 {
+            history.push(chip8_with_registers(value).snapshot());
+        }
+        assert_eq!(history.len(), 5);
+        let mut chip8 = Chip8::new(Platform::default());
+        for value in (7..12).rev() {
+            chip8.restore(&history.pop().unwrap());
+            assert_eq!(chip8.registers[0].0, value);
+        }
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn unchanged_state_is_cheap() {
+        let mut history = History::new(10_000, 60);
+        let chip8 = chip8_with_registers(1);
+        for _ in 0..10_000 {
+            history.push(chip8.snapshot());
+        }
+        assert!(history.memory_usage() < 1_000_000);
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::time::Duration;
+use rand_core::{RngCore, SeedableRng};
+use rand_xoshiro::Xoroshiro64StarStar;
+use crate::chip8::{Chip8, Instruction};
+use crate::replay::Recording;
+
+/// SCHIP's stack is 16 deep and the VIP's 12, so anything deeper is runaway recursion
+pub const MAX_STACK_DEPTH: usize = 16;
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// A seed for when none was given
+pub fn random_seed() -> u64 {
+    Xoroshiro64StarStar::from_entropy().next_u64()
+}
+
+/// Presses keys the way someone playing might: one at a time, held for a few frames with gaps
+/// in between, mostly ones the program has been seen checking with EX9E/EXA1
+pub struct KeyFuzzer {
+    rng: Xoroshiro64StarStar,
+    polled: Vec<usize>,
+    keys: [bool; 16],
+    /// Emulated time the current press or gap ends
+    until: Duration,
+}
+
+impl KeyFuzzer {
+    pub fn new(seed: u64) -> Self {
+        KeyFuzzer { rng: Xoroshiro64StarStar::seed_from_u64(seed), polled: Vec::new(), keys: [false; 16], until: Duration::ZERO }
+    }
+
+    /// Call before each cycle, to notice which keys the program cares about
+    pub fn observe(&mut self, chip8: &Chip8) {
+        let checked = chip8.instruction_at(chip8.pc).and_then(|raw| chip8.platform.decode(raw));
+        if let Some(Instruction::SkipPressed { register } | Instruction::SkipNotPressed { register }) = checked {
+            let key = (chip8.registers[register as usize].0 & 0xf) as usize;
+            if !self.polled.contains(&key) {
+                self.polled.push(key);
+            }
+        }
+    }
+
+    fn frames(&mut self, min: u32, max: u32) -> Duration {
+        FRAME * (min + self.rng.next_u32() % (max - min + 1))
+    }
+
+    /// The keys to hold `elapsed` into the run
+    pub fn keys(&mut self, elapsed: Duration) -> [bool; 16] {
+        if elapsed < self.until {
+            return self.keys;
+        }
+        if self.keys.contains(&true) {
+            self.keys = [false; 16];
+            self.until = elapsed + self.frames(1, 20);
+        } else {
+            // Now and then try a key nothing has checked yet, e.g. one only FX0A waits for
+            let key = match self.rng.next_u32() % 5 {
+                0 => self.rng.next_u32() as usize % 16,
+                _ if self.polled.is_empty() => self.rng.next_u32() as usize % 16,
+                _ => self.polled[self.rng.next_u32() as usize % self.polled.len()],
+            };
+            self.keys[key] = true;
+            self.until = elapsed + self.frames(2, 30);
+        }
+        self.keys
+    }
+}
+
+/// Something that should never happen, however the program is played
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The core panicked, with its message
+    Crashed(String),
+    PcOutOfBounds(usize),
+    StackTooDeep(usize),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Crashed(message) => write!(f, "the core crashed: {}", message),
+            Violation::PcOutOfBounds(pc) => write!(f, "the PC left the program's memory ({:#x})", pc),
+            Violation::StackTooDeep(depth) => write!(f, "the stack is {} calls deep, more than {}", depth, MAX_STACK_DEPTH),
+        }
+    }
+}
+
+/// How a soak went
+#[derive(Debug)]
+pub struct Soak {
+    pub cycles: u64,
+    pub elapsed: Duration,
+    /// The program jumped to itself or exited, so nothing could change after
+    pub halted: bool,
+    pub violation: Option<Violation>,
+}
+
+/// Plays `chip8` with a `KeyFuzzer` for `duration` of emulated time, checking after every cycle
+/// that the PC is in bounds and the stack isn't too deep, and stopping at the first problem.
+/// Every cycle goes into `recording`.
+pub fn soak(chip8: &mut Chip8, duration: Duration, clock_speed: u32, seed: u64, recording: &mut Recording) -> Soak {
+    let clock_gap = Duration::from_secs(1) / clock_speed;
+    let mut fuzzer = KeyFuzzer::new(seed);
+    let mut elapsed = Duration::ZERO;
+    let mut cycles = 0;
+    let mut halted = false;
+    // The panic has already been printed with the call stack
+    let run = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        while elapsed < duration {
+            if chip8.halted() {
+                halted = true;
+                return None;
+            }
+            if !chip8.pc_inbounds() {
+                return Some(Violation::PcOutOfBounds(chip8.pc));
+            }
+            fuzzer.observe(chip8);
+            let keys = fuzzer.keys(elapsed);
+            recording.record_cycle(keys, clock_gap);
+            elapsed += clock_gap;
+            chip8.cycle(keys, clock_gap);
+            cycles += 1;
+            if chip8.stack.len() > MAX_STACK_DEPTH {
+                return Some(Violation::StackTooDeep(chip8.stack.len()));
+            }
+        }
+        None
+    }));
+    let violation = run.unwrap_or_else(|panic| {
+        let message = panic.downcast_ref::<String>().cloned()
+            .or_else(|| panic.downcast_ref::<&str>().map(|message| message.to_string()))
+            .unwrap_or_default();
+        Some(Violation::Crashed(message.lines().next().unwrap_or_default().to_string()))
+    });
+    Soak { cycles, elapsed, halted, violation }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chip8::Instruction;
+    use crate::replay::Recording;
+    use crate::testing::Machine;
+    use super::{soak, KeyFuzzer, Violation};
+
+    #[test]
+    fn favours_keys_the_program_checks() {
+        let machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 7 },
+            Instruction::SkipPressed { register: 0 },
+        ]);
+        let mut chip8 = machine.chip8;
+        chip8.pc = 0x202;
+        chip8.registers[0].0 = 7;
+        let mut fuzzer = KeyFuzzer::new(1);
+        fuzzer.observe(&chip8);
+        let presses: Vec<usize> = (0..600)
+            .filter_map(|frame| fuzzer.keys(Duration::from_millis(frame * 17)).iter().position(|&held| held))
+            .collect();
+        let sevens = presses.iter().filter(|&&key| key == 7).count();
+        assert!(sevens > presses.len() / 2, "{} of {}", sevens, presses.len());
+    }
+
+    #[test]
+    fn stops_at_runaway_recursion() {
+        let mut machine = Machine::from_instructions(&[Instruction::CallSubroutine { dest: 0x200 }]);
+        let mut recording = Recording::start(&machine.chip8, [false; 16], Duration::from_millis(2));
+        let result = soak(&mut machine.chip8, Duration::from_secs(60), 500, 0, &mut recording);
+        assert_eq!(result.violation, Some(Violation::StackTooDeep(17)));
+        assert_eq!(result.cycles, 17);
+        assert_eq!(recording.len(), 17);
+
+        let mut machine = Machine::from_instructions(&[Instruction::Jump { dest: 0x200 }]);
+        let result = soak(&mut machine.chip8, Duration::from_secs(60), 500, 0, &mut recording);
+        assert_eq!((result.violation, result.halted), (None, true));
+    }
+}
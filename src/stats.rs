@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use crate::chip8::Timestamp;
+
+/// How far a machine has got, readable from any thread without locking while it runs,
+/// e.g. by cloning `chip8.stats` before moving the machine to a thread of its own.
+/// Each counter is updated separately, so reading them mid-cycle can mix this cycle and the last.
+#[derive(Debug, Default)]
+pub struct Stats {
+    cycles: AtomicU64,
+    frames: AtomicU64,
+    emulated_nanos: AtomicU64,
+}
+
+impl Stats {
+    pub fn record(&self, timestamp: Timestamp) {
+        self.cycles.store(timestamp.cycle, Ordering::Relaxed);
+        self.frames.store(timestamp.frame(), Ordering::Relaxed);
+        self.emulated_nanos.store(timestamp.nanos, Ordering::Relaxed);
+    }
+
+    /// Instructions executed
+    pub fn cycles(&self) -> u64 {
+        self.cycles.load(Ordering::Relaxed)
+    }
+
+    /// 60Hz timer frames emulated
+    pub fn frames(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    pub fn emulated_time(&self) -> Duration {
+        Duration::from_nanos(self.emulated_nanos.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chip8::Instruction;
+    use crate::testing::Machine;
+
+    #[test]
+    fn counts_from_another_thread() {
+        let mut machine = Machine::from_instructions(&[Instruction::Jump { dest: 0x202 }, Instruction::Jump { dest: 0x200 }]);
+        let stats = machine.chip8.stats.clone();
+        std::thread::spawn(move || machine.run(1000)).join().unwrap();
+        assert_eq!(stats.cycles(), 1000);
+        assert_eq!(stats.frames(), 60);
+        assert_eq!(stats.emulated_time(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Names for addresses, so subroutines can be shown as something better than a number
+#[derive(Default)]
+pub struct Symbols {
+    names: HashMap<usize, String>,
+}
+
+impl Symbols {
+    /// One `address name` pair per line, with the address in hex (`0x` optional).
+    /// Blank lines and anything after a `#` are ignored.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut names = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (address, name) = line.split_once(char::is_whitespace)
+                .ok_or_else(|| format!("Line {}: expected an address and a name", i + 1))?;
+            let address = usize::from_str_radix(address.trim_start_matches("0x"), 16)
+                .map_err(|e| format!("Line {}: bad address {}: {}", i + 1, address, e))?;
+            names.insert(address, name.trim().to_string());
+        }
+        Ok(Symbols { names })
+    }
+
+    /// Reads the symbol file for a ROM if there is one, warning if it's unusable
+    pub fn load(rom_path: &str) -> Self {
+        let path = symbols_path(rom_path);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Symbols::default(),
+            Err(e) => {
+                log::warn!("Couldn't read symbols {}: {}", path.display(), e);
+                return Symbols::default();
+            },
+        };
+        Symbols::parse(&text).unwrap_or_else(|e| {
+            log::warn!("Ignoring symbols {}: {}", path.display(), e);
+            Symbols::default()
+        })
+    }
+
+    pub fn has_name(&self, address: usize) -> bool {
+        self.names.contains_key(&address)
+    }
+
+    /// The address with this label, or the one a `sub_XXX` name stands for
+    pub fn address_of(&self, name: &str) -> Option<usize> {
+        self.names.iter()
+            .find(|(_, label)| *label == name)
+            .map(|(&address, _)| address)
+            .or_else(|| name.strip_prefix("sub_").and_then(|hex| usize::from_str_radix(hex, 16).ok()))
+    }
+
+    /// The address's label, or `sub_XXX` if it doesn't have one
+    pub fn name(&self, address: usize) -> String {
+        match self.names.get(&address) {
+            Some(name) => name.clone(),
+            None => format!("sub_{:03x}", address),
+        }
+    }
+}
+
+/// Symbols live next to the ROM, as `<rom>.sym`
+pub fn symbols_path(rom_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.sym", rom_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Symbols;
+
+    #[test]
+    fn parses_symbol_files() {
+        let symbols = Symbols::parse("# Pong\n0x200 start\n2a4 draw_paddle  # both of them\n\n").unwrap();
+        assert_eq!(symbols.name(0x200), "start");
+        assert_eq!(symbols.name(0x2a4), "draw_paddle");
+        assert_eq!(symbols.name(0x2b0), "sub_2b0");
+        assert_eq!(symbols.address_of("draw_paddle"), Some(0x2a4));
+        assert_eq!(symbols.address_of("sub_2b0"), Some(0x2b0));
+        assert_eq!(symbols.address_of("draw_ball"), None);
+        assert!(Symbols::parse("0x200").is_err());
+        assert!(Symbols::parse("0x20g start").is_err());
+    }
+}
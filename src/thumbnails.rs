@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::chip8::{Chip8, HIRES_WIDTH};
+use crate::display::RgbaBuffer;
+use crate::headless;
+use crate::platform::Platform;
+
+/// Extensions taken to be ROMs, with the platform each implies if it does
+const ROM_EXTENSIONS: [(&str, Option<Platform>); 6] = [
+    ("ch8", None),
+    ("c8", None),
+    ("sc8", Some(Platform::Schip)),
+    ("xo8", Some(Platform::XoChip)),
+    ("c8x", Some(Platform::Chip8X)),
+    ("mc8", Some(Platform::MegaChip)),
+];
+
+/// The ROMs in `dir` in name order, each with the platform its extension implies, else `platform`
+pub fn roms_in(dir: &Path, platform: Platform) -> std::io::Result<Vec<(PathBuf, Platform)>> {
+    let mut roms = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+        let rom = ROM_EXTENSIONS.iter().find(|(rom_extension, _)| extension.as_deref() == Some(*rom_extension));
+        if let Some(&(_, implied)) = rom.filter(|_| path.is_file()) {
+            roms.push((path, implied.unwrap_or(platform)));
+        }
+    }
+    roms.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(roms)
+}
+
+/// A ROM's thumbnail goes next to it, as `<rom>.png`
+pub fn thumbnail_path(rom_path: &Path) -> PathBuf {
+    let mut path = rom_path.as_os_str().to_owned();
+    path.push(".png");
+    PathBuf::from(path)
+}
+
+/// The screen after `warm_up` of emulated time with no keys held, `scale` times the hires size
+/// whichever mode the program is in, so thumbnails line up. MEGA-CHIP's is bigger, and stays so.
+pub fn thumbnail(chip8: &mut Chip8, warm_up: Duration, scale: usize) -> RgbaBuffer {
+    let clock_speed = chip8.clock_speed();
+    let cycles = (warm_up.as_secs_f64() * clock_speed as f64) as u64;
+    let mut buffer = RgbaBuffer::new();
+    headless::run(chip8, cycles, clock_speed, [false; 16], &mut buffer, &mut |_| {});
+    buffer.scaled((HIRES_WIDTH / buffer.width).max(1) * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+    use crate::chip8::Chip8;
+    use crate::platform::Platform;
+    use super::{roms_in, thumbnail, thumbnail_path};
+
+    #[test]
+    fn thumbnails_every_rom_at_one_size() {
+        let roms = roms_in(Path::new("test"), Platform::Schip).unwrap();
+        assert!(roms.contains(&(Path::new("test").join("ibm_logo.ch8"), Platform::Schip)));
+        assert!(roms.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(thumbnail_path(Path::new("test/ibm_logo.ch8")), Path::new("test/ibm_logo.ch8.png"));
+
+        let mut chip8 = Chip8::new(Platform::Chip8);
+        chip8.read_program(std::fs::File::open("test/ibm_logo.ch8").unwrap()).unwrap();
+        let buffer = thumbnail(&mut chip8, Duration::from_secs(1), 1);
+        assert_eq!((buffer.width, buffer.height), (128, 64));
+        // The top left of the I in IBM, at (12, 8) in lores
+        assert_eq!(buffer.frame[(16 * 128 + 24) * 4], 255);
+    }
+}
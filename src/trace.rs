@@ -0,0 +1,132 @@
+use std::io::Write;
+use std::ops::Range;
+use std::str::FromStr;
+use crate::chip8::Chip8;
+use crate::decode::decode;
+use crate::symbols::Symbols;
+
+/// Part of a program: an inclusive range of addresses in hex like `200-2ff`, or a subroutine by its
+/// symbol (or `sub_XXX`), which covers everything run while it's on the call stack, callees included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    Range(Range<usize>),
+    Subroutine(String),
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let address = |hex: &str| usize::from_str_radix(hex.trim_start_matches("0x"), 16).ok();
+        match s.split_once('-').map(|(start, end)| (address(start), address(end))) {
+            Some((Some(start), Some(end))) if start <= end && end < 4096 => Ok(Scope::Range(start..end + 1)),
+            Some(_) => Err(format!("{} isn't a range of memory", s)),
+            None if s.is_empty() => Err(String::from("Expected a range like 200-2ff or a symbol")),
+            None => Ok(Scope::Subroutine(s.to_string())),
+        }
+    }
+}
+
+/// Which instructions to trace: those inside any `only` scope (or everywhere if there are none)
+/// that aren't inside a `skip` scope
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    pub only: Vec<Scope>,
+    pub skip: Vec<Scope>,
+}
+
+/// A scope with its symbol looked up
+enum Resolved {
+    Range(Range<usize>),
+    Subroutine(usize),
+}
+
+impl Resolved {
+    fn contains(&self, chip8: &Chip8) -> bool {
+        match self {
+            Resolved::Range(range) => range.contains(&chip8.pc),
+            Resolved::Subroutine(address) => chip8.call_chain().contains(address),
+        }
+    }
+}
+
+/// Writes a line for each instruction the filter lets through, just before it runs
+pub struct Tracer<W: Write> {
+    only: Vec<Resolved>,
+    skip: Vec<Resolved>,
+    out: W,
+}
+
+impl<W: Write> Tracer<W> {
+    /// Fails if the filter names a symbol that isn't in `symbols`
+    pub fn new(filter: &TraceFilter, symbols: &Symbols, out: W) -> Result<Self, String> {
+        let resolve = |scopes: &[Scope]| scopes.iter().map(|scope| match scope {
+            Scope::Range(range) => Ok(Resolved::Range(range.clone())),
+            Scope::Subroutine(name) => symbols.address_of(name)
+                .map(Resolved::Subroutine)
+                .ok_or_else(|| format!("No subroutine called {}", name)),
+        }).collect::<Result<Vec<_>, String>>();
+        Ok(Tracer { only: resolve(&filter.only)?, skip: resolve(&filter.skip)?, out })
+    }
+
+    /// Call before each cycle. Lines are `cycle pc: opcode => instruction`, indented by call depth.
+    pub fn observe(&mut self, chip8: &Chip8) {
+        let included = self.only.is_empty() || self.only.iter().any(|scope| scope.contains(chip8));
+        if !included || self.skip.iter().any(|scope| scope.contains(chip8)) {
+            return;
+        }
+        let raw = chip8.instruction_at(chip8.pc).unwrap_or(0);
+        let line = writeln!(
+            self.out,
+            "{:>8} {:indent$}{:#05x}: {:#06x} => {:?}",
+            chip8.cycles, "", chip8.pc, raw, decode(raw), indent = chip8.stack.len() * 2,
+        );
+        if let Err(e) = line {
+            log::warn!("Couldn't write trace: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::symbols::Symbols;
+    use crate::testing::Machine;
+    use super::{Scope, TraceFilter, Tracer};
+
+    fn trace(filter: TraceFilter) -> Vec<String> {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::CallSubroutine { dest: 0x206 },
+            Instruction::SetRegister { register: 0, value: 1 },
+            Instruction::Jump { dest: 0x204 },
+            Instruction::CallSubroutine { dest: 0x20a },
+            Instruction::Return,
+            Instruction::ClearScreen,
+            Instruction::Return,
+        ]);
+        machine.chip8.symbols = Symbols::parse("206 draw\n20a clear").unwrap();
+        let mut out = Vec::new();
+        let mut tracer = Tracer::new(&filter, &machine.chip8.symbols, &mut out).unwrap();
+        for _ in 0..7 {
+            tracer.observe(&machine.chip8);
+            machine.step();
+        }
+        String::from_utf8(out).unwrap().lines().map(|line| line.split(':').next().unwrap().trim().to_string()).collect()
+    }
+
+    #[test]
+    fn filters_by_scope() {
+        let pcs = |filter| trace(filter).into_iter().map(|line| line.rsplit(' ').next().unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(pcs(TraceFilter::default()), ["0x200", "0x206", "0x20a", "0x20c", "0x208", "0x202", "0x204"]);
+        let only_draw = TraceFilter { only: vec![Scope::Subroutine("draw".into())], skip: vec![] };
+        assert_eq!(pcs(only_draw.clone()), ["0x206", "0x20a", "0x20c", "0x208"]);
+        let skip_clear = TraceFilter { skip: vec!["clear".parse().unwrap()], ..only_draw };
+        assert_eq!(pcs(skip_clear), ["0x206", "0x208"]);
+        let range = TraceFilter { only: vec!["200-203".parse().unwrap()], skip: vec![] };
+        assert_eq!(pcs(range), ["0x200", "0x202"]);
+        // Lines are indented by call depth
+        assert_eq!(trace(TraceFilter::default())[2], "2     0x20a");
+        assert!(Tracer::new(&TraceFilter { only: vec![Scope::Subroutine("nope".into())], skip: vec![] }, &Symbols::default(), Vec::new()).is_err());
+        assert!("300-200".parse::<Scope>().is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use crate::audio::{synthesize, Tone, WAV_SAMPLE_RATE};
+use crate::chip8::{HIRES_HEIGHT, HIRES_WIDTH};
+use crate::display::{DisplaySink, RgbaBuffer};
+use crate::replay::Player;
+
+const FRAME_RATE: u32 = 60;
+
+/// Re-executes a replay from the start and encodes it to `output` with ffmpeg, which picks codecs from the extension.
+/// Audio is rendered in a first pass to a temporary WAV, then the frames are piped to ffmpeg in a second,
+/// since ffmpeg can only take one input through stdin. The beeper plays `tone`.
+pub fn render_replay(player: &mut Player, output: &Path, scale: u32, tone: Tone) -> Result<(), Error> {
+    let frame_gap = Duration::from_secs(1) / FRAME_RATE;
+
+    // The video can't change size, so it's hires throughout if the replay ever switches to it
+    player.seek(0);
+    let mut hires = player.chip8.display.hires();
+    let mut audio = synthesize(player, tone, WAV_SAMPLE_RATE, |chip8| hires |= chip8.display.hires());
+    let (width, height) = if hires { (HIRES_WIDTH, HIRES_HEIGHT) } else { player.chip8.display.size() };
+    // Pad to a whole number of frames so neither stream is cut short
+    let frames = (player.elapsed().as_nanos() / frame_gap.as_nanos()) as u32 + 1;
+    audio.fill_until(frame_gap * frames);
+    let wav_path = std::env::temp_dir().join(format!("chip8-replay-{}.wav", std::process::id()));
+    audio.write_wav(BufWriter::new(std::fs::File::create(&wav_path)?))?;
+
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .args(["-s", &format!("{}x{}", width, height)])
+        .args(["-r", &FRAME_RATE.to_string(), "-i", "-"])
+        .arg("-i").arg(&wav_path)
+        // Nearest neighbour keeps the pixels sharp, and yuv420p is what most players expect
+        .args(["-vf", &format!("scale=iw*{}:ih*{}:flags=neighbor", scale, scale)])
+        .args(["-pix_fmt", "yuv420p", "-shortest"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            std::fs::remove_file(&wav_path).ok();
+            Error::new(e.kind(), format!("Couldn't start ffmpeg, is it installed? ({})", e))
+        })?;
+
+    player.seek(0);
+    let mut buffer = RgbaBuffer::new();
+    let piped = (|| {
+        let mut stdin = BufWriter::new(ffmpeg.stdin.take().unwrap());
+        for _ in 0..frames {
+            buffer.present(&player.chip8.display, player.chip8.timestamp());
+            match width / buffer.width {
+                1 => buffer.write_rgb(&mut stdin)?,
+                factor => buffer.scaled(factor).write_rgb(&mut stdin)?,
+            }
+            player.advance(frame_gap);
+        }
+        stdin.flush()
+    })();
+    let status = ffmpeg.wait();
+    std::fs::remove_file(&wav_path).ok();
+    piped?;
+    match status? {
+        status if status.success() => Ok(()),
+        status => Err(Error::other(format!("ffmpeg failed ({})", status))),
+    }
+}
@@ -0,0 +1,147 @@
+use crate::chip8::Instruction;
+
+/// The 1802's machine cycles in a 60Hz frame, at 1.7609MHz and 8 clocks a machine cycle
+pub const MACHINE_CYCLES_PER_FRAME: i32 = 3668;
+/// What's left of a frame for the interpreter once the display interrupt and the DMA that feeds the
+/// screen to the video chip have had theirs
+pub const FRAME_BUDGET: i32 = 2572;
+/// How often the frontend should run cycles with `Quirks::vip_timing`: more often than the VIP could
+/// get through its quickest instructions, so the budget is what sets the speed
+pub const VIP_TICK_SPEED: u32 = 4000;
+
+/// The interpreter's fetch and decode, which every instruction pays on top of its own cost
+const FETCH: u32 = 40;
+
+/// Roughly how many machine cycles the VIP's interpreter spends on an instruction, fetch included.
+/// Skips are costed as not taken, and DXYN without waiting for the display interrupt, which
+/// `Quirks::display_wait` covers. Instructions the VIP doesn't have cost as little as its cheapest.
+pub fn machine_cycles(instruction: &Instruction) -> u32 {
+    FETCH + match *instruction {
+        Instruction::ClearScreen => 3078,
+        Instruction::Return => 10,
+        Instruction::Jump { .. } | Instruction::SetIndexRegister { .. } => 12,
+        Instruction::CallSubroutine { .. } => 26,
+        Instruction::SkipEQ { .. } | Instruction::SkipNEQ { .. } => 10,
+        Instruction::SkipEQR { .. } | Instruction::SkipNEQR { .. } => 14,
+        Instruction::SetRegister { .. } => 6,
+        Instruction::AddToRegister { .. } => 10,
+        Instruction::JumpOffset { .. } => 22,
+        Instruction::MovRegister { .. }
+        | Instruction::BinaryOr { .. }
+        | Instruction::BinaryAnd { .. }
+        | Instruction::BinaryXor { .. }
+        | Instruction::Add { .. }
+        | Instruction::SubtractForward { .. }
+        | Instruction::SubtractBackward { .. }
+        | Instruction::ShiftRight { .. }
+        | Instruction::ShiftLeft { .. } => 44,
+        Instruction::Random { .. } => 36,
+        // Shifting each row into place and XORing it onto the screen
+        Instruction::Draw { height, .. } => 26 + 68 * height as u32,
+        Instruction::SkipPressed { .. } | Instruction::SkipNotPressed { .. } => 14,
+        Instruction::GetDelayTimer { .. }
+        | Instruction::GetKey { .. }
+        | Instruction::SetDelayTimer { .. }
+        | Instruction::SetSoundTimer { .. } => 10,
+        Instruction::AddToIndex { .. } | Instruction::FontChar { .. } => 16,
+        // Repeated subtraction, so it depends on the digits, but this is about the middle
+        Instruction::RegToDecimal { .. } => 248,
+        Instruction::StoreMemory { register } | Instruction::LoadMemory { register } => 14 + 14 * (register as u32 + 1),
+        _ => 6,
+    }
+}
+
+/// Keeps count of the machine cycles left in the current frame. An instruction can start as long as
+/// some are left, and any it takes past the end of the frame come out of the next ones, so 00E0,
+/// which takes more than a whole frame, holds up the instructions after it as it did on the VIP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VipClock {
+    /// Negative while an instruction is still finishing
+    pub balance: i32,
+    /// The frame the balance was last topped up in, or `None` to start from the next cycle's
+    frame: Option<u64>,
+}
+
+impl VipClock {
+    /// Tops up the balance for the frames since the last call, up to one frame's worth since time
+    /// the interpreter spends idle isn't saved up, then spends `cost` on the next instruction if it
+    /// can start. Returns whether it can.
+    pub fn spend(&mut self, frame: u64, cost: u32) -> bool {
+        // No instruction costs two frames, so that's enough to pay off any debt
+        let frames = self.frame.map_or(0, |last| frame.saturating_sub(last)).min(2) as i32;
+        self.frame = Some(frame);
+        self.balance = (self.balance + frames * FRAME_BUDGET).min(FRAME_BUDGET);
+        if self.balance <= 0 {
+            return false;
+        }
+        self.balance -= cost as i32;
+        true
+    }
+
+    /// After a restore, where the frame it was last topped up in means nothing
+    pub fn with_balance(balance: i32) -> Self {
+        VipClock { balance, frame: None }
+    }
+}
+
+impl Default for VipClock {
+    fn default() -> Self {
+        VipClock::with_balance(FRAME_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::chip8::Instruction;
+    use crate::testing::Machine;
+    use super::{machine_cycles, VipClock, FRAME_BUDGET, VIP_TICK_SPEED};
+
+    #[test]
+    fn spends_a_budget_each_frame() {
+        let mut clock = VipClock::default();
+        let mut frames = Vec::new();
+        let mut frame = 0;
+        // 00E0 takes a frame and a bit, so one in every six frames has to wait
+        while frames.len() < 6 {
+            if clock.spend(frame, machine_cycles(&Instruction::ClearScreen)) {
+                frames.push(frame);
+            }
+            frame += 1;
+        }
+        assert_eq!(frames, [0, 1, 2, 3, 4, 6]);
+        // Time spent idle isn't saved up
+        let mut clock = VipClock::default();
+        assert!(clock.spend(0, 0));
+        assert!(clock.spend(10, FRAME_BUDGET as u32));
+        assert!(!clock.spend(10, 0));
+    }
+
+    #[test]
+    fn runs_at_the_vips_speed() {
+        // A tight loop of 7XNN and a jump back, at 40 + 10 and 40 + 12
+        let mut machine = Machine::from_instructions(&[
+            Instruction::AddToRegister { register: 0, value: 1 },
+            Instruction::Jump { dest: 0x200 },
+        ]);
+        machine.chip8.quirks.vip_timing = true;
+        let mut machine = machine.with_clock_gap(Duration::from_secs(1) / VIP_TICK_SPEED);
+        let mut executed = 0;
+        // Two seconds, counting the cycles that weren't held up
+        for _ in 0..2 * VIP_TICK_SPEED {
+            let pc = machine.chip8.pc;
+            machine.step();
+            executed += (machine.chip8.pc != pc) as u32;
+        }
+        let expected = 120 * FRAME_BUDGET as u32 / 51;
+        assert!(executed.abs_diff(expected) <= 2, "{} instructions, expected about {}", executed, expected);
+        // Only those count as instructions
+        assert_eq!(machine.chip8.cycles, executed as u64);
+        // What's left of the frame is kept in snapshots
+        let snapshot = machine.chip8.snapshot();
+        let mut restored = Machine::new(&[]);
+        restored.chip8.quirks.vip_timing = true;
+        restored.chip8.restore(&snapshot);
+        assert_eq!(restored.chip8.snapshot().bytes, snapshot.bytes);
+    }
+}
@@ -0,0 +1,1250 @@
+//! The windowed frontend: the emulator with its debugger, and the replay player.
+//! Part of the binary, and only built with the `gui` feature.
+
+use std::time::{Duration, Instant};
+use chip8::{asm, boot, rpl};
+use chip8::audio::{self, synthesize, Beeper, Buzzer, Tone, WAV_SAMPLE_RATE};
+use chip8::breakpoints::Breakpoints;
+use chip8::calibrate::{Calibrator, Tempo};
+use chip8::cli::{MachineOptions, MAX_CLOCK_SPEED, MIN_CLOCK_SPEED};
+use chip8::diagnostics::Severity;
+use chip8::experiment::{Experiment, EXPERIMENT_CYCLES};
+use chip8::display::{draw_heatmap, draw_memory, draw_timeline, DisplaySink, FrameSkipper, Phosphor, RgbaBuffer, MEMORY_VIEW_HEIGHT, MEMORY_VIEW_WIDTH, TIMELINE_HEIGHT};
+use chip8::hotkeys::{Action, Hotkeys};
+use chip8::keypad::Keypad;
+use chip8::latency::LatencyProbe;
+use chip8::layout::{Layout, Placement};
+use chip8::effects::{Effects, FrameInfo};
+use chip8::look::{Look, Palette, Setting};
+use chip8::notify::{Announcement, Announcer, PrintNotifier};
+use chip8::platform::Platform;
+use chip8::practice::Practice;
+use chip8::quirks::Quirks;
+use chip8::replay::{InstantReplay, Player, Recording};
+use chip8::rom::{decode_pasted, pasted_path};
+use chip8::chip8::{draw_screen, Chip8, Cycle, Screen, SCREEN_HEIGHT, SCREEN_WIDTH};
+use chip8::savestate::{slot_path, SaveState, SLOTS};
+use chip8::score::{Progress, ScoreTracker};
+use chip8::settings::Settings;
+use chip8::slowlog::SlowLog;
+use chip8::snapshot::History;
+use pixels::{Pixels, SurfaceTexture};
+use pixels::wgpu::SurfaceError;
+use winit::dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget};
+use winit::event::{Event, StartCause, VirtualKeyCode, WindowEvent};
+use winit::window::{Fullscreen, Window, WindowId};
+use winit_input_helper::{TextChar, WinitInputHelper};
+use crate::{load_rom, load_state, print_diagnostics, read_recording, read_rom, save_recording, save_wav, Launch, DEFAULT_CLOCK_SPEED};
+
+/// Commands that print the clipboard's text, tried in order
+const CLIPBOARD_COMMANDS: [&[&str]; 4] = [
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-o"],
+    &["pbpaste"],
+    &["powershell", "-NoProfile", "-Command", "Get-Clipboard"],
+];
+
+fn read_clipboard() -> Result<String, String> {
+    CLIPBOARD_COMMANDS.iter()
+        .filter_map(|command| std::process::Command::new(command[0]).args(&command[1..]).output().ok())
+        .find(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .ok_or_else(|| String::from("Couldn't read the clipboard, install wl-paste or xclip"))
+}
+
+/// The clipboard's text decoded as a ROM, for grabbing small programs from forums and documentation
+fn paste_rom() -> Option<Vec<u8>> {
+    match read_clipboard().and_then(|text| decode_pasted(&text)) {
+        Ok(rom) => Some(rom),
+        Err(e) => {
+            log::warn!("{}", e);
+            None
+        },
+    }
+}
+
+fn read_slot(rom_path: &str, slot: u8) -> Option<SaveState> {
+    let path = slot_path(rom_path, slot);
+    match std::fs::File::open(&path).and_then(SaveState::read) {
+        Ok(state) => Some(state),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            log::warn!("Couldn't read save state {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Returns whether the state was saved
+fn save_slot(state: &SaveState, rom_path: &str, slot: u8) -> bool {
+    let path = slot_path(rom_path, slot);
+    match std::fs::File::create(&path).and_then(|file| state.write(std::io::BufWriter::new(file))) {
+        Ok(()) => {
+            log::info!("Saved state to slot {}", slot);
+            true
+        },
+        Err(e) => {
+            log::error!("Couldn't write save state {}: {}", path.display(), e);
+            false
+        },
+    }
+}
+
+/// Returns the saved play time if the state was loaded
+fn load_slot(chip8: &mut Chip8, rom_path: &str, rom_hash: u64, slot: u8, force: bool) -> Option<Duration> {
+    let state = read_slot(rom_path, slot)?;
+    if state.rom_hash != rom_hash && !force {
+        log::warn!("Save state in slot {} was made with a different ROM; hold Shift to load it anyway", slot);
+        return None;
+    }
+    chip8.restore(&state.snapshot);
+    log::info!("Loaded state from slot {}", slot);
+    Some(state.play_time)
+}
+
+fn save_practice(practice: &Practice, rom_path: &str) {
+    if let Err(e) = practice.save(rom_path) {
+        log::error!("Couldn't save the practice checkpoint: {}", e);
+    }
+}
+
+/// Shows something in the title bar for a few seconds
+fn show_toast(window: &Window, message: impl std::fmt::Display, toast: &mut Option<Instant>) {
+    window.set_title(&format!("{} - {}", TITLE, message));
+    *toast = Some(Instant::now() + TOAST_TIME);
+}
+
+fn describe_slot(state: &Option<SaveState>, rom_hash: u64, slot: u8) -> String {
+    match state {
+        Some(state) if state.rom_hash != rom_hash => format!("Slot {}: {} (different ROM)", slot, state.describe()),
+        Some(state) => format!("Slot {}: {}", slot, state.describe()),
+        None => format!("Slot {}: empty", slot),
+    }
+}
+
+fn describe_look(look: &Look, setting: Setting) -> String {
+    format!("Look: {} {:.0}%", setting, look.get(setting) * 100.0)
+}
+
+const KEY_MAPPING: [(VirtualKeyCode, usize); 16] = [
+    (VirtualKeyCode::Key1, 1),
+    (VirtualKeyCode::Key2, 2),
+    (VirtualKeyCode::Key3, 3),
+    (VirtualKeyCode::Key4, 0xc),
+    (VirtualKeyCode::Q, 4),
+    (VirtualKeyCode::W, 5),
+    (VirtualKeyCode::E, 6),
+    (VirtualKeyCode::R, 0xd),
+    (VirtualKeyCode::A, 7),
+    (VirtualKeyCode::S, 8),
+    (VirtualKeyCode::D, 9),
+    (VirtualKeyCode::F, 0xe),
+    (VirtualKeyCode::Z, 0xa),
+    (VirtualKeyCode::X, 0),
+    (VirtualKeyCode::C, 0xb),
+    (VirtualKeyCode::V, 0xf),
+];
+
+// CHIP-8X's second keypad is on the numpad, laid out by its digits
+const SECOND_KEY_MAPPING: [(VirtualKeyCode, usize); 16] = [
+    (VirtualKeyCode::Numpad0, 0),
+    (VirtualKeyCode::Numpad1, 1),
+    (VirtualKeyCode::Numpad2, 2),
+    (VirtualKeyCode::Numpad3, 3),
+    (VirtualKeyCode::Numpad4, 4),
+    (VirtualKeyCode::Numpad5, 5),
+    (VirtualKeyCode::Numpad6, 6),
+    (VirtualKeyCode::Numpad7, 7),
+    (VirtualKeyCode::Numpad8, 8),
+    (VirtualKeyCode::Numpad9, 9),
+    (VirtualKeyCode::NumpadDecimal, 0xa),
+    (VirtualKeyCode::NumpadEnter, 0xb),
+    (VirtualKeyCode::NumpadDivide, 0xc),
+    (VirtualKeyCode::NumpadMultiply, 0xd),
+    (VirtualKeyCode::NumpadSubtract, 0xe),
+    (VirtualKeyCode::NumpadAdd, 0xf),
+];
+
+// Speeds the number keys pick in the replay player
+const PLAYBACK_SPEEDS: [(VirtualKeyCode, u32); 3] = [
+    (VirtualKeyCode::Key1, 1),
+    (VirtualKeyCode::Key2, 2),
+    (VirtualKeyCode::Key4, 4),
+];
+
+pub fn play(replay_path: &str) {
+    let mut player = Player::new(read_recording(replay_path));
+    let event_loop = EventLoop::new();
+    let mut input = WinitInputHelper::new();
+    let (window, width, height, _) = create_window(TITLE, &event_loop);
+    let surface_texture = SurfaceTexture::new(width, height, &window);
+    let mut pixels = Pixels::new(SCREEN_WIDTH as u32, (SCREEN_HEIGHT + TIMELINE_HEIGHT) as u32, surface_texture)
+        .expect("Failed to start graphics library");
+    let mut buffer_size = (SCREEN_WIDTH, SCREEN_HEIGHT + TIMELINE_HEIGHT);
+    let frame_gap = Duration::from_secs_f32(1.0 / 60.0);
+    let mut next_frame = Instant::now();
+    let mut paused = false;
+    let mut speed = 1;
+    let mut title = String::new();
+    event_loop.run(move |mut event, _, control_flow| {
+        if let Event::WindowEvent { event: WindowEvent::ScaleFactorChanged { new_inner_size, .. }, .. } = &mut event {
+            fit_to_scale_factor(&window, new_inner_size, &mut pixels, buffer_size.0 as u32, buffer_size.1 as u32);
+        }
+        if input.update(&event) {
+            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            if let Some(size) = input.window_resized() {
+                pixels.resize_surface(size.width, size.height);
+            }
+            if input.key_pressed(VirtualKeyCode::Space) {
+                paused ^= true;
+            }
+            for (key, key_speed) in PLAYBACK_SPEEDS {
+                if input.key_pressed(key) {
+                    speed = key_speed;
+                }
+            }
+            // A frame's worth of cycles at the current clock speed
+            let frame_cycles = (frame_gap.as_nanos() / player.clock_gap().as_nanos().max(1)).max(1) as u64;
+            if input.key_pressed(VirtualKeyCode::Right) {
+                paused = true;
+                player.step(frame_cycles);
+            }
+            if input.key_pressed(VirtualKeyCode::Left) {
+                paused = true;
+                player.seek(player.position().saturating_sub(frame_cycles));
+            }
+            // A tenth of the replay at a time, for seeking without the mouse
+            let jump = (player.len() / 10).max(1);
+            if input.key_pressed(VirtualKeyCode::PageDown) {
+                player.seek((player.position() + jump).min(player.len()));
+            }
+            if input.key_pressed(VirtualKeyCode::PageUp) {
+                player.seek(player.position().saturating_sub(jump));
+            }
+            if input.key_pressed(VirtualKeyCode::Home) {
+                player.seek(0);
+            }
+            if input.key_pressed(VirtualKeyCode::End) {
+                player.seek(player.len());
+            }
+            if input.mouse_held(0) {
+                if let Some((x, y)) = input.mouse().and_then(|pos| pixels.window_pos_to_pixel(pos).ok()) {
+                    let (width, height) = player.chip8.display.size();
+                    if y >= height {
+                        let fraction = (x as f64 + 0.5) / width as f64;
+                        player.seek((fraction * player.len() as f64) as u64);
+                    }
+                }
+            }
+            window.request_redraw();
+        }
+
+        match event {
+            Event::RedrawRequested(_) => {
+                let (width, height) = player.chip8.display.size();
+                fit_buffer(&mut pixels, &mut buffer_size, (width, height + TIMELINE_HEIGHT));
+                let frame = pixels.get_frame();
+                draw_screen(&player.chip8.display, &Palette::default(), frame);
+                draw_timeline(frame, width, player.position() as f64 / player.len().max(1) as f64);
+                render(&mut pixels, &window, buffer_size.0 as u32, buffer_size.1 as u32);
+            },
+            Event::Resumed => reconfigure_surface(&mut pixels, &window),
+            Event::NewEvents(StartCause::Init) => {
+                *control_flow = ControlFlow::WaitUntil(next_frame);
+            },
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                if !paused && !player.at_end() {
+                    player.advance(frame_gap * speed);
+                    window.request_redraw();
+                }
+                let elapsed = player.elapsed().as_secs();
+                let new_title = format!(
+                    "{} - replay {}:{:02} ({}/{} cycles, {}x{})",
+                    TITLE, elapsed / 60, elapsed % 60, player.position(), player.len(), speed,
+                    if paused { ", paused" } else { "" },
+                );
+                if new_title != title {
+                    window.set_title(&new_title);
+                    title = new_title;
+                }
+                next_frame += frame_gap;
+                *control_flow = ControlFlow::WaitUntil(next_frame);
+            },
+            _ => {}
+        }
+    });
+}
+
+/// Saves the screen next to the ROM, named after the cycle so screenshots don't overwrite each other
+fn save_screenshot(chip8: &Chip8, rom_path: &str) {
+    let mut buffer = RgbaBuffer::new();
+    buffer.present(&chip8.display, chip8.timestamp());
+    let path = format!("{}.{}.ppm", rom_path, chip8.timestamp().cycle);
+    match std::fs::File::create(&path).and_then(|file| buffer.write_ppm(std::io::BufWriter::new(file))) {
+        Ok(()) => println!("Saved screenshot to {}", path),
+        Err(e) => log::warn!("Couldn't save screenshot to {}: {}", path, e),
+    }
+}
+
+const DISPLAY_WINDOW: &str = "display";
+const MEMORY_WINDOW: &str = "memory";
+const MIRROR_WINDOW: &str = "mirror";
+
+fn placement(window: &Window) -> Option<Placement> {
+    let position = window.outer_position().ok()?;
+    let size = window.inner_size();
+    Some(Placement { x: position.x, y: position.y, width: size.width, height: size.height })
+}
+
+fn place_window(window: &Window, placement: Placement) {
+    window.set_outer_position(PhysicalPosition::new(placement.x, placement.y));
+    window.set_inner_size(PhysicalSize::new(placement.width, placement.height));
+}
+
+/// Shows the frame, getting the window's surface back if it went away (GPU reset, suspend and resume,
+/// moving to another monitor) rather than giving up. Anything worse restarts the graphics library on
+/// the same window with a `width` by `height` buffer. A frame that still can't be shown is dropped.
+fn render(pixels: &mut Pixels, window: &Window, width: u32, height: u32) {
+    match pixels.render() {
+        Ok(()) => return,
+        Err(pixels::Error::Surface(SurfaceError::Timeout)) => {
+            log::debug!("Timed out waiting for the window surface, dropping a frame");
+            return;
+        },
+        Err(pixels::Error::Surface(SurfaceError::Lost | SurfaceError::Outdated)) => {
+            log::warn!("Lost the window surface, setting it up again");
+            reconfigure_surface(pixels, window);
+        },
+        Err(e) => {
+            log::warn!("Couldn't render ({}), restarting the graphics library", e);
+            let size = window.inner_size();
+            match Pixels::new(width, height, SurfaceTexture::new(size.width, size.height, window)) {
+                Ok(mut restarted) => {
+                    restarted.get_frame().copy_from_slice(pixels.get_frame());
+                    *pixels = restarted;
+                },
+                Err(e) => log::error!("Couldn't restart the graphics library: {}", e),
+            }
+        },
+    }
+    if let Err(e) = pixels.render() {
+        log::warn!("Dropped a frame: {}", e);
+    }
+}
+
+/// When a window moves to a monitor with a different DPI, winit suggests a new size that keeps it the same
+/// size on screen. This rounds that to a whole number of physical pixels per emulated pixel, so the display
+/// stays sharp rather than being scaled unevenly, and resizes the surface to match. The buffer is `width`
+/// by `height`. Fullscreen windows are left to the OS.
+fn fit_to_scale_factor(window: &Window, new_inner_size: &mut PhysicalSize<u32>, pixels: &mut Pixels, width: u32, height: u32) {
+    if window.fullscreen().is_none() {
+        let scale = (new_inner_size.width as f64 / width as f64)
+            .min(new_inner_size.height as f64 / height as f64)
+            .round()
+            .max(1.0) as u32;
+        *new_inner_size = PhysicalSize::new(width * scale, height * scale);
+    }
+    pixels.resize_surface(new_inner_size.width, new_inner_size.height);
+}
+
+/// Resizes the buffer to `size` if it isn't already, e.g. when a SCHIP program switches to hires.
+/// The window keeps its size, since both modes are 2:1.
+fn fit_buffer(pixels: &mut Pixels, buffer_size: &mut (usize, usize), size: (usize, usize)) {
+    if *buffer_size != size {
+        pixels.resize_buffer(size.0 as u32, size.1 as u32);
+        *buffer_size = size;
+    }
+}
+
+fn reconfigure_surface(pixels: &mut Pixels, window: &Window) {
+    let size = window.inner_size();
+    pixels.resize_surface(size.width, size.height);
+}
+
+/// A window showing memory a pixel per bit, where it was last time if there was one
+fn open_memory_view(target: &EventLoopWindowTarget<()>, placement: Option<Placement>) -> (Window, Pixels) {
+    let window = winit::window::WindowBuilder::new()
+        .with_title(format!("{} - memory", TITLE))
+        .with_inner_size(LogicalSize::new(MEMORY_VIEW_WIDTH as f64 * 2.0, MEMORY_VIEW_HEIGHT as f64 * 2.0))
+        .build(target)
+        .expect("Failed to open memory view");
+    if let Some(placement) = placement {
+        place_window(&window, placement);
+    }
+    let size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
+    let pixels = Pixels::new(MEMORY_VIEW_WIDTH as u32, MEMORY_VIEW_HEIGHT as u32, surface_texture)
+        .expect("Failed to start graphics library");
+    (window, pixels)
+}
+
+/// A second window showing the display, e.g. to put on a projector while the first stays on the laptop
+fn open_mirror(target: &EventLoopWindowTarget<()>, placement: Option<Placement>) -> (Window, Pixels) {
+    let window = winit::window::WindowBuilder::new()
+        .with_title(format!("{} - mirror", TITLE))
+        .with_inner_size(LogicalSize::new(SCREEN_WIDTH as f64 * 10.0, SCREEN_HEIGHT as f64 * 10.0))
+        .build(target)
+        .expect("Failed to open mirror");
+    if let Some(placement) = placement {
+        place_window(&window, placement);
+    }
+    let size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(size.width, size.height, &window);
+    let pixels = Pixels::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, surface_texture)
+        .expect("Failed to start graphics library");
+    (window, pixels)
+}
+
+/// Closes and resizes `view` (the memory view or mirror) if the event is for it, returning whether it was.
+/// The input helper can't tell windows apart, so these would otherwise look like the display's.
+fn handle_view_event(view: &mut Option<(Window, Pixels)>, window_id: WindowId, event: &WindowEvent) -> bool {
+    let Some((_, view_pixels)) = view.as_mut().filter(|(view_window, _)| view_window.id() == window_id) else {
+        return false;
+    };
+    match event {
+        WindowEvent::CloseRequested => *view = None,
+        WindowEvent::Resized(size) => view_pixels.resize_surface(size.width, size.height),
+        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+            view_pixels.resize_surface(new_inner_size.width, new_inner_size.height);
+        },
+        _ => return false,
+    }
+    true
+}
+
+/// Saves the recording, and its audio, if there are paths for them. The audio is played back from
+/// the recording, so it lines up with it sample for sample, rewinds and state loads and all.
+fn save_capture(capture: &Capture, recording: &Recording, tone: Tone) {
+    if let Some(path) = &capture.replay {
+        save_recording(recording, path);
+    }
+    if let Some(path) = &capture.wav {
+        let audio = synthesize(&mut Player::new(recording.clone()), tone, WAV_SAMPLE_RATE, |_| {});
+        save_wav(&audio, path);
+    }
+}
+
+/// Saves where the windows are, for next time. `diagnostics` is the machine to report on, for --strict.
+fn quit(
+    diagnostics: Option<&Chip8>,
+    layout: &mut Layout,
+    window: &Window,
+    memory_view: &Option<(Window, Pixels)>,
+    mirror: &Option<(Window, Pixels)>,
+) {
+    if let Some(chip8) = diagnostics {
+        print_diagnostics(chip8);
+    }
+    layout.set(DISPLAY_WINDOW, placement(window));
+    layout.set(MEMORY_WINDOW, memory_view.as_ref().and_then(|(memory_window, _)| placement(memory_window)));
+    layout.set(MIRROR_WINDOW, mirror.as_ref().and_then(|(mirror_window, _)| placement(mirror_window)));
+    if let Err(e) = layout.save() {
+        log::warn!("Couldn't save window layout: {}", e);
+    }
+}
+
+/// A new machine running `rom`, set up the way the command line asked, and the ROM's hash
+fn start_rom(rom: &[u8], rom_path: &str, machine: &MachineOptions, breakpoints: &Breakpoints) -> (Chip8, u64) {
+    let mut chip8 = Chip8::new(machine.platform.for_rom(rom));
+    let rom_hash = load_rom(&mut chip8, rom, rom_path);
+    machine.apply(&mut chip8);
+    chip8.breakpoints = breakpoints.clone();
+    chip8.rpl = rpl::load(rom_path);
+    (chip8, rom_hash)
+}
+
+/// How the window plays the program out: frames to skip when behind, and how the beeper sounds
+/// before the settings file fills in the rest
+pub struct Presentation {
+    pub max_frameskip: u32,
+    pub buzzer: Buzzer,
+}
+
+/// What to save when the window closes: a replay for `--record`, and its audio for `--wav`
+pub struct Capture {
+    pub replay: Option<String>,
+    pub wav: Option<String>,
+}
+
+/// What the window watches the program for: anything questionable for `--strict`, breakpoints,
+/// and instructions slower than `--slow-instruction`
+pub struct Checks {
+    pub strict: bool,
+    pub breakpoints: Breakpoints,
+    pub slow_instruction: Option<Duration>,
+}
+
+pub fn run(launch: Launch, presentation: Presentation, capture: Capture, machine: MachineOptions, checks: Checks, auto_speed: bool, practice: Option<Vec<usize>>) {
+    let Checks { strict, breakpoints, slow_instruction } = checks;
+    // A speed given on the command line is kept to until +/- change it
+    let auto_speed = auto_speed && machine.ips.is_none();
+    let mut time = Instant::now();
+    let settings = Settings::load();
+    let (rom, mut rom_path) = read_rom(&launch.rom);
+    let (mut chip8, mut rom_hash) = start_rom(&rom, &rom_path, &machine, &breakpoints);
+    chip8.print_program();
+    let saved_play_time = launch.state.map(|state| load_state(&mut chip8, &state, &rom));
+    // The boot animation runs on a machine of its own, then the ROM starts on a fresh one.
+    // Starting from a state skips it, since it's somewhere in the middle already.
+    let mut booting = settings.boot_animation && saved_play_time.is_none();
+    let mut announcer = Announcer::default();
+    if settings.announce {
+        announcer.add(PrintNotifier);
+    }
+    if booting {
+        chip8 = boot::machine();
+    } else {
+        announcer.announce(Announcement::RomLoaded { path: rom_path.clone() });
+    }
+    let mut clock_speed: u32 = if booting { boot::BOOT_CLOCK_SPEED } else { machine.clock_speed(&chip8) };
+    let mut clock_gap: Duration = Duration::from_secs_f32(1.0) / clock_speed;
+    let event_loop = EventLoop::new();
+    let mut input = WinitInputHelper::new();
+    // The numpad's hotkeys give way to the second keypad when there is one
+    let second_keypad = if machine.platform == Platform::Chip8X { &SECOND_KEY_MAPPING[..] } else { &[] };
+    let keypad: Vec<VirtualKeyCode> = KEY_MAPPING.iter().chain(second_keypad).map(|&(key, _)| key).collect();
+    let hotkeys = Hotkeys::load(&keypad);
+    let (window, width, height, _) = create_window(&title(clock_speed), &event_loop);
+    // The speed in the title bar, which catches up whenever nothing else has the title
+    let mut shown_speed = clock_speed;
+    // Windows open where they were last time; F2 toggles the memory view
+    let mut layout = Layout::load();
+    let (width, height) = match layout.get(DISPLAY_WINDOW) {
+        Some(placement) => {
+            place_window(&window, placement);
+            (placement.width, placement.height)
+        },
+        None => (width, height),
+    };
+    let surface_texture = SurfaceTexture::new(width, height, &window);
+    let mut pixels = Pixels::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, surface_texture).expect("Failed to start graphics library");
+    // Follows the screen between lores and hires
+    let mut buffer_size = (SCREEN_WIDTH, SCREEN_HEIGHT);
+    let mut memory_view = layout.get(MEMORY_WINDOW).map(|placement| open_memory_view(&event_loop, Some(placement)));
+    // F8 mirrors the display to a window of its own, which F11 makes fullscreen while it has focus
+    let mut mirror = layout.get(MIRROR_WINDOW).map(|placement| open_mirror(&event_loop, Some(placement)));
+    let mut mirror_focused = false;
+    println!("Starting CHIP-8 emulator");
+
+    let mut keypad = Keypad::default();
+    // Starts paused in the debugger, once any boot animation is over
+    let mut debugging = !booting;
+    let mut next_cycle = false;
+    let mut last_render = time;
+    let mut wanna_render = Cycle::Complete;
+    let frame_gap = Duration::from_secs_f32(1.0 / 60.0);
+    let mut history = History::new(REWIND_FRAMES, REWIND_KEYFRAME_INTERVAL);
+    let mut last_snapshot = time;
+    let mut rewinding = false;
+    let mut play_time = saved_play_time.unwrap_or_default();
+    let mut slot: u8 = 1;
+    let mut slot_preview: Option<Option<SaveState>> = None;
+    // With --practice, End takes a checkpoint and Delete goes back to it
+    let mut practice = practice.map(|marks| Practice::load(&rom_path, rom_hash, marks));
+    // Follows the score if <rom>.score says where it is, keeping the session's best on the leaderboard
+    let mut score = ScoreTracker::load(&rom_path);
+    let mut look = Look::load(&rom_path);
+    // Recolours each frame as it's drawn, from the effects the look turns on
+    let mut effects = Effects::builtin(look.effects);
+    let mut look_setting: Option<Setting> = None;
+    // A line being typed into the title bar to assemble into memory
+    let mut assembling: Option<String> = None;
+    // Until when the title bar shows the latest diagnostic, if it is
+    let mut toast: Option<Instant> = None;
+    let mut phosphor = Phosphor::new();
+    // Tints the screen by the order of the latest frame's draws instead (F1 by default)
+    let mut heatmap = false;
+    // Timing keypad presses to when the screen shows them, while on
+    let mut latency: Option<LatencyProbe> = None;
+    let mut slow_log = slow_instruction.map(SlowLog::new);
+    let mut beeper = Beeper::new(presentation.buzzer.or(settings.buzzer).tone());
+    let mut audio = audio::output();
+    let mut frame_skipper = FrameSkipper::new(presentation.max_frameskip);
+    // Speeds up programs that don't keep up with their own frame timing, until +/- are used
+    let mut calibrator = auto_speed.then(Calibrator::default);
+    let mut turbo = false;
+    // Nothing to do until there's input, so the loop sleeps rather than ticking every clock_gap
+    let mut idle = false;
+    // Nothing runs while the OS has suspended us or the display is minimized, and the clock
+    // starts over when it's back, as it does after any idle spell
+    let mut suspended = false;
+    let mut minimized = false;
+    // Everything since the last time the state jumped (rewind, loading a state),
+    // for stepping backwards and saving with --record
+    let mut recording = Recording::start(&chip8, keypad.held(), clock_gap);
+    // The last 30 seconds or so, kept whatever --record says, for saving after something happens (F10 by default)
+    let mut instant_replay = InstantReplay::start(INSTANT_REPLAY_WINDOW, &chip8, keypad.held(), clock_gap);
+    // Each instruction is its own wakeup, so input is handled between every instruction
+    // rather than once per frame, and a key press is seen by the very next instruction
+    event_loop.run(move |mut event, target, control_flow| {
+        if idle && matches!(event, Event::WindowEvent { .. } | Event::Resumed) {
+            idle = false;
+            time = Instant::now();
+            *control_flow = ControlFlow::WaitUntil(time);
+        }
+        if toast.is_some_and(|until| Instant::now() >= until) {
+            toast = None;
+            // Unless something else has the title bar by now
+            if assembling.is_none() && look_setting.is_none() && slot_preview.is_none() {
+                window.set_title(&title(clock_speed));
+            }
+        }
+        if clock_speed != shown_speed && assembling.is_none() && look_setting.is_none() && slot_preview.is_none() && toast.is_none() {
+            shown_speed = clock_speed;
+            window.set_title(&title(clock_speed));
+        }
+        // The input helper can't tell windows apart, so the other windows' closing and resizing are handled here
+        if let Event::WindowEvent { window_id, event: window_event } = &mut event {
+            if *window_id == window.id() {
+                if let WindowEvent::ScaleFactorChanged { new_inner_size, .. } = window_event {
+                    fit_to_scale_factor(&window, new_inner_size, &mut pixels, buffer_size.0 as u32, buffer_size.1 as u32);
+                }
+            } else if handle_view_event(&mut memory_view, *window_id, window_event)
+                || handle_view_event(&mut mirror, *window_id, window_event) {
+                return;
+            }
+            if let WindowEvent::Resized(size) = window_event {
+                if *window_id == window.id() {
+                    // Minimizing shrinks the window to nothing on some platforms
+                    minimized = size.width == 0 || size.height == 0;
+                    if minimized {
+                        beeper.silence(&chip8, &mut audio);
+                    }
+                }
+            }
+            if let WindowEvent::Focused(true) = window_event {
+                mirror_focused = mirror.as_ref().is_some_and(|(mirror_window, _)| mirror_window.id() == *window_id);
+            }
+        }
+        // Any key cuts the boot animation short, and then does whatever it does to the ROM
+        let key_event = matches!(event, Event::WindowEvent { event: WindowEvent::KeyboardInput { .. }, .. });
+        if booting && (boot::finished(&chip8) || key_event) {
+            booting = false;
+            (chip8, _) = start_rom(&rom, &rom_path, &machine, &breakpoints);
+            beeper.reset(&chip8, &mut audio);
+            debugging = true;
+            announcer.announce(Announcement::RomLoaded { path: rom_path.clone() });
+            clock_speed = machine.clock_speed(&chip8);
+            clock_gap = Duration::from_secs_f32(1.0) / clock_speed;
+            calibrator = auto_speed.then(Calibrator::default);
+            history = History::new(REWIND_FRAMES, REWIND_KEYFRAME_INTERVAL);
+            recording = Recording::start(&chip8, keypad.held(), clock_gap);
+            instant_replay.restart(&chip8, keypad.held(), clock_gap);
+            play_time = Duration::ZERO;
+            window.request_redraw();
+        }
+        let updated = input.update(&event);
+        // Typing goes to the line rather than the keypad or hotkeys until Enter or Escape
+        if let Some(line) = assembling.as_mut().filter(|_| updated) {
+            let mut entered = false;
+            for c in input.text() {
+                match c {
+                    TextChar::Back => {
+                        line.pop();
+                    },
+                    TextChar::Char('\r' | '\n') => entered = true,
+                    TextChar::Char(c) if !c.is_control() => line.push(c),
+                    TextChar::Char(_) => {},
+                }
+            }
+            if input.key_pressed(VirtualKeyCode::Escape) {
+                assembling = None;
+                window.set_title(&title(clock_speed));
+            } else if let Some(address) = line.strip_suffix('?').filter(|_| entered) {
+                // e.g. `0x300?` asks what jumps to, calls, points I at, reads or writes 0x300
+                match asm::parse_address(address) {
+                    Ok(address) => {
+                        println!("XREFS {:#05x}", address);
+                        for xref in chip8.xrefs.to(address) {
+                            if chip8.symbols.has_name(xref.from) {
+                                println!("    {} ({})", xref, chip8.symbols.name(xref.from));
+                            } else {
+                                println!("    {}", xref);
+                            }
+                        }
+                        assembling = None;
+                        window.set_title(&title(clock_speed));
+                    },
+                    Err(e) => window.set_title(&format!("Assemble: {}_ ({})", line, e)),
+                }
+            } else if entered {
+                match asm::parse_patch(line) {
+                    Ok((address, instruction)) => {
+                        chip8.patch(address, &instruction);
+                        // Replaying from before the patch wouldn't get here
+                        recording = Recording::start(&chip8, keypad.held(), clock_gap);
+                        instant_replay.restart(&chip8, keypad.held(), clock_gap);
+                        println!("PATCHED {:#05x}: {:?}", address, instruction);
+                        chip8.print_debug_view();
+                        assembling = None;
+                        window.set_title(&title(clock_speed));
+                        if let Some((memory_window, _)) = &memory_view {
+                            memory_window.request_redraw();
+                        }
+                    },
+                    Err(e) => window.set_title(&format!("Assemble: {}_ ({})", line, e)),
+                }
+            } else {
+                window.set_title(&format!("Assemble: {}_", line));
+            }
+        } else if updated {
+            if hotkeys.pressed(&input, Action::Quit) || input.quit() {
+                save_capture(&capture, &recording, beeper.tone);
+                quit(strict.then_some(&chip8), &mut layout, &window, &memory_view, &mirror);
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            if let Some(size) = input.window_resized() {
+                pixels.resize_surface(size.width, size.height);
+            }
+
+            for (key, num) in KEY_MAPPING {
+                if input.key_pressed(key) {
+                    keypad.press(num);
+                    if let Some(probe) = &mut latency {
+                        probe.press(&chip8, Instant::now());
+                    }
+                }
+                if input.key_released(key) {
+                    keypad.release(num);
+                }
+            }
+            for &(key, num) in second_keypad {
+                if input.key_pressed(key) {
+                    chip8.second_keypad[num] = true;
+                }
+                if input.key_released(key) {
+                    chip8.second_keypad[num] = false;
+                }
+            }
+
+            if hotkeys.pressed(&input, Action::Pause) {
+                debugging ^= true;
+                announcer.announce(if debugging { Announcement::Paused } else { Announcement::Resumed });
+            }
+
+            if hotkeys.pressed(&input, Action::Fullscreen) {
+                let window = mirror.as_ref().filter(|_| mirror_focused).map_or(&window, |(mirror_window, _)| mirror_window);
+                window.set_fullscreen(match window.fullscreen() {
+                    Some(_) => None,
+                    None => Some(Fullscreen::Borderless(None)),
+                });
+            }
+
+            // A ROM pasted in replaces the running one, as if it had been given on the command line
+            if hotkeys.pressed(&input, Action::PasteRom) {
+                if let Some(rom) = paste_rom() {
+                    rom_path = pasted_path(&rom);
+                    (chip8, rom_hash) = start_rom(&rom, &rom_path, &machine, &breakpoints);
+                    beeper.reset(&chip8, &mut audio);
+                    look = Look::load(&rom_path);
+                    effects = Effects::builtin(look.effects);
+                    score = ScoreTracker::load(&rom_path);
+                    if let Some(marks) = practice.take().map(|practice| practice.marks) {
+                        practice = Some(Practice::load(&rom_path, rom_hash, marks));
+                    }
+                    history = History::new(REWIND_FRAMES, REWIND_KEYFRAME_INTERVAL);
+                    recording = Recording::start(&chip8, keypad.held(), clock_gap);
+                    instant_replay.restart(&chip8, keypad.held(), clock_gap);
+                    play_time = Duration::ZERO;
+                    slot_preview = None;
+                    window.set_title(&title(clock_speed));
+                    window.request_redraw();
+                    announcer.announce(Announcement::RomLoaded { path: rom_path.clone() });
+                }
+            }
+
+            if hotkeys.pressed(&input, Action::Screenshot) {
+                save_screenshot(&chip8, &rom_path);
+            }
+
+            if hotkeys.pressed(&input, Action::Heatmap) {
+                heatmap ^= true;
+                log::info!("Draw-order heatmap {}", if heatmap { "on" } else { "off" });
+                window.request_redraw();
+            }
+
+            if hotkeys.pressed(&input, Action::Latency) {
+                latency = match latency {
+                    Some(_) => None,
+                    None => Some(LatencyProbe::default()),
+                };
+                log::info!("Input latency timing {}", if latency.is_some() { "on" } else { "off" });
+            }
+
+            // Named after the cycle like screenshots, and played or rendered like any other replay
+            if hotkeys.pressed(&input, Action::InstantReplay) {
+                save_recording(&instant_replay.export(), &format!("{}.{}.c8r", rom_path, chip8.timestamp().cycle));
+            }
+
+            // The look picker (F7 by default) chooses a setting to adjust with PageUp/PageDown, saved for this ROM
+            if hotkeys.pressed(&input, Action::Look) {
+                look_setting = Setting::next(look_setting);
+                match look_setting {
+                    Some(setting) => window.set_title(&describe_look(&look, setting)),
+                    None => window.set_title(&title(clock_speed)),
+                }
+            }
+            if let Some(setting) = look_setting {
+                let up = hotkeys.pressed(&input, Action::LookUp);
+                if up != hotkeys.pressed(&input, Action::LookDown) {
+                    look.adjust(setting, up);
+                    window.set_title(&describe_look(&look, setting));
+                    if let Err(e) = look.save(&rom_path) {
+                        log::warn!("Couldn't save look settings: {}", e);
+                    }
+                    window.request_redraw();
+                }
+            }
+
+            // The mini-assembler (F3 by default) pauses and takes a line like `0x220: LD V1, 0x05`
+            if hotkeys.pressed(&input, Action::Assemble) {
+                debugging = true;
+                assembling = Some(String::new());
+                window.set_title("Assemble: _");
+            }
+
+            // Experiments (F4 by default) answer whether the program depends on a quirk from here on
+            if hotkeys.pressed(&input, Action::Experiment) {
+                debugging = true;
+                println!("EXPERIMENT: {} cycles from {:#05x} with each quirk flipped", EXPERIMENT_CYCLES, chip8.pc);
+                for quirk in Quirks::NAMES {
+                    print!("{}", Experiment::run(&chip8, quirk, EXPERIMENT_CYCLES, clock_gap));
+                }
+            }
+
+            if hotkeys.pressed(&input, Action::MemoryView) {
+                memory_view = match memory_view {
+                    Some(_) => None,
+                    None => Some(open_memory_view(target, None)),
+                };
+                announcer.announce(Announcement::Window { name: "Memory", open: memory_view.is_some() });
+            }
+
+            if hotkeys.pressed(&input, Action::Mirror) {
+                mirror = match mirror {
+                    Some(_) => None,
+                    None => Some(open_mirror(target, None)),
+                };
+                mirror_focused = false;
+                window.request_redraw();
+                announcer.announce(Announcement::Window { name: "Mirror", open: mirror.is_some() });
+            }
+
+            if hotkeys.released(&input, Action::Step) {
+                next_cycle = true;
+            }
+
+            if debugging && hotkeys.released(&input, Action::StepBack) && !recording.is_empty() {
+                let target = recording.len() - 1;
+                recording.reconstruct(&mut chip8, target);
+                recording.truncate(target);
+                instant_replay.restart(&chip8, keypad.held(), clock_gap);
+                println!("STEPPED BACK");
+                chip8.print_debug_view();
+                window.request_redraw();
+            }
+
+            if hotkeys.pressed(&input, Action::Rewind) && !history.is_empty() {
+                log::debug!("Rewinding through {} snapshots ({} bytes)", history.len(), history.memory_usage());
+            }
+            rewinding = hotkeys.held(&input, Action::Rewind);
+            turbo = hotkeys.held(&input, Action::Turbo);
+
+            // Save and load the selected slot (F5/F9 by default), or open the slot picker (F6; arrows to choose, Enter to load)
+            let mut load_requested = hotkeys.pressed(&input, Action::LoadState);
+            let mut slot_changed = false;
+            if hotkeys.pressed(&input, Action::SlotPicker) {
+                slot_changed = true;
+                slot_preview = match slot_preview {
+                    Some(_) => None,
+                    None => Some(read_slot(&rom_path, slot)),
+                };
+            }
+            if let Some(preview) = &mut slot_preview {
+                let previous_slot = slot;
+                if input.key_pressed(VirtualKeyCode::Left) {
+                    slot = if slot == 1 { SLOTS } else { slot - 1 };
+                }
+                if input.key_pressed(VirtualKeyCode::Right) {
+                    slot = if slot == SLOTS { 1 } else { slot + 1 };
+                }
+                if slot != previous_slot {
+                    *preview = read_slot(&rom_path, slot);
+                    slot_changed = true;
+                }
+                load_requested |= input.key_pressed(VirtualKeyCode::Return);
+            }
+            if hotkeys.pressed(&input, Action::SaveState) {
+                let state = SaveState::new(chip8.snapshot(), &chip8.display, rom_hash, play_time);
+                if save_slot(&state, &rom_path, slot) {
+                    announcer.announce(Announcement::StateSaved { slot });
+                }
+                if let Some(preview) = &mut slot_preview {
+                    *preview = Some(state);
+                    slot_changed = true;
+                }
+            }
+            if let Some(practice) = &mut practice {
+                if hotkeys.pressed(&input, Action::Checkpoint) {
+                    practice.take(&chip8, rom_hash, play_time);
+                    save_practice(practice, &rom_path);
+                    if assembling.is_none() && look_setting.is_none() && slot_preview.is_none() {
+                        show_toast(&window, Announcement::CheckpointTaken, &mut toast);
+                    }
+                    announcer.announce(Announcement::CheckpointTaken);
+                }
+                if hotkeys.pressed(&input, Action::Retry) {
+                    match practice.retry(&mut chip8) {
+                        Some(saved_play_time) => {
+                            play_time = saved_play_time;
+                            recording = Recording::start(&chip8, keypad.held(), clock_gap);
+                            instant_replay.restart(&chip8, keypad.held(), clock_gap);
+                            let retry = Announcement::Retry { count: practice.retries };
+                            if assembling.is_none() && look_setting.is_none() && slot_preview.is_none() {
+                                show_toast(&window, &retry, &mut toast);
+                            }
+                            announcer.announce(retry);
+                            window.request_redraw();
+                        },
+                        None => println!("No checkpoint yet, take one with the checkpoint key (End by default)"),
+                    }
+                }
+            }
+            if load_requested {
+                if let Some(saved_play_time) = load_slot(&mut chip8, &rom_path, rom_hash, slot, input.held_shift()) {
+                    play_time = saved_play_time;
+                    recording = Recording::start(&chip8, keypad.held(), clock_gap);
+                    instant_replay.restart(&chip8, keypad.held(), clock_gap);
+                    slot_preview = None;
+                    slot_changed = true;
+                    announcer.announce(Announcement::StateLoaded { slot });
+                }
+            }
+            if slot_changed {
+                match &slot_preview {
+                    Some(preview) => window.set_title(&describe_slot(preview, rom_hash, slot)),
+                    None => window.set_title(&title(clock_speed)),
+                }
+                window.request_redraw();
+            }
+
+            let faster = hotkeys.pressed(&input, Action::Faster);
+            let slower = hotkeys.pressed(&input, Action::Slower);
+            if faster != slower {
+                if calibrator.take().is_some() {
+                    log::info!("Automatic speed off");
+                }
+                clock_speed = adjust_clock_speed(clock_speed, faster);
+                clock_gap = Duration::from_secs_f32(1.0) / clock_speed;
+                // Restart the schedule from now so the new speed doesn't try to catch up
+                // (or wait out) cycles budgeted at the old speed.
+                // Timers follow emulated time, which advances one clock_gap per instruction, so they stay at 60Hz.
+                time = Instant::now();
+                log::info!("Clock speed set to {} instructions per second", clock_speed);
+                announcer.announce(Announcement::ClockSpeed(clock_speed));
+            }
+        }
+
+        match event {
+            Event::RedrawRequested(window_id) if window_id == window.id() => {
+                // Empty slots, and states saved without a thumbnail, show as blank
+                let blank = Screen::default();
+                let shown = match &slot_preview {
+                    Some(state) => state.as_ref().and_then(|state| state.thumbnail.as_ref()).unwrap_or(&blank),
+                    None => &chip8.display,
+                };
+                fit_buffer(&mut pixels, &mut buffer_size, shown.size());
+                let palette = effects.apply(&look.palette, &FrameInfo::of(&chip8));
+                match &slot_preview {
+                    Some(_) => draw_screen(shown, &palette, pixels.get_frame()),
+                    None if heatmap => draw_heatmap(&chip8.display, &chip8.draw_order, pixels.get_frame()),
+                    None => {
+                        phosphor.draw(&chip8.display, &Look { palette, ..look }, pixels.get_frame());
+                        // Keep drawing frames until the afterglow has faded
+                        if phosphor.fading() {
+                            wanna_render = Cycle::RedrawRequested;
+                        }
+                    },
+                }
+                render(&mut pixels, &window, buffer_size.0 as u32, buffer_size.1 as u32);
+                if let Some(shown) = latency.as_mut().and_then(|probe| probe.check(&chip8, Instant::now())) {
+                    log::info!("Key press shown after {}", shown);
+                }
+                if let Some((mirror_window, mirror_pixels)) = &mut mirror {
+                    // Both resolutions are 2:1, so the frame's length tells them apart
+                    if mirror_pixels.get_frame().len() != pixels.get_frame().len() {
+                        mirror_pixels.resize_buffer(buffer_size.0 as u32, buffer_size.1 as u32);
+                    }
+                    mirror_pixels.get_frame().copy_from_slice(pixels.get_frame());
+                    render(mirror_pixels, mirror_window, buffer_size.0 as u32, buffer_size.1 as u32);
+                }
+                if let Some((memory_window, _)) = &memory_view {
+                    memory_window.request_redraw();
+                }
+            },
+            // The mirror only ever shows what the display last drew
+            Event::RedrawRequested(window_id) if mirror.as_ref().is_some_and(|(mirror_window, _)| mirror_window.id() == window_id) => {
+                window.request_redraw();
+            },
+            Event::RedrawRequested(_) => {
+                if let Some((memory_window, memory_pixels)) = &mut memory_view {
+                    draw_memory(&chip8.memory, chip8.pc, chip8.index_register.0 as usize, memory_pixels.get_frame());
+                    render(memory_pixels, memory_window, MEMORY_VIEW_WIDTH as u32, MEMORY_VIEW_HEIGHT as u32);
+                }
+            },
+            Event::Suspended => {
+                suspended = true;
+                beeper.silence(&chip8, &mut audio);
+            },
+            // The surface may not have survived being suspended
+            Event::Resumed => {
+                suspended = false;
+                reconfigure_surface(&mut pixels, &window);
+                if let Some((memory_window, memory_pixels)) = &mut memory_view {
+                    reconfigure_surface(memory_pixels, memory_window);
+                }
+                if let Some((mirror_window, mirror_pixels)) = &mut mirror {
+                    reconfigure_surface(mirror_pixels, mirror_window);
+                }
+                window.request_redraw();
+            },
+            Event::NewEvents(StartCause::Init) => {
+                *control_flow = ControlFlow::WaitUntil(time + clock_gap);
+            },
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                // Too far behind to have just been busy, e.g. the machine slept without telling us.
+                // Running the missed instructions flat out would fast-forward the program.
+                let behind = Instant::now().saturating_duration_since(time);
+                if behind > MAX_CATCH_UP {
+                    log::info!("{}ms behind, not catching up", behind.as_millis());
+                    time = Instant::now();
+                }
+                if rewinding {
+                    let now = Instant::now();
+                    if now.duration_since(last_snapshot) >= frame_gap {
+                        last_snapshot = now;
+                        if let Some(snapshot) = history.pop() {
+                            chip8.restore(&snapshot);
+                            recording = Recording::start(&chip8, keypad.held(), clock_gap);
+                            instant_replay.restart(&chip8, keypad.held(), clock_gap);
+                            window.request_redraw();
+                        }
+                    }
+                } else if slot_preview.is_none() && !suspended && !minimized && (!debugging || next_cycle) {
+                    let now = Instant::now();
+                    if let Some(slow_log) = &mut slow_log {
+                        slow_log.start(&chip8, now);
+                    }
+                    play_time += clock_gap;
+                    let keys = keypad.take();
+                    recording.record_cycle(keys, clock_gap);
+                    instant_replay.record_cycle(&chip8, keys, clock_gap);
+                    let instruction = chip8.instruction_at(chip8.pc).and_then(|raw| chip8.platform.decode(raw));
+                    // The core's timers tick from clock_gap, so they keep pace with the program even in turbo
+                    match chip8.cycle(keys, clock_gap) {
+                        Cycle::RedrawRequested => wanna_render = Cycle::RedrawRequested,
+                        Cycle::Complete => {},
+                        // The boot animation doesn't exit, so this is the ROM's
+                        Cycle::Exit => {
+                            println!("EXIT: the program ran 00FD at {:#05x}", chip8.pc);
+                            save_capture(&capture, &recording, beeper.tone);
+                            quit(strict.then_some(&chip8), &mut layout, &window, &memory_view, &mirror);
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        },
+                    }
+                    let frame = chip8.timestamp().frame();
+                    if let Some(tempo) = calibrator.as_mut().and_then(|calibrator| calibrator.observe(instruction, frame)) {
+                        let calibrated = adjust_clock_speed(clock_speed, tempo == Tempo::TooSlow)
+                            .clamp(DEFAULT_CLOCK_SPEED, MAX_CALIBRATED_CLOCK_SPEED);
+                        if calibrated != clock_speed {
+                            clock_speed = calibrated;
+                            clock_gap = Duration::from_secs_f32(1.0) / clock_speed;
+                            time = Instant::now();
+                            log::info!("Automatic speed set to {} instructions per second, +/- take over", clock_speed);
+                        }
+                    }
+                    beeper.update(&mut chip8, &mut audio);
+                    if effects.stale(&FrameInfo::of(&chip8)) {
+                        wanna_render = Cycle::RedrawRequested;
+                    }
+                    if let Some(practice) = &mut practice {
+                        if practice.check(&chip8, rom_hash, play_time) {
+                            save_practice(practice, &rom_path);
+                            if assembling.is_none() && look_setting.is_none() {
+                                show_toast(&window, Announcement::CheckpointTaken, &mut toast);
+                            }
+                            announcer.announce(Announcement::CheckpointTaken);
+                        }
+                    }
+                    // Like the RPL flags, the leaderboard is saved as soon as it changes
+                    if let Some(score) = score.as_mut().filter(|_| !booting) {
+                        if let Some(progress) = score.observe(&chip8) {
+                            if let Err(e) = score.leaderboard.save(&rom_path) {
+                                log::error!("Couldn't save the leaderboard: {}", e);
+                            }
+                            if let Progress::NewBest(best) = progress {
+                                if assembling.is_none() && look_setting.is_none() {
+                                    show_toast(&window, Announcement::NewBest { score: best }, &mut toast);
+                                }
+                                announcer.announce(Announcement::NewBest { score: best });
+                            }
+                        }
+                    }
+                    for report in chip8.diagnostics.take_new().into_iter().filter(|report| report.severity >= Severity::Warning) {
+                        if assembling.is_none() && look_setting.is_none() {
+                            show_toast(&window, &report, &mut toast);
+                        }
+                        announcer.announce(Announcement::Diagnostic(report));
+                    }
+                    // Programs keep high scores and settings in the RPL flags, so they're saved right away
+                    if std::mem::take(&mut chip8.rpl_changed) {
+                        if let Err(e) = rpl::save(&rom_path, &chip8.rpl) {
+                            log::warn!("Couldn't save RPL flags: {}", e);
+                        }
+                    }
+                    if let Some(hit) = chip8.hit.take() {
+                        println!("BREAK: {}", hit);
+                        debugging = true;
+                        announcer.announce(Announcement::Breakpoint(hit));
+                    }
+                    if debugging {
+                        next_cycle = false;
+                        print!("DEBUGGING: {}", debugging);
+                        chip8.print_debug_view();
+                        if let Some((memory_window, _)) = &memory_view {
+                            memory_window.request_redraw();
+                        }
+                    }
+                    if now.duration_since(last_snapshot) >= frame_gap {
+                        last_snapshot = now;
+                        history.push(chip8.snapshot());
+                    }
+                    if let Some(slow) = slow_log.as_mut().and_then(|slow_log| slow_log.finish(Instant::now())) {
+                        println!("SLOW: {}", slow);
+                    }
+                    if let Cycle::RedrawRequested = wanna_render {
+                        // Coalesce harder during a draw storm so rendering doesn't starve input handling
+                        let render_gap = if chip8.draw_storm { frame_gap * DRAW_STORM_RENDER_DIVISOR } else { frame_gap };
+                        if now.duration_since(last_render) >= render_gap {
+                            last_render = now;
+                            let behind = turbo || now.saturating_duration_since(time) > frame_gap;
+                            if frame_skipper.should_render(behind) {
+                                wanna_render = Cycle::Complete;
+                                window.request_redraw();
+                            }
+                        }
+                    }
+                }
+                time += if turbo { clock_gap / TURBO_FACTOR } else { clock_gap };
+                let paused = slot_preview.is_some() || suspended || minimized || (debugging && !next_cycle);
+                let keys = keypad.peek();
+                // The boot animation halts after drawing, but still has to run out its time
+                idle = !rewinding && !booting && (paused || chip8.waiting_for_input(keys));
+                if idle {
+                    if let Cycle::RedrawRequested = std::mem::replace(&mut wanna_render, Cycle::Complete) {
+                        window.request_redraw();
+                    }
+                    *control_flow = ControlFlow::Wait;
+                } else {
+                    *control_flow = ControlFlow::WaitUntil(time);
+                }
+            },
+            _ => {}
+        }
+    });
+}
+
+const TITLE: &str = "CHIP-8 Emulator";
+
+// A snapshot is taken every frame, so this is 3 minutes of rewind
+const REWIND_FRAMES: usize = 60 * 60 * 3;
+const REWIND_KEYFRAME_INTERVAL: usize = 60;
+
+const INSTANT_REPLAY_WINDOW: Duration = Duration::from_secs(30);
+
+// How long a diagnostic stays in the title bar
+const TOAST_TIME: Duration = Duration::from_secs(5);
+
+// During a draw storm, only every this many frames is rendered
+const DRAW_STORM_RENDER_DIVISOR: u32 = 4;
+
+// Behind schedule by more than this, the clock starts over rather than catching up
+const MAX_CATCH_UP: Duration = Duration::from_millis(250);
+
+// How much faster the emulator runs while Tab is held
+const TURBO_FACTOR: u32 = 4;
+
+/// Automatic speed goes between the default and this, since timer-paced programs
+/// that need more than this are more likely spinning on something else
+const MAX_CALIBRATED_CLOCK_SPEED: u32 = 4_000;
+
+/// Steps the clock speed up or down by a quarter, clamped to a sane range
+fn adjust_clock_speed(clock_speed: u32, faster: bool) -> u32 {
+    let adjusted = if faster {
+        clock_speed + clock_speed / 4
+    } else {
+        clock_speed - clock_speed / 5
+    };
+    adjusted.clamp(MIN_CLOCK_SPEED, MAX_CLOCK_SPEED)
+}
+
+/// The display's title bar, when it isn't showing anything else
+fn title(clock_speed: u32) -> String {
+    format!("{} ({} IPS)", TITLE, clock_speed)
+}
+
+/// Tuple of `(window, surface, width, height, hidpi_factor)`
+/// `width` and `height` are in `PhysicalSize` units.
+fn create_window(
+    title: &str,
+    event_loop: &EventLoop<()>,
+) -> (winit::window::Window, u32, u32, f64) {
+    // Create a hidden window so we can estimate a good default window size
+    let window = winit::window::WindowBuilder::new()
+        .with_visible(false)
+        .with_title(title)
+        .build(event_loop)
+        .unwrap();
+    let hidpi_factor = window.scale_factor();
+
+    // Get dimensions
+    let width = SCREEN_WIDTH as f64;
+    let height = SCREEN_HEIGHT as f64;
+    let (monitor_width, monitor_height) = {
+        if let Some(monitor) = window.current_monitor() {
+            let size = monitor.size().to_logical(hidpi_factor);
+            (size.width, size.height)
+        } else {
+            (width, height)
+        }
+    };
+    let scale = (monitor_height / height * 2.0 / 3.0).round().max(1.0);
+
+    // Resize, center, and display the window. The minimum is a physical pixel per emulated pixel
+    // on whichever monitor the window is on.
+    let min_size = PhysicalSize::new(width, height);
+    let default_size = LogicalSize::new(width * scale, height * scale);
+    let center = LogicalPosition::new(
+        (monitor_width - width * scale) / 2.0,
+        (monitor_height - height * scale) / 2.0,
+    );
+    window.set_inner_size(default_size);
+    window.set_min_inner_size(Some(min_size));
+    window.set_outer_position(center);
+    window.set_visible(true);
+
+    let size = default_size.to_physical::<f64>(hidpi_factor);
+
+    (
+        window,
+        size.width.round() as u32,
+        size.height.round() as u32,
+        hidpi_factor,
+    )
+}
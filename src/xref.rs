@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+use crate::chip8::Instruction;
+use crate::decode::decode;
+use crate::disasm::{Item, Line};
+
+/// How an instruction refers to an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Jump,
+    Call,
+    /// ANNN points I at it
+    Index,
+    Read,
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Access::Jump => "jump",
+            Access::Call => "call",
+            Access::Index => "index",
+            Access::Read => "read",
+            Access::Write => "write",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Xref {
+    /// Address of the instruction
+    pub from: usize,
+    pub access: Access,
+}
+
+/// e.g. `jump from 0x202` or `read by 0x208`
+impl fmt::Display for Xref {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let how = match self.access {
+            Access::Jump => "jump from",
+            Access::Call => "call from",
+            Access::Index => "I set by",
+            Access::Read => "read by",
+            Access::Write => "written by",
+        };
+        write!(f, "{} {:#05x}", how, self.from)
+    }
+}
+
+/// For each address, the instructions that jump to, call, point I at, read or write it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Xrefs {
+    refs: BTreeMap<usize, BTreeSet<Xref>>,
+}
+
+impl Xrefs {
+    /// What can be worked out without running the program. Reads and writes through I are only
+    /// found where I was set by ANNN earlier in the same straight run of code, assuming FX55/FX65
+    /// leave it alone (the default quirks); computed jumps aren't followed.
+    pub fn analyze(lines: &[Line]) -> Self {
+        let mut xrefs = Xrefs::default();
+        let instructions = lines.iter().filter_map(|line| match line.item {
+            Item::Instruction(raw) => Some((line.address, decode(raw).unwrap())),
+            Item::Data(_) => None,
+        });
+        for (from, instruction) in instructions.clone() {
+            if let Instruction::Jump { dest } = instruction {
+                xrefs.add(dest as usize, Xref { from, access: Access::Jump });
+            }
+            if let Instruction::CallSubroutine { dest } = instruction {
+                xrefs.add(dest as usize, Xref { from, access: Access::Call });
+            }
+        }
+        let mut index = None;
+        let mut next = None;
+        for (from, instruction) in instructions {
+            // Other paths into this instruction could have set I to anything
+            if next != Some(from) || xrefs.refs.get(&from).is_some_and(|refs| refs.iter().any(|xref| xref.access <= Access::Call)) {
+                index = None;
+            }
+            next = Some(from + 2);
+            let mut access = |range: Range<usize>, access| xrefs.add_range(range, Xref { from, access });
+            match (instruction, index) {
+                (Instruction::SetIndexRegister { value }, _) => {
+                    access(value as usize..value as usize + 1, Access::Index);
+                    index = Some(value as usize);
+                },
+                (Instruction::Draw { height, .. }, Some(i)) => access(i..i + height as usize, Access::Read),
+                (Instruction::LoadMemory { register }, Some(i)) => access(i..i + register as usize + 1, Access::Read),
+                (Instruction::StoreMemory { register }, Some(i)) => access(i..i + register as usize + 1, Access::Write),
+                (Instruction::LoadRange { register1, register2 }, Some(i)) => access(i..i + register1.abs_diff(register2) as usize + 1, Access::Read),
+                (Instruction::StoreRange { register1, register2 }, Some(i)) => access(i..i + register1.abs_diff(register2) as usize + 1, Access::Write),
+                (Instruction::RegToDecimal { .. }, Some(i)) => access(i..i + 3, Access::Write),
+                (Instruction::LoadAudio, Some(i)) => access(i..i + 16, Access::Read),
+                (
+                    Instruction::AddToIndex { .. }
+                    | Instruction::FontChar { .. }
+                    | Instruction::BigFontChar { .. }
+                    | Instruction::CallSubroutine { .. }
+                    | Instruction::Jump { .. }
+                    | Instruction::JumpOffset { .. }
+                    | Instruction::Return,
+                    _,
+                ) => index = None,
+                _ => {},
+            }
+        }
+        xrefs
+    }
+
+    pub fn add(&mut self, address: usize, xref: Xref) {
+        self.refs.entry(address).or_default().insert(xref);
+    }
+
+    pub fn add_range(&mut self, range: Range<usize>, xref: Xref) {
+        for address in range {
+            self.add(address, xref);
+        }
+    }
+
+    /// The instructions that refer to `address`, in order of their addresses
+    pub fn to(&self, address: usize) -> impl Iterator<Item = &Xref> {
+        self.refs.get(&address).into_iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::Instruction;
+    use crate::disasm::disassemble;
+    use crate::encode::assemble;
+    use crate::platform::Platform;
+    use crate::testing::Machine;
+    use super::{Access, Xref, Xrefs};
+
+    #[test]
+    fn follows_i_through_straight_code() {
+        let rom = assemble(&[
+            Instruction::SetIndexRegister { value: 0x300 },
+            Instruction::Draw { x_r: 0, y_r: 0, height: 2 },
+            Instruction::StoreMemory { register: 0 },
+            Instruction::CallSubroutine { dest: 0x20a },
+            Instruction::Jump { dest: 0x208 },
+            // 0x20a: I could be anything when called
+            Instruction::LoadMemory { register: 1 },
+            Instruction::Return,
+        ]);
+        let xrefs = Xrefs::analyze(&disassemble(&rom, Platform::Chip8));
+        let to = |address| xrefs.to(address).copied().collect::<Vec<_>>();
+        assert_eq!(to(0x300), [
+            Xref { from: 0x200, access: Access::Index },
+            Xref { from: 0x202, access: Access::Read },
+            Xref { from: 0x204, access: Access::Write },
+        ]);
+        assert_eq!(to(0x301), [Xref { from: 0x202, access: Access::Read }]);
+        assert_eq!(to(0x302), []);
+        assert_eq!(to(0x208), [Xref { from: 0x208, access: Access::Jump }]);
+        assert_eq!(to(0x20a), [Xref { from: 0x206, access: Access::Call }]);
+    }
+
+    #[test]
+    fn records_what_runs() {
+        let mut machine = Machine::from_instructions(&[
+            Instruction::SetRegister { register: 0, value: 4 },
+            Instruction::JumpOffset { dest: 0x202 },
+            Instruction::Jump { dest: 0x204 },
+            // 0x206: only reached through the computed jump
+            Instruction::AddToIndex { register: 0 },
+            Instruction::StoreMemory { register: 0 },
+            Instruction::Jump { dest: 0x20c },
+        ]);
+        machine.run(5);
+        let to = |address| machine.chip8.xrefs.to(address).copied().collect::<Vec<_>>();
+        assert_eq!(to(0x206), [Xref { from: 0x202, access: Access::Jump }]);
+        assert_eq!(to(0x004), [Xref { from: 0x208, access: Access::Write }]);
+    }
+}